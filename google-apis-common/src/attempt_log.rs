@@ -0,0 +1,119 @@
+//! Recording of the individual HTTP requests a call made
+//!
+//! A call may take long because a single request was slow, or because it was retried a couple of
+//! times. The [`AttemptLog`] delegate keeps track of each attempt to tell these apart.
+//!
+//! # Example
+//! ```ignore
+//! let mut log = AttemptLog::default();
+//! let result = hub.objects().get("bucket", "object").delegate(&mut log).doit().await;
+//! for attempt in log.attempts() {
+//!     println!("{:?} after {:?}, then waited {:?}", attempt.status, attempt.duration, attempt.backoff);
+//! }
+//! ```
+use std::time::{Duration, SystemTime};
+
+use hyper::StatusCode;
+
+use crate::{DefaultDelegate, Delegate, MethodInfo};
+
+/// A single HTTP request made as part of a call.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attempt {
+    /// When the request was sent
+    pub started_at: SystemTime,
+    /// The time it took until the response headers arrived, or the request failed
+    pub duration: Duration,
+    /// The status of the response, or `None` if the connection failed
+    pub status: Option<StatusCode>,
+    /// The time waited before the next attempt, if the request was retried
+    pub backoff: Option<Duration>,
+}
+
+/// A [`Delegate`] recording an [`Attempt`] for each HTTP request of the calls it is used with.
+///
+/// All calls are forwarded to the wrapped delegate, which keeps deciding about retries
+/// and resumable uploads.
+pub struct AttemptLog<D = DefaultDelegate> {
+    inner: D,
+    attempts: Vec<Attempt>,
+}
+
+impl Default for AttemptLog {
+    fn default() -> Self {
+        AttemptLog::new(DefaultDelegate)
+    }
+}
+
+impl<D: Delegate> AttemptLog<D> {
+    /// Records the attempts of calls made with `inner`.
+    pub fn new(inner: D) -> Self {
+        AttemptLog {
+            inner,
+            attempts: Vec::new(),
+        }
+    }
+
+    /// The attempts recorded since the last call began, oldest first.
+    pub fn attempts(&self) -> &[Attempt] {
+        &self.attempts
+    }
+
+    /// Returns the recorded attempts and the wrapped delegate.
+    pub fn into_parts(self) -> (Vec<Attempt>, D) {
+        (self.attempts, self.inner)
+    }
+}
+
+impl<D: Delegate> Delegate for AttemptLog<D> {
+    fn forward_to(&mut self) -> Option<&mut dyn Delegate> {
+        Some(&mut self.inner)
+    }
+
+    fn begin(&mut self, info: MethodInfo) {
+        self.attempts.clear();
+        self.inner.begin(info)
+    }
+
+    fn retry_scheduled(&mut self, attempt: u32, delay: Duration) {
+        if let Some(last) = self.attempts.last_mut() {
+            last.backoff = Some(delay);
+        }
+        self.inner.retry_scheduled(attempt, delay)
+    }
+
+    fn attempt_completed(&mut self, status: Option<StatusCode>, elapsed: Duration) {
+        self.attempts.push(Attempt {
+            started_at: SystemTime::now() - elapsed,
+            duration: elapsed,
+            status,
+            backoff: None,
+        });
+        self.inner.attempt_completed(status, elapsed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn backoff_is_attributed_to_the_retried_attempt() {
+        let mut log = AttemptLog::default();
+        log.attempt_completed(
+            Some(StatusCode::SERVICE_UNAVAILABLE),
+            Duration::from_millis(5),
+        );
+        log.retry_scheduled(1, Duration::from_secs(1));
+        log.attempt_completed(Some(StatusCode::OK), Duration::from_millis(3));
+        log.finished(true);
+
+        let attempts = log.attempts();
+        assert_eq!(attempts.len(), 2);
+        assert_eq!(attempts[0].status, Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert_eq!(attempts[0].backoff, Some(Duration::from_secs(1)));
+        assert_eq!(attempts[1].duration, Duration::from_millis(3));
+        assert_eq!(attempts[1].backoff, None);
+        assert!(attempts[0].started_at <= attempts[1].started_at);
+    }
+}
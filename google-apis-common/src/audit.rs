@@ -0,0 +1,215 @@
+//! An audit trail of the calls changing resources
+//!
+//! Once an [`Auditor`] is set on a hub with its `audit()` method, every call which doesn't use
+//! `GET` is recorded as [`AuditRecord`] to the [`AuditSink`] of the auditor when it finishes.
+//! Calls rejected before a request was sent, for instance for lack of a token, are not recorded.
+//!
+//! # Example
+//! ```ignore
+//! let file = std::fs::OpenOptions::new().create(true).append(true).open("audit.jsonl")?;
+//! hub.audit(Auditor::new(JsonLinesSink::new(file)).principal("deployer@project.iam.gserviceaccount.com"));
+//! ```
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use hyper::{Method, StatusCode};
+use ring::digest;
+use serde::Serialize;
+
+use crate::signed_url::hex;
+use crate::Delegate;
+
+/// A mutating call made through a hub.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct AuditRecord {
+    /// When the call finished
+    pub timestamp: DateTime<Utc>,
+    /// The id of the API method, like `storage.objects.delete`
+    pub method_id: &'static str,
+    pub http_method: String,
+    /// The path of the URL of the changed resource, like `/storage/v1/b/bucket/o/object`
+    pub resource: String,
+    /// The identity the call was made as, as configured on the [`Auditor`]
+    pub principal: Option<String>,
+    /// The hex encoded SHA-256 of the JSON request body, if there was one
+    pub request_hash: Option<String>,
+    /// The status of the last response, if there was one
+    pub status: Option<u16>,
+    /// Whether the call succeeded
+    pub success: bool,
+}
+
+/// Receives the [`AuditRecord`]s of a hub.
+pub trait AuditSink: Send + Sync {
+    fn record(&self, record: AuditRecord);
+}
+
+/// Writes each [`AuditRecord`] as a line of JSON.
+pub struct JsonLinesSink<W> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send> JsonLinesSink<W> {
+    pub fn new(writer: W) -> Self {
+        JsonLinesSink {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+            .into_inner()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<W: Write + Send> AuditSink for JsonLinesSink<W> {
+    fn record(&self, record: AuditRecord) {
+        let mut writer = self.writer.lock().unwrap_or_else(|err| err.into_inner());
+        let written = serde_json::to_writer(&mut *writer, &record)
+            .map_err(std::io::Error::from)
+            .and_then(|_| writer.write_all(b"\n"))
+            .and_then(|_| writer.flush());
+        if let Err(err) = written {
+            log::error!("Failed to write audit record {:?}: {}", record, err);
+        }
+    }
+}
+
+/// Configures auditing of a hub.
+#[derive(Clone)]
+pub struct Auditor {
+    sink: Arc<dyn AuditSink>,
+    principal: Option<String>,
+}
+
+impl Auditor {
+    pub fn new<S: AuditSink + 'static>(sink: S) -> Self {
+        Auditor {
+            sink: Arc::new(sink),
+            principal: None,
+        }
+    }
+
+    /// Record calls as made by `principal`, like the email of a service account.
+    pub fn principal<P: Into<String>>(mut self, principal: P) -> Self {
+        self.principal = Some(principal.into());
+        self
+    }
+}
+
+/// A [`Delegate`] recording the outcome of a call to an [`Auditor`] while forwarding all calls to
+/// the delegate of the call. It is used by audited hubs.
+pub struct AuditDelegate<'a> {
+    inner: &'a mut dyn Delegate,
+    auditor: Auditor,
+    record: AuditRecord,
+}
+
+impl<'a> AuditDelegate<'a> {
+    pub fn new(
+        inner: &'a mut dyn Delegate,
+        auditor: &Auditor,
+        method_id: &'static str,
+        http_method: Method,
+        url: &::url::Url,
+        request_body: Option<&[u8]>,
+    ) -> Self {
+        AuditDelegate {
+            inner,
+            auditor: auditor.clone(),
+            record: AuditRecord {
+                timestamp: Utc::now(),
+                method_id,
+                http_method: http_method.to_string(),
+                resource: url.path().to_string(),
+                principal: auditor.principal.clone(),
+                request_hash: request_body
+                    .map(|body| hex(digest::digest(&digest::SHA256, body).as_ref())),
+                status: None,
+                success: false,
+            },
+        }
+    }
+}
+
+impl<'a> Delegate for AuditDelegate<'a> {
+    fn forward_to(&mut self) -> Option<&mut dyn Delegate> {
+        Some(&mut *self.inner)
+    }
+
+    fn attempt_completed(&mut self, status: Option<StatusCode>, elapsed: Duration) {
+        self.record.status = status.map(|status| status.as_u16());
+        self.inner.attempt_completed(status, elapsed)
+    }
+
+    fn finished(&mut self, is_success: bool) {
+        let mut record = self.record.clone();
+        record.timestamp = Utc::now();
+        record.success = is_success;
+        self.auditor.sink.record(record);
+        self.inner.finished(is_success)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::DefaultDelegate;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn finished_calls_are_recorded() {
+        let buffer = Buffer::default();
+        let auditor = Auditor::new(JsonLinesSink::new(buffer.clone())).principal("me@example.com");
+        let url = ::url::Url::parse(
+            "https://storage.googleapis.com/storage/v1/b/bucket/o/object?alt=json",
+        )
+        .unwrap();
+
+        // mirrors how generated calls wrap their delegate
+        let mut dd = DefaultDelegate;
+        let mut dlg: &mut dyn Delegate = &mut dd;
+        let mut ad;
+        ad = AuditDelegate::new(
+            dlg,
+            &auditor,
+            "storage.objects.patch",
+            Method::PATCH,
+            &url,
+            Some(b"{}"),
+        );
+        dlg = &mut ad;
+        dlg.attempt_completed(Some(StatusCode::SERVICE_UNAVAILABLE), Duration::ZERO);
+        dlg.attempt_completed(Some(StatusCode::OK), Duration::ZERO);
+        dlg.finished(true);
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(output.lines().count(), 1);
+        let record: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(record["method_id"], "storage.objects.patch");
+        assert_eq!(record["http_method"], "PATCH");
+        assert_eq!(record["resource"], "/storage/v1/b/bucket/o/object");
+        assert_eq!(record["principal"], "me@example.com");
+        assert_eq!(
+            record["request_hash"],
+            "44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+        );
+        assert_eq!(record["status"], 200);
+        assert_eq!(record["success"], true);
+    }
+}
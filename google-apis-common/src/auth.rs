@@ -6,6 +6,7 @@
 //! - [`Authenticator`] : An authenticator which supports a variety of authentication methods
 //! - [`String`] : Plain oauth2 token in String format
 //! - [`NoToken`] : No token, used for APIs which do not require a token
+//! - [`StaticToken`] : A fixed token, used for tests against fake servers
 //!
 //! # Usage
 //! [`GetToken`] instances are designed to be used with the Hub constructor provided by the
@@ -20,6 +21,9 @@
 //!
 //! If you intend to use APIs which do not require authentication, use [`NoToken`].
 //!
+//! If you test against a mock or stub server, use [`StaticToken`], which also keeps track of the
+//! requested scopes.
+//!
 //! If you have custom authentication requirements, you can implement [`GetToken`] manually.
 //!
 //! # Example
@@ -73,6 +77,7 @@
 //! [`Authenticator`]: yup_oauth2::authenticator::Authenticator
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 
 type GetTokenOutput<'a> = Pin<
     Box<
@@ -114,6 +119,14 @@ impl GetToken for String {
     }
 }
 
+/// Lets boxed authenticators, like the one of a `client_builder::SharedClient`, be handed to hubs
+/// as they are.
+impl GetToken for Box<dyn GetToken> {
+    fn get_token<'a>(&'a self, scopes: &'a [&str]) -> GetTokenOutput<'a> {
+        (**self).get_token(scopes)
+    }
+}
+
 /// In the event that the API endpoint does not require an oauth2 token, `NoToken` should be provided to the hub to avoid specifying an
 /// authenticator.
 #[derive(Default, Clone)]
@@ -125,6 +138,46 @@ impl GetToken for NoToken {
     }
 }
 
+/// Provides the same token for all scopes, without contacting any server. It is meant for tests
+/// against mock servers, which don't check tokens.
+///
+/// Clones share the requested scopes.
+#[derive(Clone)]
+pub struct StaticToken {
+    token: String,
+    requested_scopes: Arc<Mutex<Vec<Vec<String>>>>,
+}
+
+impl Default for StaticToken {
+    fn default() -> Self {
+        StaticToken::new("static-token")
+    }
+}
+
+impl StaticToken {
+    pub fn new<T: Into<String>>(token: T) -> Self {
+        StaticToken {
+            token: token.into(),
+            requested_scopes: Default::default(),
+        }
+    }
+
+    /// The scopes of each token request made so far, oldest first.
+    pub fn requested_scopes(&self) -> Vec<Vec<String>> {
+        self.requested_scopes.lock().unwrap().clone()
+    }
+}
+
+impl GetToken for StaticToken {
+    fn get_token<'a>(&'a self, scopes: &'a [&str]) -> GetTokenOutput<'a> {
+        self.requested_scopes
+            .lock()
+            .unwrap()
+            .push(scopes.iter().map(|s| s.to_string()).collect());
+        Box::pin(async move { Ok(Some(self.token.clone())) })
+    }
+}
+
 #[cfg(feature = "yup-oauth2")]
 mod yup_oauth2_impl {
     use super::{GetToken, GetTokenOutput};
@@ -165,4 +218,13 @@ mod test {
         let dgt: &mut dyn GetToken = &mut gt;
         with_send(dgt);
     }
+
+    #[tokio::test]
+    async fn static_token_records_scopes() {
+        let auth = StaticToken::new("token");
+        let hub_auth: Box<dyn GetToken> = Box::new(auth.clone());
+        let token = hub_auth.get_token(&["scope-a", "scope-b"]).await.unwrap();
+        assert_eq!(token.as_deref(), Some("token"));
+        assert_eq!(auth.requested_scopes(), [["scope-a", "scope-b"]]);
+    }
 }
@@ -0,0 +1,320 @@
+//! Recording of HTTP interactions into cassettes, and their deterministic replay
+//!
+//! Requires the `cassette` feature.
+//!
+//! A [`CassetteConnector`] is used as connector of the client of a hub. In record mode, it forwards
+//! all requests to a real client and records each interaction. In replay mode, requests are answered
+//! from a [`Cassette`], without any network access. Requests are matched by method, URL and body.
+//!
+//! Credentials are scrubbed: headers of requests aren't recorded, the `key` and `access_token`
+//! query parameters are redacted, and sensitive response headers are dropped.
+//!
+//! # Example
+//! ```ignore
+//! // record once ...
+//! let connector = CassetteConnector::record(hyper::Client::builder().build(https));
+//! let hub = Storage::new(hyper::Client::builder().build(connector.clone()), auth);
+//! hub.buckets().list("project").doit().await?;
+//! connector.cassette().save("tests/cassettes/list-buckets.json")?;
+//!
+//! // ... and replay in tests
+//! let connector = CassetteConnector::replay(Cassette::load("tests/cassettes/list-buckets.json")?);
+//! let hub = Storage::new(hyper::Client::builder().build(connector), NoToken);
+//! ```
+use std::error::Error as StdError;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures::future::BoxFuture;
+use hyper::{Body, Request, Response, StatusCode, Uri};
+use serde::{Deserialize, Serialize};
+
+use crate::in_memory::{self, InMemoryStream};
+use crate::{is_sensitive_header, redact_uri};
+
+/// Recorded interactions, stored as JSON.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// The URL, with credentials in its query redacted
+    pub uri: String,
+    pub body: RecordedBody,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: RecordedBody,
+}
+
+/// A body, as text if it is valid UTF-8, or else base64 encoded.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedBody {
+    Text(String),
+    Base64(String),
+}
+
+impl RecordedBody {
+    fn new(bytes: &[u8]) -> Self {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => RecordedBody::Text(text.to_string()),
+            Err(_) => RecordedBody::Base64(base64::encode(bytes)),
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            RecordedBody::Text(text) => text.as_bytes().to_vec(),
+            RecordedBody::Base64(encoded) => base64::decode(encoded).unwrap_or_default(),
+        }
+    }
+}
+
+impl Cassette {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = io::BufReader::new(fs::File::open(path)?);
+        serde_json::from_reader(file).map_err(io::Error::from)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let file = io::BufWriter::new(fs::File::create(path)?);
+        serde_json::to_writer_pretty(file, self).map_err(io::Error::from)
+    }
+}
+
+type Forward =
+    Arc<dyn Fn(Request<Body>) -> BoxFuture<'static, hyper::Result<Response<Body>>> + Send + Sync>;
+
+enum Mode {
+    Record(Forward),
+    /// Holds which of the interactions were replayed already
+    Replay(Vec<bool>),
+}
+
+struct State {
+    cassette: Cassette,
+    mode: Mode,
+}
+
+/// A connector recording or replaying the interactions of the client using it.
+///
+/// Clones share their cassette.
+#[derive(Clone)]
+pub struct CassetteConnector {
+    state: Arc<Mutex<State>>,
+}
+
+impl CassetteConnector {
+    /// Forwards all requests to `client`, recording them into an empty cassette.
+    pub fn record<C>(client: hyper::Client<C, Body>) -> Self
+    where
+        C: hyper::client::connect::Connect + Clone + Send + Sync + 'static,
+    {
+        let forward: Forward = Arc::new(move |req| Box::pin(client.request(req)));
+        CassetteConnector::new(Cassette::default(), Mode::Record(forward))
+    }
+
+    /// Answers requests with the interactions of `cassette`. Each interaction is used once,
+    /// in the recorded order for equal requests.
+    pub fn replay(cassette: Cassette) -> Self {
+        let replayed = vec![false; cassette.interactions.len()];
+        CassetteConnector::new(cassette, Mode::Replay(replayed))
+    }
+
+    fn new(cassette: Cassette, mode: Mode) -> Self {
+        CassetteConnector {
+            state: Arc::new(Mutex::new(State { cassette, mode })),
+        }
+    }
+
+    /// Returns the cassette, with all interactions recorded so far.
+    pub fn cassette(&self) -> Cassette {
+        self.state.lock().unwrap().cassette.clone()
+    }
+
+    async fn handle(
+        self,
+        req: Request<Body>,
+    ) -> Result<Response<Body>, Box<dyn StdError + Send + Sync>> {
+        let (parts, body) = req.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+        let recorded_request = RecordedRequest {
+            method: parts.method.to_string(),
+            uri: redact_uri(&parts.uri),
+            body: RecordedBody::new(&body),
+        };
+
+        let forward = {
+            let mut state = self.state.lock().unwrap();
+            let State { cassette, mode } = &mut *state;
+            match mode {
+                Mode::Record(forward) => forward.clone(),
+                Mode::Replay(replayed) => return Ok(replay(cassette, replayed, &recorded_request)),
+            }
+        };
+        let res = forward(Request::from_parts(parts, body.into())).await?;
+        let (parts, body) = res.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+        let response = RecordedResponse {
+            status: parts.status.as_u16(),
+            headers: parts
+                .headers
+                .iter()
+                .filter(|(k, _)| is_recorded_header(k))
+                .map(|(k, v)| (k.to_string(), String::from_utf8_lossy(v.as_bytes()).into()))
+                .collect(),
+            body: RecordedBody::new(&body),
+        };
+        self.state
+            .lock()
+            .unwrap()
+            .cassette
+            .interactions
+            .push(Interaction {
+                request: recorded_request,
+                response,
+            });
+        Ok(Response::from_parts(parts, body.into()))
+    }
+}
+
+/// Returns false for credentials, and for headers describing the connection rather than the response.
+fn is_recorded_header(name: &hyper::header::HeaderName) -> bool {
+    !is_sensitive_header(name)
+        && name != hyper::header::SET_COOKIE
+        && name != hyper::header::CONNECTION
+        && name != hyper::header::TRANSFER_ENCODING
+}
+
+/// Returns the response of the first interaction with `request` which wasn't replayed yet.
+fn replay(cassette: &Cassette, replayed: &mut [bool], request: &RecordedRequest) -> Response<Body> {
+    let index =
+        (0..replayed.len()).find(|&i| !replayed[i] && cassette.interactions[i].request == *request);
+    let response = match index {
+        Some(index) => {
+            replayed[index] = true;
+            &cassette.interactions[index].response
+        }
+        None => {
+            let error = serde_json::json!({
+                "error": {
+                    "code": 501,
+                    "message": format!("The cassette has no interaction left for {} {}", request.method, request.uri),
+                }
+            });
+            return Response::builder()
+                .status(StatusCode::NOT_IMPLEMENTED)
+                .body(Body::from(error.to_string()))
+                .unwrap();
+        }
+    };
+    let mut builder = Response::builder().status(response.status);
+    for (name, value) in &response.headers {
+        builder = builder.header(name, value);
+    }
+    builder
+        .body(Body::from(response.body.to_bytes()))
+        .unwrap_or_else(|_| Response::new(Body::empty()))
+}
+
+impl tower_service::Service<Uri> for CassetteConnector {
+    type Response = InMemoryStream;
+    type Error = io::Error;
+    type Future = futures::future::Ready<io::Result<InMemoryStream>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, origin: Uri) -> Self::Future {
+        let connector = self.clone();
+        futures::future::ready(Ok(in_memory::connect(origin, move |req| {
+            connector.clone().handle(req)
+        })))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn client(connector: &CassetteConnector) -> hyper::Client<CassetteConnector, Body> {
+        hyper::Client::builder().build(connector.clone())
+    }
+
+    fn cassette() -> Cassette {
+        Cassette {
+            interactions: vec![Interaction {
+                request: RecordedRequest {
+                    method: "POST".into(),
+                    uri: "https://storage.googleapis.com/storage/v1/b?key=<redacted>".into(),
+                    body: RecordedBody::Text("{\"name\":\"bucket\"}".into()),
+                },
+                response: RecordedResponse {
+                    status: 200,
+                    headers: vec![("content-type".into(), "application/json".into())],
+                    body: RecordedBody::Text("{\"id\":\"bucket\"}".into()),
+                },
+            }],
+        }
+    }
+
+    fn request() -> Request<Body> {
+        Request::post("https://storage.googleapis.com/storage/v1/b?key=secret")
+            .header(hyper::header::AUTHORIZATION, "Bearer secret")
+            .body(Body::from("{\"name\":\"bucket\"}"))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn interactions_are_replayed_once() {
+        let connector = CassetteConnector::replay(cassette());
+        let client = client(&connector);
+
+        let res = client.request(request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["content-type"], "application/json");
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"{\"id\":\"bucket\"}");
+
+        let res = client.request(request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_IMPLEMENTED);
+    }
+
+    #[tokio::test]
+    async fn interactions_are_recorded_without_credentials() {
+        let replaying = CassetteConnector::replay(cassette());
+        let recording = CassetteConnector::record(client(&replaying));
+
+        let res = client(&recording).request(request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        hyper::body::to_bytes(res.into_body()).await.unwrap();
+
+        let recorded = recording.cassette();
+        assert_eq!(recorded.interactions.len(), 1);
+        assert_eq!(
+            recorded.interactions[0].request,
+            cassette().interactions[0].request
+        );
+        assert_eq!(
+            recorded.interactions[0].response.body,
+            cassette().interactions[0].response.body
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! Injection of faults into the HTTP interactions of a hub, to test the handling of errors
+//!
+//! Requires the `chaos` feature.
+//!
+//! A [`ChaosConnector`] is used as connector of the client of a hub. It forwards all requests to
+//! a real client, but fails some of them instead, each with a configured probability:
+//! - with `429 Too Many Requests`, or `500 Internal Server Error`, without forwarding the request,
+//! - by closing the connection without a response after a delay, like a timed out request,
+//! - by truncating the JSON body of the response of the real client.
+//!
+//! # Example
+//! ```ignore
+//! let connector = ChaosConnector::new(hyper::Client::builder().build(https))
+//!     .too_many_requests(0.1)
+//!     .server_error(0.05)
+//!     .timeout(0.05, Duration::from_secs(1))
+//!     .malformed_json(0.05)
+//!     .seed(42);
+//! let hub = Storage::new(hyper::Client::builder().build(connector.clone()), auth);
+//! // ... run the code to test with the hub
+//! println!("injected {:?}", connector.faults());
+//! ```
+use std::error::Error as StdError;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::future::BoxFuture;
+use hyper::{Body, Request, Response, StatusCode, Uri};
+
+use crate::in_memory::{self, InMemoryStream};
+
+/// A fault injected into an interaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    TooManyRequests,
+    ServerError,
+    Timeout,
+    MalformedJson,
+}
+
+type Forward =
+    Arc<dyn Fn(Request<Body>) -> BoxFuture<'static, hyper::Result<Response<Body>>> + Send + Sync>;
+
+struct State {
+    /// Of a splitmix64 generator, which makes runs with the same seed inject the same faults
+    rng: u64,
+    faults: Vec<(Uri, Fault)>,
+}
+
+impl State {
+    /// Returns a number in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        self.rng = self.rng.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// A connector forwarding requests to a client, and injecting [`Fault`]s at random.
+///
+/// By default, no faults are injected. Clones share the faults they injected.
+#[derive(Clone)]
+pub struct ChaosConnector {
+    forward: Forward,
+    probabilities: [(Fault, f64); 4],
+    timeout_delay: Duration,
+    state: Arc<Mutex<State>>,
+}
+
+impl ChaosConnector {
+    pub fn new<C>(client: hyper::Client<C, Body>) -> Self
+    where
+        C: hyper::client::connect::Connect + Clone + Send + Sync + 'static,
+    {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        ChaosConnector {
+            forward: Arc::new(move |req| Box::pin(client.request(req))),
+            probabilities: [
+                (Fault::TooManyRequests, 0.0),
+                (Fault::ServerError, 0.0),
+                (Fault::Timeout, 0.0),
+                (Fault::MalformedJson, 0.0),
+            ],
+            timeout_delay: Duration::ZERO,
+            state: Arc::new(Mutex::new(State {
+                rng: seed,
+                faults: Vec::new(),
+            })),
+        }
+    }
+
+    fn probability(mut self, fault: Fault, probability: f64) -> Self {
+        for (f, p) in self.probabilities.iter_mut() {
+            if *f == fault {
+                *p = probability.clamp(0.0, 1.0);
+            }
+        }
+        self
+    }
+
+    /// Answer requests with `429 Too Many Requests` with the given probability.
+    pub fn too_many_requests(self, probability: f64) -> Self {
+        self.probability(Fault::TooManyRequests, probability)
+    }
+
+    /// Answer requests with `500 Internal Server Error` with the given probability.
+    pub fn server_error(self, probability: f64) -> Self {
+        self.probability(Fault::ServerError, probability)
+    }
+
+    /// Close connections without a response after `delay` with the given probability.
+    pub fn timeout(mut self, probability: f64, delay: Duration) -> Self {
+        self.timeout_delay = delay;
+        self.probability(Fault::Timeout, probability)
+    }
+
+    /// Truncate the bodies of responses with the given probability.
+    pub fn malformed_json(self, probability: f64) -> Self {
+        self.probability(Fault::MalformedJson, probability)
+    }
+
+    /// Inject faults based on `seed`, which makes the faults of runs making the same requests
+    /// reproducible. By default, the seed is based on the current time.
+    pub fn seed(self, seed: u64) -> Self {
+        self.state.lock().unwrap().rng = seed;
+        self
+    }
+
+    /// Returns the URIs of all requests faults were injected into so far, with their fault.
+    pub fn faults(&self) -> Vec<(Uri, Fault)> {
+        self.state.lock().unwrap().faults.clone()
+    }
+
+    /// Returns the fault to inject into a request to `uri`, if any.
+    fn choose_fault(&self, uri: &Uri) -> Option<Fault> {
+        let mut state = self.state.lock().unwrap();
+        let mut roll = state.next_f64();
+        let fault = self.probabilities.iter().find_map(|&(fault, p)| {
+            roll -= p;
+            (roll < 0.0).then_some(fault)
+        })?;
+        state.faults.push((uri.clone(), fault));
+        Some(fault)
+    }
+
+    async fn handle(
+        self,
+        req: Request<Body>,
+    ) -> Result<Response<Body>, Box<dyn StdError + Send + Sync>> {
+        let fault = self.choose_fault(req.uri());
+        match fault {
+            Some(Fault::TooManyRequests) => Ok(error_response(
+                StatusCode::TOO_MANY_REQUESTS,
+                "RESOURCE_EXHAUSTED",
+            )),
+            Some(Fault::ServerError) => Ok(error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "INTERNAL",
+            )),
+            Some(Fault::Timeout) => {
+                tokio::time::sleep(self.timeout_delay).await;
+                Err(Box::new(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "timeout injected by ChaosConnector",
+                )))
+            }
+            Some(Fault::MalformedJson) => {
+                let (mut parts, body) = (self.forward)(req).await?.into_parts();
+                let body = hyper::body::to_bytes(body).await?;
+                parts.headers.remove(hyper::header::CONTENT_LENGTH);
+                let truncated = if body.is_empty() {
+                    &b"{"[..]
+                } else {
+                    &body[..body.len() / 2]
+                };
+                Ok(Response::from_parts(parts, Body::from(truncated.to_vec())))
+            }
+            None => Ok((self.forward)(req).await?),
+        }
+    }
+}
+
+fn error_response(status: StatusCode, reason: &str) -> Response<Body> {
+    let error = serde_json::json!({
+        "error": {
+            "code": status.as_u16(),
+            "message": "Injected by ChaosConnector",
+            "status": reason,
+        }
+    });
+    Response::builder()
+        .status(status)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(error.to_string()))
+        .unwrap()
+}
+
+impl tower_service::Service<Uri> for ChaosConnector {
+    type Response = InMemoryStream;
+    type Error = io::Error;
+    type Future = futures::future::Ready<io::Result<InMemoryStream>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, origin: Uri) -> Self::Future {
+        let connector = self.clone();
+        futures::future::ready(Ok(in_memory::connect(origin, move |req| {
+            connector.clone().handle(req)
+        })))
+    }
+}
+
+#[cfg(all(test, feature = "cassette"))]
+mod test {
+    use super::*;
+    use crate::cassette::{
+        Cassette, CassetteConnector, Interaction, RecordedBody, RecordedRequest, RecordedResponse,
+    };
+
+    const URI: &str = "https://storage.googleapis.com/storage/v1/b/bucket";
+
+    fn upstream(responses: usize) -> hyper::Client<CassetteConnector, Body> {
+        let interaction = Interaction {
+            request: RecordedRequest {
+                method: "GET".into(),
+                uri: URI.into(),
+                body: RecordedBody::Text(String::new()),
+            },
+            response: RecordedResponse {
+                status: 200,
+                headers: vec![("content-type".into(), "application/json".into())],
+                body: RecordedBody::Text("{\"id\":\"bucket\"}".into()),
+            },
+        };
+        let connector = CassetteConnector::replay(Cassette {
+            interactions: vec![interaction; responses],
+        });
+        hyper::Client::builder().build(connector)
+    }
+
+    async fn get(connector: &ChaosConnector) -> hyper::Result<(StatusCode, Vec<u8>)> {
+        let client: hyper::Client<_, Body> = hyper::Client::builder().build(connector.clone());
+        let res = client.get(URI.parse().unwrap()).await?;
+        let status = res.status();
+        let body = hyper::body::to_bytes(res.into_body()).await?;
+        Ok((status, body.to_vec()))
+    }
+
+    #[tokio::test]
+    async fn requests_are_forwarded_without_faults() {
+        let connector = ChaosConnector::new(upstream(1));
+        let (status, body) = get(&connector).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, b"{\"id\":\"bucket\"}");
+        assert!(connector.faults().is_empty());
+    }
+
+    #[tokio::test]
+    async fn faults_are_injected() {
+        let connector = ChaosConnector::new(upstream(1)).too_many_requests(1.0);
+        let (status, body) = get(&connector).await.unwrap();
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error["error"]["code"], 429);
+
+        let connector = ChaosConnector::new(upstream(1)).malformed_json(1.0);
+        let (status, body) = get(&connector).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert!(serde_json::from_slice::<serde_json::Value>(&body).is_err());
+
+        let connector = ChaosConnector::new(upstream(1)).timeout(1.0, Duration::ZERO);
+        assert!(get(&connector).await.is_err());
+        assert_eq!(
+            connector.faults(),
+            [(URI.parse::<Uri>().unwrap(), Fault::Timeout)]
+        );
+    }
+
+    #[tokio::test]
+    async fn seeds_reproduce_faults() {
+        let run = |seed| async move {
+            let connector = ChaosConnector::new(upstream(20))
+                .server_error(0.3)
+                .malformed_json(0.3)
+                .seed(seed);
+            for _ in 0..20 {
+                get(&connector).await.unwrap();
+            }
+            connector.faults()
+        };
+        let faults = run(7).await;
+        assert!(!faults.is_empty() && faults.len() < 20);
+        assert_eq!(faults, run(7).await);
+    }
+}
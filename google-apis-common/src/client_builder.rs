@@ -0,0 +1,242 @@
+//! One client and authenticator for the hubs of all APIs a program uses
+//!
+//! Requires the `client-builder` feature. Each hub gets a client and an authenticator when it's
+//! created, and programs calling several APIs, like Testing, Storage and ToolResults, usually
+//! create them once per hub. A [`ClientBuilder`] creates the TLS connector, the client and the
+//! authenticator once instead, and the [`SharedClient`] it builds hands clones of them to any
+//! number of hubs, which then share the pool of connections and the tokens.
+//!
+//! # Example
+//! ```ignore
+//! let shared = ClientBuilder::new().pool_max_idle_per_host(8).build().await?;
+//! let testing = Testing::new(shared.client(), shared.auth());
+//! let storage = Storage::new(shared.client(), shared.auth());
+//! ```
+use std::ffi::OsString;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use hyper::client::HttpConnector;
+use yup_oauth2::authenticator::ApplicationDefaultCredentialsTypes;
+use yup_oauth2::authorized_user::AuthorizedUserSecret;
+use yup_oauth2::hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use yup_oauth2::{
+    ApplicationDefaultCredentialsAuthenticator, ApplicationDefaultCredentialsFlowOpts,
+    AuthorizedUserAuthenticator, ServiceAccountAuthenticator, ServiceAccountKey,
+};
+
+use crate::GetToken;
+
+/// The connector of shared clients, making HTTPS requests with the native root certificates.
+pub type Connector = HttpsConnector<HttpConnector>;
+
+enum Credentials {
+    ApplicationDefault,
+    ServiceAccount(ServiceAccountKey),
+    AuthorizedUser(AuthorizedUserSecret),
+    Custom(Box<dyn GetToken>),
+}
+
+/// Configures the client and the authenticator shared by hubs.
+///
+/// Without other credentials, the authenticator uses the application default credentials.
+pub struct ClientBuilder {
+    credentials: Credentials,
+    http2: bool,
+    pool_idle_timeout: Option<Duration>,
+    pool_max_idle_per_host: Option<usize>,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        ClientBuilder::new()
+    }
+}
+
+impl ClientBuilder {
+    pub fn new() -> Self {
+        ClientBuilder {
+            credentials: Credentials::ApplicationDefault,
+            http2: true,
+            pool_idle_timeout: None,
+            pool_max_idle_per_host: None,
+        }
+    }
+
+    /// Authenticates with the key of a service account.
+    pub fn service_account_key(mut self, key: ServiceAccountKey) -> Self {
+        self.credentials = Credentials::ServiceAccount(key);
+        self
+    }
+
+    /// Authenticates with the credentials of a user, like those of
+    /// `gcloud auth application-default login`.
+    pub fn authorized_user(mut self, secret: AuthorizedUserSecret) -> Self {
+        self.credentials = Credentials::AuthorizedUser(secret);
+        self
+    }
+
+    /// Authenticates with `auth`, like a [`NoToken`](crate::NoToken) for APIs which don't need
+    /// tokens, instead of creating an authenticator.
+    pub fn auth<A: GetToken + 'static>(mut self, auth: A) -> Self {
+        self.credentials = Credentials::Custom(Box::new(auth));
+        self
+    }
+
+    /// Makes all requests with HTTP/1, even to servers supporting HTTP/2.
+    pub fn http1_only(mut self) -> Self {
+        self.http2 = false;
+        self
+    }
+
+    /// Closes connections which weren't used for `timeout`.
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Keeps at most `max` unused connections to each host open.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Creates the connector, the client and the authenticator, which makes its token requests
+    /// with the client as well.
+    pub async fn build(self) -> io::Result<SharedClient> {
+        let connector = HttpsConnectorBuilder::new()
+            .with_native_roots()
+            .https_or_http()
+            .enable_http1();
+        let connector = if self.http2 {
+            connector.enable_http2().build()
+        } else {
+            connector.build()
+        };
+        let mut builder = hyper::Client::builder();
+        if let Some(timeout) = self.pool_idle_timeout {
+            builder.pool_idle_timeout(timeout);
+        }
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder.pool_max_idle_per_host(max);
+        }
+        let client = builder.build(connector);
+
+        let auth: Box<dyn GetToken> = match self.credentials {
+            Credentials::ApplicationDefault => {
+                Box::new(application_default_credentials(&client).await?)
+            }
+            Credentials::ServiceAccount(key) => Box::new(
+                ServiceAccountAuthenticator::with_client(key, client.clone())
+                    .build()
+                    .await?,
+            ),
+            Credentials::AuthorizedUser(secret) => Box::new(
+                AuthorizedUserAuthenticator::with_client(secret, client.clone())
+                    .build()
+                    .await?,
+            ),
+            Credentials::Custom(auth) => auth,
+        };
+        Ok(SharedClient { client, auth })
+    }
+}
+
+/// The client and the authenticator built by a [`ClientBuilder`], whose clones share connections
+/// and tokens.
+#[derive(Clone)]
+pub struct SharedClient {
+    client: hyper::Client<Connector, hyper::Body>,
+    auth: Box<dyn GetToken>,
+}
+
+impl SharedClient {
+    /// Returns the client to create a hub with.
+    pub fn client(&self) -> hyper::Client<Connector, hyper::Body> {
+        self.client.clone()
+    }
+
+    /// Returns the authenticator to create a hub with.
+    pub fn auth(&self) -> Box<dyn GetToken> {
+        self.auth.clone()
+    }
+}
+
+/// Returns an authenticator using the application default credentials, which are looked up like
+/// the Google Cloud SDKs do:
+///
+/// 1. the service account key in the file named by `GOOGLE_APPLICATION_CREDENTIALS`,
+/// 2. the user credentials stored by `gcloud auth application-default login`,
+/// 3. the service account of the instance metadata server, when running on Google Cloud.
+pub(crate) async fn application_default_credentials(
+    client: &hyper::Client<Connector, hyper::Body>,
+) -> io::Result<yup_oauth2::authenticator::Authenticator<Connector>> {
+    if std::env::var_os("GOOGLE_APPLICATION_CREDENTIALS").is_none() {
+        if let Some(path) = gcloud_credentials_path().filter(|path| path.is_file()) {
+            let secret = yup_oauth2::read_authorized_user_secret(path).await?;
+            return AuthorizedUserAuthenticator::with_client(secret, client.clone())
+                .build()
+                .await;
+        }
+    }
+    let opts = ApplicationDefaultCredentialsFlowOpts::default();
+    match ApplicationDefaultCredentialsAuthenticator::with_client(opts, client.clone()).await {
+        ApplicationDefaultCredentialsTypes::ServiceAccount(builder) => builder.build().await,
+        ApplicationDefaultCredentialsTypes::InstanceMetadata(builder) => builder.build().await,
+    }
+}
+
+/// The file `gcloud auth application-default login` stores user credentials in.
+pub(crate) fn gcloud_credentials_path() -> Option<PathBuf> {
+    gcloud_credentials_path_in(std::env::var_os("CLOUDSDK_CONFIG"))
+}
+
+/// The credentials file in the gcloud configuration directory `config_dir`, or in the default
+/// one of the platform if it isn't given.
+fn gcloud_credentials_path_in(config_dir: Option<OsString>) -> Option<PathBuf> {
+    let config_dir = match config_dir {
+        Some(dir) => PathBuf::from(dir),
+        None if cfg!(windows) => PathBuf::from(std::env::var_os("APPDATA")?).join("gcloud"),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".config/gcloud"),
+    };
+    Some(config_dir.join("application_default_credentials.json"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::StaticToken;
+
+    #[test]
+    fn gcloud_credentials_are_found_in_the_config_dir() {
+        assert_eq!(
+            gcloud_credentials_path_in(Some("/tmp/gcloud".into())),
+            Some(PathBuf::from(
+                "/tmp/gcloud/application_default_credentials.json"
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn clones_share_the_authenticator() {
+        let auth = StaticToken::new("token");
+        let shared = ClientBuilder::new()
+            .auth(auth.clone())
+            .pool_max_idle_per_host(2)
+            .build()
+            .await
+            .unwrap();
+        let first = shared.auth();
+        let second = shared.clone().auth();
+        assert_eq!(first.get_token(&["a"]).await.unwrap(), Some("token".into()));
+        assert_eq!(
+            second.get_token(&["b"]).await.unwrap(),
+            Some("token".into())
+        );
+        assert_eq!(
+            auth.requested_scopes(),
+            vec![vec!["a".to_string()], vec!["b".to_string()]]
+        );
+    }
+}
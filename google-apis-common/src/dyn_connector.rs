@@ -0,0 +1,135 @@
+//! A connector with its type erased
+//!
+//! Hubs and call builders are generic over the connector of their client, so each connector a
+//! program uses compiles all of them once more. Wrapping connectors into a [`DynConnector`]
+//! instead compiles them once, at the cost of a boxed future and stream per connection.
+//!
+//! # Example
+//! ```ignore
+//! let connector = if use_proxy {
+//!     DynConnector::new(proxy_connector)
+//! } else {
+//!     DynConnector::new(https_connector)
+//! };
+//! let hub = DynStorage::new(hyper::Client::builder().build(connector), auth);
+//! ```
+use std::error::Error as StdError;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::future::{poll_fn, BoxFuture};
+use hyper::client::connect::{Connected, Connection};
+use hyper::Uri;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tower_service::Service;
+
+type BoxError = Box<dyn StdError + Send + Sync>;
+type Connect = dyn Fn(Uri) -> BoxFuture<'static, Result<DynStream, BoxError>> + Send + Sync;
+
+/// The streams of connectors, as used by hyper.
+pub trait Io: Connection + AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: Connection + AsyncRead + AsyncWrite + Send + Unpin> Io for T {}
+
+/// A connector wrapping any other connector.
+#[derive(Clone)]
+pub struct DynConnector {
+    connect: Arc<Connect>,
+}
+
+impl DynConnector {
+    /// Makes all connections with `connector`.
+    pub fn new<S>(connector: S) -> Self
+    where
+        S: Service<Uri> + Clone + Send + Sync + 'static,
+        S::Response: Io + 'static,
+        S::Future: Send + 'static,
+        S::Error: Into<BoxError>,
+    {
+        DynConnector {
+            connect: Arc::new(move |uri| {
+                let mut connector = connector.clone();
+                Box::pin(async move {
+                    poll_fn(|cx| connector.poll_ready(cx))
+                        .await
+                        .map_err(Into::into)?;
+                    let stream = connector.call(uri).await.map_err(Into::into)?;
+                    Ok(DynStream(Box::new(stream)))
+                })
+            }),
+        }
+    }
+}
+
+impl Service<Uri> for DynConnector {
+    type Response = DynStream;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<DynStream, BoxError>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        (self.connect)(uri)
+    }
+}
+
+/// A connection of a [`DynConnector`].
+pub struct DynStream(Box<dyn Io>);
+
+impl Connection for DynStream {
+    fn connected(&self) -> Connected {
+        self.0.connected()
+    }
+}
+
+impl AsyncRead for DynStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for DynStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod test {
+    use hyper::{Body, Client};
+
+    use super::*;
+    use crate::mock::MockConnector;
+
+    #[tokio::test]
+    async fn requests_are_made_through_the_wrapped_connector() {
+        let connector = DynConnector::new(MockConnector::json(serde_json::json!({"name": "b"})));
+        let client: Client<DynConnector, Body> = Client::builder().build(connector);
+        let res = client
+            .get("http://storage.googleapis.com/b".parse().unwrap())
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], br#"{"name":"b"}"#);
+    }
+}
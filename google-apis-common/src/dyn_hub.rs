@@ -0,0 +1,176 @@
+//! Calls of any method of an API by its id, with JSON values
+//!
+//! Hubs implement [`DynHub`], through which plugin systems and scripting layers can call any
+//! method knowing only its id and parameters, like `storage.buckets.get` with a `bucket`. Calls
+//! are made like those of call builders, with the authenticator, retries and base URL of the
+//! hub, but take and return `serde_json::Value`s instead of the types of the API. Methods are
+//! called without media, so downloads return the resource instead, and uploads aren't possible.
+use hyper::body::Bytes;
+use hyper::Method;
+use serde_json::Value;
+
+use crate::exec::{self, Call, Transport};
+use crate::methods::MethodMetadata;
+use crate::url::Params;
+use crate::{uri_template, BoxFuture, DefaultDelegate, Delegate, Error, MethodInfo, Result};
+
+/// The scope calls are authorized with if the method accepts it, as it covers all others.
+const CLOUD_PLATFORM_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+
+/// A hub whose methods are called by their id, with JSON values.
+pub trait DynHub: Send + Sync {
+    /// Returns the methods of the API, sorted by id.
+    fn methods(&self) -> &'static [MethodMetadata];
+
+    /// Calls the method `method_id` with `params`, path and query parameters alike, and the
+    /// `request` value, if it takes one. Returns the response value, which is null for methods
+    /// without one.
+    fn call_method<'a>(
+        &'a self,
+        method_id: &'a str,
+        params: &'a [(&'a str, &'a str)],
+        request: Option<&'a Value>,
+    ) -> BoxFuture<'a, Result<Value>>;
+}
+
+/// Calls `method` through `transport`, which is what hubs implement [`DynHub::call_method()`]
+/// with.
+///
+/// `base_url` and `service_path` are those of the hub, and `api_key` is sent with methods which
+/// don't take a token.
+pub async fn call(
+    transport: &Transport<'_>,
+    base_url: &str,
+    service_path: &str,
+    api_key: Option<&str>,
+    method: &'static MethodMetadata,
+    params: &[(&str, &str)],
+    request: Option<&Value>,
+) -> Result<Value> {
+    let mut dd = DefaultDelegate;
+    let dlg: &mut dyn Delegate = &mut dd;
+    let http_method = Method::from_bytes(method.http_method.as_bytes()).unwrap_or(Method::GET);
+    dlg.begin(MethodInfo {
+        id: method.id,
+        http_method: http_method.clone(),
+        request_id: None,
+    });
+
+    // The paths of methods start with the service path, which the base URL ends with
+    let path = method.path.trim_start_matches('/');
+    let path = path.strip_prefix(service_path).unwrap_or(path);
+    let path_params = uri_template::param_names(path);
+    let mut query = Params::new();
+    for &(name, value) in params {
+        if path_params.contains(&name) {
+            query.push_path(name, value);
+        } else {
+            query.push(name, value);
+        }
+    }
+    if query.get("alt").is_none() {
+        query.push("alt", "json");
+    }
+    if method.is_key_only() {
+        match api_key {
+            Some(key) => query.push("key", key),
+            None => {
+                dlg.finished(false);
+                return Err(Error::MissingAPIKey);
+            }
+        }
+    }
+    let url = uri_template::expand(path, &query)
+        .and_then(|path| query.parse_with_url(&format!("{}{}", base_url, path)));
+    let url = match url {
+        Ok(url) => url,
+        Err(err) => {
+            dlg.finished(false);
+            return Err(Error::InvalidUrl(err));
+        }
+    };
+
+    let request = request.map(|value| Bytes::from(value.to_string()));
+    let scopes = [if method.scopes.contains(&CLOUD_PLATFORM_SCOPE) {
+        CLOUD_PLATFORM_SCOPE
+    } else {
+        method.scopes.first().copied().unwrap_or_default()
+    }];
+    let call = Call {
+        method_id: method.id,
+        http_method,
+        url: &url,
+        scopes: (!method.is_key_only()).then_some(&scopes[..]),
+        request: request.as_ref(),
+        read_body: true,
+    };
+    let (res, body) = exec::execute(transport, call, dlg).await?;
+    let (_, value) = exec::decode(res, body, dlg)?;
+    dlg.finished(true);
+    Ok(value)
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod test {
+    use hyper::{Body, Response};
+
+    use super::*;
+    use crate::mock::MockConnector;
+    use crate::NoToken;
+
+    const GET: MethodMetadata = MethodMetadata {
+        id: "storage.objects.get",
+        http_method: "GET",
+        path: "/storage/v1/b/{bucket}/o/{object}",
+        required_params: &["bucket", "object"],
+        scopes: &["https://www.googleapis.com/auth/devstorage.read_only"],
+    };
+
+    #[tokio::test]
+    async fn methods_are_called_by_id() {
+        let connector = MockConnector::new(|req| {
+            assert_eq!(
+                req.uri().path_and_query().unwrap().as_str(),
+                "/storage/v1/b/bucket/o/a%2Fb?generation=1&alt=json"
+            );
+            Response::new(Body::from(r#"{"name": "a/b"}"#))
+        });
+        let client = hyper::Client::builder().build(connector);
+        let transport = Transport {
+            client: &client,
+            auth: &NoToken,
+            user_agent: "test-agent",
+            api_client: "gl-rust/1.70.0 gdcl/1.0.0",
+            metrics: None,
+            har: None,
+            #[cfg(feature = "audit")]
+            audit: None,
+            max_response_bytes: None,
+            failover: None,
+        };
+        let value = call(
+            &transport,
+            "https://storage.googleapis.com/storage/v1/",
+            "storage/v1/",
+            None,
+            &GET,
+            &[("bucket", "bucket"), ("object", "a/b"), ("generation", "1")],
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(value["name"], "a/b");
+
+        let missing = call(
+            &transport,
+            "https://storage.googleapis.com/storage/v1/",
+            "storage/v1/",
+            None,
+            &GET,
+            &[("bucket", "bucket")],
+            None,
+        )
+        .await;
+        assert!(matches!(missing, Err(Error::InvalidUrl(_))));
+    }
+}
@@ -0,0 +1,61 @@
+//! Helpers to use hubs with local emulators, like those of Pub/Sub, Firestore or Spanner
+//!
+//! Requires the `emulator` feature, which also gives each hub a `for_emulator()` constructor.
+//! Hubs of APIs with an emulator started by `gcloud beta emulators` additionally get a
+//! `from_emulator_env()` constructor, which uses the emulator named by the environment variable
+//! `gcloud beta emulators <emulator> env-init` sets, like `PUBSUB_EMULATOR_HOST`.
+//!
+//! Emulators serve plain HTTP and accept any credentials, which is why the hubs don't use TLS
+//! and send no tokens.
+//!
+//! # Example
+//! ```ignore
+//! let hub = Pubsub::from_emulator_env().unwrap_or_else(|| Pubsub::for_emulator("localhost:8085"));
+//! hub.projects().topics_create(Topic::default(), "projects/test/topics/events").doit().await?;
+//! ```
+use hyper::client::HttpConnector;
+
+/// A client making plain HTTP requests, as emulators expect them.
+pub fn http_client() -> hyper::Client<HttpConnector, hyper::Body> {
+    hyper::Client::builder().build(HttpConnector::new())
+}
+
+/// The URL to pass to the `root_url()` method of a hub for the emulator at `host`, which is
+/// either a `host:port` pair, or a URL like `http://localhost:9023`.
+pub fn root_url(host: &str) -> String {
+    let host = host.trim_end_matches('/');
+    if host.contains("://") {
+        format!("{}/", host)
+    } else {
+        format!("http://{}/", host)
+    }
+}
+
+/// The URL to pass to the `base_url()` method of a hub for the emulator at `host`, given the
+/// service path of the API.
+pub fn base_url(host: &str, service_path: &str) -> String {
+    root_url(host) + service_path
+}
+
+/// Returns the host of the emulator in the environment variable `var`, if it is set and not empty.
+pub fn host_from_env(var: &str) -> Option<String> {
+    std::env::var(var)
+        .ok()
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn urls_point_to_the_emulator() {
+        assert_eq!(root_url("localhost:8085"), "http://localhost:8085/");
+        assert_eq!(root_url("http://localhost:9023/"), "http://localhost:9023/");
+        assert_eq!(
+            base_url("localhost:9023", "storage/v1/"),
+            "http://localhost:9023/storage/v1/"
+        );
+    }
+}
@@ -0,0 +1,185 @@
+//! A stream of structured events about the calls made through a hub
+//!
+//! Subscribe to it using the `events()` method of a hub, to build live dashboards or
+//! debugging tools over the API activity of an application.
+//! Events are sent through a [`broadcast`](tokio::sync::broadcast) channel. Receivers which
+//! fall behind by more than [`EVENT_CHANNEL_CAPACITY`] events lose the oldest ones.
+use std::time::{Duration, Instant};
+
+use hyper::{Method, StatusCode};
+use tokio::sync::broadcast;
+
+use crate::{Delegate, MethodInfo};
+
+/// The amount of events buffered for each receiver.
+pub const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+pub type EventSender = broadcast::Sender<HubEvent>;
+pub type EventReceiver = broadcast::Receiver<HubEvent>;
+
+/// Creates the sender of a new event channel.
+pub fn event_channel() -> EventSender {
+    broadcast::channel(EVENT_CHANNEL_CAPACITY).0
+}
+
+/// Something that happened during a call. `method_id` identifies the API method, like
+/// `storage.objects.get`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HubEvent {
+    /// A call started
+    CallStarted {
+        method_id: &'static str,
+        http_method: Method,
+        request_id: Option<String>,
+    },
+    /// An HTTP request of a call completed, with the status of the response if there was one
+    RequestCompleted {
+        method_id: &'static str,
+        status: Option<StatusCode>,
+        duration: Duration,
+    },
+    /// A call will be retried after `delay`
+    RetryScheduled {
+        method_id: &'static str,
+        attempt: u32,
+        delay: Duration,
+    },
+    /// The server accepted `bytes_sent` of the `total` bytes of a media upload
+    UploadProgress {
+        method_id: &'static str,
+        bytes_sent: u64,
+        total: u64,
+    },
+    /// A call finished, `duration` after it started
+    CallFinished {
+        method_id: &'static str,
+        success: bool,
+        duration: Duration,
+    },
+}
+
+/// A [`Delegate`] sending [`HubEvent`]s while forwarding all calls to the delegate of the call.
+/// It is used by hubs with subscribed events.
+pub struct EventDelegate<'a> {
+    inner: &'a mut dyn Delegate,
+    sender: EventSender,
+    method_id: &'static str,
+    started: Instant,
+}
+
+impl<'a> EventDelegate<'a> {
+    pub fn new(inner: &'a mut dyn Delegate, sender: EventSender) -> Self {
+        EventDelegate {
+            inner,
+            sender,
+            method_id: "",
+            started: Instant::now(),
+        }
+    }
+
+    fn send(&self, event: HubEvent) {
+        // there may be no receiver right now, which is fine
+        self.sender.send(event).ok();
+    }
+}
+
+impl<'a> Delegate for EventDelegate<'a> {
+    fn forward_to(&mut self) -> Option<&mut dyn Delegate> {
+        Some(&mut *self.inner)
+    }
+
+    fn begin(&mut self, info: MethodInfo) {
+        self.method_id = info.id;
+        self.started = Instant::now();
+        self.send(HubEvent::CallStarted {
+            method_id: info.id,
+            http_method: info.http_method.clone(),
+            request_id: info.request_id.clone(),
+        });
+        self.inner.begin(info)
+    }
+
+    fn retry_scheduled(&mut self, attempt: u32, delay: Duration) {
+        self.send(HubEvent::RetryScheduled {
+            method_id: self.method_id,
+            attempt,
+            delay,
+        });
+        self.inner.retry_scheduled(attempt, delay)
+    }
+
+    fn attempt_completed(&mut self, status: Option<StatusCode>, elapsed: Duration) {
+        self.send(HubEvent::RequestCompleted {
+            method_id: self.method_id,
+            status,
+            duration: elapsed,
+        });
+        self.inner.attempt_completed(status, elapsed)
+    }
+
+    fn upload_progress(&mut self, bytes_sent: u64, total: u64) {
+        self.send(HubEvent::UploadProgress {
+            method_id: self.method_id,
+            bytes_sent,
+            total,
+        });
+        self.inner.upload_progress(bytes_sent, total)
+    }
+
+    fn finished(&mut self, is_success: bool) {
+        self.send(HubEvent::CallFinished {
+            method_id: self.method_id,
+            success: is_success,
+            duration: self.started.elapsed(),
+        });
+        self.inner.finished(is_success)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::DefaultDelegate;
+
+    #[test]
+    fn events_are_sent_and_calls_forwarded() {
+        let sender = event_channel();
+        let mut receiver = sender.subscribe();
+
+        // mirrors how generated calls wrap their delegate
+        let mut dd = DefaultDelegate;
+        let mut ed;
+        let mut dlg: &mut dyn Delegate = &mut dd;
+        ed = EventDelegate::new(dlg, sender.clone());
+        dlg = &mut ed;
+
+        dlg.begin(MethodInfo {
+            id: "compute.instances.insert",
+            http_method: Method::POST,
+            request_id: Some("a1b2".to_string()),
+        });
+        dlg.attempt_completed(Some(StatusCode::OK), Duration::from_millis(1));
+        dlg.finished(true);
+        assert_eq!(dlg.chunk_size(), DefaultDelegate.chunk_size());
+
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            HubEvent::CallStarted {
+                method_id: "compute.instances.insert",
+                http_method: Method::POST,
+                request_id: Some("a1b2".to_string()),
+            }
+        );
+        assert!(matches!(
+            receiver.try_recv().unwrap(),
+            HubEvent::RequestCompleted {
+                status: Some(StatusCode::OK),
+                ..
+            }
+        ));
+        assert!(matches!(
+            receiver.try_recv().unwrap(),
+            HubEvent::CallFinished { success: true, .. }
+        ));
+    }
+}
@@ -0,0 +1,634 @@
+//! The request loop shared by all calls which don't transfer media
+//!
+//! Call builders validate their parameters and build the URL of their request, and leave the
+//! rest to [`execute()`]: obtaining tokens, sending the request, retrying as the delegate
+//! decides, collecting metrics and turning failures into errors. As this function is neither
+//! generic over the connector of the hub nor over the call, it is compiled once per API crate,
+//! instead of once per method.
+//!
+//! Calls uploading or downloading media still run their own loop, as they deal with readers and
+//! writers of their caller.
+//!
+//! Requests are built with the types of the `http` crate by [`http_request()`], which executors
+//! other than hyper can use as well, together with `CallParts::to_http_request()`.
+use std::borrow::Cow;
+use std::error::Error as StdError;
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+use http::Uri;
+use hyper::body::Bytes;
+use hyper::client::connect::Connection;
+use hyper::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, USER_AGENT};
+use hyper::{Body, Method, Request, Response};
+use serde::de::DeserializeOwned;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::sleep;
+use tower_service::Service;
+
+#[cfg(feature = "audit")]
+use crate::audit::{AuditDelegate, Auditor};
+use crate::failover::Failover;
+use crate::har::HarRecorder;
+use crate::uri_template::UriTemplateError;
+use crate::{Delegate, Error, GetToken, Metrics, Result, Retry, StatusClass};
+
+/// A client sending requests, with its connector type erased.
+pub trait HttpClient: Send + Sync {
+    fn request(&self, req: Request<Body>) -> BoxFuture<'_, hyper::Result<Response<Body>>>;
+}
+
+impl<S> HttpClient for hyper::Client<S, Body>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    fn request(&self, req: Request<Body>) -> BoxFuture<'_, hyper::Result<Response<Body>>> {
+        Box::pin(hyper::Client::request(self, req))
+    }
+}
+
+/// Everything of a hub needed to make calls.
+pub struct Transport<'a> {
+    pub client: &'a dyn HttpClient,
+    pub auth: &'a dyn GetToken,
+    pub user_agent: &'a str,
+    /// The value of the `x-goog-api-client` header, identifying the API crate and the libraries
+    /// using it
+    pub api_client: &'a str,
+    pub metrics: Option<&'a dyn Metrics>,
+    pub har: Option<&'a HarRecorder>,
+    #[cfg(feature = "audit")]
+    pub audit: Option<&'a Auditor>,
+    /// The size bodies of responses may have at most to be read, if they are limited
+    pub max_response_bytes: Option<u64>,
+    /// The endpoints to fail over between, if there are several
+    pub failover: Option<&'a Failover>,
+}
+
+/// A request of a call, ready to be sent.
+pub struct Call<'a> {
+    /// The id of the method, like `storage.buckets.get`
+    pub method_id: &'static str,
+    pub http_method: Method,
+    pub url: &'a url::Url,
+    /// The scopes to obtain a token for, or `None` if the method doesn't need one.
+    pub scopes: Option<&'a [&'a str]>,
+    /// The request value serialized as JSON, if the method takes one. Each attempt sends the same
+    /// bytes, without copying them.
+    pub request: Option<&'a Bytes>,
+    /// Whether to read the body of successful responses, to decode it afterwards.
+    pub read_body: bool,
+}
+
+/// Makes the request of `call` until it succeeds, or the delegate gives up retrying.
+///
+/// Returns the successful response, along with its body if `read_body` was set. On error,
+/// `dlg.finished(false)` was called already, while the caller has to call `dlg.finished(true)`
+/// once it handled the response.
+pub async fn execute(
+    transport: &Transport<'_>,
+    call: Call<'_>,
+    dlg: &mut dyn Delegate,
+) -> Result<(Response<Body>, Option<Bytes>)> {
+    if let Some(request) = call.request {
+        dlg.request_serialized(request.len() as u64);
+    }
+    #[cfg(feature = "audit")]
+    let mut ad;
+    #[cfg(feature = "audit")]
+    let mut dlg: &mut dyn Delegate = dlg;
+    #[cfg(feature = "audit")]
+    if call.http_method != Method::GET {
+        if let Some(auditor) = transport.audit {
+            ad = AuditDelegate::new(
+                dlg,
+                auditor,
+                call.method_id,
+                call.http_method.clone(),
+                call.url,
+                call.request.map(|request| &request[..]),
+            );
+            dlg = &mut ad;
+        }
+    }
+
+    let mut retries = 0;
+    // The endpoints which couldn't be connected to, which are failed over from at once
+    let mut unreachable = Vec::new();
+    loop {
+        let endpoint = transport
+            .failover
+            .and_then(|failover| Some((failover, failover.select(&unreachable)?)));
+        let url = match endpoint {
+            Some((failover, index)) => failover.rebase(call.url, index),
+            None => Cow::Borrowed(call.url),
+        };
+
+        let mut token = None;
+        if let Some(scopes) = call.scopes {
+            let token_started = Instant::now();
+            token = match transport.auth.get_token(scopes).await {
+                Ok(token) => token,
+                Err(e) => match dlg.token(e) {
+                    Ok(token) => token,
+                    Err(e) => {
+                        dlg.finished(false);
+                        return Err(Error::MissingToken(e));
+                    }
+                },
+            };
+            dlg.token_obtained(token_started.elapsed());
+        }
+
+        let request_started = Instant::now();
+        dlg.pre_request();
+        let mut req_builder = Request::builder()
+            .method(call.http_method.clone())
+            .uri(url.as_str())
+            .header(USER_AGENT, transport.user_agent)
+            .header(crate::API_CLIENT_HEADER, transport.api_client);
+        if let Some(token) = token.as_ref() {
+            req_builder = req_builder.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        req_builder = crate::prepare_request(dlg, req_builder);
+        let request = match http_request(req_builder, call.request.cloned()) {
+            Ok(request) => request.map(Body::from),
+            Err(err) => {
+                dlg.finished(false);
+                return Err(Error::InvalidRequest(err));
+            }
+        };
+        let request = crate::log_request(request).await;
+        let (request, mut har_entry) = crate::har::capture_request(transport.har, request).await;
+        let req_result = transport.client.request(request).await;
+
+        let request_duration = request_started.elapsed();
+        dlg.attempt_completed(
+            req_result.as_ref().ok().map(|res| res.status()),
+            request_duration,
+        );
+        if let Some(metrics) = transport.metrics {
+            let status = StatusClass::of(&req_result);
+            metrics.increment_request_count(call.method_id, status);
+            metrics.record_request_latency(call.method_id, status, request_duration);
+            if status.is_error() {
+                metrics.increment_error_count(
+                    call.method_id,
+                    crate::canonical_code(req_result.as_ref().ok().map(|res| res.status())),
+                );
+            }
+        }
+
+        if let Some((failover, index)) = endpoint {
+            let failed = match &req_result {
+                Ok(res) => res.status().is_server_error(),
+                Err(err) => err.is_connect(),
+            };
+            failover.record(index, failed);
+            if matches!(&req_result, Err(err) if err.is_connect()) {
+                unreachable.push(index);
+                if failover.select(&unreachable).is_some() {
+                    continue;
+                }
+                // All endpoints are unreachable, a retry starts over with the first healthy one
+                unreachable.clear();
+            }
+        }
+
+        let mut res = match req_result {
+            Err(err) => {
+                if let Retry::After(d) = dlg.http_error(&err) {
+                    retries += 1;
+                    dlg.retry_scheduled(retries, d);
+                    sleep(d).await;
+                    continue;
+                }
+                dlg.finished(false);
+                return Err(Error::HttpError(err));
+            }
+            Ok(res) => res,
+        };
+        crate::log_response(&res);
+        if let Some(entry) = har_entry.as_mut() {
+            entry.response(&res);
+        }
+        if !res.status().is_success() {
+            let res_body = match crate::get_body_as_bytes_limited(
+                res.body_mut(),
+                transport.max_response_bytes,
+            )
+            .await
+            {
+                Ok(res_body) => res_body,
+                Err(err) => {
+                    dlg.finished(false);
+                    return Err(err);
+                }
+            };
+            let res_body_string = String::from_utf8_lossy(&res_body).into_owned();
+            crate::log_response_body(&res_body_string);
+            if let Some(entry) = har_entry.as_mut() {
+                entry.response_body(&res_body_string);
+            }
+            let (parts, _) = res.into_parts();
+            let restored_response =
+                Response::from_parts(parts, Body::from(res_body_string.clone()));
+            let server_response = serde_json::from_str::<serde_json::Value>(&res_body_string).ok();
+
+            if let Retry::After(d) = dlg.http_failure(&restored_response, server_response.clone()) {
+                retries += 1;
+                dlg.retry_scheduled(retries, d);
+                sleep(d).await;
+                continue;
+            }
+            dlg.finished(false);
+            return match server_response {
+                Some(error_value) => Err(Error::BadRequest(error_value)),
+                None => Err(Error::Failure(restored_response)),
+            };
+        }
+
+        let body = if call.read_body {
+            let body = match crate::get_body_as_bytes_limited(
+                res.body_mut(),
+                transport.max_response_bytes,
+            )
+            .await
+            {
+                Ok(body) => body,
+                Err(err) => {
+                    dlg.finished(false);
+                    return Err(err);
+                }
+            };
+            crate::log_response_body(&body);
+            if let Some(entry) = har_entry.as_mut() {
+                entry.response_body(&body);
+            }
+            Some(body)
+        } else {
+            None
+        };
+        return Ok((res, body));
+    }
+}
+
+/// Connects to the server at `url` and obtains a token for `scopes`, if given, concurrently, so
+/// that the first call finds both ready instead of waiting for them.
+///
+/// The connection, including its TLS handshake, is made with a `HEAD` request. Whatever its
+/// status, the response leaves the connection in the pool of the client for the calls to come.
+///
+/// Fails with `Error::MissingToken` if the token couldn't be obtained, and otherwise with
+/// `Error::HttpError` if the connection failed.
+pub async fn warm_up(transport: &Transport<'_>, url: &str, scopes: Option<&[&str]>) -> Result<()> {
+    let connect = async {
+        let request = Request::builder()
+            .method(Method::HEAD)
+            .uri(url)
+            .header(USER_AGENT, transport.user_agent)
+            .body(Body::empty())
+            .map_err(|err| {
+                Error::InvalidUrl(UriTemplateError::InvalidUrl(
+                    url.to_string(),
+                    err.to_string(),
+                ))
+            })?;
+        let mut res = transport
+            .client
+            .request(request)
+            .await
+            .map_err(Error::HttpError)?;
+        hyper::body::to_bytes(res.body_mut())
+            .await
+            .map_err(Error::HttpError)?;
+        Ok(())
+    };
+    let token = async {
+        match scopes {
+            Some(scopes) => transport
+                .auth
+                .get_token(scopes)
+                .await
+                .map(|_| ())
+                .map_err(Error::MissingToken),
+            None => Ok(()),
+        }
+    };
+    let (connected, token) = futures::join!(connect, token);
+    // Every call needs the token, whereas the next one may well connect
+    token.and(connected)
+}
+
+/// Finishes the request of `builder`, with `json` as body if the method takes a request value.
+///
+/// It only uses the types of the `http` crate, so that executors other than hyper can send the
+/// requests of calls as well. The body is any buffer, like a `Vec<u8>`, or [`Bytes`] which
+/// retries share.
+pub fn http_request<B: AsRef<[u8]> + Default>(
+    builder: http::request::Builder,
+    json: Option<B>,
+) -> http::Result<Request<B>> {
+    match json {
+        Some(json) => builder
+            .header(CONTENT_TYPE, mime::APPLICATION_JSON.to_string())
+            .header(CONTENT_LENGTH, json.as_ref().len() as u64)
+            .body(json),
+        None => builder.body(B::default()),
+    }
+}
+
+/// Decodes the `body` [`execute()`] read into the response value of a call, or returns the
+/// default value if there is no body.
+#[allow(clippy::result_large_err)]
+pub fn decode<T: DeserializeOwned + Default>(
+    res: Response<Body>,
+    body: Option<Bytes>,
+    dlg: &mut dyn Delegate,
+) -> Result<(Response<Body>, T)> {
+    let body = match body {
+        Some(body) => body,
+        None => return Ok((res, T::default())),
+    };
+    let decode_started = Instant::now();
+    match crate::decode_json_slice(&body) {
+        Ok(decoded) => {
+            dlg.response_decoded(decode_started.elapsed());
+            Ok((res, decoded))
+        }
+        Err(err) => {
+            let body = String::from_utf8_lossy(&body).into_owned();
+            dlg.response_json_decode_error(&body, &err);
+            Err(Error::JsonDecodeError(body, err))
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use hyper::StatusCode;
+
+    use super::*;
+    use crate::mock::MockConnector;
+    use crate::NoToken;
+
+    #[derive(Default)]
+    struct RetryOnce {
+        retried: bool,
+        finished: Option<bool>,
+    }
+
+    impl Delegate for RetryOnce {
+        fn http_failure(&mut self, _: &Response<Body>, _: Option<serde_json::Value>) -> Retry {
+            if self.retried {
+                Retry::Abort
+            } else {
+                self.retried = true;
+                Retry::After(Duration::from_millis(1))
+            }
+        }
+
+        fn finished(&mut self, is_success: bool) {
+            self.finished = Some(is_success);
+        }
+    }
+
+    #[derive(Debug, Default, PartialEq, serde::Deserialize)]
+    struct Bucket {
+        name: Option<String>,
+    }
+
+    fn client_failing(failures: usize, status: StatusCode) -> hyper::Client<MockConnector, Body> {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let connector = MockConnector::new(move |req| {
+            assert_eq!(req.headers()[USER_AGENT], "test-agent");
+            let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+            let (status, body) = if attempt < failures {
+                (status, r#"{"error": {"code": 503}}"#)
+            } else {
+                (StatusCode::OK, r#"{"name": "bucket"}"#)
+            };
+            Response::builder()
+                .status(status)
+                .body(Body::from(body))
+                .unwrap()
+        });
+        hyper::Client::builder().build(connector)
+    }
+
+    fn transport<'a>(client: &'a dyn HttpClient) -> Transport<'a> {
+        Transport {
+            client,
+            auth: &NoToken,
+            user_agent: "test-agent",
+            api_client: "gl-rust/1.70.0 gdcl/1.0.0",
+            metrics: None,
+            har: None,
+            #[cfg(feature = "audit")]
+            audit: None,
+            max_response_bytes: None,
+            failover: None,
+        }
+    }
+
+    fn call(url: &url::Url) -> Call<'_> {
+        Call {
+            method_id: "storage.buckets.get",
+            http_method: Method::GET,
+            url,
+            scopes: Some(&[]),
+            request: None,
+            read_body: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn calls_are_retried_and_decoded() {
+        let client = client_failing(1, StatusCode::SERVICE_UNAVAILABLE);
+        let url = url::Url::parse("https://storage.googleapis.com/storage/v1/b/bucket").unwrap();
+        let mut dlg = RetryOnce::default();
+        let (res, body) = execute(&transport(&client), call(&url), &mut dlg)
+            .await
+            .unwrap();
+        let (_, bucket): (_, Bucket) = decode(res, body, &mut dlg).unwrap();
+        assert!(dlg.retried);
+        assert_eq!(bucket.name.as_deref(), Some("bucket"));
+    }
+
+    #[tokio::test]
+    async fn failures_become_errors() {
+        let client = client_failing(2, StatusCode::SERVICE_UNAVAILABLE);
+        let url = url::Url::parse("https://storage.googleapis.com/storage/v1/b/bucket").unwrap();
+        let mut dlg = RetryOnce::default();
+        match execute(&transport(&client), call(&url), &mut dlg).await {
+            Err(Error::BadRequest(value)) => assert_eq!(value["error"]["code"], 503),
+            other => panic!("unexpected result: {:?}", other.map(|(res, _)| res)),
+        }
+        assert_eq!(dlg.finished, Some(false));
+    }
+
+    #[tokio::test]
+    async fn invalid_headers_become_errors() {
+        let client = client_failing(0, StatusCode::OK);
+        let url = url::Url::parse("https://storage.googleapis.com/storage/v1/b/bucket").unwrap();
+        let mut dlg = RetryOnce::default();
+        let transport = Transport {
+            user_agent: "test-agent\nX-Injected: 1",
+            ..transport(&client)
+        };
+        match execute(&transport, call(&url), &mut dlg).await {
+            Err(Error::InvalidRequest(_)) => {}
+            other => panic!("unexpected result: {:?}", other.map(|(res, _)| res)),
+        }
+        assert_eq!(dlg.finished, Some(false));
+    }
+
+    #[tokio::test]
+    async fn large_responses_are_rejected() {
+        let client = client_failing(0, StatusCode::OK);
+        let url = url::Url::parse("https://storage.googleapis.com/storage/v1/b/bucket").unwrap();
+        let mut dlg = RetryOnce::default();
+        let transport = Transport {
+            max_response_bytes: Some(8),
+            ..transport(&client)
+        };
+        match execute(&transport, call(&url), &mut dlg).await {
+            Err(Error::ResponseTooLarge(8)) => {}
+            other => panic!("unexpected result: {:?}", other.map(|(res, _)| res)),
+        }
+        assert_eq!(dlg.finished, Some(false));
+    }
+
+    #[tokio::test]
+    async fn failing_endpoints_are_failed_over() {
+        let connector = MockConnector::new(|req| {
+            let host = req.headers().get(hyper::header::HOST);
+            let (status, body) = match host.and_then(|host| host.to_str().ok()) {
+                Some("storage.googleapis.com") => (StatusCode::OK, r#"{"name": "bucket"}"#),
+                _ => (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    r#"{"error": {"code": 503}}"#,
+                ),
+            };
+            Response::builder()
+                .status(status)
+                .body(Body::from(body))
+                .unwrap()
+        });
+        let client = hyper::Client::builder().build(connector);
+        let failover = Failover::new(vec![
+            "https://storage.europe-west1.rep.googleapis.com/storage/v1/".into(),
+            "https://storage.googleapis.com/storage/v1/".into(),
+        ])
+        .unhealthy_after(1);
+        let transport = Transport {
+            failover: Some(&failover),
+            ..transport(&client)
+        };
+        let url =
+            url::Url::parse("https://storage.europe-west1.rep.googleapis.com/storage/v1/b/bucket")
+                .unwrap();
+        let mut dlg = RetryOnce::default();
+        let (res, body) = execute(&transport, call(&url), &mut dlg).await.unwrap();
+        let (_, bucket): (_, Bucket) = decode(res, body, &mut dlg).unwrap();
+        assert!(dlg.retried);
+        assert_eq!(bucket.name.as_deref(), Some("bucket"));
+        assert!(!failover.is_healthy(0));
+        assert!(failover.is_healthy(1));
+    }
+
+    #[tokio::test]
+    async fn hubs_are_warmed_up() {
+        let connector = MockConnector::new(|req| {
+            assert_eq!(req.method(), Method::HEAD);
+            Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .unwrap()
+        });
+        let client = hyper::Client::builder().build(connector);
+        let scopes = ["https://www.googleapis.com/auth/devstorage.read_only"];
+        warm_up(
+            &transport(&client),
+            "https://storage.googleapis.com/storage/v1/",
+            Some(&scopes),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            warm_up(&transport(&client), "not a url", None).await,
+            Err(Error::InvalidUrl(_))
+        ));
+    }
+
+    #[derive(Clone)]
+    struct FailingToken;
+
+    impl GetToken for FailingToken {
+        fn get_token<'a>(
+            &'a self,
+            _: &'a [&str],
+        ) -> BoxFuture<'a, std::result::Result<Option<String>, Box<dyn StdError + Send + Sync>>>
+        {
+            Box::pin(async { Err("no credentials".into()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn warm_up_tells_token_and_connection_failures_apart() {
+        let scopes = ["https://www.googleapis.com/auth/devstorage.read_only"];
+        let unreachable = hyper::Client::new();
+        assert!(matches!(
+            warm_up(
+                &transport(&unreachable),
+                "http://127.0.0.1:1/",
+                Some(&scopes)
+            )
+            .await,
+            Err(Error::HttpError(_))
+        ));
+
+        let connector = MockConnector::new(|_| Response::new(Body::empty()));
+        let client = hyper::Client::builder().build(connector);
+        let failing_token = Transport {
+            auth: &FailingToken,
+            ..transport(&client)
+        };
+        assert!(matches!(
+            warm_up(
+                &failing_token,
+                "https://storage.googleapis.com/",
+                Some(&scopes)
+            )
+            .await,
+            Err(Error::MissingToken(_))
+        ));
+        let both_failing = Transport {
+            auth: &FailingToken,
+            ..transport(&unreachable)
+        };
+        assert!(matches!(
+            warm_up(&both_failing, "http://127.0.0.1:1/", Some(&scopes)).await,
+            Err(Error::MissingToken(_))
+        ));
+    }
+
+    #[test]
+    fn request_bodies_are_shared() {
+        let json = Bytes::from_static(br#"{"name":"bucket"}"#);
+        let request = http_request(Request::builder().uri("/b"), Some(json.clone())).unwrap();
+        assert_eq!(request.headers()[CONTENT_LENGTH], "17");
+        assert_eq!(request.body().as_ptr(), json.as_ptr());
+
+        let request = http_request::<Vec<u8>>(Request::builder().uri("/b"), None).unwrap();
+        assert!(request.body().is_empty());
+        assert!(request.headers().get(CONTENT_TYPE).is_none());
+    }
+}
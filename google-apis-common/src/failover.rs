@@ -0,0 +1,184 @@
+//! Failover between the endpoints of an API
+//!
+//! A [`Failover`] holds base URLs in the order of preference, like a regional endpoint followed
+//! by the global one. Calls are sent to the first healthy endpoint. An endpoint which can't be
+//! connected to is failed over from at once, while one answering with server errors becomes
+//! unhealthy only after several of them in a row, so that a single error is left to the retries
+//! of the delegate. Once its cooldown passed, an unhealthy endpoint is tried again, and traffic
+//! returns to it if it recovered.
+use std::borrow::Cow;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use ::url::Url;
+
+/// The number of failures in a row which make an endpoint unhealthy by default.
+pub const DEFAULT_UNHEALTHY_AFTER: u32 = 3;
+
+/// How long an endpoint stays unhealthy by default, before it's tried again.
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default)]
+struct Health {
+    failures: u32,
+    unhealthy_until: Option<Instant>,
+}
+
+/// Base URLs to fail over between, in the order of preference, with their health.
+#[derive(Debug)]
+pub struct Failover {
+    base_urls: Vec<String>,
+    health: Mutex<Vec<Health>>,
+    unhealthy_after: u32,
+    cooldown: Duration,
+}
+
+impl Failover {
+    /// Returns a failover between `base_urls`, the first of which is preferred.
+    ///
+    /// # Panics
+    ///
+    /// If there are no base URLs.
+    pub fn new(base_urls: Vec<String>) -> Failover {
+        assert!(!base_urls.is_empty(), "a failover needs a base URL");
+        let health = base_urls.iter().map(|_| Health::default()).collect();
+        Failover {
+            base_urls,
+            health: Mutex::new(health),
+            unhealthy_after: DEFAULT_UNHEALTHY_AFTER,
+            cooldown: DEFAULT_COOLDOWN,
+        }
+    }
+
+    /// Sets the number of failures in a row which make an endpoint unhealthy.
+    pub fn unhealthy_after(mut self, failures: u32) -> Failover {
+        self.unhealthy_after = failures.max(1);
+        self
+    }
+
+    /// Sets how long an endpoint stays unhealthy, before it's tried again.
+    pub fn cooldown(mut self, cooldown: Duration) -> Failover {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Returns the preferred base URL, which call builders build the URLs of calls with.
+    pub fn preferred(&self) -> &str {
+        &self.base_urls[0]
+    }
+
+    /// Returns the base URLs, in the order of preference.
+    pub fn base_urls(&self) -> &[String] {
+        &self.base_urls
+    }
+
+    /// Returns the index of the endpoint to send the next request to, which is the first healthy
+    /// one not in `excluded`, or the first one not in `excluded` if none of them is healthy.
+    /// Without any endpoint left, it's `None`.
+    pub fn select(&self, excluded: &[usize]) -> Option<usize> {
+        let health = self.health.lock().unwrap();
+        let now = Instant::now();
+        let mut candidates = (0..self.base_urls.len()).filter(|i| !excluded.contains(i));
+        let first = candidates.clone().next();
+        candidates
+            .find(|&i| health[i].unhealthy_until.map_or(true, |until| until <= now))
+            .or(first)
+    }
+
+    /// Records the outcome of a request to the endpoint at `index`, which failed if it couldn't
+    /// connect or the server answered with a server error.
+    pub fn record(&self, index: usize, failed: bool) {
+        let mut health = self.health.lock().unwrap();
+        let health = &mut health[index];
+        if !failed {
+            *health = Health::default();
+            return;
+        }
+        health.failures += 1;
+        if health.failures >= self.unhealthy_after {
+            health.unhealthy_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+
+    /// Returns whether the endpoint at `index` is healthy.
+    pub fn is_healthy(&self, index: usize) -> bool {
+        let health = self.health.lock().unwrap();
+        health[index]
+            .unhealthy_until
+            .map_or(true, |until| until <= Instant::now())
+    }
+
+    /// Returns `url`, built with the preferred base URL, with the base URL of the endpoint at
+    /// `index` instead. URLs with another base URL, like those of uploads, are returned as they
+    /// are.
+    pub fn rebase<'u>(&self, url: &'u Url, index: usize) -> Cow<'u, Url> {
+        if index == 0 {
+            return Cow::Borrowed(url);
+        }
+        let rest = match url.as_str().strip_prefix(self.preferred()) {
+            Some(rest) => rest,
+            None => return Cow::Borrowed(url),
+        };
+        match Url::parse(&format!("{}{}", self.base_urls[index], rest)) {
+            Ok(url) => Cow::Owned(url),
+            Err(_) => Cow::Borrowed(url),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn failover() -> Failover {
+        Failover::new(vec![
+            "https://storage.europe-west1.rep.googleapis.com/storage/v1/".into(),
+            "https://storage.googleapis.com/storage/v1/".into(),
+        ])
+        .unhealthy_after(2)
+    }
+
+    #[test]
+    fn unhealthy_endpoints_are_failed_over() {
+        let failover = failover();
+        assert_eq!(failover.select(&[]), Some(0));
+        failover.record(0, true);
+        assert_eq!(failover.select(&[]), Some(0));
+        failover.record(0, true);
+        assert!(!failover.is_healthy(0));
+        assert_eq!(failover.select(&[]), Some(1));
+        assert_eq!(failover.select(&[1]), Some(0));
+        assert_eq!(failover.select(&[0, 1]), None);
+
+        failover.record(1, true);
+        failover.record(1, false);
+        failover.record(1, true);
+        assert!(failover.is_healthy(1));
+    }
+
+    #[test]
+    fn endpoints_recover_after_their_cooldown() {
+        let failover = failover().cooldown(Duration::ZERO);
+        failover.record(0, true);
+        failover.record(0, true);
+        assert_eq!(failover.select(&[]), Some(0));
+        failover.record(0, false);
+        assert!(failover.is_healthy(0));
+    }
+
+    #[test]
+    fn urls_are_rebased() {
+        let failover = failover();
+        let url = Url::parse(
+            "https://storage.europe-west1.rep.googleapis.com/storage/v1/b/bucket?alt=json",
+        )
+        .unwrap();
+        assert_eq!(failover.rebase(&url, 0).as_str(), url.as_str());
+        assert_eq!(
+            failover.rebase(&url, 1).as_str(),
+            "https://storage.googleapis.com/storage/v1/b/bucket?alt=json"
+        );
+        let upload = Url::parse("https://storage.googleapis.com/upload/storage/v1/b").unwrap();
+        assert_eq!(failover.rebase(&upload, 1).as_str(), upload.as_str());
+    }
+}
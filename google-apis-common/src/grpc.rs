@@ -0,0 +1,218 @@
+//! Making calls through gRPC
+//!
+//! APIs publishing protos, like Pub/Sub, Spanner and Firestore, can also be called through gRPC,
+//! which supports streaming and has less overhead than REST and JSON. With the `grpc` feature,
+//! their hubs connect a [`GrpcTransport`] to the gRPC endpoint of the API with
+//! `grpc_transport()`, which authenticates and retries calls like the hub does. The clients are
+//! those `tonic-build` generates from the protos of the API, like the messages of `doit_proto()`.
+//!
+//! ```ignore
+//! let transport = hub.grpc_transport().await?;
+//! let topic = transport
+//!     .call(GetTopicRequest { topic }, |channel, request| async move {
+//!         PublisherClient::new(channel).get_topic(request).await
+//!     })
+//!     .await?;
+//! ```
+use std::future::Future;
+use std::time::Duration;
+
+use hyper::StatusCode;
+use tokio::time::sleep;
+use tonic::metadata::MetadataValue;
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+use tonic::{Code, Request, Response, Status};
+
+use crate::retry::RetryPolicy;
+use crate::{Error, GetToken, Result, API_CLIENT_HEADER};
+
+/// A connection to the gRPC endpoint of an API, which authenticates and retries calls like the
+/// hub which connected it.
+#[derive(Clone)]
+pub struct GrpcTransport {
+    channel: Channel,
+    auth: Box<dyn GetToken>,
+    scopes: Vec<String>,
+    api_client: Option<String>,
+    retry_policy: Option<RetryPolicy>,
+}
+
+impl GrpcTransport {
+    /// Connects to `endpoint`, like `https://pubsub.googleapis.com`, with TLS unless it's an
+    /// `http` endpoint like the one of an emulator, to authorize calls with the tokens of `auth`.
+    pub async fn connect(endpoint: &str, auth: Box<dyn GetToken>) -> Result<GrpcTransport> {
+        let mut builder = Endpoint::from_shared(endpoint.to_string()).map_err(grpc_error)?;
+        if !endpoint.starts_with("http://") {
+            builder = builder
+                .tls_config(ClientTlsConfig::new())
+                .map_err(grpc_error)?;
+        }
+        let channel = builder.connect().await.map_err(grpc_error)?;
+        Ok(GrpcTransport::new(channel, auth))
+    }
+
+    /// Returns a transport making calls through `channel`, to authorize them with the tokens of
+    /// `auth`.
+    pub fn new(channel: Channel, auth: Box<dyn GetToken>) -> GrpcTransport {
+        GrpcTransport {
+            channel,
+            auth,
+            scopes: Vec::new(),
+            api_client: None,
+            retry_policy: None,
+        }
+    }
+
+    /// Sets the scopes of the tokens authorizing calls.
+    pub fn scopes<I: IntoIterator<Item = String>>(mut self, scopes: I) -> Self {
+        self.scopes = scopes.into_iter().collect();
+        self
+    }
+
+    /// Sets the value of the `x-goog-api-client` header of calls.
+    pub fn api_client(mut self, api_client: String) -> Self {
+        self.api_client = Some(api_client);
+        self
+    }
+
+    /// Sets the policy retrying calls failing with a status which corresponds to one of its
+    /// retryable HTTP statuses, like `UNAVAILABLE` for `503 Service Unavailable`.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// Returns the channel calls are made through, for clients managing their requests
+    /// themselves, like those of bidirectional streams.
+    pub fn channel(&self) -> Channel {
+        self.channel.clone()
+    }
+
+    /// Returns the request of `message`, authorized with a token for the scopes of this transport.
+    pub async fn request<T>(&self, message: T) -> Result<Request<T>> {
+        let scopes: Vec<&str> = self.scopes.iter().map(String::as_str).collect();
+        let token = self
+            .auth
+            .get_token(&scopes)
+            .await
+            .map_err(Error::MissingToken)?;
+        let mut request = Request::new(message);
+        if let Some(token) = token {
+            let authorization =
+                MetadataValue::try_from(format!("Bearer {}", token)).map_err(grpc_error)?;
+            request
+                .metadata_mut()
+                .insert("authorization", authorization);
+        }
+        if let Some(api_client) = self.api_client.as_deref() {
+            let api_client = MetadataValue::try_from(api_client).map_err(grpc_error)?;
+            request.metadata_mut().insert(API_CLIENT_HEADER, api_client);
+        }
+        Ok(request)
+    }
+
+    /// Makes the call `call` with the request of `message` until it succeeds, or fails in a way
+    /// the retry policy doesn't retry, and returns its response, like a `tonic::Streaming` of
+    /// messages for server streaming calls.
+    pub async fn call<T, R, F, Fut>(&self, message: T, mut call: F) -> Result<R>
+    where
+        T: Clone,
+        F: FnMut(Channel, Request<T>) -> Fut,
+        Fut: Future<Output = std::result::Result<Response<R>, Status>>,
+    {
+        let mut retries = 0;
+        loop {
+            let request = self.request(message.clone()).await?;
+            match call(self.channel.clone(), request).await {
+                Ok(response) => return Ok(response.into_inner()),
+                Err(status) => {
+                    retries += 1;
+                    match self.retry_delay(&status, retries) {
+                        Some(delay) => sleep(delay).await,
+                        None => return Err(Error::Grpc(Box::new(status))),
+                    }
+                }
+            }
+        }
+    }
+
+    fn retry_delay(&self, status: &Status, retry: u32) -> Option<Duration> {
+        let policy = self.retry_policy.as_ref()?;
+        let status = http_status(status.code())?;
+        if !policy.is_retryable_status(status) {
+            return None;
+        }
+        policy.delay(retry)
+    }
+}
+
+/// Returns the HTTP status of the transient failures among the gRPC status codes.
+fn http_status(code: Code) -> Option<StatusCode> {
+    match code {
+        Code::ResourceExhausted => Some(StatusCode::TOO_MANY_REQUESTS),
+        Code::Internal => Some(StatusCode::INTERNAL_SERVER_ERROR),
+        Code::Unavailable => Some(StatusCode::SERVICE_UNAVAILABLE),
+        Code::DeadlineExceeded => Some(StatusCode::GATEWAY_TIMEOUT),
+        _ => None,
+    }
+}
+
+fn grpc_error<E: std::error::Error + Send + Sync + 'static>(err: E) -> Error {
+    Error::Grpc(Box::new(err))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    fn transport(token: &str) -> GrpcTransport {
+        let channel = Endpoint::from_static("http://localhost:1").connect_lazy();
+        let policy = RetryPolicy::new()
+            .max_attempts(3)
+            .base_delay(Duration::from_millis(1))
+            .jitter(0.0);
+        GrpcTransport::new(channel, Box::new(token.to_string()))
+            .api_client("gl-rust/1".into())
+            .retry_policy(policy)
+    }
+
+    #[tokio::test]
+    async fn requests_are_authorized() {
+        let request = transport("t").request(()).await.unwrap();
+        let metadata = request.metadata();
+        assert_eq!(metadata.get("authorization").unwrap(), "Bearer t");
+        assert_eq!(metadata.get(API_CLIENT_HEADER).unwrap(), "gl-rust/1");
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_retried() {
+        let transport = transport("t");
+        let attempts = AtomicU32::new(0);
+        let result: Result<()> = transport
+            .call((), |_, _| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                async { Err(Status::unavailable("down")) }
+            })
+            .await;
+        assert!(matches!(result, Err(Error::Grpc(_))));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        attempts.store(0, Ordering::SeqCst);
+        let result: Result<()> = transport
+            .call((), |_, _| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                async { Err(Status::not_found("gone")) }
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        let value = transport
+            .call((), |_, _| async { Ok(Response::new(42)) })
+            .await
+            .unwrap();
+        assert_eq!(value, 42);
+    }
+}
@@ -0,0 +1,434 @@
+//! Capturing of requests and responses into [HTTP Archive (HAR)](http://www.softwareishard.com/blog/har-12-spec/) files
+//!
+//! HAR files can be opened by the developer tools of browsers and other HTTP tooling, which makes
+//! them handy to share with Google support or to attach to bug reports.
+//! Credentials in headers and query parameters are redacted, and bodies are truncated to
+//! [`HarRecorder::max_body_len()`] bytes.
+//!
+//! # Example
+//! ```ignore
+//! let recorder = HarRecorder::new();
+//! hub.capture_har(recorder.clone());
+//! hub.objects().get("bucket", "object").doit().await?;
+//! recorder.save("objects-get.har")?;
+//! ```
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use hyper::header::{HeaderMap, CONTENT_TYPE};
+use hyper::Version;
+use serde::Serialize;
+
+use crate::{is_sensitive_header, redact_uri, REDACTED};
+
+/// The amount of bytes of each body captured by default.
+pub const DEFAULT_MAX_BODY_LEN: usize = 64 * 1024;
+
+/// Collects the requests made by the hubs it is set on, to be written as HAR file.
+///
+/// Clones share their entries, so a clone can be handed to a hub while the original is
+/// used to save the capture later.
+#[derive(Clone)]
+pub struct HarRecorder {
+    entries: Arc<Mutex<Vec<Entry>>>,
+    max_body_len: usize,
+}
+
+impl Default for HarRecorder {
+    fn default() -> Self {
+        HarRecorder::new()
+    }
+}
+
+impl HarRecorder {
+    pub fn new() -> Self {
+        HarRecorder {
+            entries: Arc::new(Mutex::new(Vec::new())),
+            max_body_len: DEFAULT_MAX_BODY_LEN,
+        }
+    }
+
+    /// Capture at most `max_body_len` bytes of each request and response body.
+    /// Defaults to [`DEFAULT_MAX_BODY_LEN`].
+    pub fn max_body_len(mut self, max_body_len: usize) -> Self {
+        self.max_body_len = max_body_len;
+        self
+    }
+
+    /// The number of captured requests.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all captured requests.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear()
+    }
+
+    /// Writes all captured requests as HAR document to `writer`.
+    pub fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        let entries = self.entries.lock().unwrap();
+        let har = Har {
+            log: Log {
+                version: "1.2",
+                creator: Creator {
+                    name: env!("CARGO_PKG_NAME"),
+                    version: env!("CARGO_PKG_VERSION"),
+                },
+                entries: &entries,
+            },
+        };
+        serde_json::to_writer_pretty(writer, &har).map_err(io::Error::from)
+    }
+
+    /// Writes all captured requests to the HAR file at `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_to(io::BufWriter::new(fs::File::create(path)?))
+    }
+
+    /// Captures `req`, returning it along with the entry to add the response to.
+    ///
+    /// The body is buffered to capture it, which is why this should only be used with bodies
+    /// which are in memory already.
+    pub async fn capture_request(
+        &self,
+        req: hyper::Request<hyper::body::Body>,
+    ) -> (hyper::Request<hyper::body::Body>, PendingEntry) {
+        let (parts, body) = req.into_parts();
+        let body = hyper::body::to_bytes(body).await.unwrap_or_default();
+        let url = redact_uri(&parts.uri);
+        let query_string = parts
+            .uri
+            .query()
+            .map(|query| {
+                url::form_urlencoded::parse(query.as_bytes())
+                    .map(|(name, value)| {
+                        let value = if crate::SENSITIVE_QUERY_PARAMS.contains(&name.as_ref()) {
+                            REDACTED.to_string()
+                        } else {
+                            value.into_owned()
+                        };
+                        NameValue {
+                            name: name.into_owned(),
+                            value,
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let post_data = if body.is_empty() {
+            None
+        } else {
+            let (text, encoding) = self.body_text(&body);
+            Some(PostData {
+                mime_type: mime_type(&parts.headers),
+                text,
+                encoding,
+            })
+        };
+        let entry = Entry {
+            started_date_time: Utc::now(),
+            time: 0.0,
+            request: Request {
+                method: parts.method.to_string(),
+                url,
+                http_version: http_version(parts.version),
+                cookies: Vec::new(),
+                headers: headers(&parts.headers),
+                query_string,
+                post_data,
+                headers_size: -1,
+                body_size: body.len() as i64,
+            },
+            response: Response {
+                status: 0,
+                status_text: String::new(),
+                http_version: String::new(),
+                cookies: Vec::new(),
+                headers: Vec::new(),
+                content: Content {
+                    size: 0,
+                    mime_type: String::new(),
+                    text: None,
+                },
+                redirect_url: String::new(),
+                headers_size: -1,
+                body_size: -1,
+            },
+            cache: Cache {},
+            timings: Timings {
+                send: 0.0,
+                wait: 0.0,
+                receive: 0.0,
+            },
+        };
+        let pending = PendingEntry {
+            recorder: self.clone(),
+            entry: Some(entry),
+            started: Instant::now(),
+        };
+        (hyper::Request::from_parts(parts, body.into()), pending)
+    }
+
+    /// Returns the truncated `body`, and its encoding if it isn't valid UTF-8.
+    fn body_text(&self, body: &[u8]) -> (String, Option<&'static str>) {
+        let len = body.len().min(self.max_body_len);
+        match std::str::from_utf8(body) {
+            Ok(text) => {
+                let len = (0..=len)
+                    .rev()
+                    .find(|&i| text.is_char_boundary(i))
+                    .unwrap_or(0);
+                (text[..len].to_string(), None)
+            }
+            Err(_) => (base64::encode(&body[..len]), Some("base64")),
+        }
+    }
+}
+
+/// A captured request, which is added to its [`HarRecorder`] once dropped.
+///
+/// If no response is added, the request is recorded as failed, with status 0.
+pub struct PendingEntry {
+    recorder: HarRecorder,
+    entry: Option<Entry>,
+    started: Instant,
+}
+
+impl PendingEntry {
+    /// Adds status and headers of the response to the request.
+    pub fn response<B>(&mut self, res: &hyper::Response<B>) {
+        let entry = self.entry.as_mut().expect("entry is present until dropped");
+        let elapsed = self.started.elapsed().as_secs_f64() * 1000.0;
+        entry.time = elapsed;
+        entry.timings.wait = elapsed;
+        let response = &mut entry.response;
+        response.status = res.status().as_u16();
+        response.status_text = res
+            .status()
+            .canonical_reason()
+            .unwrap_or_default()
+            .to_string();
+        response.http_version = http_version(res.version());
+        response.headers = headers(res.headers());
+        response.content.mime_type = mime_type(res.headers());
+        response.redirect_url = res
+            .headers()
+            .get(hyper::header::LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+    }
+
+    /// Adds the body of the response to the request.
+    pub fn response_body<B: AsRef<[u8]> + ?Sized>(&mut self, body: &B) {
+        let body = body.as_ref();
+        let (text, _) = self.recorder.body_text(body);
+        let entry = self.entry.as_mut().expect("entry is present until dropped");
+        entry.response.body_size = body.len() as i64;
+        let content = &mut entry.response.content;
+        content.size = body.len() as u64;
+        content.text = Some(text);
+    }
+}
+
+impl Drop for PendingEntry {
+    fn drop(&mut self) {
+        if let Some(entry) = self.entry.take() {
+            if let Ok(mut entries) = self.recorder.entries.lock() {
+                entries.push(entry);
+            }
+        }
+    }
+}
+
+/// Captures `req` with `recorder`, if there is one.
+pub async fn capture_request(
+    recorder: Option<&HarRecorder>,
+    req: hyper::Request<hyper::body::Body>,
+) -> (hyper::Request<hyper::body::Body>, Option<PendingEntry>) {
+    match recorder {
+        Some(recorder) => {
+            let (req, entry) = recorder.capture_request(req).await;
+            (req, Some(entry))
+        }
+        None => (req, None),
+    }
+}
+
+fn headers(headers: &HeaderMap) -> Vec<NameValue> {
+    headers
+        .iter()
+        .map(|(k, v)| NameValue {
+            name: k.to_string(),
+            value: if is_sensitive_header(k) {
+                REDACTED.to_string()
+            } else {
+                String::from_utf8_lossy(v.as_bytes()).into_owned()
+            },
+        })
+        .collect()
+}
+
+fn mime_type(headers: &HeaderMap) -> String {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn http_version(version: Version) -> String {
+    format!("{:?}", version)
+}
+
+#[derive(Serialize)]
+struct Har<'a> {
+    log: Log<'a>,
+}
+
+#[derive(Serialize)]
+struct Log<'a> {
+    version: &'static str,
+    creator: Creator,
+    entries: &'a [Entry],
+}
+
+#[derive(Serialize)]
+struct Creator {
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    started_date_time: DateTime<Utc>,
+    time: f64,
+    request: Request,
+    response: Response,
+    cache: Cache,
+    timings: Timings,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Request {
+    method: String,
+    url: String,
+    http_version: String,
+    cookies: Vec<NameValue>,
+    headers: Vec<NameValue>,
+    query_string: Vec<NameValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    post_data: Option<PostData>,
+    headers_size: i64,
+    body_size: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Response {
+    status: u16,
+    status_text: String,
+    http_version: String,
+    cookies: Vec<NameValue>,
+    headers: Vec<NameValue>,
+    content: Content,
+    #[serde(rename = "redirectURL")]
+    redirect_url: String,
+    headers_size: i64,
+    body_size: i64,
+}
+
+#[derive(Serialize)]
+struct NameValue {
+    name: String,
+    value: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PostData {
+    mime_type: String,
+    text: String,
+    /// Not part of HAR 1.2, which only supports encoded response bodies
+    #[serde(rename = "_encoding", skip_serializing_if = "Option::is_none")]
+    encoding: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Content {
+    size: u64,
+    mime_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Cache {}
+
+#[derive(Serialize)]
+struct Timings {
+    send: f64,
+    wait: f64,
+    receive: f64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn captures_redacted_requests_and_responses() {
+        let recorder = HarRecorder::new().max_body_len(4);
+        let req = hyper::Request::builder()
+            .method(hyper::Method::POST)
+            .uri("https://storage.googleapis.com/storage/v1/b?key=secret&project=p")
+            .header(hyper::header::AUTHORIZATION, "Bearer secret")
+            .header(CONTENT_TYPE, "application/json")
+            .body(hyper::body::Body::from("{\"name\":\"bucket\"}"))
+            .unwrap();
+        let (req, mut entry) = capture_request(Some(&recorder), req).await;
+        // the request is still complete
+        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+        assert_eq!(body.len(), 17);
+
+        let res = hyper::Response::builder()
+            .status(404)
+            .body(hyper::body::Body::empty())
+            .unwrap();
+        let entry_ref = entry.as_mut().unwrap();
+        entry_ref.response(&res);
+        entry_ref.response_body("not found");
+        assert!(recorder.is_empty());
+        drop(entry);
+        assert_eq!(recorder.len(), 1);
+
+        let mut har = Vec::new();
+        recorder.write_to(&mut har).unwrap();
+        let har: serde_json::Value = serde_json::from_slice(&har).unwrap();
+        let entry = &har["log"]["entries"][0];
+        assert_eq!(har["log"]["version"], "1.2");
+        assert_eq!(
+            entry["request"]["url"],
+            "https://storage.googleapis.com/storage/v1/b?key=<redacted>&project=p"
+        );
+        assert_eq!(entry["request"]["headers"][0]["value"], REDACTED);
+        assert_eq!(entry["request"]["queryString"][0]["value"], REDACTED);
+        assert_eq!(entry["request"]["postData"]["text"], "{\"na");
+        assert_eq!(entry["request"]["bodySize"], 17);
+        assert_eq!(entry["response"]["status"], 404);
+        assert_eq!(entry["response"]["content"]["text"], "not ");
+        assert_eq!(entry["response"]["content"]["size"], 9);
+    }
+}
@@ -0,0 +1,90 @@
+//! In-memory connections, answered by a function rather than a server
+//!
+//! Used by the connectors of the `cassette`, `chaos` and `mock` modules, which need to see the
+//! requests of a client and choose its responses.
+use std::error::Error as StdError;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use hyper::client::connect::{Connected, Connection};
+use hyper::service::service_fn;
+use hyper::{Body, Request, Response, Uri};
+use tokio::io::{AsyncRead, AsyncWrite, DuplexStream, ReadBuf};
+
+/// The size of the in-memory buffer of each connection.
+const CONNECTION_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Returns a connection on which each request is answered by `handle`, with its URI made absolute
+/// using the scheme and authority of `origin`, the URI the connection was made for.
+///
+/// If `handle` fails, the connection is closed without a response.
+pub(crate) fn connect<F, Fut>(origin: Uri, handle: F) -> InMemoryStream
+where
+    F: Fn(Request<Body>) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<Response<Body>, Box<dyn StdError + Send + Sync>>> + Send + 'static,
+{
+    let (client_io, server_io) = tokio::io::duplex(CONNECTION_BUFFER_SIZE);
+    let service = service_fn(move |mut req: Request<Body>| {
+        *req.uri_mut() = absolute_uri(&origin, req.uri());
+        handle(req)
+    });
+    tokio::spawn(
+        hyper::server::conn::Http::new()
+            .http1_only(true)
+            .serve_connection(server_io, service),
+    );
+    InMemoryStream(client_io)
+}
+
+fn absolute_uri(origin: &Uri, uri: &Uri) -> Uri {
+    let mut absolute = Uri::builder();
+    if let Some(scheme) = origin.scheme() {
+        absolute = absolute.scheme(scheme.clone());
+    }
+    if let Some(authority) = origin.authority() {
+        absolute = absolute.authority(authority.clone());
+    }
+    if let Some(path_and_query) = uri.path_and_query() {
+        absolute = absolute.path_and_query(path_and_query.clone());
+    }
+    absolute.build().unwrap_or_else(|_| uri.clone())
+}
+
+/// The client side of an in-memory connection.
+pub struct InMemoryStream(DuplexStream);
+
+impl Connection for InMemoryStream {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+impl AsyncRead for InMemoryStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for InMemoryStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,58 @@
+//! Serialization of request values
+//!
+//! Hubs serialize the request values of calls with a [`JsonSerializer`], compactly with
+//! serde_json by default. Their `json_serializer()` method replaces it, for users with special
+//! needs, like writing floats with a fixed number of decimals.
+//!
+//! How serde_json handles numbers and the order of keys is decided for the whole build by its
+//! features, which this crate forwards: `arbitrary-precision` keeps numbers as exactly as they
+//! were written, `float-roundtrip` parses floats without losing precision, and `preserve-order`
+//! keeps the keys of objects in the order they were inserted.
+use serde_json as json;
+use serde_json::value::Value;
+
+/// Serializes the request values of calls into the bodies of their requests.
+pub trait JsonSerializer: Send + Sync {
+    /// Returns `value` serialized, with its null values removed and its keys sorted already.
+    fn serialize(&self, value: &Value) -> Vec<u8>;
+}
+
+/// Serializes values compactly with serde_json, which hubs do unless told otherwise.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultJsonSerializer;
+
+impl JsonSerializer for DefaultJsonSerializer {
+    fn serialize(&self, value: &Value) -> Vec<u8> {
+        json::to_vec(value).expect("serde to work")
+    }
+}
+
+impl<F: Fn(&Value) -> Vec<u8> + Send + Sync> JsonSerializer for F {
+    fn serialize(&self, value: &Value) -> Vec<u8> {
+        self(value)
+    }
+}
+
+/// Returns `value` serialized by `serializer`, or by the [`DefaultJsonSerializer`] without one.
+pub fn serialize(serializer: Option<&dyn JsonSerializer>, value: &Value) -> Vec<u8> {
+    serializer
+        .unwrap_or(&DefaultJsonSerializer)
+        .serialize(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn values_are_serialized_by_the_serializer() {
+        let value = json::json!({"a": 1.5, "b": [true]});
+        assert_eq!(serialize(None, &value), br#"{"a":1.5,"b":[true]}"#);
+
+        let pretty: &dyn JsonSerializer = &|value: &Value| json::to_vec_pretty(value).unwrap();
+        assert_eq!(
+            serialize(Some(pretty), &value),
+            json::to_vec_pretty(&value).unwrap()
+        );
+    }
+}
@@ -1,20 +1,72 @@
+pub mod attempt_log;
+#[cfg(feature = "audit")]
+pub mod audit;
 pub mod auth;
-pub mod field_mask;
-pub mod serde;
+#[cfg(feature = "cassette")]
+pub mod cassette;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+#[cfg(feature = "client-builder")]
+pub mod client_builder;
+pub mod dyn_connector;
+pub mod dyn_hub;
+#[cfg(feature = "emulator")]
+pub mod emulator;
+pub mod events;
+pub mod exec;
+pub mod failover;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod har;
+#[cfg(any(feature = "cassette", feature = "chaos", feature = "mock"))]
+pub mod in_memory;
+pub mod json_serializer;
+#[cfg(feature = "live")]
+pub mod live;
+pub mod methods;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod openapi;
+pub mod parallel;
+pub mod path_param;
+#[cfg(feature = "indicatif")]
+pub mod progress;
+#[cfg(feature = "prometheus")]
+pub mod prometheus_metrics;
+#[cfg(feature = "proto")]
+pub mod proto;
+pub mod retry;
+pub mod service;
+#[cfg(feature = "signing")]
+pub mod signed_url;
+#[cfg(feature = "signing")]
+pub mod signing;
+pub mod standard_params;
+#[cfg(feature = "stub-server")]
+pub mod stub_server;
+pub mod uri_template;
 pub mod url;
 
+use std::collections::BTreeSet;
 use std::error;
 use std::error::Error as StdError;
 use std::fmt::{self, Display};
 use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::pin::Pin;
 use std::str::FromStr;
+use std::task::{Context, Poll};
 use std::time::Duration;
 
+#[cfg(feature = "gzip")]
+use flate2::write::GzDecoder;
 use itertools::Itertools;
 
+use hyper::body::HttpBody;
 use hyper::http::Uri;
 
-use hyper::header::{HeaderMap, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, USER_AGENT};
+use hyper::header::{
+    HeaderMap, AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, USER_AGENT,
+};
 use hyper::Method;
 use hyper::StatusCode;
 
@@ -22,18 +74,130 @@ use mime::Mime;
 
 use serde_json as json;
 
-use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
 use tokio::time::sleep;
 
-pub use auth::{GetToken, NoToken};
-pub use chrono;
-pub use field_mask::FieldMask;
-pub use serde_with;
+pub use auth::{GetToken, NoToken, StaticToken};
+pub use dyn_connector::DynConnector;
+pub use futures;
+pub use futures::future::BoxFuture;
+pub use google_types_common::{
+    chrono, conformance, convert, field_mask, serde, serde_with, FieldMask, NestedType, Part,
+    RequestValue, Resource, ResponseResult, ToParts, UnusedType,
+};
+pub use parallel::{execute_all, ExecutionPolicy, PartialResult, RateLimiter};
+#[cfg(feature = "proto")]
+pub use prost;
+#[cfg(feature = "grpc")]
+pub use tonic;
 #[cfg(feature = "yup-oauth2")]
 pub use yup_oauth2 as oauth2;
 
 const LINE_ENDING: &str = "\r\n";
 
+/// The name of the header identifying the client library to Google.
+pub const API_CLIENT_HEADER: &str = "x-goog-api-client";
+
+/// Headers set by calls, which [`Delegate::prepare_request()`] cannot change.
+pub const RESERVED_HEADERS: &[&str] = &[
+    "authorization",
+    "content-length",
+    "content-range",
+    "content-type",
+    "range",
+    "user-agent",
+    "x-goog-api-client",
+    "x-upload-content-type",
+];
+
+/// Lets `dlg` [prepare](Delegate::prepare_request()) the request built by `builder`, keeping
+/// the [`RESERVED_HEADERS`] as they were.
+pub fn prepare_request(
+    dlg: &mut dyn Delegate,
+    builder: hyper::http::request::Builder,
+) -> hyper::http::request::Builder {
+    let reserved: Vec<_> = builder
+        .headers_ref()
+        .map(|headers| {
+            headers
+                .iter()
+                .filter(|(name, _)| RESERVED_HEADERS.contains(&name.as_str()))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut builder = dlg.prepare_request(builder);
+    if let Some(headers) = builder.headers_mut() {
+        for name in RESERVED_HEADERS {
+            headers.remove(*name);
+        }
+        for (name, value) in reserved {
+            headers.append(name, value);
+        }
+    }
+    builder
+}
+
+/// Returns the value of the [`API_CLIENT_HEADER`], which identifies the version of the
+/// compiler and of the API crate, given as `crate_version`.
+pub fn api_client_header(crate_version: &str) -> String {
+    format!("gl-rust/{} gdcl/{}", env!("RUSTC_VERSION"), crate_version)
+}
+
+/// Appends `token`, like `my-library/1.2.0`, to `value`, the value of the [`API_CLIENT_HEADER`]
+/// or the user-agent, unless it has the token already.
+///
+/// Characters which can't be part of a token, like spaces separating tokens, are replaced by `_`.
+pub fn append_client_token(value: &mut String, token: &str) {
+    let token: String = token
+        .chars()
+        .map(|c| if c.is_ascii_graphic() { c } else { '_' })
+        .collect();
+    if token.is_empty() || value.split(' ').any(|t| t == token) {
+        return;
+    }
+    if !value.is_empty() {
+        value.push(' ');
+    }
+    value.push_str(&token);
+}
+
+/// Wraps the future of a call to the method with the given id, like `storage.objects.get`.
+///
+/// With the `tracing` feature, the call runs in a `google_api_call` span with a `method` field
+/// holding the id, which names otherwise anonymous futures in tools like `tokio-console` and in
+/// the output of `tracing` subscribers. Without it, `call` is returned as is.
+pub fn instrument<F: std::future::Future>(
+    method_id: &'static str,
+    call: F,
+) -> impl std::future::Future<Output = F::Output> {
+    #[cfg(feature = "tracing")]
+    {
+        use tracing::Instrument;
+        call.instrument(tracing::info_span!("google_api_call", method = method_id))
+    }
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = method_id;
+        call
+    }
+}
+
+/// Runs `call` until it completes, or fails it with [`Error::Deadline`] once `timeout` elapsed,
+/// dropping it. Without `timeout`, `call` runs until it completes.
+pub async fn with_timeout<T, F>(timeout: Option<Duration>, call: F) -> Result<T>
+where
+    F: std::future::Future<Output = Result<T>>,
+{
+    match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, call).await {
+            Ok(result) => result,
+            Err(_) => Err(Error::Deadline(timeout)),
+        },
+        None => call.await,
+    }
+}
+
 pub enum Retry {
     /// Signal you don't want to retry
     Abort,
@@ -58,35 +222,136 @@ pub trait MethodsBuilder {}
 /// Identifies types which represent builders for a particular resource method
 pub trait CallBuilder {}
 
-/// Identifies types which can be inserted and deleted.
-/// Types with this trait are most commonly used by clients of this API.
-pub trait Resource {}
+/// A utility to specify reader types which provide seeking capabilities too
+pub trait ReadSeek: Seek + Read + Send {}
+impl<T: Seek + Read + Send> ReadSeek for T {}
+
+/// A utility to specify asynchronous reader types which provide seeking capabilities too.
+///
+/// This is what media uploads read from. Blocking sources implementing [`ReadSeek`], like
+/// `std::fs::File`, can be used by wrapping them into a [`ReadSeekAdapter`].
+pub trait AsyncReadSeek: AsyncRead + AsyncSeek + Send + Unpin {}
+impl<T: AsyncRead + AsyncSeek + Send + Unpin> AsyncReadSeek for T {}
+
+/// Makes a blocking [`ReadSeek`] usable where an [`AsyncReadSeek`] is expected.
+///
+/// All reads and seeks are performed in place, blocking the executor while they run.
+/// This is fine for local files and in-memory buffers, which is what it is meant for.
+pub struct ReadSeekAdapter<R> {
+    inner: R,
+    seek_result: Option<io::Result<u64>>,
+}
+
+impl<R: ReadSeek> ReadSeekAdapter<R> {
+    pub fn new(inner: R) -> Self {
+        ReadSeekAdapter {
+            inner,
+            seek_result: None,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
 
-/// Identifies types which are used in API responses.
-pub trait ResponseResult {}
+impl<R: ReadSeek + Unpin> AsyncRead for ReadSeekAdapter<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let n = self.get_mut().inner.read(buf.initialize_unfilled())?;
+        buf.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
 
-/// Identifies types which are used in API requests.
-pub trait RequestValue {}
+impl<R: ReadSeek + Unpin> AsyncSeek for ReadSeekAdapter<R> {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        this.seek_result = Some(this.inner.seek(position));
+        Ok(())
+    }
 
-/// Identifies types which are not actually used by the API
-/// This might be a bug within the google API schema.
-pub trait UnusedType {}
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+        Poll::Ready(match this.seek_result.take() {
+            Some(result) => result,
+            None => this.inner.stream_position(),
+        })
+    }
+}
 
-/// Identifies types which are only used as part of other types, which
-/// usually are carrying the `Resource` trait.
-pub trait Part {}
+/// Returns the size of the given media in bytes, and rewinds it to its start.
+pub async fn media_size<R: AsyncSeek + Unpin + ?Sized>(reader: &mut R) -> io::Result<u64> {
+    use tokio::io::AsyncSeekExt;
 
-/// Identifies types which are only used by other types internally.
-/// They have no special meaning, this trait just marks them for completeness.
-pub trait NestedType {}
+    let size = reader.seek(SeekFrom::End(0)).await?;
+    reader.seek(SeekFrom::Start(0)).await?;
+    Ok(size)
+}
 
-/// A utility to specify reader types which provide seeking capabilities too
-pub trait ReadSeek: Seek + Read + Send {}
-impl<T: Seek + Read + Send> ReadSeek for T {}
+/// Reads the given media from its current position to the end into memory.
+pub async fn read_media<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> io::Result<Vec<u8>> {
+    use tokio::io::AsyncReadExt;
 
-/// A trait for all types that can convert themselves into a *parts* string
-pub trait ToParts {
-    fn to_parts(&self) -> String;
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).await?;
+    Ok(bytes)
+}
+
+/// A `Read` and `Seek` implementation providing access to a section of a file only.
+/// Seeking is relative to the start of the section, which makes it suitable for uploading
+/// parts of a file independently of each other.
+pub struct FileSection {
+    file: std::fs::File,
+    start: u64,
+    len: u64,
+    pos: u64,
+}
+
+impl FileSection {
+    /// Opens the file at `path` to read `len` bytes, starting at offset `start`.
+    pub fn open<P: AsRef<std::path::Path>>(path: P, start: u64, len: u64) -> io::Result<Self> {
+        let mut file = std::fs::File::open(path)?;
+        file.seek(SeekFrom::Start(start))?;
+        Ok(FileSection {
+            file,
+            start,
+            len,
+            pos: 0,
+        })
+    }
+}
+
+impl Read for FileSection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.pos);
+        let max = buf.len().min(remaining as usize);
+        let n = self.file.read(&mut buf[..max])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for FileSection {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(d) => self.len.checked_add_signed(d),
+            SeekFrom::Current(d) => self.pos.checked_add_signed(d),
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )
+        })?;
+        self.file.seek(SeekFrom::Start(self.start + new_pos))?;
+        self.pos = new_pos;
+        Ok(new_pos)
+    }
 }
 
 /// A trait specifying functionality to help controlling any request performed by the API.
@@ -95,13 +360,24 @@ pub trait ToParts {
 /// It contains methods to deal with all common issues, as well with the ones related to
 /// uploading media
 pub trait Delegate: Send {
+    /// Returns the delegate this one wraps, if any. All calls this delegate doesn't implement
+    /// itself are forwarded to it, so that a wrapper observing or changing some of them, like
+    /// [`events::EventDelegate`], only implements those.
+    fn forward_to(&mut self) -> Option<&mut dyn Delegate> {
+        None
+    }
+
     /// Called at the beginning of any API request. The delegate should store the method
     /// information if he is interesting in knowing more context when further calls to it
     /// are made.
     /// The matching `finished()` call will always be made, no matter whether or not the API
     /// request was successful. That way, the delegate may easily maintain a clean state
     /// between various API calls.
-    fn begin(&mut self, _info: MethodInfo) {}
+    fn begin(&mut self, info: MethodInfo) {
+        if let Some(inner) = self.forward_to() {
+            inner.begin(info)
+        }
+    }
 
     /// Called whenever there is an [HttpError](hyper::Error), usually if there are network problems.
     ///
@@ -109,15 +385,18 @@ pub trait Delegate: Send {
     /// [exponential backoff algorithm](http://en.wikipedia.org/wiki/Exponential_backoff).
     ///
     /// Return retry information.
-    fn http_error(&mut self, _err: &hyper::Error) -> Retry {
-        Retry::Abort
+    fn http_error(&mut self, err: &hyper::Error) -> Retry {
+        match self.forward_to() {
+            Some(inner) => inner.http_error(err),
+            None => Retry::Abort,
+        }
     }
 
     /// Called whenever there is the need for your applications API key after
     /// the official authenticator implementation didn't provide one, for some reason.
     /// If this method returns None as well, the underlying operation will fail
     fn api_key(&mut self) -> Option<String> {
-        None
+        self.forward_to().and_then(|inner| inner.api_key())
     }
 
     /// Called whenever the Authenticator didn't yield a token. The delegate
@@ -129,7 +408,10 @@ pub trait Delegate: Send {
         &mut self,
         e: Box<dyn StdError + Send + Sync>,
     ) -> std::result::Result<Option<String>, Box<dyn StdError + Send + Sync>> {
-        Err(e)
+        match self.forward_to() {
+            Some(inner) => inner.token(e),
+            None => Err(e),
+        }
     }
 
     /// Called during resumable uploads to provide a URL for the impending upload.
@@ -140,7 +422,7 @@ pub trait Delegate: Send {
     /// The returned URL will be used exactly once - if it fails again and the delegate allows
     /// to retry, we will ask the server for a new upload URL.
     fn upload_url(&mut self) -> Option<String> {
-        None
+        self.forward_to().and_then(|inner| inner.upload_url())
     }
 
     /// Called after we have retrieved a new upload URL for a resumable upload to store it
@@ -150,7 +432,9 @@ pub trait Delegate: Send {
     /// to forget the URL. That way, we will not attempt to resume an upload that has already
     /// finished.
     fn store_upload_url(&mut self, url: Option<&str>) {
-        let _ = url;
+        if let Some(inner) = self.forward_to() {
+            inner.store_upload_url(url)
+        }
     }
 
     /// Called whenever a server response could not be decoded from json.
@@ -166,8 +450,9 @@ pub trait Delegate: Send {
         json_encoded_value: &str,
         json_decode_error: &json::Error,
     ) {
-        let _ = json_encoded_value;
-        let _ = json_decode_error;
+        if let Some(inner) = self.forward_to() {
+            inner.response_json_decode_error(json_encoded_value, json_decode_error)
+        }
     }
 
     /// Called whenever the http request returns with a non-success status code.
@@ -180,22 +465,86 @@ pub trait Delegate: Send {
     /// [exponential backoff algorithm](http://en.wikipedia.org/wiki/Exponential_backoff).
     fn http_failure(
         &mut self,
-        _: &hyper::Response<hyper::body::Body>,
-        _err: Option<serde_json::Value>,
+        res: &hyper::Response<hyper::body::Body>,
+        err: Option<serde_json::Value>,
     ) -> Retry {
-        Retry::Abort
+        match self.forward_to() {
+            Some(inner) => inner.http_failure(res, err),
+            None => Retry::Abort,
+        }
+    }
+
+    /// Called before waiting `delay` to retry, after `http_error()` or `http_failure()`
+    /// asked for it. `attempt` is the number of the upcoming retry of the call, starting at 1.
+    fn retry_scheduled(&mut self, attempt: u32, delay: Duration) {
+        if let Some(inner) = self.forward_to() {
+            inner.retry_scheduled(attempt, delay)
+        }
+    }
+
+    /// Called after each HTTP request, with the status of the response if there was one,
+    /// and the time it took until its headers arrived.
+    /// See [`attempt_log::AttemptLog`] for a delegate recording these.
+    fn attempt_completed(&mut self, status: Option<StatusCode>, elapsed: Duration) {
+        if let Some(inner) = self.forward_to() {
+            inner.attempt_completed(status, elapsed)
+        }
+    }
+
+    /// Called after a token was obtained for a request, with the time it took.
+    /// Tokens are usually cached, a long duration indicates that it was refreshed.
+    fn token_obtained(&mut self, elapsed: Duration) {
+        if let Some(inner) = self.forward_to() {
+            inner.token_obtained(elapsed)
+        }
+    }
+
+    /// Called once the request value was serialized, with its size in bytes.
+    fn request_serialized(&mut self, size: u64) {
+        if let Some(inner) = self.forward_to() {
+            inner.request_serialized(size)
+        }
+    }
+
+    /// Called after the response was decoded from json, with the time decoding took.
+    fn response_decoded(&mut self, elapsed: Duration) {
+        if let Some(inner) = self.forward_to() {
+            inner.response_decoded(elapsed)
+        }
     }
 
     /// Called prior to sending the main request of the given method. It can be used to time
     /// the call or to print progress information.
     /// It's also useful as you can be sure that a request will definitely be made.
-    fn pre_request(&mut self) {}
+    fn pre_request(&mut self) {
+        if let Some(inner) = self.forward_to() {
+            inner.pre_request()
+        }
+    }
+
+    /// Called with the builder of each request before it is sent, to add headers, or to
+    /// rewrite the URI, for example for an egress proxy.
+    ///
+    /// Changes to the [`RESERVED_HEADERS`] are discarded.
+    fn prepare_request(
+        &mut self,
+        builder: hyper::http::request::Builder,
+    ) -> hyper::http::request::Builder {
+        match self.forward_to() {
+            Some(inner) => inner.prepare_request(builder),
+            None => builder,
+        }
+    }
 
     /// Return the size of each chunk of a resumable upload.
-    /// Must be a power of two, with 1<<18 being the smallest allowed chunk size.
-    /// Will be called once before starting any resumable upload.
+    /// It will be rounded up to a multiple of [`MIN_CHUNK_SIZE`], the smallest allowed chunk size.
+    /// Will be called once before starting any resumable upload, unless the call builder
+    /// was given a chunk size already.
     fn chunk_size(&mut self) -> u64 {
-        1 << 23
+        match self.forward_to() {
+            Some(inner) => inner.chunk_size(),
+            None => 1 << 23,
+        }
     }
 
     /// Called before the given chunk is uploaded to the server.
@@ -203,8 +552,37 @@ pub trait Delegate: Send {
     /// However, it may be resumable if you stored the upload URL in a previous call
     /// to `store_upload_url()`
     fn cancel_chunk_upload(&mut self, chunk: &ContentRange) -> bool {
-        let _ = chunk;
-        false
+        self.forward_to()
+            .is_some_and(|inner| inner.cancel_chunk_upload(chunk))
+    }
+
+    /// Called after `cancel_chunk_upload()` interrupted an upload.
+    /// If true is returned, the upload session is deleted on the server using [`cancel_upload()`],
+    /// which frees its resources right away, and `store_upload_url(None)` is called.
+    /// The upload can not be resumed afterwards.
+    fn delete_cancelled_upload(&mut self) -> bool {
+        self.forward_to()
+            .is_some_and(|inner| inner.delete_cancelled_upload())
+    }
+
+    /// Called whenever the server accepted more bytes of a media upload.
+    /// `bytes_sent` is the amount of bytes transferred so far, and `total` is the size of the
+    /// entire media. For resumable uploads it is called after each chunk, for simple uploads
+    /// once the request completed.
+    fn upload_progress(&mut self, bytes_sent: u64, total: u64) {
+        if let Some(inner) = self.forward_to() {
+            inner.upload_progress(bytes_sent, total)
+        }
+    }
+
+    /// Called whenever more bytes of a media download were received, see
+    /// [`download_with_progress()`].
+    /// `total` is taken from the `Content-Length` header of the response and may be unknown,
+    /// which is always the case when a gzip-compressed body is decoded, see [`download_size()`].
+    fn download_progress(&mut self, bytes_received: u64, total: Option<u64>) {
+        if let Some(inner) = self.forward_to() {
+            inner.download_progress(bytes_received, total)
+        }
     }
 
     /// Called before the API request method returns, in every case. It can be used to clean up
@@ -214,9 +592,130 @@ pub trait Delegate: Send {
     /// # Arguments
     ///
     /// * `is_success` - a true value indicates the operation was successful. If false, you should
-    ///                  discard all values stored during `store_upload_url`.
+    ///   discard all values stored during `store_upload_url`.
     fn finished(&mut self, is_success: bool) {
-        let _ = is_success;
+        if let Some(inner) = self.forward_to() {
+            inner.finished(is_success)
+        }
+    }
+}
+
+/// Receives metrics about the requests made through a hub, to feed them into a monitoring
+/// system like Prometheus or StatsD.
+/// The trait is default-implemented, so only the metrics of interest need to be implemented.
+///
+/// Each HTTP request of a call counts separately, including retries. `method_id` identifies
+/// the API method, like `storage.objects.get`.
+pub trait Metrics: Send + Sync {
+    /// Called after every request, to be used as counter.
+    fn increment_request_count(&self, method_id: &'static str, status: StatusClass) {
+        let _ = method_id;
+        let _ = status;
+    }
+
+    /// Called after every request with the time it took until the response headers arrived,
+    /// to be used as histogram.
+    fn record_request_latency(
+        &self,
+        method_id: &'static str,
+        status: StatusClass,
+        duration: Duration,
+    ) {
+        let _ = method_id;
+        let _ = status;
+        let _ = duration;
+    }
+
+    /// Called after every failed request with the [canonical error code](canonical_code)
+    /// of its status, like `NOT_FOUND`, to be used as counter.
+    fn increment_error_count(&self, method_id: &'static str, code: &'static str) {
+        let _ = method_id;
+        let _ = code;
+    }
+}
+
+/// Returns the canonical error code of Google APIs, like `NOT_FOUND`, corresponding to the
+/// HTTP status of a response, or `UNAVAILABLE` if there was no response.
+/// See <https://cloud.google.com/apis/design/errors#handling_errors> for the mapping.
+pub fn canonical_code(status: Option<StatusCode>) -> &'static str {
+    let status = match status {
+        Some(status) => status,
+        None => return "UNAVAILABLE",
+    };
+    match status.as_u16() {
+        200..=299 => "OK",
+        400 => "INVALID_ARGUMENT",
+        401 => "UNAUTHENTICATED",
+        403 => "PERMISSION_DENIED",
+        404 => "NOT_FOUND",
+        409 => "ABORTED",
+        412 => "FAILED_PRECONDITION",
+        416 => "OUT_OF_RANGE",
+        429 => "RESOURCE_EXHAUSTED",
+        499 => "CANCELLED",
+        501 => "UNIMPLEMENTED",
+        503 => "UNAVAILABLE",
+        504 => "DEADLINE_EXCEEDED",
+        _ if status.is_client_error() => "FAILED_PRECONDITION",
+        _ if status.is_server_error() => "INTERNAL",
+        _ => "UNKNOWN",
+    }
+}
+
+/// The class of a request's HTTP status code, as reported to [`Metrics`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum StatusClass {
+    /// 1xx
+    Informational,
+    /// 2xx
+    Success,
+    /// 3xx, which includes the *308 Resume Incomplete* of resumable uploads
+    Redirection,
+    /// 4xx
+    ClientError,
+    /// 5xx
+    ServerError,
+    /// There was no response, as the connection failed
+    NoResponse,
+}
+
+impl StatusClass {
+    pub fn of<B>(result: &hyper::Result<hyper::Response<B>>) -> Self {
+        let status = match result {
+            Ok(res) => res.status(),
+            Err(_) => return StatusClass::NoResponse,
+        };
+        if status.is_informational() {
+            StatusClass::Informational
+        } else if status.is_success() {
+            StatusClass::Success
+        } else if status.is_redirection() {
+            StatusClass::Redirection
+        } else if status.is_client_error() {
+            StatusClass::ClientError
+        } else {
+            StatusClass::ServerError
+        }
+    }
+
+    /// Returns a label like `2xx`, or `none` if there was no response.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StatusClass::Informational => "1xx",
+            StatusClass::Success => "2xx",
+            StatusClass::Redirection => "3xx",
+            StatusClass::ClientError => "4xx",
+            StatusClass::ServerError => "5xx",
+            StatusClass::NoResponse => "none",
+        }
+    }
+
+    /// Returns true for client and server errors, and if there was no response.
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            StatusClass::ClientError | StatusClass::ServerError | StatusClass::NoResponse
+        )
     }
 }
 
@@ -227,6 +726,8 @@ pub struct DefaultDelegate;
 
 impl Delegate for DefaultDelegate {}
 
+/// The error of calls to all APIs, which API crates re-export, so that one function can handle
+/// the errors of calls to any of them.
 #[derive(Debug)]
 pub enum Error {
     /// The http connection failed
@@ -262,6 +763,87 @@ pub enum Error {
 
     /// An IO error occurred while reading a stream into memory
     Io(std::io::Error),
+
+    /// Signing a URL failed, or it was asked to be signed with invalid parameters
+    Signing(Box<dyn StdError + Send + Sync>),
+
+    /// The URL of the call couldn't be built from the path template of its method and the base
+    /// URL of the hub
+    InvalidUrl(uri_template::UriTemplateError),
+
+    /// The request of the call couldn't be built, as a header value like the user agent of the
+    /// hub, or one added by the delegate, isn't valid
+    InvalidRequest(hyper::http::Error),
+
+    /// The body of the response is larger than the maximum response size of the hub, in field `.0`
+    ResponseTooLarge(u64),
+
+    /// Shows that we failed to decode the protobuf response of a call made with `alt=proto`.
+    ProtoDecodeError(Box<dyn StdError + Send + Sync>),
+
+    /// The API has no method with the id of field `.0`
+    UnknownMethod(String),
+
+    /// The call didn't complete within its timeout, in field `.0`
+    Deadline(Duration),
+
+    /// A call through the `grpc` transport failed, with the `tonic::Status` of
+    /// the call or the error of the connection in field `.0`
+    Grpc(Box<dyn StdError + Send + Sync>),
+}
+
+impl Error {
+    /// Returns the HTTP status the server answered the call with, if it did.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::Failure(response) => Some(response.status()),
+            Error::BadRequest(err) => err["error"]["code"]
+                .as_u64()
+                .and_then(|code| u16::try_from(code).ok())
+                .and_then(|code| StatusCode::from_u16(code).ok()),
+            _ => None,
+        }
+    }
+
+    /// Returns the canonical error code of Google APIs, like `NOT_FOUND`, of [`Error::status()`],
+    /// `DEADLINE_EXCEEDED` if the call timed out, or `UNAVAILABLE` if there was no response.
+    pub fn canonical_code(&self) -> &'static str {
+        match self {
+            Error::Deadline(_) => "DEADLINE_EXCEEDED",
+            _ => canonical_code(self.status()),
+        }
+    }
+
+    /// Returns the reason the server gave for the error, like `rateLimitExceeded`, from the
+    /// `ErrorInfo` details of the error or its first error, if it gave one.
+    pub fn reason(&self) -> Option<&str> {
+        let error = match self {
+            Error::BadRequest(err) => &err["error"],
+            _ => return None,
+        };
+        error["details"]
+            .as_array()
+            .and_then(|details| details.iter().find_map(|detail| detail["reason"].as_str()))
+            .or_else(|| error["errors"][0]["reason"].as_str())
+    }
+
+    /// Returns the message the server gave for the error, if it gave one.
+    pub fn message(&self) -> Option<&str> {
+        match self {
+            Error::BadRequest(err) => err["error"]["message"].as_str(),
+            _ => None,
+        }
+    }
+
+    /// Returns how long the server asked to wait before retrying the call, from the
+    /// `Retry-After` header of the response or the `RetryInfo` details of the error, if it did.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::Failure(response) => retry::retry_after_header(response.headers()),
+            Error::BadRequest(err) => retry::retry_info_delay(err),
+            _ => None,
+        }
+    }
 }
 
 impl Display for Error {
@@ -296,6 +878,22 @@ impl Display for Error {
             Error::Failure(response) => {
                 writeln!(f, "Http status indicates failure: {:?}", response)
             }
+            Error::Signing(err) => writeln!(f, "Signing failed: {}", err),
+            Error::InvalidUrl(err) => writeln!(f, "Invalid URL: {}", err),
+            Error::InvalidRequest(err) => writeln!(f, "Invalid request: {}", err),
+            Error::ResponseTooLarge(max_size) => writeln!(
+                f,
+                "The response body exceeds the maximum response size of {} bytes",
+                max_size
+            ),
+            Error::ProtoDecodeError(err) => {
+                writeln!(f, "Failed to decode the protobuf response: {}", err)
+            }
+            Error::UnknownMethod(id) => writeln!(f, "The API has no method '{}'", id),
+            Error::Deadline(timeout) => {
+                writeln!(f, "The call didn't complete within {:?}", timeout)
+            }
+            Error::Grpc(err) => writeln!(f, "The gRPC call failed: {}", err),
         }
     }
 }
@@ -323,6 +921,105 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub struct MethodInfo {
     pub id: &'static str,
     pub http_method: Method,
+    /// The `requestId` set on the call, for methods supporting one. Servers use it to
+    /// deduplicate retried requests, and it appears in their logs.
+    pub request_id: Option<String>,
+}
+
+/// The values a call was built with, as returned by the `into_parts()` method of call builders.
+///
+/// It allows tests to check that a call was built correctly, without making it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallParts {
+    pub method_id: &'static str,
+    pub http_method: Method,
+    /// The URL of the request, with the path parameters substituted and the query parameters
+    pub url: String,
+    /// The parameters substituted into the path of the URL, by name
+    pub path_params: Vec<(&'static str, String)>,
+    /// The parameters of the query, in the order they are sent
+    pub query_params: Vec<(String, String)>,
+    /// The JSON request body, without `null` values
+    pub request: Option<json::Value>,
+    pub scopes: BTreeSet<String>,
+}
+
+impl CallParts {
+    /// Returns the value of the path parameter `name`, if set.
+    pub fn path_param(&self, name: &str) -> Option<&str> {
+        self.path_params
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the first value of the query parameter `name`, if set.
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query_params
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the request of the call, to make it with an executor other than the hub, like
+    /// `reqwest` or the fetch API of browsers.
+    ///
+    /// The executor has to authorize the request, with a token for one of the `scopes`, or with
+    /// the `key` query parameter if there are none, and decode the JSON response.
+    pub fn to_http_request(&self) -> hyper::http::Result<hyper::http::Request<Vec<u8>>> {
+        let builder = hyper::http::Request::builder()
+            .method(self.http_method.clone())
+            .uri(self.url.as_str());
+        let json = self
+            .request
+            .as_ref()
+            .map(|value| json::to_vec(value).expect("serde to work"));
+        exec::http_request(builder, json)
+    }
+}
+
+/// Parses the responses of a call whose request was built by the `build_request()` method of its
+/// call builder, and sent without the hub, like through a message queue or a replay engine.
+pub struct ResponseParser<T> {
+    _value: std::marker::PhantomData<fn() -> T>,
+}
+
+impl<T> Default for ResponseParser<T> {
+    fn default() -> Self {
+        ResponseParser {
+            _value: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<T: ::serde::de::DeserializeOwned + Default> ResponseParser<T> {
+    /// Returns the response and its decoded body, or the error `doit()` would return for it.
+    ///
+    /// An empty body is decoded into the default value.
+    pub fn parse(
+        &self,
+        res: hyper::Response<hyper::body::Bytes>,
+    ) -> Result<(hyper::Response<hyper::body::Bytes>, T)> {
+        if !res.status().is_success() {
+            return Err(match json::from_slice(res.body()) {
+                Ok(error_value) => Error::BadRequest(error_value),
+                Err(_) => {
+                    let (parts, body) = res.into_parts();
+                    Error::Failure(hyper::Response::from_parts(parts, hyper::Body::from(body)))
+                }
+            });
+        }
+        if res.body().iter().all(u8::is_ascii_whitespace) {
+            return Ok((res, T::default()));
+        }
+        match decode_json_slice(res.body()) {
+            Ok(decoded) => Ok((res, decoded)),
+            Err(err) => {
+                let body = String::from_utf8_lossy(res.body()).into_owned();
+                Err(Error::JsonDecodeError(body, err))
+            }
+        }
+    }
 }
 
 const BOUNDARY: &str = "MDuXWGyeE33QFXGchb2VFWc4Z7945d";
@@ -336,6 +1033,7 @@ pub struct MultiPartReader<'a> {
     raw_parts: Vec<(HeaderMap, &'a mut (dyn Read + Send))>,
     current_part: Option<(Cursor<Vec<u8>>, &'a mut (dyn Read + Send))>,
     last_part_boundary: Option<Cursor<Vec<u8>>>,
+    first_part_started: bool,
 }
 
 impl<'a> MultiPartReader<'a> {
@@ -414,12 +1112,17 @@ impl<'a> Read for MultiPartReader<'a> {
             (n, true, _) if n > 0 => {
                 let (headers, reader) = self.raw_parts.remove(0);
                 let mut c = Cursor::new(Vec::<u8>::new());
-                //TODO: The first line ending should be omitted for the first part,
-                // fortunately Google's API serves don't seem to mind.
+                // The line ending belongs to the boundary delimiter, which the first part doesn't have.
+                let delimiter = if self.first_part_started {
+                    LINE_ENDING
+                } else {
+                    ""
+                };
+                self.first_part_started = true;
                 (write!(
                     &mut c,
                     "{}--{}{}{}{}{}",
-                    LINE_ENDING,
+                    delimiter,
                     BOUNDARY,
                     LINE_ENDING,
                     headers
@@ -559,23 +1262,46 @@ impl ContentRange {
 pub struct RangeResponseHeader(pub Chunk);
 
 impl RangeResponseHeader {
-    fn from_bytes(raw: &[u8]) -> Self {
-        if !raw.is_empty() {
-            if let Ok(s) = std::str::from_utf8(raw) {
-                const PREFIX: &str = "bytes ";
-                if let Some(stripped) = s.strip_prefix(PREFIX) {
-                    if let Ok(c) = <Chunk as FromStr>::from_str(stripped) {
-                        return RangeResponseHeader(c);
-                    }
-                }
-            }
-        }
+    /// Parses `bytes=%i-%i`, as sent by the server, as well as `bytes %i-%i`.
+    fn from_bytes(raw: &[u8]) -> Option<Self> {
+        let s = std::str::from_utf8(raw).ok()?;
+        let range = s
+            .strip_prefix("bytes=")
+            .or_else(|| s.strip_prefix("bytes "))?;
+        <Chunk as FromStr>::from_str(range)
+            .ok()
+            .map(RangeResponseHeader)
+    }
+}
+
+/// Returns the amount of bytes the server persisted of a resumable upload, as indicated
+/// by the `Range` header of a *308 Resume Incomplete* response.
+/// Without such header, nothing was persisted yet.
+fn committed_bytes(headers: &HeaderMap) -> u64 {
+    headers
+        .get("Range")
+        .and_then(|h| RangeResponseHeader::from_bytes(h.as_bytes()))
+        .map_or(0, |h| h.0.last + 1)
+}
 
-        panic!("Unable to parse Range header {:?}", raw)
+/// The smallest chunk size accepted by the server for resumable uploads.
+/// All chunks but the last one must be a multiple of it.
+pub const MIN_CHUNK_SIZE: u64 = 1 << 18;
+
+/// Returns the given chunk size rounded up to the next multiple of [`MIN_CHUNK_SIZE`].
+fn valid_chunk_size(chunk_size: u64) -> u64 {
+    match chunk_size % MIN_CHUNK_SIZE {
+        _ if chunk_size < MIN_CHUNK_SIZE => MIN_CHUNK_SIZE,
+        0 => chunk_size,
+        remainder => chunk_size + (MIN_CHUNK_SIZE - remainder),
     }
 }
 
 /// A utility type to perform a resumable upload from start to end.
+///
+/// Chunks are uploaded sequentially, as the server only accepts the chunk following the bytes it
+/// has. Uploads are parallelized by splitting the media into several objects instead, like the
+/// `composite_upload()` of the storage API does.
 pub struct ResumableUploadHelper<'a, A: 'a, S>
 where
     S: tower_service::Service<Uri> + Clone + Send + Sync + 'static,
@@ -589,11 +1315,15 @@ where
     pub start_at: Option<u64>,
     pub auth: &'a A,
     pub user_agent: &'a str,
+    /// The value of the [`API_CLIENT_HEADER`]
+    pub api_client: &'a str,
     pub auth_header: String,
     pub url: &'a str,
-    pub reader: &'a mut dyn ReadSeek,
+    pub reader: &'a mut dyn AsyncReadSeek,
     pub media_type: Mime,
     pub content_length: u64,
+    /// Overrides `Delegate::chunk_size()` if set.
+    pub chunk_size: Option<u64>,
 }
 impl<'a, A, S> ResumableUploadHelper<'a, A, S>
 where
@@ -603,88 +1333,103 @@ where
     S::Future: Send + Unpin + 'static,
     S::Error: Into<Box<dyn StdError + Send + Sync>>,
 {
+    /// Returns the amount of bytes the server has, or the result to end the upload with if it
+    /// completed already or the status can't be queried. `retries` counts the retries of the
+    /// upload, which include those of this query.
     async fn query_transfer_status(
         &mut self,
-    ) -> std::result::Result<u64, hyper::Result<hyper::Response<hyper::body::Body>>> {
+        retries: &mut u32,
+    ) -> std::result::Result<u64, Result<hyper::Response<hyper::body::Body>>> {
         loop {
-            match self
-                .client
-                .request(
-                    hyper::Request::builder()
-                        .method(hyper::Method::POST)
-                        .uri(self.url)
-                        .header(USER_AGENT, self.user_agent.to_string())
-                        .header(
-                            "Content-Range",
-                            ContentRange {
-                                range: None,
-                                total_length: self.content_length,
-                            }
-                            .header_value(),
-                        )
-                        .header(AUTHORIZATION, self.auth_header.clone())
-                        .body(hyper::body::Body::empty())
-                        .unwrap(),
-                )
-                .await
+            let builder = hyper::Request::builder()
+                .method(hyper::Method::POST)
+                .uri(self.url)
+                .header(USER_AGENT, self.user_agent.to_string())
+                .header(API_CLIENT_HEADER, self.api_client)
+                .header(
+                    "Content-Range",
+                    ContentRange {
+                        range: None,
+                        total_length: self.content_length,
+                    }
+                    .header_value(),
+                );
+            let request = match prepare_request(self.delegate, builder)
+                .header(AUTHORIZATION, self.auth_header.clone())
+                .body(hyper::body::Body::empty())
             {
+                Ok(request) => request,
+                Err(err) => return Err(Err(Error::InvalidRequest(err))),
+            };
+            match self.client.request(request).await {
                 Ok(r) => {
                     // 308 = resume-incomplete == PermanentRedirect
-                    let headers = r.headers().clone();
-                    let h: RangeResponseHeader = match headers.get("Range") {
-                        Some(hh) if r.status() == StatusCode::PERMANENT_REDIRECT => {
-                            RangeResponseHeader::from_bytes(hh.as_bytes())
-                        }
-                        None | Some(_) => {
-                            if let Retry::After(d) = self.delegate.http_failure(&r, None) {
-                                sleep(d).await;
-                                continue;
-                            }
-                            return Err(Ok(r));
-                        }
-                    };
-                    return Ok(h.0.last);
+                    if r.status() == StatusCode::PERMANENT_REDIRECT {
+                        return Ok(committed_bytes(r.headers()));
+                    }
+                    // the upload was completed already, which is what the caller is after
+                    if r.status().is_success() {
+                        return Err(Ok(r));
+                    }
+                    if let Retry::After(d) = self.delegate.http_failure(&r, None) {
+                        *retries += 1;
+                        self.delegate.retry_scheduled(*retries, d);
+                        sleep(d).await;
+                        continue;
+                    }
+                    return Err(Ok(r));
                 }
                 Err(err) => {
                     if let Retry::After(d) = self.delegate.http_error(&err) {
+                        *retries += 1;
+                        self.delegate.retry_scheduled(*retries, d);
                         sleep(d).await;
                         continue;
                     }
-                    return Err(Err(err));
+                    return Err(Err(Error::HttpError(err)));
                 }
             }
         }
     }
 
-    /// returns None if operation was cancelled by delegate, or the HttpResult.
+    /// returns None if operation was cancelled by delegate, or the result of the last request.
     /// It can be that we return the result just because we didn't understand the status code -
-    /// caller should check for status himself before assuming it's OK to use
-    pub async fn upload(&mut self) -> Option<hyper::Result<hyper::Response<hyper::body::Body>>> {
+    /// caller should check for status himself before assuming it's OK to use.
+    /// Failing to read the media yields `Error::Io`, requests which can't be built, like those with
+    /// an invalid upload URL, `Error::InvalidRequest`, and failed requests `Error::HttpError`.
+    pub async fn upload(&mut self) -> Option<Result<hyper::Response<hyper::body::Body>>> {
+        use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+        let mut retries = 0;
+
         let mut start = match self.start_at {
             Some(s) => s,
-            None => match self.query_transfer_status().await {
+            None => match self.query_transfer_status(&mut retries).await {
                 Ok(s) => s,
                 Err(result) => return Some(result),
             },
         };
 
-        const MIN_CHUNK_SIZE: u64 = 1 << 18;
-        let chunk_size = match self.delegate.chunk_size() {
-            cs if cs > MIN_CHUNK_SIZE => cs,
-            _ => MIN_CHUNK_SIZE,
-        };
+        let chunk_size = valid_chunk_size(
+            self.chunk_size
+                .unwrap_or_else(|| self.delegate.chunk_size()),
+        );
 
         loop {
-            self.reader.seek(SeekFrom::Start(start)).unwrap();
+            if let Err(err) = self.reader.seek(SeekFrom::Start(start)).await {
+                return Some(Err(Error::Io(err)));
+            }
 
             let request_size = match self.content_length - start {
                 rs if rs > chunk_size => chunk_size,
                 rs => rs,
             };
 
-            let mut section_reader = self.reader.take(request_size);
+            let mut section_reader = (&mut self.reader).take(request_size);
             let mut req_bytes = vec![];
-            section_reader.read_to_end(&mut req_bytes).unwrap();
+            if let Err(err) = section_reader.read_to_end(&mut req_bytes).await {
+                return Some(Err(Error::Io(err)));
+            }
             let range_header = ContentRange {
                 range: Some(Chunk {
                     first: start,
@@ -693,33 +1438,53 @@ where
                 total_length: self.content_length,
             };
             if self.delegate.cancel_chunk_upload(&range_header) {
+                if self.delegate.delete_cancelled_upload() {
+                    // the session expires on its own if this fails, there is nothing else to do
+                    cancel_upload(
+                        self.client,
+                        self.delegate,
+                        self.url,
+                        self.user_agent,
+                        self.api_client,
+                    )
+                    .await
+                    .ok();
+                    self.delegate.store_upload_url(None);
+                }
                 return None;
             }
-            let res = self
-                .client
-                .request(
-                    hyper::Request::builder()
-                        .uri(self.url)
-                        .method(hyper::Method::POST)
-                        .header("Content-Range", range_header.header_value())
-                        .header(CONTENT_TYPE, format!("{}", self.media_type))
-                        .header(USER_AGENT, self.user_agent.to_string())
-                        .body(hyper::body::Body::from(req_bytes))
-                        .unwrap(),
-                )
-                .await;
+            let request_started = std::time::Instant::now();
+            let builder = hyper::Request::builder()
+                .uri(self.url)
+                .method(hyper::Method::POST)
+                .header("Content-Range", range_header.header_value())
+                .header(CONTENT_TYPE, format!("{}", self.media_type))
+                .header(USER_AGENT, self.user_agent.to_string())
+                .header(API_CLIENT_HEADER, self.api_client);
+            let request = match prepare_request(self.delegate, builder)
+                .body(hyper::body::Body::from(req_bytes))
+            {
+                Ok(request) => request,
+                Err(err) => return Some(Err(Error::InvalidRequest(err))),
+            };
+            let res = self.client.request(request).await;
+            self.delegate.attempt_completed(
+                res.as_ref().ok().map(|res| res.status()),
+                request_started.elapsed(),
+            );
             match res {
                 Ok(res) => {
-                    start += request_size;
-
                     if res.status() == StatusCode::PERMANENT_REDIRECT {
+                        // the server may have persisted less than we sent
+                        start = committed_bytes(res.headers());
+                        self.delegate.upload_progress(start, self.content_length);
                         continue;
                     }
 
                     let (res_parts, res_body) = res.into_parts();
                     let res_body = match hyper::body::to_bytes(res_body).await {
                         Ok(res_body) => res_body.into_iter().collect(),
-                        Err(err) => return Some(Err(err)),
+                        Err(err) => return Some(Err(Error::HttpError(err))),
                     };
                     let res_body_string: String = String::from_utf8(res_body).unwrap();
                     let reconstructed_result =
@@ -730,24 +1495,94 @@ where
                             &reconstructed_result,
                             json::from_str(&res_body_string).ok(),
                         ) {
+                            retries += 1;
+                            self.delegate.retry_scheduled(retries, d);
                             sleep(d).await;
+                            start = match self.query_transfer_status(&mut retries).await {
+                                Ok(s) => s,
+                                Err(result) => return Some(result),
+                            };
                             continue;
                         }
+                    } else {
+                        self.delegate
+                            .upload_progress(self.content_length, self.content_length);
                     }
                     return Some(Ok(reconstructed_result));
                 }
                 Err(err) => {
                     if let Retry::After(d) = self.delegate.http_error(&err) {
+                        retries += 1;
+                        self.delegate.retry_scheduled(retries, d);
                         sleep(d).await;
+                        // the chunk may have been transferred partially, continue after the last
+                        // byte the server has
+                        start = match self.query_transfer_status(&mut retries).await {
+                            Ok(s) => s,
+                            Err(result) => return Some(result),
+                        };
                         continue;
                     }
-                    return Some(Err(err));
+                    return Some(Err(Error::HttpError(err)));
                 }
             }
         }
     }
 }
 
+/// Cancels the resumable upload session at `upload_url`, as obtained through
+/// `Delegate::store_upload_url()`, so it doesn't linger on the server until it expires.
+/// The server answers with status *499 Client Closed Request* on success.
+///
+/// The request is made like those of the upload, so `delegate` may add headers to it and retry
+/// it, and is told about each attempt.
+pub async fn cancel_upload<S>(
+    client: &hyper::client::Client<S, hyper::body::Body>,
+    delegate: &mut dyn Delegate,
+    upload_url: &str,
+    user_agent: &str,
+    api_client: &str,
+) -> Result<hyper::Response<hyper::body::Body>>
+where
+    S: tower_service::Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response:
+        hyper::client::connect::Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    let mut retries = 0;
+    loop {
+        let builder = hyper::Request::builder()
+            .method(hyper::Method::DELETE)
+            .uri(upload_url)
+            .header(USER_AGENT, user_agent.to_string())
+            .header(API_CLIENT_HEADER, api_client)
+            .header(CONTENT_LENGTH, 0);
+        let request = prepare_request(delegate, builder)
+            .body(hyper::body::Body::empty())
+            .map_err(Error::InvalidRequest)?;
+        let request_started = std::time::Instant::now();
+        let res = client.request(request).await;
+        delegate.attempt_completed(
+            res.as_ref().ok().map(|res| res.status()),
+            request_started.elapsed(),
+        );
+        let retry = match &res {
+            Ok(res) if res.status().as_u16() == 499 || res.status().is_success() => Retry::Abort,
+            Ok(res) => delegate.http_failure(res, None),
+            Err(err) => delegate.http_error(err),
+        };
+        match retry {
+            Retry::After(d) => {
+                retries += 1;
+                delegate.retry_scheduled(retries, d);
+                sleep(d).await;
+            }
+            Retry::Abort => return res.map_err(Error::HttpError),
+        }
+    }
+}
+
 // TODO(ST): Allow sharing common code between program types
 pub fn remove_json_null_values(value: &mut json::value::Value) {
     match value {
@@ -763,6 +1598,139 @@ pub fn remove_json_null_values(value: &mut json::value::Value) {
     }
 }
 
+/// Sorts the keys of all objects in `value`, so that its serialization doesn't depend on whether
+/// `serde_json` preserves the insertion order, which follows `HashMap` iteration for maps.
+pub fn sort_json_keys(value: &mut json::value::Value) {
+    match value {
+        json::value::Value::Object(map) => {
+            let mut entries: Vec<_> = std::mem::take(map).into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            for (key, mut value) in entries {
+                sort_json_keys(&mut value);
+                map.insert(key, value);
+            }
+        }
+        json::value::Value::Array(arr) => arr.iter_mut().for_each(sort_json_keys),
+        _ => {}
+    }
+}
+
+/// The log target of the request and response logs, see [`log_request()`].
+pub const LOG_TARGET: &str = "google_apis_common::http";
+
+/// Bodies are truncated to this amount of bytes when logged.
+const MAX_LOGGED_BODY_LEN: usize = 1024;
+pub(crate) const REDACTED: &str = "<redacted>";
+/// Query parameters carrying credentials
+pub(crate) const SENSITIVE_QUERY_PARAMS: &[&str] = &["key", "access_token"];
+
+/// Logs `req` at debug level to [`LOG_TARGET`], if enabled, and returns it.
+///
+/// Credentials in headers and query parameters are redacted, and the body is truncated.
+/// To log it, the body is buffered, which is why this should only be used with bodies
+/// which are in memory already.
+pub async fn log_request(
+    req: hyper::Request<hyper::body::Body>,
+) -> hyper::Request<hyper::body::Body> {
+    if !log::log_enabled!(target: LOG_TARGET, log::Level::Debug) {
+        return req;
+    }
+    let (parts, body) = req.into_parts();
+    let body = hyper::body::to_bytes(body).await.unwrap_or_default();
+    log::debug!(
+        target: LOG_TARGET,
+        "{} {}\n{}{}",
+        parts.method,
+        redact_uri(&parts.uri),
+        format_headers(&parts.headers),
+        truncate_body(&body)
+    );
+    hyper::Request::from_parts(parts, body.into())
+}
+
+/// Logs status and headers of `res` at debug level to [`LOG_TARGET`], if enabled.
+/// Credentials in headers are redacted.
+pub fn log_response<B>(res: &hyper::Response<B>) {
+    log::debug!(
+        target: LOG_TARGET,
+        "{:?} {}\n{}",
+        res.version(),
+        res.status(),
+        format_headers(res.headers())
+    );
+}
+
+/// Logs the body of a response at debug level to [`LOG_TARGET`], if enabled. It is truncated.
+pub fn log_response_body<B: AsRef<[u8]> + ?Sized>(body: &B) {
+    log::debug!(target: LOG_TARGET, "{}", truncate_body(body.as_ref()));
+}
+
+/// Returns method, URL, headers and body of `req`, like a request built by the `build_request()`
+/// method of a call builder, to show which request a call would send. Credentials in headers and
+/// query parameters are redacted, and JSON bodies are pretty-printed.
+pub fn describe_request<B: AsRef<[u8]>>(req: &hyper::Request<B>) -> String {
+    let body = req.body().as_ref();
+    let body = match json::from_slice::<json::Value>(body) {
+        Ok(value) => json::to_string_pretty(&value).expect("serde to work"),
+        Err(_) => String::from_utf8_lossy(body).into_owned(),
+    };
+    format!(
+        "{} {}\n{}\n{}",
+        req.method(),
+        redact_uri(req.uri()),
+        format_headers(req.headers()),
+        body
+    )
+}
+
+pub(crate) fn is_sensitive_header(name: &hyper::header::HeaderName) -> bool {
+    name == AUTHORIZATION
+        || name == hyper::header::PROXY_AUTHORIZATION
+        || name == hyper::header::COOKIE
+        || name.as_str() == "x-goog-api-key"
+}
+
+fn format_headers(headers: &HeaderMap) -> String {
+    headers
+        .iter()
+        .map(|(k, v)| {
+            let value = if is_sensitive_header(k) {
+                REDACTED
+            } else {
+                v.to_str().unwrap_or("<binary>")
+            };
+            format!("{}: {}\n", k, value)
+        })
+        .collect()
+}
+
+pub(crate) fn redact_uri(uri: &Uri) -> String {
+    let uri = uri.to_string();
+    let (path, query) = match uri.split_once('?') {
+        Some(parts) => parts,
+        None => return uri,
+    };
+    let query = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((k, _)) if SENSITIVE_QUERY_PARAMS.contains(&k) => format!("{}={}", k, REDACTED),
+            _ => pair.to_string(),
+        })
+        .join("&");
+    format!("{}?{}", path, query)
+}
+
+fn truncate_body(body: &[u8]) -> String {
+    if body.len() <= MAX_LOGGED_BODY_LEN {
+        return String::from_utf8_lossy(body).into_owned();
+    }
+    format!(
+        "{}... ({} bytes total)",
+        String::from_utf8_lossy(&body[..MAX_LOGGED_BODY_LEN]),
+        body.len()
+    )
+}
+
 // Borrowing the body object as mutable and converts it to a string
 pub async fn get_body_as_string(res_body: &mut hyper::Body) -> String {
     let res_body_buf = hyper::body::to_bytes(res_body).await.unwrap();
@@ -770,6 +1738,272 @@ pub async fn get_body_as_string(res_body: &mut hyper::Body) -> String {
     res_body_string.to_string()
 }
 
+/// Reads the body of a response into one buffer, which responses to be decoded are read into
+/// instead of a `String`, as [`decode_json_slice()`] doesn't need one.
+pub async fn get_body_as_bytes(res_body: &mut hyper::Body) -> hyper::Result<hyper::body::Bytes> {
+    hyper::body::to_bytes(res_body).await
+}
+
+/// Reads the body of a response like [`get_body_as_bytes()`], but fails with
+/// [`Error::ResponseTooLarge`] as soon as it's known to be larger than `max_bytes`, if set, so
+/// that the rest of it is never buffered.
+pub async fn get_body_as_bytes_limited(
+    res_body: &mut hyper::Body,
+    max_bytes: Option<u64>,
+) -> Result<hyper::body::Bytes> {
+    use hyper::body::HttpBody;
+
+    let max_bytes = match max_bytes {
+        Some(max_bytes) => max_bytes,
+        None => return get_body_as_bytes(res_body).await.map_err(Error::HttpError),
+    };
+    // The lower bound is the length of bodies with a `Content-Length` header
+    if res_body.size_hint().lower() > max_bytes {
+        return Err(Error::ResponseTooLarge(max_bytes));
+    }
+    let mut buf = Vec::new();
+    while let Some(chunk) = res_body.data().await {
+        let chunk = chunk.map_err(Error::HttpError)?;
+        if (buf.len() + chunk.len()) as u64 > max_bytes {
+            return Err(Error::ResponseTooLarge(max_bytes));
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(buf.into())
+}
+
+/// Decodes a JSON response body.
+///
+/// With the `simd-json` feature, the body is parsed with simd-json, which is considerably faster
+/// on large bodies, and only if that fails once more with serde_json, whose error is the one
+/// reported.
+pub fn decode_json<T: ::serde::de::DeserializeOwned>(body: &str) -> json::Result<T> {
+    decode_json_slice(body.as_bytes())
+}
+
+/// Decodes a JSON response body from its bytes, like [`decode_json()`].
+///
+/// Strings are only validated as UTF-8 while they are decoded, so the body doesn't have to be
+/// copied into a `String` and validated as a whole first.
+pub fn decode_json_slice<T: ::serde::de::DeserializeOwned>(body: &[u8]) -> json::Result<T> {
+    #[cfg(feature = "simd-json")]
+    {
+        let mut buf = body.to_vec();
+        if let Ok(decoded) = simd_json::serde::from_slice(&mut buf) {
+            return Ok(decoded);
+        }
+    }
+    json::from_slice(body)
+}
+
+/// Returns the value of the `Content-Length` header, if present and valid.
+pub fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+}
+
+/// Returns true if the body of the response with the given `headers` is gzip-compressed,
+/// i.e. it was served with `Content-Encoding: gzip`.
+///
+/// Objects uploaded with that encoding are served decompressed by Google Cloud Storage
+/// (*decompressive transcoding*), unless the request accepts gzip or the object prohibits
+/// transformations through `Cache-Control: no-transform`.
+pub fn is_gzip_encoded(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(',').any(|e| e.trim().eq_ignore_ascii_case("gzip")))
+        .unwrap_or(false)
+}
+
+/// Returns true if the body of a media download with the given `headers` is decompressed, which
+/// it is if it is gzip-compressed, `keep_encoding` is not set and the `gzip` feature is enabled.
+pub fn decodes_gzip(headers: &HeaderMap, keep_encoding: bool) -> bool {
+    cfg!(feature = "gzip") && !keep_encoding && is_gzip_encoded(headers)
+}
+
+/// Returns the amount of bytes a media download will produce, if known.
+///
+/// Gzip-compressed bodies are decoded as described in [`decodes_gzip()`]. The `Content-Length`
+/// header of such responses describes the compressed body, which is why the size of decoded media
+/// is unknown. The same is true for media transcoded by the server, which are sent without
+/// `Content-Length`.
+pub fn download_size(headers: &HeaderMap, keep_encoding: bool) -> Option<u64> {
+    if decodes_gzip(headers, keep_encoding) {
+        return None;
+    }
+    content_length(headers)
+}
+
+#[cfg(feature = "gzip")]
+fn gunzip(decoder: &mut GzDecoder<Vec<u8>>, chunk: &[u8]) -> io::Result<hyper::body::Bytes> {
+    decoder.write_all(chunk)?;
+    Ok(std::mem::take(decoder.get_mut()).into())
+}
+
+/// An [`AsyncRead`] view over a response body, reading its chunks as they arrive.
+///
+/// The body is still available as byte stream through [`BodyReader::into_inner()`].
+pub struct BodyReader {
+    body: hyper::body::Body,
+    chunk: hyper::body::Bytes,
+    #[cfg(feature = "gzip")]
+    decoder: Option<GzDecoder<Vec<u8>>>,
+}
+
+impl BodyReader {
+    pub fn new(body: hyper::body::Body) -> Self {
+        BodyReader {
+            body,
+            chunk: hyper::body::Bytes::new(),
+            #[cfg(feature = "gzip")]
+            decoder: None,
+        }
+    }
+
+    /// Reads the gzip-compressed `body` decompressed.
+    #[cfg(feature = "gzip")]
+    pub fn gunzip(body: hyper::body::Body) -> Self {
+        BodyReader {
+            decoder: Some(GzDecoder::new(Vec::new())),
+            ..BodyReader::new(body)
+        }
+    }
+
+    /// Returns the remaining body. Bytes of a partially read chunk are lost.
+    pub fn into_inner(self) -> hyper::body::Body {
+        self.body
+    }
+}
+
+impl AsyncRead for BodyReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        while this.chunk.is_empty() {
+            match Pin::new(&mut this.body).poll_data(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    #[cfg(feature = "gzip")]
+                    let chunk = match this.decoder.as_mut() {
+                        Some(decoder) => gunzip(decoder, &chunk)?,
+                        None => chunk,
+                    };
+                    this.chunk = chunk;
+                }
+                Poll::Ready(Some(Err(err))) => {
+                    return Poll::Ready(Err(io::Error::new(io::ErrorKind::Other, err)))
+                }
+                Poll::Ready(None) => {
+                    #[cfg(feature = "gzip")]
+                    if let Some(decoder) = this.decoder.take() {
+                        this.chunk = decoder.finish()?.into();
+                        continue;
+                    }
+                    return Poll::Ready(Ok(()));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let n = buf.remaining().min(this.chunk.len());
+        buf.put_slice(&this.chunk.split_to(n));
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Provides the body of a media download as [`BodyReader`], decoding it if it is gzip-compressed
+/// as described in [`decodes_gzip()`].
+///
+/// The `Content-Encoding` and `Content-Length` headers are removed from decoded responses, as
+/// they describe the compressed body.
+pub fn download_reader(
+    res: hyper::Response<hyper::body::Body>,
+    keep_encoding: bool,
+) -> hyper::Response<BodyReader> {
+    if decodes_gzip(res.headers(), keep_encoding) {
+        #[cfg(feature = "gzip")]
+        {
+            let mut res = res;
+            res.headers_mut().remove(CONTENT_ENCODING);
+            res.headers_mut().remove(CONTENT_LENGTH);
+            return res.map(BodyReader::gunzip);
+        }
+    }
+    res.map(BodyReader::new)
+}
+
+/// Writes the body of a media download into `writer` as it arrives, calling
+/// `Delegate::download_progress()` after each received chunk.
+/// Gzip-compressed bodies are written decompressed with the `gzip` feature.
+/// Returns the amount of bytes written.
+pub async fn download_with_progress<W: Write>(
+    mut res: hyper::Response<hyper::body::Body>,
+    writer: &mut W,
+    delegate: &mut dyn Delegate,
+) -> Result<u64> {
+    write_body(&mut res, writer, delegate, 0, false).await
+}
+
+/// Appends the body of a media download to `file`, which is expected to contain the first
+/// `offset` bytes of the media already, e.g. from a previous download that was interrupted.
+/// The response is usually the result of a request with a `Range: bytes={offset}-` header.
+/// Gzip-compressed bodies are written decompressed as described in [`decodes_gzip()`], otherwise
+/// the range applies to the compressed media.
+///
+/// Returns the size of `file` once the body was written completely. If the connection fails
+/// while reading the body, `Error::HttpError` is returned and everything received until then
+/// remains in `file`, allowing the download to be resumed from there.
+pub async fn append_download(
+    res: &mut hyper::Response<hyper::body::Body>,
+    file: &mut std::fs::File,
+    delegate: &mut dyn Delegate,
+    offset: u64,
+    keep_encoding: bool,
+) -> Result<u64> {
+    let len = write_body(res, file, delegate, offset, keep_encoding).await?;
+    file.flush()?;
+    Ok(len)
+}
+
+async fn write_body<W: Write>(
+    res: &mut hyper::Response<hyper::body::Body>,
+    writer: &mut W,
+    delegate: &mut dyn Delegate,
+    offset: u64,
+    keep_encoding: bool,
+) -> Result<u64> {
+    let total = download_size(res.headers(), keep_encoding).map(|len| offset + len);
+    #[cfg(feature = "gzip")]
+    let mut decoder =
+        decodes_gzip(res.headers(), keep_encoding).then(|| GzDecoder::new(Vec::new()));
+    let mut bytes_received = offset;
+    while let Some(chunk) = res.body_mut().data().await {
+        let chunk = chunk.map_err(Error::HttpError)?;
+        #[cfg(feature = "gzip")]
+        let chunk = match decoder.as_mut() {
+            Some(decoder) => gunzip(decoder, &chunk)?,
+            None => chunk,
+        };
+        writer.write_all(&chunk)?;
+        bytes_received += chunk.len() as u64;
+        delegate.download_progress(bytes_received, total);
+    }
+    #[cfg(feature = "gzip")]
+    if let Some(decoder) = decoder {
+        let rest = decoder.finish()?;
+        if !rest.is_empty() {
+            writer.write_all(&rest)?;
+            bytes_received += rest.len() as u64;
+            delegate.download_progress(bytes_received, total);
+        }
+    }
+    Ok(bytes_received)
+}
+
 #[cfg(test)]
 mod test_api {
     use super::*;
@@ -777,6 +2011,7 @@ mod test_api {
     use std::str::FromStr;
 
     use ::serde::{Deserialize, Serialize};
+    use hyper::header::RETRY_AFTER;
 
     use serde_json as json;
 
@@ -811,6 +2046,13 @@ mod test_api {
         let b: Bar = json::from_str(j).unwrap();
         assert_eq!(b.snoo_snoo, "foo");
 
+        let b: Bar = decode_json(j).unwrap();
+        assert_eq!(b.snoo_snoo, "foo");
+        assert!(decode_json::<Bar>("{\"snooSnoo\":").is_err());
+        let b: Bar = decode_json_slice(j.as_bytes()).unwrap();
+        assert_eq!(b.snoo_snoo, "foo");
+        assert!(decode_json_slice::<Bar>(b"{\"snooSnoo\":\"\xff\"}").is_err());
+
         // We can't have unknown fields with structs.
         // #[derive(Default, Serialize, Deserialize)]
         // struct BarOpt {
@@ -829,15 +2071,699 @@ mod test_api {
         )
     }
 
+    #[test]
+    fn committed_bytes_from_range_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(committed_bytes(&headers), 0);
+
+        headers.insert("Range", "bytes=0-262143".parse().unwrap());
+        assert_eq!(committed_bytes(&headers), 262144);
+        headers.insert("Range", "bytes 0-41".parse().unwrap());
+        assert_eq!(committed_bytes(&headers), 42);
+        headers.insert("Range", "garbage".parse().unwrap());
+        assert_eq!(committed_bytes(&headers), 0);
+    }
+
+    #[test]
+    fn status_class_of_response() {
+        let response = |status: u16| {
+            Ok(hyper::Response::builder()
+                .status(status)
+                .body(hyper::Body::empty())
+                .unwrap())
+        };
+        assert_eq!(StatusClass::of(&response(200)), StatusClass::Success);
+        assert_eq!(StatusClass::of(&response(308)), StatusClass::Redirection);
+        assert_eq!(StatusClass::of(&response(404)), StatusClass::ClientError);
+        assert_eq!(StatusClass::of(&response(503)).as_str(), "5xx");
+        assert!(StatusClass::NoResponse.is_error());
+
+        assert_eq!(canonical_code(Some(StatusCode::NOT_FOUND)), "NOT_FOUND");
+        assert_eq!(canonical_code(Some(StatusCode::BAD_GATEWAY)), "INTERNAL");
+        assert_eq!(canonical_code(None), "UNAVAILABLE");
+    }
+
+    #[test]
+    fn status_of_errors() {
+        let failure = Error::Failure(
+            hyper::Response::builder()
+                .status(503)
+                .body(hyper::Body::empty())
+                .unwrap(),
+        );
+        assert_eq!(failure.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+
+        let bad_request = Error::BadRequest(json::json!({
+            "error": { "code": 404, "message": "Not Found" }
+        }));
+        assert_eq!(bad_request.status(), Some(StatusCode::NOT_FOUND));
+        assert_eq!(bad_request.canonical_code(), "NOT_FOUND");
+
+        assert_eq!(Error::Cancelled.status(), None);
+        assert_eq!(Error::Cancelled.canonical_code(), "UNAVAILABLE");
+    }
+
+    #[test]
+    fn details_of_errors() {
+        let rate_limited = Error::BadRequest(json::json!({
+            "error": {
+                "code": 429,
+                "message": "Quota exceeded",
+                "errors": [{ "reason": "rateLimitExceeded" }],
+                "details": [
+                    { "@type": "type.googleapis.com/google.rpc.RetryInfo", "retryDelay": "1.5s" }
+                ]
+            }
+        }));
+        assert_eq!(rate_limited.reason(), Some("rateLimitExceeded"));
+        assert_eq!(rate_limited.message(), Some("Quota exceeded"));
+        assert_eq!(
+            rate_limited.retry_after(),
+            Some(Duration::from_millis(1500))
+        );
+
+        let unavailable = Error::Failure(
+            hyper::Response::builder()
+                .status(503)
+                .header(RETRY_AFTER, "30")
+                .body(hyper::Body::empty())
+                .unwrap(),
+        );
+        assert_eq!(unavailable.retry_after(), Some(Duration::from_secs(30)));
+        assert_eq!(unavailable.reason(), None);
+        assert_eq!(Error::Cancelled.retry_after(), None);
+    }
+
+    #[test]
+    fn logs_are_redacted() {
+        let uri: Uri = "https://storage.googleapis.com/b?alt=json&key=secret&access_token=t"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            redact_uri(&uri),
+            "https://storage.googleapis.com/b?alt=json&key=<redacted>&access_token=<redacted>"
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, "Bearer token".parse().unwrap());
+        headers.insert(CONTENT_TYPE, "application/json".parse().unwrap());
+        assert_eq!(
+            format_headers(&headers),
+            "authorization: <redacted>\ncontent-type: application/json\n"
+        );
+
+        let body = vec![b'a'; MAX_LOGGED_BODY_LEN + 1];
+        assert!(truncate_body(&body)
+            .ends_with(&format!("a... ({} bytes total)", MAX_LOGGED_BODY_LEN + 1)));
+
+        let req = hyper::Request::post("https://storage.googleapis.com/b?key=secret")
+            .header(AUTHORIZATION, "Bearer token")
+            .body(br#"{"name":"a"}"#.to_vec())
+            .unwrap();
+        assert_eq!(
+            describe_request(&req),
+            "POST https://storage.googleapis.com/b?key=<redacted>\nauthorization: <redacted>\n\n{\n  \"name\": \"a\"\n}"
+        );
+    }
+
+    #[test]
+    fn api_client_header_names_rust_and_crate_version() {
+        let value = api_client_header("5.0.2+20230123");
+        assert!(value.starts_with("gl-rust/"));
+        assert!(value.ends_with(" gdcl/5.0.2+20230123"));
+        assert!(hyper::header::HeaderValue::from_str(&value).is_ok());
+    }
+
+    #[test]
+    fn client_tokens_are_appended_once() {
+        let mut value = api_client_header("5.0.2");
+        append_client_token(&mut value, "my-lib/1.0");
+        append_client_token(&mut value, "my-lib/1.0");
+        append_client_token(&mut value, "my app/ü");
+        append_client_token(&mut value, "");
+        assert!(value.ends_with(" gdcl/5.0.2 my-lib/1.0 my_app/_"));
+        assert!(hyper::header::HeaderValue::from_str(&value).is_ok());
+
+        let mut value = String::new();
+        append_client_token(&mut value, "my-lib/1.0");
+        assert_eq!(value, "my-lib/1.0");
+    }
+
+    #[test]
+    fn prepared_requests_keep_reserved_headers() {
+        struct Proxy;
+        impl Delegate for Proxy {
+            fn prepare_request(
+                &mut self,
+                builder: hyper::http::request::Builder,
+            ) -> hyper::http::request::Builder {
+                builder
+                    .uri("https://proxy.example.com/storage/v1/b")
+                    .header("x-proxy-token", "abc")
+                    .header(USER_AGENT, "other")
+                    .header(CONTENT_TYPE, "text/plain")
+            }
+        }
+
+        let builder = hyper::Request::builder()
+            .uri("https://storage.googleapis.com/storage/v1/b")
+            .header(USER_AGENT, "google-api-rust-client/5.0.2");
+        let req = prepare_request(&mut Proxy, builder)
+            .body(hyper::body::Body::empty())
+            .unwrap();
+
+        assert_eq!(req.uri().host(), Some("proxy.example.com"));
+        assert_eq!(req.headers()["x-proxy-token"], "abc");
+        assert_eq!(
+            req.headers().get_all(USER_AGENT).iter().collect::<Vec<_>>(),
+            ["google-api-rust-client/5.0.2"]
+        );
+        assert!(req.headers().get(CONTENT_TYPE).is_none());
+    }
+
+    #[tokio::test]
+    async fn instrumented_calls_keep_their_output() {
+        let call = async { Ok::<_, Error>(42) };
+        assert_eq!(instrument("storage.objects.get", call).await.unwrap(), 42);
+    }
+
+    #[tokio::test]
+    async fn calls_fail_after_their_timeout() {
+        let timeout = Duration::from_millis(10);
+        let hung = futures::future::pending::<Result<()>>();
+        let err = with_timeout(Some(timeout), hung).await.unwrap_err();
+        assert!(matches!(err, Error::Deadline(d) if d == timeout));
+        assert_eq!(err.canonical_code(), "DEADLINE_EXCEEDED");
+
+        let call = async { Ok::<_, Error>(42) };
+        assert_eq!(with_timeout(None, call).await.unwrap(), 42);
+    }
+
+    #[test]
+    fn chunk_size_is_multiple_of_min_chunk_size() {
+        assert_eq!(valid_chunk_size(0), MIN_CHUNK_SIZE);
+        assert_eq!(valid_chunk_size(MIN_CHUNK_SIZE), MIN_CHUNK_SIZE);
+        assert_eq!(valid_chunk_size(MIN_CHUNK_SIZE + 1), 2 * MIN_CHUNK_SIZE);
+        assert_eq!(valid_chunk_size(1 << 23), 1 << 23);
+    }
+
     #[test]
     fn dyn_delegate_is_send() {
         fn with_send(_x: impl Send) {}
 
-        let mut dd = DefaultDelegate::default();
+        let mut dd = DefaultDelegate;
         let dlg: &mut dyn Delegate = &mut dd;
         with_send(dlg);
     }
 
+    #[test]
+    fn unimplemented_calls_are_forwarded() {
+        struct Inner;
+        impl Delegate for Inner {
+            fn chunk_size(&mut self) -> u64 {
+                42
+            }
+        }
+        struct Wrapper(Inner);
+        impl Delegate for Wrapper {
+            fn forward_to(&mut self) -> Option<&mut dyn Delegate> {
+                Some(&mut self.0)
+            }
+
+            fn delete_cancelled_upload(&mut self) -> bool {
+                true
+            }
+        }
+
+        let mut dlg = Wrapper(Inner);
+        assert_eq!(dlg.chunk_size(), 42);
+        assert!(dlg.delete_cancelled_upload());
+        assert!(dlg.upload_url().is_none());
+        assert_eq!(DefaultDelegate.chunk_size(), 1 << 23);
+    }
+
+    #[tokio::test]
+    async fn download_progress_is_reported() {
+        #[derive(Default)]
+        struct ProgressDelegate(Vec<(u64, Option<u64>)>);
+        impl Delegate for ProgressDelegate {
+            fn download_progress(&mut self, bytes_received: u64, total: Option<u64>) {
+                self.0.push((bytes_received, total));
+            }
+        }
+
+        let res = hyper::Response::builder()
+            .header(CONTENT_LENGTH, 11)
+            .body(hyper::body::Body::from("hello world"))
+            .unwrap();
+        let mut dlg = ProgressDelegate::default();
+        let mut out = Vec::new();
+        let written = download_with_progress(res, &mut out, &mut dlg)
+            .await
+            .unwrap();
+
+        assert_eq!(written, 11);
+        assert_eq!(out, b"hello world");
+        assert_eq!(dlg.0.last(), Some(&(11, Some(11))));
+    }
+
+    #[cfg(feature = "gzip")]
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn gzip_encoded_download_is_decoded_without_total() {
+        #[derive(Default)]
+        struct ProgressDelegate(Vec<(u64, Option<u64>)>);
+        impl Delegate for ProgressDelegate {
+            fn download_progress(&mut self, bytes_received: u64, total: Option<u64>) {
+                self.0.push((bytes_received, total));
+            }
+        }
+
+        let compressed = gzip(b"hello world");
+        let response = || {
+            hyper::Response::builder()
+                .header(CONTENT_ENCODING, "gzip")
+                .header(CONTENT_LENGTH, compressed.len())
+                .body(hyper::body::Body::from(compressed.clone()))
+                .unwrap()
+        };
+        let mut dlg = ProgressDelegate::default();
+        let mut out = Vec::new();
+        download_with_progress(response(), &mut out, &mut dlg)
+            .await
+            .unwrap();
+        assert_eq!(out, b"hello world");
+        assert_eq!(dlg.0.last(), Some(&(11, None)));
+
+        let mut raw = Vec::new();
+        let written = write_body(&mut response(), &mut raw, &mut DefaultDelegate, 0, true)
+            .await
+            .unwrap();
+        assert_eq!(raw, compressed);
+        assert_eq!(written, compressed.len() as u64);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn download_reader_decodes_gzip() {
+        use tokio::io::AsyncReadExt;
+
+        let res = hyper::Response::builder()
+            .header(CONTENT_ENCODING, "gzip")
+            .header(CONTENT_LENGTH, 31)
+            .body(hyper::body::Body::from(gzip(b"0123456789")))
+            .unwrap();
+        let mut res = download_reader(res, false);
+        assert_eq!(content_length(res.headers()), None);
+        assert!(!is_gzip_encoded(res.headers()));
+
+        let mut out = Vec::new();
+        res.body_mut().read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, b"0123456789");
+    }
+
+    #[cfg(feature = "cassette")]
+    #[tokio::test]
+    async fn unreadable_upload_media_fail_with_io_error() {
+        struct BrokenMedia;
+        impl AsyncRead for BrokenMedia {
+            fn poll_read(
+                self: Pin<&mut Self>,
+                _cx: &mut Context<'_>,
+                _buf: &mut ReadBuf<'_>,
+            ) -> Poll<io::Result<()>> {
+                Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()))
+            }
+        }
+        impl AsyncSeek for BrokenMedia {
+            fn start_seek(self: Pin<&mut Self>, _position: SeekFrom) -> io::Result<()> {
+                Ok(())
+            }
+            fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+                Poll::Ready(Ok(0))
+            }
+        }
+
+        let connector = cassette::CassetteConnector::replay(cassette::Cassette::default());
+        let client = hyper::Client::builder().build(connector);
+        let mut media = BrokenMedia;
+        let result = ResumableUploadHelper {
+            client: &client,
+            delegate: &mut DefaultDelegate,
+            start_at: Some(0),
+            auth: &(),
+            user_agent: "test",
+            api_client: "test",
+            auth_header: String::new(),
+            url: "https://storage.googleapis.com/upload",
+            reader: &mut media,
+            media_type: "text/plain".parse().unwrap(),
+            content_length: 10,
+            chunk_size: None,
+        }
+        .upload()
+        .await;
+        assert!(matches!(result, Some(Err(Error::Io(_)))));
+    }
+
+    #[cfg(feature = "cassette")]
+    #[tokio::test]
+    async fn invalid_upload_urls_fail_with_invalid_request() {
+        let connector = cassette::CassetteConnector::replay(cassette::Cassette::default());
+        let client = hyper::Client::builder().build(connector);
+        for start_at in [Some(0), None] {
+            let mut media = ReadSeekAdapter::new(Cursor::new(b"0123456789".to_vec()));
+            let result = ResumableUploadHelper {
+                client: &client,
+                delegate: &mut DefaultDelegate,
+                start_at,
+                auth: &(),
+                user_agent: "test",
+                api_client: "test",
+                auth_header: String::new(),
+                url: "https://storage.googleapis.com/an upload",
+                reader: &mut media,
+                media_type: "text/plain".parse().unwrap(),
+                content_length: 10,
+                chunk_size: None,
+            }
+            .upload()
+            .await;
+            assert!(matches!(result, Some(Err(Error::InvalidRequest(_)))));
+        }
+    }
+
+    #[cfg(feature = "cassette")]
+    #[tokio::test]
+    async fn retries_of_the_upload_status_are_scheduled_with_the_delegate() {
+        #[derive(Default)]
+        struct RetryOnce(Vec<u32>);
+        impl Delegate for RetryOnce {
+            fn http_failure(
+                &mut self,
+                _res: &hyper::Response<hyper::body::Body>,
+                _err: Option<json::Value>,
+            ) -> Retry {
+                match self.0.is_empty() {
+                    true => Retry::After(Duration::from_millis(1)),
+                    false => Retry::Abort,
+                }
+            }
+            fn retry_scheduled(&mut self, attempt: u32, _delay: Duration) {
+                self.0.push(attempt);
+            }
+        }
+
+        let url = "https://storage.googleapis.com/upload";
+        let interaction = |status: u16| cassette::Interaction {
+            request: cassette::RecordedRequest {
+                method: "POST".into(),
+                uri: url.into(),
+                body: cassette::RecordedBody::Text(String::new()),
+            },
+            response: cassette::RecordedResponse {
+                status,
+                headers: Vec::new(),
+                body: cassette::RecordedBody::Text(String::new()),
+            },
+        };
+        let connector = cassette::CassetteConnector::replay(cassette::Cassette {
+            interactions: vec![interaction(503), interaction(200)],
+        });
+        let client = hyper::Client::builder().build(connector);
+        let mut dlg = RetryOnce::default();
+        let mut media = ReadSeekAdapter::new(Cursor::new(b"0123456789".to_vec()));
+        let result = ResumableUploadHelper {
+            client: &client,
+            delegate: &mut dlg,
+            start_at: None,
+            auth: &(),
+            user_agent: "test",
+            api_client: "test",
+            auth_header: String::new(),
+            url,
+            reader: &mut media,
+            media_type: "text/plain".parse().unwrap(),
+            content_length: 10,
+            chunk_size: None,
+        }
+        .upload()
+        .await;
+        assert!(matches!(result, Some(Ok(res)) if res.status() == StatusCode::OK));
+        assert_eq!(dlg.0, [1]);
+    }
+
+    #[cfg(feature = "cassette")]
+    #[tokio::test]
+    async fn cancelling_invalid_upload_urls_fails_with_invalid_request() {
+        let connector = cassette::CassetteConnector::replay(cassette::Cassette::default());
+        let client = hyper::Client::builder().build(connector);
+        let result = cancel_upload(
+            &client,
+            &mut DefaultDelegate,
+            "https://storage.googleapis.com/an upload",
+            "test",
+            "test",
+        )
+        .await;
+        assert!(matches!(result, Err(Error::InvalidRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn append_download_resumes_at_offset() {
+        let path = std::env::temp_dir().join(format!(
+            "google-apis-common-append-download-{}",
+            std::process::id()
+        ));
+        std::fs::write(&path, b"hello ").unwrap();
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+
+        let mut res = hyper::Response::builder()
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(CONTENT_LENGTH, 5)
+            .body(hyper::body::Body::from("world"))
+            .unwrap();
+        let len = append_download(&mut res, &mut file, &mut DefaultDelegate, 6, false)
+            .await
+            .unwrap();
+
+        assert_eq!(len, 11);
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello world");
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn file_section_reads_and_seeks_within_section() {
+        let path = std::env::temp_dir().join(format!(
+            "google-apis-common-file-section-{}",
+            std::process::id()
+        ));
+        std::fs::write(&path, b"0123456789").unwrap();
+
+        let mut section = FileSection::open(&path, 3, 4).unwrap();
+        let mut buf = String::new();
+        section.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "3456");
+
+        assert_eq!(section.seek(SeekFrom::End(0)).unwrap(), 4);
+        assert_eq!(section.seek(SeekFrom::Start(1)).unwrap(), 1);
+        buf.clear();
+        section.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "456");
+        assert!(section.seek(SeekFrom::Current(-5)).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn read_seek_adapter_provides_async_media() {
+        let mut reader = ReadSeekAdapter::new(Cursor::new(b"0123456789".to_vec()));
+        let media: &mut dyn AsyncReadSeek = &mut reader;
+
+        assert_eq!(media_size(media).await.unwrap(), 10);
+        assert_eq!(read_media(media).await.unwrap(), b"0123456789");
+
+        {
+            use tokio::io::AsyncSeekExt;
+            assert_eq!(media.seek(SeekFrom::Start(6)).await.unwrap(), 6);
+        }
+        assert_eq!(read_media(media).await.unwrap(), b"6789");
+    }
+
+    #[tokio::test]
+    async fn body_reader_reads_all_chunks() {
+        use tokio::io::AsyncReadExt;
+
+        let (mut sender, body) = hyper::Body::channel();
+        tokio::spawn(async move {
+            for chunk in ["0123", "", "456789"] {
+                sender.send_data(chunk.into()).await.unwrap();
+            }
+        });
+        let mut reader = BodyReader::new(body);
+
+        let mut head = [0u8; 6];
+        reader.read_exact(&mut head).await.unwrap();
+        assert_eq!(&head, b"012345");
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, b"6789");
+    }
+
+    #[test]
+    fn multi_part_body_contains_metadata_and_media() {
+        let mut metadata = Cursor::new(br#"{"name":"a"}"#.to_vec());
+        let mut media = Cursor::new(b"media".to_vec());
+        let mut reader = MultiPartReader::default();
+        reader
+            .add_part(&mut metadata, 12, mime::APPLICATION_JSON)
+            .add_part(&mut media, 5, mime::TEXT_PLAIN);
+
+        let mut body = String::new();
+        reader.read_to_string(&mut body).unwrap();
+        assert_eq!(
+            body,
+            format!(
+                "--{b}\r\ncontent-type: application/json\r\ncontent-length: 12\r\n\r\n{{\"name\":\"a\"}}\
+                 \r\n--{b}\r\ncontent-type: text/plain\r\ncontent-length: 5\r\n\r\nmedia\
+                 \r\n--{b}--\r\n",
+                b = BOUNDARY
+            )
+        );
+    }
+
+    #[test]
+    fn requests_serialize_deterministically() {
+        let mut additional_params = std::collections::HashMap::new();
+        for name in ["quotaUser", "fields", "prettyPrint", "userIp"] {
+            additional_params.insert(name.to_string(), "1".to_string());
+        }
+        let mut params = crate::url::Params::with_capacity(5);
+        params.push("alt", "json");
+        params.extend(additional_params.iter());
+        assert_eq!(
+            params
+                .parse_with_url("https://example.com/")
+                .unwrap()
+                .as_str(),
+            "https://example.com/?alt=json&fields=1&prettyPrint=1&quotaUser=1&userIp=1"
+        );
+
+        let mut value = json::json!({"labels": {"b": 1, "a": [{"d": 2, "c": 3}]}, "id": null});
+        sort_json_keys(&mut value);
+        assert_eq!(
+            value.to_string(),
+            r#"{"id":null,"labels":{"a":[{"c":3,"d":2}],"b":1}}"#
+        );
+    }
+
+    #[test]
+    fn path_params_are_percent_encoded() {
+        let mut params = crate::url::Params::with_capacity(2);
+        params.push("object", "a/b c?d#e~f");
+        params.push("name", "a/b c?d#e~f");
+        let url = params.uri_replacement("b/o/{object}".into(), "object", "{object}", true);
+        assert_eq!(url, "b/o/a%2Fb%20c%3Fd%23e~f");
+        let url = params.uri_replacement("v1/{+name}:cancel".into(), "name", "{+name}", true);
+        assert_eq!(url, "v1/a/b%20c%3Fd%23e~f:cancel");
+    }
+
+    #[test]
+    fn path_params_are_left_out_of_the_query() {
+        let mut params = crate::url::Params::new();
+        params.push_path("bucket", "my-bucket");
+        params.push("alt", "json");
+        params.set("alt", "media");
+        params.set("prefix", "logs/");
+        let path = crate::uri_template::expand("b/{bucket}/o", &params).unwrap();
+        assert_eq!(
+            params
+                .parse_with_url(&format!("https://example.com/{}", path))
+                .unwrap()
+                .as_str(),
+            "https://example.com/b/my-bucket/o?alt=media&prefix=logs%2F"
+        );
+        assert_eq!(params.get("bucket"), Some("my-bucket"));
+        assert_eq!(
+            params.into_vec(),
+            [
+                ("alt".to_string(), "media".to_string()),
+                ("prefix".to_string(), "logs/".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn call_parts_expose_params() {
+        let mut params = crate::url::Params::with_capacity(2);
+        params.push("bucket", "my-bucket");
+        params.push("prefix", "logs/");
+        let parts = CallParts {
+            method_id: "storage.objects.list",
+            http_method: Method::GET,
+            url: "https://storage.googleapis.com/storage/v1/b/my-bucket/o?prefix=logs%2F".into(),
+            path_params: vec![("bucket", "my-bucket".into())],
+            query_params: params.into_vec().split_off(1),
+            request: None,
+            scopes: BTreeSet::new(),
+        };
+        assert_eq!(parts.path_param("bucket"), Some("my-bucket"));
+        assert_eq!(parts.query_param("prefix"), Some("logs/"));
+        assert_eq!(parts.query_param("bucket"), None);
+    }
+
+    #[test]
+    fn call_parts_become_http_requests() {
+        let parts = CallParts {
+            method_id: "storage.buckets.insert",
+            http_method: Method::POST,
+            url: "https://storage.googleapis.com/storage/v1/b?project=p".into(),
+            path_params: Vec::new(),
+            query_params: vec![("project".into(), "p".into())],
+            request: Some(json::json!({"name": "my-bucket"})),
+            scopes: BTreeSet::new(),
+        };
+        let req = parts.to_http_request().unwrap();
+        assert_eq!(req.method(), Method::POST);
+        assert_eq!(
+            req.uri(),
+            "https://storage.googleapis.com/storage/v1/b?project=p"
+        );
+        assert_eq!(req.headers()[CONTENT_TYPE], "application/json");
+        assert_eq!(req.body().as_slice(), br#"{"name":"my-bucket"}"#);
+    }
+
+    #[test]
+    fn response_parser_decodes_bodies_and_errors() {
+        #[derive(Default, Deserialize, Debug, PartialEq)]
+        struct Bucket {
+            name: Option<String>,
+        }
+        let parser = ResponseParser::<Bucket>::default();
+        let ok = hyper::Response::new(hyper::body::Bytes::from_static(br#"{"name":"b"}"#));
+        let (_, bucket) = parser.parse(ok).unwrap();
+        assert_eq!(bucket.name.as_deref(), Some("b"));
+
+        let empty = hyper::Response::new(hyper::body::Bytes::new());
+        assert_eq!(parser.parse(empty).unwrap().1, Bucket::default());
+
+        let mut failed = hyper::Response::new(hyper::body::Bytes::from_static(b"{\"error\":{}}"));
+        *failed.status_mut() = StatusCode::NOT_FOUND;
+        assert!(matches!(parser.parse(failed), Err(Error::BadRequest(_))));
+
+        let mut failed = hyper::Response::new(hyper::body::Bytes::from_static(b"not found"));
+        *failed.status_mut() = StatusCode::NOT_FOUND;
+        assert!(matches!(parser.parse(failed), Err(Error::Failure(_))));
+    }
+
     #[test]
     fn test_mime() {
         let mime = MultiPartReader::mime_type();
@@ -0,0 +1,54 @@
+//! Helpers to run tests of hubs against the real endpoints of their API
+//!
+//! Requires the `live` feature. Each generated crate has a `tests/live.rs` with a few read-only
+//! calls using these helpers, which detect breakage between regenerations of the crates.
+//!
+//! Live tests are opt-in: they only make calls if [`PROJECT_ENV`] names the project to use, and
+//! pass without doing anything otherwise. They authenticate with the application default
+//! credentials, see [`application_default_credentials()`].
+//!
+//! # Example
+//! ```ignore
+//! GOOGLE_APIS_LIVE_PROJECT=my-project cargo test --features live --test live
+//! ```
+use std::io;
+
+use yup_oauth2::authenticator::Authenticator;
+use yup_oauth2::hyper_rustls::HttpsConnectorBuilder;
+
+use crate::client_builder;
+
+/// The environment variable naming the project live tests run in.
+pub const PROJECT_ENV: &str = "GOOGLE_APIS_LIVE_PROJECT";
+
+/// The connector of hubs used in live tests.
+pub type Connector = client_builder::Connector;
+
+/// Returns the project to run live tests in, or `None` if live tests are disabled.
+pub fn project_id() -> Option<String> {
+    std::env::var(PROJECT_ENV)
+        .ok()
+        .map(|project| project.trim().to_string())
+        .filter(|project| !project.is_empty())
+}
+
+/// A client making HTTPS requests, with the native root certificates.
+pub fn https_client() -> hyper::Client<Connector, hyper::Body> {
+    let connector = HttpsConnectorBuilder::new()
+        .with_native_roots()
+        .https_or_http()
+        .enable_http1()
+        .enable_http2()
+        .build();
+    hyper::Client::builder().build(connector)
+}
+
+/// Returns an authenticator using the application default credentials, which are looked up like
+/// the Google Cloud SDKs do:
+///
+/// 1. the service account key in the file named by `GOOGLE_APPLICATION_CREDENTIALS`,
+/// 2. the user credentials stored by `gcloud auth application-default login`,
+/// 3. the service account of the instance metadata server, when running on Google Cloud.
+pub async fn application_default_credentials() -> io::Result<Authenticator<Connector>> {
+    client_builder::application_default_credentials(&https_client()).await
+}
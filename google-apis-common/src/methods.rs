@@ -0,0 +1,64 @@
+//! Metadata of the methods of an API
+//!
+//! Each API crate has a `METHODS` table in its `api` module, listing all of its methods sorted by
+//! id, which allows gateways, routers and documentation tools to inspect the API without
+//! parsing its discovery document.
+
+/// The description of an API method.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MethodMetadata {
+    /// The id of the method, like `storage.buckets.get`
+    pub id: &'static str,
+    /// Like `GET`
+    pub http_method: &'static str,
+    /// The URI template of the path, like `/storage/v1/b/{bucket}`
+    pub path: &'static str,
+    /// The parameters the method requires, in their `parameterOrder` of the discovery document
+    pub required_params: &'static [&'static str],
+    /// The scopes of which one is needed to call the method, or none if it takes an API key
+    pub scopes: &'static [&'static str],
+}
+
+impl MethodMetadata {
+    /// Returns true if the method is authorized with an API key, instead of a token.
+    pub fn is_key_only(&self) -> bool {
+        self.scopes.is_empty()
+    }
+}
+
+/// Returns the method with the given `id` from a `METHODS` table, which must be sorted by id.
+pub fn find(methods: &'static [MethodMetadata], id: &str) -> Option<&'static MethodMetadata> {
+    methods
+        .binary_search_by(|method| method.id.cmp(id))
+        .ok()
+        .map(|index| &methods[index])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const METHODS: &[MethodMetadata] = &[
+        MethodMetadata {
+            id: "storage.buckets.get",
+            http_method: "GET",
+            path: "/storage/v1/b/{bucket}",
+            required_params: &["bucket"],
+            scopes: &["https://www.googleapis.com/auth/devstorage.read_only"],
+        },
+        MethodMetadata {
+            id: "storage.objects.get",
+            http_method: "GET",
+            path: "/storage/v1/b/{bucket}/o/{object}",
+            required_params: &["bucket", "object"],
+            scopes: &[],
+        },
+    ];
+
+    #[test]
+    fn methods_are_found_by_id() {
+        assert_eq!(find(METHODS, "storage.buckets.get"), Some(&METHODS[0]));
+        assert!(find(METHODS, "storage.objects.get").unwrap().is_key_only());
+        assert_eq!(find(METHODS, "storage.objects.list"), None);
+    }
+}
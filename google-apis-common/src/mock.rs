@@ -0,0 +1,209 @@
+//! Helpers to test hubs against mock servers like `wiremock` or `httpmock`
+//!
+//! Requires the `mock` feature, which also gives each hub a `for_mock_server()` constructor and a
+//! `method_route()` function, returning the [`MethodRoute`] of a method by its id.
+//!
+//! Code which doesn't need a mock server can use a [`MockConnector`], which answers requests in
+//! memory.
+//!
+//! # Example
+//! ```ignore
+//! let server = wiremock::MockServer::start().await;
+//! let route = Storage::<HttpConnector>::method_route("storage.buckets.get").unwrap();
+//! Mock::given(method(route.http_method))
+//!     .and(path_regex(route.path_regex()))
+//!     .respond_with(ResponseTemplate::new(200).set_body_json(json!({"name": "bucket"})))
+//!     .mount(&server)
+//!     .await;
+//!
+//! let hub = Storage::for_mock_server(&server.uri());
+//! let (_, bucket) = hub.buckets().get("bucket").doit().await?;
+//! ```
+use std::io;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use hyper::client::HttpConnector;
+use hyper::{Body, Request, Response, StatusCode, Uri};
+
+use crate::in_memory::{self, InMemoryStream};
+
+/// The HTTP method and path of the requests of an API method.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MethodRoute {
+    /// The id of the API method, like `storage.buckets.get`
+    pub method_id: &'static str,
+    /// Like `GET`
+    pub http_method: &'static str,
+    /// The URI template of the path, like `/storage/v1/b/{bucket}`
+    pub path: &'static str,
+}
+
+impl MethodRoute {
+    /// Returns a regular expression matching the paths of the requests of the method, as taken by
+    /// `wiremock::matchers::path_regex()` and the `path_matches()` of `httpmock`.
+    pub fn path_regex(&self) -> String {
+        template_regex(self.path)
+    }
+}
+
+type Respond = dyn Fn(&Request<Body>) -> Response<Body> + Send + Sync;
+
+/// A connector answering all requests of the client using it in memory, without a server.
+#[derive(Clone)]
+pub struct MockConnector {
+    respond: Arc<Respond>,
+}
+
+impl MockConnector {
+    /// Answers each request with the response `respond` returns for it.
+    pub fn new<F>(respond: F) -> Self
+    where
+        F: Fn(&Request<Body>) -> Response<Body> + Send + Sync + 'static,
+    {
+        MockConnector {
+            respond: Arc::new(respond),
+        }
+    }
+
+    /// Answers all requests with `200 OK` and `value` as JSON body.
+    pub fn json(value: serde_json::Value) -> Self {
+        let body = value.to_string();
+        MockConnector::new(move |_| {
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(hyper::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.clone()))
+                .unwrap()
+        })
+    }
+}
+
+impl tower_service::Service<Uri> for MockConnector {
+    type Response = InMemoryStream;
+    type Error = io::Error;
+    type Future = futures::future::Ready<io::Result<InMemoryStream>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, origin: Uri) -> Self::Future {
+        let respond = self.respond.clone();
+        futures::future::ready(Ok(in_memory::connect(origin, move |req| {
+            futures::future::ready(Ok(respond(&req)))
+        })))
+    }
+}
+
+/// A client making plain HTTP requests, as mock servers expect them.
+pub fn http_client() -> hyper::Client<HttpConnector, hyper::Body> {
+    hyper::Client::builder().build(HttpConnector::new())
+}
+
+/// The URL to pass to the `root_url()` method of a hub for the mock server at `server_uri`.
+pub fn root_url(server_uri: &str) -> String {
+    format!("{}/", server_uri.trim_end_matches('/'))
+}
+
+/// The URL to pass to the `base_url()` method of a hub for the mock server at `server_uri`,
+/// given the service path of the API.
+pub fn base_url(server_uri: &str, service_path: &str) -> String {
+    root_url(server_uri) + service_path
+}
+
+/// Turns the URI template `template` into an anchored regular expression, in which `{name}`
+/// matches a single segment, and `{+name}` any amount of them.
+fn template_regex(template: &str) -> String {
+    let mut regex = String::from("^");
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        escape_into(&rest[..start], &mut regex);
+        regex.push_str(if rest[start + 1..].starts_with('+') {
+            ".+"
+        } else {
+            "[^/]+"
+        });
+        rest = &rest[end + 1..];
+    }
+    escape_into(rest, &mut regex);
+    regex.push('$');
+    regex
+}
+
+fn escape_into(literal: &str, regex: &mut String) {
+    for c in literal.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(c) {
+            regex.push('\\');
+        }
+        regex.push(c);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn paths_become_regexes() {
+        let route = MethodRoute {
+            method_id: "storage.objects.get",
+            http_method: "GET",
+            path: "/storage/v1/b/{bucket}/o/{object}",
+        };
+        assert_eq!(route.path_regex(), "^/storage/v1/b/[^/]+/o/[^/]+$");
+        assert_eq!(template_regex("/v1/{+name}:cancel"), "^/v1/.+:cancel$");
+        assert_eq!(template_regex("/v1.2/items"), "^/v1\\.2/items$");
+    }
+
+    #[tokio::test]
+    async fn mock_connectors_answer_requests() {
+        let connector = MockConnector::new(|req| {
+            let status = if req.uri().path() == "/storage/v1/b" {
+                StatusCode::OK
+            } else {
+                StatusCode::NOT_FOUND
+            };
+            Response::builder()
+                .status(status)
+                .body(Body::empty())
+                .unwrap()
+        });
+        let client: hyper::Client<_, Body> = hyper::Client::builder().build(connector);
+        let res = client
+            .get(
+                "https://storage.googleapis.com/storage/v1/b"
+                    .parse()
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let client: hyper::Client<_, Body> =
+            hyper::Client::builder().build(MockConnector::json(serde_json::json!({"id": 1})));
+        let res = client
+            .get(
+                "https://storage.googleapis.com/storage/v1/b/x"
+                    .parse()
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"{\"id\":1}");
+    }
+
+    #[test]
+    fn urls_point_to_the_server() {
+        assert_eq!(root_url("http://127.0.0.1:1234"), "http://127.0.0.1:1234/");
+        assert_eq!(
+            base_url("http://127.0.0.1:1234/", "storage/v1/"),
+            "http://127.0.0.1:1234/storage/v1/"
+        );
+    }
+}
@@ -0,0 +1,281 @@
+//! Conversion of discovery documents into OpenAPI 3 documents
+//!
+//! API crates built with their `openapi` feature embed the discovery document they were
+//! generated from, and return it as OpenAPI document from their `openapi()` function, to generate
+//! gateway configurations or clients in other languages from the same source.
+use serde_json::{json, Map, Value};
+
+/// The version of the OpenAPI specification the documents follow.
+pub const OPENAPI_VERSION: &str = "3.0.3";
+
+const AUTH_URL: &str = "https://accounts.google.com/o/oauth2/auth";
+const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+
+/// Returns the OpenAPI document describing the same API as the discovery document `doc`.
+///
+/// Methods requiring scopes use the `oauth2` security scheme, all others the `apiKey` scheme,
+/// which is the `key` query parameter.
+pub fn from_discovery(doc: &Value) -> Value {
+    let mut paths = Map::new();
+    add_paths(doc, &mut paths);
+
+    let schemas: Map<String, Value> = doc["schemas"]
+        .as_object()
+        .map(|schemas| {
+            schemas
+                .iter()
+                .map(|(id, schema)| (id.clone(), schema_of(schema)))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let scopes: Map<String, Value> = doc["auth"]["oauth2"]["scopes"]
+        .as_object()
+        .map(|scopes| {
+            scopes
+                .iter()
+                .map(|(scope, info)| (scope.clone(), info["description"].clone()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    json!({
+        "openapi": OPENAPI_VERSION,
+        "info": {
+            "title": doc["title"].as_str().or_else(|| doc["name"].as_str()).unwrap_or_default(),
+            "description": doc["description"].as_str().unwrap_or_default(),
+            "version": doc["version"].as_str().unwrap_or_default(),
+        },
+        "servers": [{ "url": doc["baseUrl"].as_str().unwrap_or_default() }],
+        "paths": paths,
+        "components": {
+            "schemas": schemas,
+            "securitySchemes": {
+                "oauth2": {
+                    "type": "oauth2",
+                    "flows": {
+                        "authorizationCode": {
+                            "authorizationUrl": AUTH_URL,
+                            "tokenUrl": TOKEN_URL,
+                            "scopes": scopes,
+                        }
+                    }
+                },
+                "apiKey": { "type": "apiKey", "in": "query", "name": "key" },
+            }
+        }
+    })
+}
+
+/// Adds the operations of the methods of `container` and its resources to `paths`.
+fn add_paths(container: &Value, paths: &mut Map<String, Value>) {
+    if let Some(methods) = container["methods"].as_object() {
+        for method in methods.values() {
+            let path = format!(
+                "/{}",
+                method["path"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .replace("{+", "{")
+            );
+            let http_method = method["httpMethod"]
+                .as_str()
+                .unwrap_or("GET")
+                .to_ascii_lowercase();
+            if let Some(operations) = paths
+                .entry(path)
+                .or_insert_with(|| Value::Object(Map::new()))
+                .as_object_mut()
+            {
+                operations.insert(http_method, operation_of(method));
+            }
+        }
+    }
+    if let Some(resources) = container["resources"].as_object() {
+        for resource in resources.values() {
+            add_paths(resource, paths);
+        }
+    }
+}
+
+fn operation_of(method: &Value) -> Value {
+    let mut operation = Map::new();
+    operation.insert("operationId".into(), method["id"].clone());
+    if let Some(description) = method["description"].as_str() {
+        operation.insert("description".into(), description.into());
+    }
+
+    let parameters: Vec<Value> = method["parameters"]
+        .as_object()
+        .map(|params| {
+            params
+                .iter()
+                .map(|(name, param)| {
+                    let location = param["location"].as_str().unwrap_or("query");
+                    let mut schema = schema_of(param);
+                    if param["repeated"].as_bool().unwrap_or(false) {
+                        schema = json!({ "type": "array", "items": schema });
+                    }
+                    let mut parameter = json!({
+                        "name": name,
+                        "in": location,
+                        "required": location == "path" || param["required"].as_bool().unwrap_or(false),
+                        "schema": schema,
+                    });
+                    if let Some(description) = param["description"].as_str() {
+                        parameter["description"] = description.into();
+                    }
+                    parameter
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if !parameters.is_empty() {
+        operation.insert("parameters".into(), parameters.into());
+    }
+
+    if let Some(request) = method["request"]["$ref"].as_str() {
+        operation.insert(
+            "requestBody".into(),
+            json!({
+                "required": true,
+                "content": { "application/json": { "schema": schema_ref(request) } }
+            }),
+        );
+    }
+
+    let mut response = json!({ "description": "Successful response" });
+    if let Some(schema) = method["response"]["$ref"].as_str() {
+        response["content"] = json!({ "application/json": { "schema": schema_ref(schema) } });
+    }
+    operation.insert("responses".into(), json!({ "200": response }));
+
+    let security = match method["scopes"].as_array() {
+        Some(scopes) if !scopes.is_empty() => json!([{ "oauth2": scopes }]),
+        _ => json!([{ "apiKey": [] }]),
+    };
+    operation.insert("security".into(), security);
+    Value::Object(operation)
+}
+
+fn schema_ref(id: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", id) })
+}
+
+/// Returns the OpenAPI schema of a discovery schema, property or parameter.
+fn schema_of(schema: &Value) -> Value {
+    if let Some(id) = schema["$ref"].as_str() {
+        return schema_ref(id);
+    }
+    let mut res = Map::new();
+    // `any` allows any value, which is what a schema without type does
+    if let Some(ty) = schema["type"].as_str().filter(|ty| *ty != "any") {
+        res.insert("type".into(), ty.into());
+    }
+    // `minimum` and `maximum` are strings in discovery documents, and left out
+    for key in ["format", "description", "enum", "pattern"] {
+        if let Some(value) = schema.get(key) {
+            res.insert(key.into(), value.clone());
+        }
+    }
+    if let Some(items) = schema.get("items") {
+        res.insert("items".into(), schema_of(items));
+    }
+    if let Some(properties) = schema["properties"].as_object() {
+        res.insert(
+            "properties".into(),
+            properties
+                .iter()
+                .map(|(name, property)| (name.clone(), schema_of(property)))
+                .collect::<Map<_, _>>()
+                .into(),
+        );
+    }
+    if let Some(additional) = schema.get("additionalProperties") {
+        res.insert("additionalProperties".into(), schema_of(additional));
+    }
+    Value::Object(res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn discovery_documents_become_openapi_documents() {
+        let doc = json!({
+            "name": "storage",
+            "title": "Cloud Storage JSON API",
+            "version": "v1",
+            "baseUrl": "https://storage.googleapis.com/storage/v1/",
+            "auth": { "oauth2": { "scopes": {
+                "https://www.googleapis.com/auth/devstorage.read_only": { "description": "View your data" }
+            } } },
+            "schemas": {
+                "Bucket": { "id": "Bucket", "type": "object", "properties": {
+                    "name": { "type": "string" },
+                    "labels": { "type": "object", "additionalProperties": { "type": "string" } },
+                    "owner": { "$ref": "Owner" },
+                } },
+                "Owner": { "id": "Owner", "type": "object", "properties": { "entity": { "type": "any" } } }
+            },
+            "resources": { "buckets": { "methods": {
+                "get": {
+                    "id": "storage.buckets.get",
+                    "path": "b/{bucket}",
+                    "httpMethod": "GET",
+                    "parameters": {
+                        "bucket": { "type": "string", "location": "path", "required": true },
+                        "fields": { "type": "string", "location": "query", "repeated": true }
+                    },
+                    "response": { "$ref": "Bucket" },
+                    "scopes": ["https://www.googleapis.com/auth/devstorage.read_only"]
+                },
+                "insert": {
+                    "id": "storage.buckets.insert",
+                    "path": "b",
+                    "httpMethod": "POST",
+                    "request": { "$ref": "Bucket" }
+                }
+            } } }
+        });
+
+        let api = from_discovery(&doc);
+        assert_eq!(api["openapi"], OPENAPI_VERSION);
+        assert_eq!(
+            api["servers"][0]["url"],
+            "https://storage.googleapis.com/storage/v1/"
+        );
+
+        let get = &api["paths"]["/b/{bucket}"]["get"];
+        assert_eq!(get["operationId"], "storage.buckets.get");
+        assert_eq!(get["parameters"][0]["in"], "path");
+        assert_eq!(get["parameters"][1]["schema"]["type"], "array");
+        assert_eq!(
+            get["responses"]["200"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/Bucket"
+        );
+        assert_eq!(
+            get["security"][0]["oauth2"][0],
+            "https://www.googleapis.com/auth/devstorage.read_only"
+        );
+
+        let insert = &api["paths"]["/b"]["post"];
+        assert!(insert["requestBody"]["required"].as_bool().unwrap());
+        assert_eq!(insert["security"][0]["apiKey"], json!([]));
+
+        let bucket = &api["components"]["schemas"]["Bucket"];
+        assert_eq!(
+            bucket["properties"]["labels"]["additionalProperties"]["type"],
+            "string"
+        );
+        assert_eq!(
+            bucket["properties"]["owner"]["$ref"],
+            "#/components/schemas/Owner"
+        );
+        assert_eq!(
+            api["components"]["schemas"]["Owner"]["properties"]["entity"],
+            json!({})
+        );
+    }
+}
@@ -0,0 +1,234 @@
+//! Making groups of calls with bounded parallelism
+//!
+//! Fetching all executions of a `TestMatrix`, or the first page of many buckets, means making
+//! many independent calls. [`execute_all()`] makes them with at most a given number in flight,
+//! starting them no faster than an optional [`RateLimiter`] allows, and collects the values and
+//! errors of all of them into a [`PartialResult`], so that one failed call doesn't lose the
+//! values of all others.
+//!
+//! ```ignore
+//! let policy = client::ExecutionPolicy::default().rate_limiter(client::RateLimiter::per_second(10));
+//! let calls = ids.iter().map(|id| hub.projects().test_matrices_get(project, id).doit());
+//! let matrices = client::execute_all(calls, 4, &policy).await.into_result()?;
+//! ```
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::stream::{FuturesUnordered, StreamExt};
+use tokio::time::sleep;
+
+use crate::{Error, Result};
+
+/// Limits the rate at which calls are started, shared by all clones of it.
+///
+/// One limiter can be used by several groups of calls, or by calls made elsewhere through
+/// [`RateLimiter::acquire()`], to keep all of them within the same quota.
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next_start: Arc<Mutex<Option<Instant>>>,
+}
+
+impl RateLimiter {
+    /// Returns a limiter starting at most `calls` calls per second, at least one.
+    pub fn per_second(calls: u32) -> Self {
+        RateLimiter::with_interval(Duration::from_secs(1) / calls.max(1))
+    }
+
+    /// Returns a limiter starting one call per `interval`.
+    pub fn with_interval(interval: Duration) -> Self {
+        RateLimiter {
+            interval,
+            next_start: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Waits until the next call may be started, and reserves its slot.
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut next_start = self.next_start.lock().expect("not poisoned");
+            let now = Instant::now();
+            let start = next_start.map_or(now, |next| next.max(now));
+            *next_start = Some(start + self.interval);
+            start - now
+        };
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+    }
+}
+
+/// How [`execute_all()`] makes its calls.
+#[derive(Clone, Debug, Default)]
+pub struct ExecutionPolicy {
+    /// Whether to stop starting calls once one of them failed, leaving the rest skipped
+    pub fail_fast: bool,
+    /// The limiter of the rate at which calls are started, if they are limited
+    pub rate_limiter: Option<RateLimiter>,
+}
+
+impl ExecutionPolicy {
+    /// Sets whether to stop starting calls once one of them failed.
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    /// Sets the limiter of the rate at which calls are started.
+    pub fn rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+}
+
+/// The outcomes of a group of calls, each with the index of its call in the group.
+#[derive(Debug)]
+pub struct PartialResult<T> {
+    /// The values of the calls which succeeded, ordered by index
+    pub succeeded: Vec<(usize, T)>,
+    /// The errors of the calls which failed, ordered by index
+    pub failed: Vec<(usize, Error)>,
+    /// The indices of the calls which weren't started, as an earlier one failed
+    pub skipped: Vec<usize>,
+}
+
+impl<T> Default for PartialResult<T> {
+    fn default() -> Self {
+        PartialResult {
+            succeeded: Vec::new(),
+            failed: Vec::new(),
+            skipped: Vec::new(),
+        }
+    }
+}
+
+impl<T> PartialResult<T> {
+    /// Returns true if all calls succeeded.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty() && self.skipped.is_empty()
+    }
+
+    /// Returns the values of all calls in the order of the calls, or the error of the first
+    /// failed call.
+    ///
+    /// # Panics
+    ///
+    /// If calls were skipped without any of them having failed, which [`execute_all()`] doesn't
+    /// do.
+    pub fn into_result(self) -> Result<Vec<T>> {
+        if let Some((_, err)) = self.failed.into_iter().next() {
+            return Err(err);
+        }
+        assert!(
+            self.skipped.is_empty(),
+            "calls are only skipped after one failed"
+        );
+        Ok(self.succeeded.into_iter().map(|(_, value)| value).collect())
+    }
+}
+
+/// Makes `calls`, like the futures of the `doit()` methods of call builders, with at most
+/// `concurrency` of them in flight, as `policy` demands, and returns the outcomes of all of them.
+///
+/// Calls are started in the order of `calls`, which is only advanced while fewer than
+/// `concurrency` calls are in flight, so it can create them lazily. With `fail_fast`, calls in
+/// flight when one fails still finish, but no others are started.
+pub async fn execute_all<I, F, T>(
+    calls: I,
+    concurrency: usize,
+    policy: &ExecutionPolicy,
+) -> PartialResult<T>
+where
+    I: IntoIterator<Item = F>,
+    F: Future<Output = Result<T>>,
+{
+    let concurrency = concurrency.max(1);
+    let rate_limiter = policy.rate_limiter.as_ref();
+    let mut calls = calls.into_iter().enumerate();
+    let mut running = FuturesUnordered::new();
+    let mut result = PartialResult::default();
+    loop {
+        while running.len() < concurrency && !(policy.fail_fast && !result.failed.is_empty()) {
+            let (index, call) = match calls.next() {
+                Some(call) => call,
+                None => break,
+            };
+            running.push(async move {
+                if let Some(rate_limiter) = rate_limiter {
+                    rate_limiter.acquire().await;
+                }
+                (index, call.await)
+            });
+        }
+        match running.next().await {
+            Some((index, Ok(value))) => result.succeeded.push((index, value)),
+            Some((index, Err(err))) => result.failed.push((index, err)),
+            None => break,
+        }
+    }
+    result.skipped.extend(calls.map(|(index, _)| index));
+    result.succeeded.sort_by_key(|&(index, _)| index);
+    result.failed.sort_by_key(|&(index, _)| index);
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn calls_are_made_with_bounded_parallelism() {
+        let in_flight = AtomicUsize::new(0);
+        let max_in_flight = AtomicUsize::new(0);
+        let calls = (0..10).map(|i| {
+            let (in_flight, max_in_flight) = (&in_flight, &max_in_flight);
+            async move {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                max_in_flight.fetch_max(now, Ordering::SeqCst);
+                sleep(Duration::from_millis(5 * (1 + i as u64 % 3))).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                if i == 4 {
+                    Err(Error::Cancelled)
+                } else {
+                    Ok(i * 2)
+                }
+            }
+        });
+        let policy = ExecutionPolicy::default().rate_limiter(RateLimiter::per_second(1000));
+        let result = execute_all(calls, 3, &policy).await;
+
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 3);
+        assert!(!result.is_complete());
+        assert_eq!(
+            result.succeeded,
+            [0, 1, 2, 3, 5, 6, 7, 8, 9].map(|i: usize| (i, i * 2))
+        );
+        assert_eq!(result.failed.len(), 1);
+        assert_eq!(result.failed[0].0, 4);
+        assert!(result.skipped.is_empty());
+        assert!(result.into_result().is_err());
+    }
+
+    #[tokio::test]
+    async fn failures_stop_further_calls_if_failing_fast() {
+        let calls = (0..5).map(|i| async move {
+            match i {
+                1 => Err(Error::Cancelled),
+                _ => Ok(i),
+            }
+        });
+        let policy = ExecutionPolicy::default().fail_fast(true);
+        let result = execute_all(calls, 1, &policy).await;
+        assert_eq!(result.succeeded, [(0, 0)]);
+        assert_eq!(result.failed.len(), 1);
+        assert_eq!(result.skipped, [2, 3, 4]);
+
+        let calls = (0..3).map(|i| async move { Ok(i) });
+        let result = execute_all(calls, 2, &policy).await;
+        assert!(result.is_complete());
+        assert_eq!(result.into_result().unwrap(), [0, 1, 2]);
+    }
+}
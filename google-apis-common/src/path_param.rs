@@ -0,0 +1,137 @@
+//! Newtypes of path parameters
+//!
+//! API crates have a `path_params` module with one type per name of a path parameter of their
+//! methods, like `ProjectId` for `projectId`, made by [`path_param!`](crate::path_param). The
+//! methods creating call builders take anything which converts into them, strings included,
+//! so the newtypes name what a parameter is and validate it, but don't keep ids passed as
+//! strings from being swapped. Strings convert with `TryFrom`, which rejects empty values like
+//! `new()` does, and the calls of builders given an empty value fail with
+//! [`UriTemplateError::EmptyParam`](crate::uri_template::UriTemplateError::EmptyParam).
+use std::error::Error as StdError;
+use std::fmt::{self, Display};
+
+/// The error of creating a path parameter from an empty value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EmptyPathParam {
+    /// The name of the parameter, like `projectId`
+    pub name: &'static str,
+}
+
+impl Display for EmptyPathParam {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the path parameter '{}' can't be empty", self.name)
+    }
+}
+
+impl StdError for EmptyPathParam {}
+
+/// Defines the newtype `$ty` of the path parameter `$name`.
+///
+/// `new()` validates the value, as do the conversions from strings with `TryFrom`, which exist
+/// so that methods taking the newtype accept strings as well.
+#[macro_export]
+macro_rules! path_param {
+    ($(#[$attr:meta])* $ty:ident, $name:literal) => {
+        $(#[$attr])*
+        #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $ty(String);
+
+        impl $ty {
+            /// The name of the path parameter.
+            pub const NAME: &'static str = $name;
+
+            /// Returns the parameter with the given value, if it isn't empty.
+            pub fn new(value: impl Into<String>) -> Result<Self, $crate::path_param::EmptyPathParam> {
+                let value = value.into();
+                if value.is_empty() {
+                    return Err($crate::path_param::EmptyPathParam { name: $name });
+                }
+                Ok($ty(value))
+            }
+
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+
+            pub fn into_string(self) -> String {
+                self.0
+            }
+        }
+
+        impl ::std::fmt::Display for $ty {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        impl AsRef<str> for $ty {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl ::std::convert::TryFrom<&str> for $ty {
+            type Error = $crate::path_param::EmptyPathParam;
+
+            fn try_from(value: &str) -> Result<Self, Self::Error> {
+                $ty::new(value)
+            }
+        }
+
+        impl ::std::convert::TryFrom<String> for $ty {
+            type Error = $crate::path_param::EmptyPathParam;
+
+            fn try_from(value: String) -> Result<Self, Self::Error> {
+                $ty::new(value)
+            }
+        }
+
+        impl ::std::convert::TryFrom<&String> for $ty {
+            type Error = $crate::path_param::EmptyPathParam;
+
+            fn try_from(value: &String) -> Result<Self, Self::Error> {
+                $ty::new(value.as_str())
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    path_param!(
+        /// A project
+        ProjectId,
+        "projectId"
+    );
+
+    path_param!(MatrixId, "matrixId");
+
+    fn matrix_url(project: impl TryInto<ProjectId>, matrix: impl TryInto<MatrixId>) -> String {
+        let project = project.try_into().map(ProjectId::into_string);
+        let matrix = matrix.try_into().map(MatrixId::into_string);
+        format!(
+            "projects/{}/matrices/{}",
+            project.unwrap_or_default(),
+            matrix.unwrap_or_default()
+        )
+    }
+
+    #[test]
+    fn path_params_are_validated_and_displayed() {
+        assert_eq!(
+            ProjectId::new(""),
+            Err(EmptyPathParam { name: "projectId" })
+        );
+        let project = ProjectId::new("p").unwrap();
+        let matrix = MatrixId::new("m").unwrap();
+        assert_eq!(matrix_url(project, matrix), "projects/p/matrices/m");
+        assert_eq!(matrix_url("p", "m"), "projects/p/matrices/m");
+        assert_eq!(
+            ProjectId::try_from(String::new()),
+            Err(EmptyPathParam { name: "projectId" })
+        );
+        assert_eq!(matrix_url("p", ""), "projects/p/matrices/");
+    }
+}
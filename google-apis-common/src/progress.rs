@@ -0,0 +1,129 @@
+//! Progress bars for uploads and downloads, rendered with [`indicatif`]
+//!
+//! Requires the `indicatif` feature.
+//!
+//! # Example
+//! ```ignore
+//! let mut progress = ProgressBarDelegate::default();
+//! hub.objects().insert(object, "bucket")
+//!     .delegate(&mut progress)
+//!     .upload_resumable(media, mime_type)
+//!     .await?;
+//! ```
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+use crate::{DefaultDelegate, Delegate, MethodInfo};
+
+const BAR_TEMPLATE: &str =
+    "{msg} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})";
+const SPINNER_TEMPLATE: &str = "{msg} [{elapsed_precise}] {spinner} {bytes} ({bytes_per_sec})";
+
+/// A [`Delegate`] showing the progress of media uploads and downloads as progress bars on stderr.
+///
+/// All other calls are forwarded to the wrapped delegate, which keeps deciding about retries
+/// and resumable uploads.
+pub struct ProgressBarDelegate<D = DefaultDelegate> {
+    inner: D,
+    draw_target: Option<ProgressDrawTarget>,
+    method: &'static str,
+    bar: Option<ProgressBar>,
+}
+
+impl Default for ProgressBarDelegate {
+    fn default() -> Self {
+        ProgressBarDelegate::new(DefaultDelegate)
+    }
+}
+
+impl<D: Delegate> ProgressBarDelegate<D> {
+    /// Shows progress bars for the transfers of calls made with `inner`.
+    pub fn new(inner: D) -> Self {
+        ProgressBarDelegate {
+            inner,
+            draw_target: None,
+            method: "",
+            bar: None,
+        }
+    }
+
+    /// Draw the progress bars to `target` instead of stderr.
+    pub fn draw_target(mut self, target: ProgressDrawTarget) -> Self {
+        self.draw_target = Some(target);
+        self
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    fn bar(&mut self, total: Option<u64>) -> &ProgressBar {
+        let (method, draw_target) = (self.method, &mut self.draw_target);
+        self.bar.get_or_insert_with(|| {
+            let (bar, template) = match total {
+                Some(total) => (ProgressBar::new(total), BAR_TEMPLATE),
+                None => (ProgressBar::new_spinner(), SPINNER_TEMPLATE),
+            };
+            if let Some(target) = draw_target.take() {
+                bar.set_draw_target(target);
+            }
+            bar.set_style(
+                ProgressStyle::with_template(template)
+                    .expect("valid template")
+                    .progress_chars("=> "),
+            );
+            bar.set_message(method);
+            bar
+        })
+    }
+}
+
+impl<D: Delegate> Delegate for ProgressBarDelegate<D> {
+    fn forward_to(&mut self) -> Option<&mut dyn Delegate> {
+        Some(&mut self.inner)
+    }
+
+    fn begin(&mut self, info: MethodInfo) {
+        self.method = info.id;
+        self.inner.begin(info)
+    }
+
+    fn upload_progress(&mut self, bytes_sent: u64, total: u64) {
+        self.bar(Some(total)).set_position(bytes_sent);
+        self.inner.upload_progress(bytes_sent, total)
+    }
+
+    fn download_progress(&mut self, bytes_received: u64, total: Option<u64>) {
+        self.bar(total).set_position(bytes_received);
+        self.inner.download_progress(bytes_received, total)
+    }
+
+    fn finished(&mut self, is_success: bool) {
+        if let Some(bar) = self.bar.take() {
+            if is_success {
+                bar.finish();
+            } else {
+                bar.abandon();
+            }
+        }
+        self.inner.finished(is_success)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bar_follows_progress_and_is_finished() {
+        let mut dlg = ProgressBarDelegate::default().draw_target(ProgressDrawTarget::hidden());
+        dlg.upload_progress(10, 100);
+        dlg.upload_progress(60, 100);
+        let bar = dlg.bar.clone().unwrap();
+        assert_eq!((bar.position(), bar.length()), (60, Some(100)));
+
+        dlg.finished(true);
+        assert!(dlg.bar.is_none());
+        assert!(bar.is_finished());
+    }
+}
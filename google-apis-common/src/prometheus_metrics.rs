@@ -0,0 +1,122 @@
+//! A [`Metrics`] implementation exporting to [`prometheus`]
+//!
+//! Requires the `prometheus` feature.
+//!
+//! # Example
+//! ```ignore
+//! let registry = prometheus::Registry::new();
+//! hub.metrics(PrometheusMetrics::new(&registry)?);
+//! ```
+use std::time::Duration;
+
+use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry};
+
+use crate::{Metrics, StatusClass};
+
+/// Exports the following metrics, all labeled with the `method` id, like `storage.objects.get`:
+///
+/// * `google_api_requests_total`, the number of requests by `status_class`, like `2xx`
+/// * `google_api_request_errors_total`, the number of failed requests by canonical `code`,
+///   like `NOT_FOUND`
+/// * `google_api_request_duration_seconds`, a histogram of request latencies by `status_class`
+#[derive(Clone)]
+pub struct PrometheusMetrics {
+    requests: IntCounterVec,
+    errors: IntCounterVec,
+    latency: HistogramVec,
+}
+
+impl PrometheusMetrics {
+    /// Creates the metrics and registers them with `registry`.
+    pub fn new(registry: &Registry) -> prometheus::Result<Self> {
+        let requests = IntCounterVec::new(
+            Opts::new(
+                "google_api_requests_total",
+                "Number of requests made to Google APIs",
+            ),
+            &["method", "status_class"],
+        )?;
+        let errors = IntCounterVec::new(
+            Opts::new(
+                "google_api_request_errors_total",
+                "Number of failed requests made to Google APIs",
+            ),
+            &["method", "code"],
+        )?;
+        let latency = HistogramVec::new(
+            HistogramOpts::new(
+                "google_api_request_duration_seconds",
+                "Latency of requests made to Google APIs",
+            ),
+            &["method", "status_class"],
+        )?;
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(errors.clone()))?;
+        registry.register(Box::new(latency.clone()))?;
+        Ok(PrometheusMetrics {
+            requests,
+            errors,
+            latency,
+        })
+    }
+}
+
+impl Metrics for PrometheusMetrics {
+    fn increment_request_count(&self, method_id: &'static str, status: StatusClass) {
+        self.requests
+            .with_label_values(&[method_id, status.as_str()])
+            .inc();
+    }
+
+    fn record_request_latency(
+        &self,
+        method_id: &'static str,
+        status: StatusClass,
+        duration: Duration,
+    ) {
+        self.latency
+            .with_label_values(&[method_id, status.as_str()])
+            .observe(duration.as_secs_f64());
+    }
+
+    fn increment_error_count(&self, method_id: &'static str, code: &'static str) {
+        self.errors.with_label_values(&[method_id, code]).inc();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn metrics_are_registered_and_labeled() {
+        let registry = Registry::new();
+        let metrics = PrometheusMetrics::new(&registry).unwrap();
+        metrics.increment_request_count("storage.objects.get", StatusClass::ClientError);
+        metrics.increment_error_count("storage.objects.get", "NOT_FOUND");
+        metrics.record_request_latency(
+            "storage.objects.get",
+            StatusClass::ClientError,
+            Duration::from_millis(20),
+        );
+
+        let families = registry.gather();
+        let names: Vec<_> = families.iter().map(|f| f.get_name()).collect();
+        assert_eq!(
+            names,
+            [
+                "google_api_request_duration_seconds",
+                "google_api_request_errors_total",
+                "google_api_requests_total"
+            ]
+        );
+        assert_eq!(
+            metrics
+                .errors
+                .with_label_values(&["storage.objects.get", "NOT_FOUND"])
+                .get(),
+            1
+        );
+        assert!(PrometheusMetrics::new(&registry).is_err());
+    }
+}
@@ -0,0 +1,98 @@
+//! Decoding of protobuf responses
+//!
+//! Methods of APIs which accept `alt=proto` get a `doit_proto()` method with the `proto` feature,
+//! which requests the response protobuf-encoded, and decodes it into a [`prost::Message`] of the
+//! caller, like the types `prost` generated from the protos of the API. Protobuf responses are a
+//! fraction of the size of JSON ones, and much faster to decode.
+use std::time::Instant;
+
+use hyper::body::Bytes;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Response};
+
+use crate::{Delegate, Error, Result};
+
+/// The Content-Type of protobuf responses.
+pub const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
+
+/// Decodes the `body` [`crate::exec::execute()`] read into the message `M`, like
+/// [`crate::exec::decode()`] decodes JSON bodies.
+///
+/// Responses which aren't protobuf-encoded, like those of servers ignoring `alt=proto`, are an
+/// [`Error::ProtoDecodeError`].
+#[allow(clippy::result_large_err)]
+pub fn decode<M: prost::Message + Default>(
+    res: Response<Body>,
+    body: Option<Bytes>,
+    dlg: &mut dyn Delegate,
+) -> Result<(Response<Body>, M)> {
+    let content_type = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !content_type.starts_with(PROTOBUF_CONTENT_TYPE) {
+        return Err(Error::ProtoDecodeError(
+            format!("the response has Content-Type '{}'", content_type).into(),
+        ));
+    }
+    let decode_started = Instant::now();
+    match M::decode(body.unwrap_or_default()) {
+        Ok(decoded) => {
+            dlg.response_decoded(decode_started.elapsed());
+            Ok((res, decoded))
+        }
+        Err(err) => Err(Error::ProtoDecodeError(Box::new(err))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use prost::Message;
+
+    use super::*;
+    use crate::DefaultDelegate;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct Bucket {
+        #[prost(string, tag = "1")]
+        name: String,
+    }
+
+    fn response(content_type: &str) -> Response<Body> {
+        Response::builder()
+            .header(CONTENT_TYPE, content_type)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn protobuf_responses_are_decoded() {
+        let bucket = Bucket {
+            name: "bucket".into(),
+        };
+        let body = Bytes::from(bucket.encode_to_vec());
+        let mut dlg = DefaultDelegate;
+
+        let (_, decoded): (_, Bucket) = decode(
+            response(PROTOBUF_CONTENT_TYPE),
+            Some(body.clone()),
+            &mut dlg,
+        )
+        .unwrap();
+        assert_eq!(decoded, bucket);
+
+        assert!(matches!(
+            decode::<Bucket>(response("application/json"), Some(body), &mut dlg),
+            Err(Error::ProtoDecodeError(_))
+        ));
+        assert!(matches!(
+            decode::<Bucket>(
+                response(PROTOBUF_CONTENT_TYPE),
+                Some(Bytes::from_static(b"\x0a\x05ab")),
+                &mut dlg
+            ),
+            Err(Error::ProtoDecodeError(_))
+        ));
+    }
+}
@@ -0,0 +1,407 @@
+//! Retrying transient failures with exponential backoff
+//!
+//! Without a [`RetryPolicy`], failed calls are only retried if their delegate asks for it, or if the
+//! server said when to retry them. With one, set on the hub or on a call builder with
+//! `retry_policy()`, calls are retried if the request failed in transit, or if the response has one
+//! of the retryable statuses, like `503 Service Unavailable`, unless the delegate asked for a retry
+//! itself. Each retry waits twice as long as the previous one, up to a maximum, shortened by a
+//! random jitter so that clients which failed at the same time don't retry at the same time, but
+//! never less than the server asked for, up to the maximum.
+//!
+//! Even without a policy, calls answered with `429 Too Many Requests` or `503 Service Unavailable`
+//! are retried after the delay the server asked for, with `Retry-After` or the `RetryInfo` details
+//! of the error, up to the `retry_after_limit()` of the hub, if the delay is at most
+//! [`MAX_RETRY_AFTER`].
+//!
+//! On wasm targets, which have no timer to wait with, calls are never retried, and fail with the
+//! error of their first attempt instead.
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use hyper::header::{HeaderMap, RETRY_AFTER};
+use hyper::StatusCode;
+
+use crate::{Delegate, Retry};
+
+/// The statuses retried by default: request timeouts, rate limits and transient server errors.
+pub const DEFAULT_RETRYABLE_STATUSES: &[u16] = &[408, 429, 500, 502, 503, 504];
+
+/// The number of times calls are retried after the delay the server asked for, unless the hub
+/// sets another limit.
+pub const DEFAULT_RETRY_AFTER_LIMIT: u32 = 3;
+
+/// The longest delay the server may ask for to be retried without a retry policy. Calls asked to
+/// wait longer fail right away.
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// How often and when calls failing in ways which may go away are retried.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// The number of attempts of a call at most, including the first one
+    pub max_attempts: u32,
+    /// The delay before the first retry, which doubles with each further retry
+    pub base_delay: Duration,
+    /// The delay before a retry at most, before it's shortened by the jitter
+    pub max_delay: Duration,
+    /// The fraction each delay is shortened by at most, randomly, from 0 for none to 1
+    pub jitter: f64,
+    /// The statuses of responses which are retried
+    pub retryable_statuses: Vec<u16>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(32),
+            jitter: 0.5,
+            retryable_statuses: DEFAULT_RETRYABLE_STATUSES.to_vec(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the default policy, making up to 4 attempts with delays from 1s to 32s, jittered
+    /// by up to half of them, for the [`DEFAULT_RETRYABLE_STATUSES`].
+    pub fn new() -> Self {
+        RetryPolicy::default()
+    }
+
+    /// Sets the number of attempts of a call at most, including the first one.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Sets the delay before the first retry.
+    pub fn base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    /// Sets the delay before a retry at most.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Sets the fraction each delay is shortened by at most, which is clamped to 0 to 1.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = if jitter.is_nan() {
+            0.0
+        } else {
+            jitter.clamp(0.0, 1.0)
+        };
+        self
+    }
+
+    /// Sets the statuses of responses which are retried, like `[500, 503]`.
+    pub fn retryable_statuses<I: IntoIterator<Item = u16>>(mut self, statuses: I) -> Self {
+        self.retryable_statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Returns the delay before the retry `retry`, starting at 1, without jitter, or None if the
+    /// call may not be attempted again.
+    pub fn backoff(&self, retry: u32) -> Option<Duration> {
+        if retry == 0 || retry >= self.max_attempts {
+            return None;
+        }
+        let delay = self
+            .base_delay
+            .checked_mul(2u32.saturating_pow(retry - 1))
+            .unwrap_or(Duration::MAX);
+        Some(delay.min(self.max_delay))
+    }
+
+    /// Returns the delay before the retry `retry` like [`Self::backoff()`], randomly shortened by
+    /// the jitter.
+    pub fn delay(&self, retry: u32) -> Option<Duration> {
+        let delay = self.backoff(retry)?;
+        Some(delay.mul_f64(1.0 - self.jitter * random_fraction()))
+    }
+
+    /// Returns true if responses with `status` are retried.
+    pub fn is_retryable_status(&self, status: StatusCode) -> bool {
+        self.retryable_statuses.contains(&status.as_u16())
+    }
+}
+
+/// Returns a number from 0 to 1, which is random enough for jitter, without a dependency on
+/// `rand`.
+fn random_fraction() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Returns the delay the server asked for before retrying, from the `Retry-After` header of the
+/// response with `headers`, or from the `RetryInfo` details of its error `err`.
+pub fn server_retry_delay(
+    headers: &HeaderMap,
+    err: Option<&serde_json::Value>,
+) -> Option<Duration> {
+    retry_after_header(headers).or_else(|| retry_info_delay(err?))
+}
+
+/// Returns the delay of the `Retry-After` header in `headers`, which is a number of seconds or an
+/// HTTP date.
+pub(crate) fn retry_after_header(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// Returns the `retryDelay` of the `RetryInfo` details of the error `err`, like `1.5s`.
+pub(crate) fn retry_info_delay(err: &serde_json::Value) -> Option<Duration> {
+    let details = err["error"]["details"].as_array()?;
+    let delay = details
+        .iter()
+        .find_map(|detail| detail["retryDelay"].as_str())?;
+    let seconds = delay.strip_suffix('s')?.parse::<f64>().ok()?;
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// A [`Delegate`] retrying failures as a [`RetryPolicy`] says, and responses asking to be retried
+/// later as they say, unless the delegate of the call retries them, while forwarding all calls to
+/// it. It is used by all calls.
+pub struct RetryDelegate<'a> {
+    inner: &'a mut dyn Delegate,
+    policy: Option<&'a RetryPolicy>,
+    retry_after_limit: u32,
+    retries: u32,
+}
+
+impl<'a> RetryDelegate<'a> {
+    /// Returns a delegate retrying as `policy` says, if there is one, or otherwise retrying at most
+    /// `retry_after_limit` times after the delay the server asked for.
+    pub fn new(
+        inner: &'a mut dyn Delegate,
+        policy: Option<&'a RetryPolicy>,
+        retry_after_limit: u32,
+    ) -> Self {
+        RetryDelegate {
+            inner,
+            policy,
+            retry_after_limit,
+            retries: 0,
+        }
+    }
+
+    fn retry(&mut self, status: Option<StatusCode>, retry_after: Option<Duration>) -> Retry {
+        let retry = self.retries + 1;
+        let delay = match self.policy {
+            Some(policy) if status.map_or(true, |status| policy.is_retryable_status(status)) => {
+                policy.delay(retry).map(|delay| {
+                    retry_after.map_or(delay, |after| after.max(delay).min(policy.max_delay))
+                })
+            }
+            _ => match (status, retry_after) {
+                (
+                    Some(StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE),
+                    Some(delay),
+                ) if retry <= self.retry_after_limit && delay <= MAX_RETRY_AFTER => Some(delay),
+                _ => None,
+            },
+        };
+        match delay {
+            Some(delay) => {
+                self.retries = retry;
+                Retry::After(delay)
+            }
+            None => Retry::Abort,
+        }
+    }
+}
+
+impl<'a> Delegate for RetryDelegate<'a> {
+    fn forward_to(&mut self) -> Option<&mut dyn Delegate> {
+        Some(&mut *self.inner)
+    }
+
+    fn http_error(&mut self, err: &hyper::Error) -> Retry {
+        let retry = match self.inner.http_error(err) {
+            Retry::Abort => self.retry(None, None),
+            retry => retry,
+        };
+        without_timer(retry)
+    }
+
+    fn http_failure(
+        &mut self,
+        res: &hyper::Response<hyper::body::Body>,
+        err: Option<serde_json::Value>,
+    ) -> Retry {
+        let retry_after = server_retry_delay(res.headers(), err.as_ref());
+        let retry = match self.inner.http_failure(res, err) {
+            Retry::Abort => self.retry(Some(res.status()), retry_after),
+            retry => retry,
+        };
+        without_timer(retry)
+    }
+}
+
+/// Returns `retry`, unless there is no timer to wait for its delay, like on wasm targets.
+#[cfg(not(target_arch = "wasm32"))]
+fn without_timer(retry: Retry) -> Retry {
+    retry
+}
+
+/// Returns `retry`, unless there is no timer to wait for its delay, like on wasm targets.
+#[cfg(target_arch = "wasm32")]
+fn without_timer(_retry: Retry) -> Retry {
+    Retry::Abort
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::DefaultDelegate;
+
+    #[test]
+    fn delays_grow_exponentially() {
+        let policy = RetryPolicy::new()
+            .max_attempts(5)
+            .base_delay(Duration::from_secs(1))
+            .max_delay(Duration::from_secs(5))
+            .jitter(0.0);
+        let delays: Vec<_> = (0..=5).map(|retry| policy.delay(retry)).collect();
+        assert_eq!(
+            delays,
+            [None, Some(1), Some(2), Some(4), Some(5), None].map(|d| d.map(Duration::from_secs))
+        );
+
+        let policy = policy.jitter(2.0);
+        assert_eq!(policy.jitter, 1.0);
+        for _ in 0..100 {
+            assert!(policy.delay(3).unwrap() <= Duration::from_secs(4));
+        }
+    }
+
+    #[test]
+    fn retryable_failures_are_retried() {
+        let policy = RetryPolicy::new()
+            .max_attempts(2)
+            .jitter(0.0)
+            .retryable_statuses([503]);
+        let mut dd = DefaultDelegate;
+        let mut rd = RetryDelegate::new(&mut dd, Some(&policy), 0);
+        let response = |status: u16, retry_after: Option<&str>| {
+            let mut builder = hyper::Response::builder().status(status);
+            if let Some(seconds) = retry_after {
+                builder = builder.header(RETRY_AFTER, seconds);
+            }
+            builder.body(hyper::body::Body::empty()).unwrap()
+        };
+
+        assert!(matches!(
+            rd.http_failure(&response(404, None), None),
+            Retry::Abort
+        ));
+        assert!(matches!(
+            rd.http_failure(&response(503, Some("30")), None),
+            Retry::After(d) if d == Duration::from_secs(30)
+        ));
+        // the second attempt was the last one
+        assert!(matches!(
+            rd.http_failure(&response(503, None), None),
+            Retry::Abort
+        ));
+    }
+
+    #[test]
+    fn server_delays_are_capped_at_the_maximum_delay() {
+        let huge = serde_json::json!({"error": {
+            "details": [{ "@type": "type.googleapis.com/google.rpc.RetryInfo", "retryDelay": "1e300s" }]
+        }});
+        assert_eq!(retry_info_delay(&huge), None);
+        let negative = serde_json::json!({"error": {"details": [{ "retryDelay": "-1s" }]}});
+        assert_eq!(retry_info_delay(&negative), None);
+
+        let policy = RetryPolicy::new()
+            .max_attempts(3)
+            .max_delay(Duration::from_secs(10))
+            .jitter(0.0);
+        let mut dd = DefaultDelegate;
+        let mut rd = RetryDelegate::new(&mut dd, Some(&policy), 0);
+        let response = |retry_after: &str| {
+            hyper::Response::builder()
+                .status(503)
+                .header(RETRY_AFTER, retry_after)
+                .body(hyper::body::Body::empty())
+                .unwrap()
+        };
+        assert!(matches!(
+            rd.http_failure(&response("86400"), None),
+            Retry::After(d) if d == Duration::from_secs(10)
+        ));
+        let unavailable = hyper::Response::builder()
+            .status(503)
+            .body(hyper::body::Body::empty())
+            .unwrap();
+        assert!(matches!(
+            rd.http_failure(&unavailable, Some(huge)),
+            Retry::After(d) if d <= Duration::from_secs(10)
+        ));
+    }
+
+    #[test]
+    fn responses_are_retried_after_the_delay_they_ask_for() {
+        let mut dd = DefaultDelegate;
+        let mut rd = RetryDelegate::new(&mut dd, None, 2);
+        let response = |status: u16, retry_after: &str| {
+            hyper::Response::builder()
+                .status(status)
+                .header(RETRY_AFTER, retry_after)
+                .body(hyper::body::Body::empty())
+                .unwrap()
+        };
+        let rate_limited = serde_json::json!({"error": {
+            "code": 429,
+            "details": [{ "@type": "type.googleapis.com/google.rpc.RetryInfo", "retryDelay": "2s" }]
+        }});
+        let no_retry_after = hyper::Response::builder()
+            .status(429)
+            .body(hyper::body::Body::empty())
+            .unwrap();
+
+        assert!(matches!(
+            rd.http_failure(&response(500, "1"), None),
+            Retry::Abort
+        ));
+        assert!(matches!(
+            rd.http_failure(&no_retry_after, Some(rate_limited)),
+            Retry::After(d) if d == Duration::from_secs(2)
+        ));
+        assert!(matches!(
+            rd.http_failure(&response(503, "3600"), None),
+            Retry::Abort
+        ));
+        assert!(matches!(
+            rd.http_failure(&response(503, "1"), None),
+            Retry::After(d) if d == Duration::from_secs(1)
+        ));
+        // the limit of 2 retries was reached
+        assert!(matches!(
+            rd.http_failure(&response(503, "1"), None),
+            Retry::Abort
+        ));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(server_retry_delay(&headers, None), Some(Duration::ZERO));
+        assert_eq!(server_retry_delay(&HeaderMap::new(), None), None);
+    }
+}
@@ -0,0 +1,94 @@
+//! Calls as [`tower_service::Service`]
+//!
+//! A [`CallService`] makes one kind of call through a shared hub for each request it gets, which
+//! allows to wrap calls into tower middleware, like `Buffer`, `ConcurrencyLimit` or `LoadShed`.
+//!
+//! # Example
+//! ```ignore
+//! let hub = std::sync::Arc::new(Storage::new(client, auth));
+//! let mut get_bucket = CallService::new(hub, |hub: &Storage<_>, name: String| {
+//!     Box::pin(async move { hub.buckets().get(&name).doit().await })
+//! });
+//! let (_, bucket) = get_bucket.call("my-bucket".into()).await?;
+//! ```
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::future::BoxFuture;
+use tower_service::Service;
+
+use crate::{Error, Result};
+
+/// The function making a call of a [`CallService`], given the hub and the request.
+pub type CallFn<H, Req, T> = for<'h> fn(&'h H, Req) -> BoxFuture<'h, Result<T>>;
+
+/// A service making a call through the hub `H` for each request of type `Req`, which holds the
+/// parameters of the call, resulting in the response of the call, `T`.
+pub struct CallService<H, Req, T> {
+    hub: Arc<H>,
+    call: CallFn<H, Req, T>,
+}
+
+impl<H, Req, T> CallService<H, Req, T> {
+    /// Returns a service making calls with `call` through `hub`.
+    pub fn new(hub: Arc<H>, call: CallFn<H, Req, T>) -> Self {
+        CallService { hub, call }
+    }
+}
+
+impl<H, Req, T> Clone for CallService<H, Req, T> {
+    fn clone(&self) -> Self {
+        CallService {
+            hub: self.hub.clone(),
+            call: self.call,
+        }
+    }
+}
+
+impl<H, Req, T> Service<Req> for CallService<H, Req, T>
+where
+    H: Send + Sync + 'static,
+    Req: Send + 'static,
+    T: 'static,
+{
+    type Response = T;
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<T>>;
+
+    /// The hub is always ready, as it limits no resources itself.
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        let hub = self.hub.clone();
+        let call = self.call;
+        Box::pin(async move { call(&hub, req).await })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Hub {
+        prefix: &'static str,
+    }
+
+    impl Hub {
+        async fn greet(&self, name: &str) -> Result<String> {
+            Ok(format!("{}, {}", self.prefix, name))
+        }
+    }
+
+    #[tokio::test]
+    async fn calls_are_made_through_the_hub() {
+        let mut greet = CallService::new(Arc::new(Hub { prefix: "Hello" }), |hub, name: String| {
+            Box::pin(async move { hub.greet(&name).await })
+        });
+        assert!(futures::future::poll_fn(|cx| greet.poll_ready(cx))
+            .await
+            .is_ok());
+        assert_eq!(greet.call("world".into()).await.unwrap(), "Hello, world");
+    }
+}
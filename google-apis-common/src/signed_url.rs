@@ -0,0 +1,401 @@
+//! V4 signed URLs for Google Cloud Storage
+//!
+//! A signed URL grants time-limited access to a single object to whoever holds it, without
+//! requiring further authentication. See
+//! <https://cloud.google.com/storage/docs/access-control/signed-urls> for details.
+//!
+//! The URL is signed on behalf of a service account by a [`SignBlob`] implementation:
+//! - [`ServiceAccountSigner`] : signs locally using the private key of a service account
+//! - [`IamSigner`] : asks the IAM credentials API to sign, for when the key is not at hand,
+//!   like on Compute Engine
+//!
+//! [`crate::signing`] signs JWTs and POST policy documents with them as well.
+use std::error::Error as StdError;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use hyper::client::connect::Connection;
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE, USER_AGENT};
+use hyper::http::Uri;
+use ring::digest;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{Error, GetToken, Result};
+
+/// The longest time a V4 signed URL may be valid for.
+pub const MAX_EXPIRATION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+pub(crate) const SIGNING_ALGORITHM: &str = "GOOG4-RSA-SHA256";
+const IAM_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+
+/// The future of [`SignBlob::sign_blob()`].
+pub type SignBlobOutput<'a> = Pin<
+    Box<
+        dyn Future<Output = std::result::Result<Vec<u8>, Box<dyn StdError + Send + Sync>>>
+            + Send
+            + 'a,
+    >,
+>;
+
+/// Signs data on behalf of a service account, using RSA with SHA-256.
+pub trait SignBlob: Send + Sync {
+    /// The email address of the service account whose key is used for signing.
+    fn client_email(&self) -> &str;
+
+    /// Returns the signature of `blob`.
+    fn sign_blob<'a>(&'a self, blob: &'a [u8]) -> SignBlobOutput<'a>;
+}
+
+/// Signs locally, using the private key of a service account.
+pub struct ServiceAccountSigner {
+    client_email: String,
+    key_pair: ring::signature::RsaKeyPair,
+}
+
+impl ServiceAccountSigner {
+    /// Creates a signer from the PEM encoded PKCS#8 `private_key` of the service account
+    /// identified by `client_email`, as found in its JSON key file.
+    pub fn new(
+        client_email: String,
+        private_key: &str,
+    ) -> std::result::Result<Self, Box<dyn StdError + Send + Sync>> {
+        let der = base64::decode(
+            private_key
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with("-----"))
+                .collect::<String>(),
+        )?;
+        let key_pair = ring::signature::RsaKeyPair::from_pkcs8(&der)
+            .map_err(|err| format!("invalid service account key: {}", err))?;
+        Ok(ServiceAccountSigner {
+            client_email,
+            key_pair,
+        })
+    }
+
+    /// Creates a signer from a service account key, as read with
+    /// [`oauth2::read_service_account_key()`](crate::oauth2::read_service_account_key).
+    #[cfg(feature = "yup-oauth2")]
+    pub fn from_service_account_key(
+        key: &crate::oauth2::ServiceAccountKey,
+    ) -> std::result::Result<Self, Box<dyn StdError + Send + Sync>> {
+        Self::new(key.client_email.clone(), &key.private_key)
+    }
+}
+
+impl SignBlob for ServiceAccountSigner {
+    fn client_email(&self) -> &str {
+        &self.client_email
+    }
+
+    fn sign_blob<'a>(&'a self, blob: &'a [u8]) -> SignBlobOutput<'a> {
+        Box::pin(async move {
+            let mut signature = vec![0; self.key_pair.public().modulus_len()];
+            self.key_pair
+                .sign(
+                    &ring::signature::RSA_PKCS1_SHA256,
+                    &ring::rand::SystemRandom::new(),
+                    blob,
+                    &mut signature,
+                )
+                .map_err(|_| "failed to sign with service account key")?;
+            Ok(signature)
+        })
+    }
+}
+
+/// Signs remotely using the `signBlob` method of the IAM credentials API.
+///
+/// The authenticated principal needs the `iam.serviceAccounts.signBlob` permission
+/// on the service account.
+pub struct IamSigner<S> {
+    pub client: hyper::Client<S, hyper::body::Body>,
+    pub auth: Box<dyn GetToken>,
+    pub service_account_email: String,
+}
+
+impl<S> IamSigner<S> {
+    /// Creates a signer for the service account `service_account_email`, which makes its
+    /// requests with `client` and `auth`, like those of a hub.
+    pub fn new<A: GetToken + 'static>(
+        client: hyper::Client<S, hyper::body::Body>,
+        auth: A,
+        service_account_email: String,
+    ) -> Self {
+        IamSigner {
+            client,
+            auth: Box::new(auth),
+            service_account_email,
+        }
+    }
+}
+
+impl<S> SignBlob for IamSigner<S>
+where
+    S: tower_service::Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    fn client_email(&self) -> &str {
+        &self.service_account_email
+    }
+
+    fn sign_blob<'a>(&'a self, blob: &'a [u8]) -> SignBlobOutput<'a> {
+        Box::pin(async move {
+            let token = self
+                .auth
+                .get_token(&[IAM_SCOPE])
+                .await?
+                .ok_or("signBlob requires a token")?;
+            let url = format!(
+                "https://iamcredentials.googleapis.com/v1/projects/-/serviceAccounts/{}:signBlob",
+                percent_encode(&self.service_account_email, false)
+            );
+            let body = serde_json::json!({ "payload": base64::encode(blob) }).to_string();
+            let request = hyper::Request::builder()
+                .method(hyper::Method::POST)
+                .uri(url)
+                .header(USER_AGENT, "google-api-rust-client")
+                .header(AUTHORIZATION, format!("Bearer {}", token))
+                .header(CONTENT_TYPE, mime::APPLICATION_JSON.to_string())
+                .body(hyper::body::Body::from(body))?;
+
+            let mut res = self.client.request(request).await?;
+            let res_body_string = crate::get_body_as_string(res.body_mut()).await;
+            if !res.status().is_success() {
+                return Err(
+                    format!("signBlob failed with {}: {}", res.status(), res_body_string).into(),
+                );
+            }
+            let response: serde_json::Value = serde_json::from_str(&res_body_string)?;
+            let signed_blob = response["signedBlob"]
+                .as_str()
+                .ok_or("signBlob response lacks 'signedBlob'")?;
+            Ok(base64::decode(signed_blob)?)
+        })
+    }
+}
+
+/// Returns a V4 signed URL allowing to perform `method` on `object` in `bucket` until `expires_in`
+/// elapsed from `now`.
+///
+/// `base_url` is the storage endpoint, usually `https://storage.googleapis.com`.
+/// `headers` must be sent along with any request using the URL, `query` are additional
+/// query parameters, like `generation`, which are signed as well.
+#[allow(clippy::too_many_arguments)]
+pub async fn sign_url(
+    signer: &dyn SignBlob,
+    base_url: &str,
+    method: &str,
+    bucket: &str,
+    object: &str,
+    expires_in: Duration,
+    headers: &[(&str, &str)],
+    query: &[(&str, &str)],
+    now: DateTime<Utc>,
+) -> Result<String> {
+    if expires_in > MAX_EXPIRATION {
+        return Err(Error::Signing(
+            format!(
+                "signed URLs may expire in at most {} seconds",
+                MAX_EXPIRATION.as_secs()
+            )
+            .into(),
+        ));
+    }
+    let base_url = base_url.trim_end_matches('/');
+    let host = base_url
+        .parse::<Uri>()
+        .ok()
+        .and_then(|uri| uri.authority().map(|a| a.to_string()))
+        .ok_or_else(|| Error::Signing(format!("invalid base url '{}'", base_url).into()))?;
+
+    let datetime = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let credential_scope = format!("{}/auto/storage/goog4_request", now.format("%Y%m%d"));
+
+    let mut canonical_headers: Vec<(String, String)> = headers
+        .iter()
+        .map(|(k, v)| (k.to_lowercase(), v.trim().to_string()))
+        .chain(Some(("host".to_string(), host)))
+        .collect();
+    canonical_headers.sort();
+    let signed_headers = canonical_headers
+        .iter()
+        .map(|(k, _)| k.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let mut params: Vec<(String, String)> = vec![
+        ("X-Goog-Algorithm".into(), SIGNING_ALGORITHM.into()),
+        (
+            "X-Goog-Credential".into(),
+            format!("{}/{}", signer.client_email(), credential_scope),
+        ),
+        ("X-Goog-Date".into(), datetime.clone()),
+        ("X-Goog-Expires".into(), expires_in.as_secs().to_string()),
+        ("X-Goog-SignedHeaders".into(), signed_headers.clone()),
+    ];
+    params.extend(query.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+    params.sort();
+    let canonical_query = params
+        .iter()
+        .map(|(k, v)| format!("{}={}", percent_encode(k, false), percent_encode(v, false)))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let canonical_uri = format!(
+        "/{}/{}",
+        percent_encode(bucket, false),
+        percent_encode(object, true)
+    );
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\nUNSIGNED-PAYLOAD",
+        method,
+        canonical_uri,
+        canonical_query,
+        canonical_headers
+            .iter()
+            .map(|(k, v)| format!("{}:{}\n", k, v))
+            .collect::<String>(),
+        signed_headers
+    );
+    let string_to_sign = format!(
+        "{}\n{}\n{}\n{}",
+        SIGNING_ALGORITHM,
+        datetime,
+        credential_scope,
+        hex(digest::digest(&digest::SHA256, canonical_request.as_bytes()).as_ref())
+    );
+
+    let signature = signer
+        .sign_blob(string_to_sign.as_bytes())
+        .await
+        .map_err(Error::Signing)?;
+    Ok(format!(
+        "{}{}?{}&X-Goog-Signature={}",
+        base_url,
+        canonical_uri,
+        canonical_query,
+        hex(&signature)
+    ))
+}
+
+/// Percent-encodes everything but unreserved characters, as defined by RFC 3986.
+fn percent_encode(s: &str, keep_slash: bool) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            b'/' if keep_slash => "/".to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+    use std::sync::Mutex;
+
+    struct RecordingSigner(Mutex<Vec<u8>>);
+
+    impl SignBlob for RecordingSigner {
+        fn client_email(&self) -> &str {
+            "signer@project.iam.gserviceaccount.com"
+        }
+
+        fn sign_blob<'a>(&'a self, blob: &'a [u8]) -> SignBlobOutput<'a> {
+            *self.0.lock().unwrap() = blob.to_vec();
+            Box::pin(async { Ok(vec![0xde, 0xad, 0xbe, 0xef]) })
+        }
+    }
+
+    #[tokio::test]
+    async fn url_and_string_to_sign_follow_v4_scheme() {
+        let signer = RecordingSigner(Mutex::new(Vec::new()));
+        let now = Utc.with_ymd_and_hms(2019, 2, 1, 9, 0, 0).unwrap();
+        let url = sign_url(
+            &signer,
+            "https://storage.googleapis.com/",
+            "GET",
+            "bucket",
+            "dir/my file.txt",
+            Duration::from_secs(900),
+            &[("X-Goog-Meta-Foo", " bar ")],
+            &[("generation", "1")],
+            now,
+        )
+        .await
+        .unwrap();
+
+        let query = "X-Goog-Algorithm=GOOG4-RSA-SHA256\
+            &X-Goog-Credential=signer%40project.iam.gserviceaccount.com%2F20190201%2Fauto%2Fstorage%2Fgoog4_request\
+            &X-Goog-Date=20190201T090000Z&X-Goog-Expires=900\
+            &X-Goog-SignedHeaders=host%3Bx-goog-meta-foo&generation=1";
+        assert_eq!(
+            url,
+            format!(
+                "https://storage.googleapis.com/bucket/dir/my%20file.txt?{}&X-Goog-Signature=deadbeef",
+                query
+            )
+        );
+
+        let canonical_request = format!(
+            "GET\n/bucket/dir/my%20file.txt\n{}\nhost:storage.googleapis.com\nx-goog-meta-foo:bar\n\n\
+             host;x-goog-meta-foo\nUNSIGNED-PAYLOAD",
+            query
+        );
+        let expected = format!(
+            "GOOG4-RSA-SHA256\n20190201T090000Z\n20190201/auto/storage/goog4_request\n{}",
+            hex(digest::digest(&digest::SHA256, canonical_request.as_bytes()).as_ref())
+        );
+        assert_eq!(*signer.0.lock().unwrap(), expected.into_bytes());
+    }
+
+    #[tokio::test]
+    async fn bucket_is_percent_encoded() {
+        let signer = RecordingSigner(Mutex::new(Vec::new()));
+        let url = sign_url(
+            &signer,
+            "https://storage.googleapis.com",
+            "GET",
+            "my/bucket?",
+            "object",
+            Duration::from_secs(900),
+            &[],
+            &[],
+            Utc::now(),
+        )
+        .await
+        .unwrap();
+        assert!(url.starts_with("https://storage.googleapis.com/my%2Fbucket%3F/object?"));
+    }
+
+    #[tokio::test]
+    async fn expiration_is_limited_to_seven_days() {
+        let signer = RecordingSigner(Mutex::new(Vec::new()));
+        let res = sign_url(
+            &signer,
+            "https://storage.googleapis.com",
+            "GET",
+            "bucket",
+            "object",
+            MAX_EXPIRATION + Duration::from_secs(1),
+            &[],
+            &[],
+            Utc::now(),
+        )
+        .await;
+        assert!(matches!(res, Err(Error::Signing(_))));
+    }
+}
@@ -0,0 +1,237 @@
+//! JWTs and POST policy documents signed on behalf of a service account
+//!
+//! Like [signed URLs](crate::signed_url), they are signed by a [`SignBlob`] implementation,
+//! locally with the key of the service account, or remotely by the IAM credentials API with the
+//! credentials of any hub, like `IamSigner::new(hub.client.clone(), hub.auth.clone(), email)`.
+//! - [`sign_jwt()`] signs JWTs with any claims, and [`sign_audience_jwt()`] those asserting the
+//!   identity of the service account to a custom audience, like a service on Cloud Run
+//! - [`sign_post_policy()`] signs V4 POST policy documents, which let HTML forms upload objects
+//!   to Cloud Storage, see <https://cloud.google.com/storage/docs/authentication/signatures#policy-document>
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+
+use crate::signed_url::{hex, SignBlob, SIGNING_ALGORITHM};
+use crate::{Error, Result};
+
+/// The longest time a JWT for a custom audience may be valid for.
+pub const MAX_JWT_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// Returns a JWT with `claims`, signed with RS256 by `signer`.
+pub async fn sign_jwt(signer: &dyn SignBlob, claims: &Value) -> Result<String> {
+    let header = json!({"alg": "RS256", "typ": "JWT"});
+    let signing_input = format!(
+        "{}.{}",
+        base64_url(header.to_string().as_bytes()),
+        base64_url(claims.to_string().as_bytes())
+    );
+    let signature = signer
+        .sign_blob(signing_input.as_bytes())
+        .await
+        .map_err(Error::Signing)?;
+    Ok(format!("{}.{}", signing_input, base64_url(&signature)))
+}
+
+/// Returns a JWT asserting the identity of the service account of `signer` to `audience`, like
+/// `https://my-service-abc123-ew.a.run.app`, valid for `lifetime` from `now`.
+pub async fn sign_audience_jwt(
+    signer: &dyn SignBlob,
+    audience: &str,
+    lifetime: Duration,
+    now: DateTime<Utc>,
+) -> Result<String> {
+    if lifetime > MAX_JWT_LIFETIME {
+        return Err(Error::Signing(
+            format!(
+                "JWTs may be valid for at most {} seconds",
+                MAX_JWT_LIFETIME.as_secs()
+            )
+            .into(),
+        ));
+    }
+    let issued_at = now.timestamp();
+    let claims = json!({
+        "iss": signer.client_email(),
+        "sub": signer.client_email(),
+        "aud": audience,
+        "iat": issued_at,
+        "exp": issued_at + lifetime.as_secs() as i64,
+    });
+    sign_jwt(signer, &claims).await
+}
+
+/// A signed POST policy document, with the form to upload an object with it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PostPolicy {
+    /// The URL to post the form to
+    pub url: String,
+    /// The fields of the form, which precede the `file` field with the content of the object
+    pub fields: Vec<(String, String)>,
+}
+
+/// Returns a V4 POST policy document allowing to upload the object `key` to `bucket` until
+/// `expires_in` elapsed from `now`.
+///
+/// `base_url` is the storage endpoint, usually `https://storage.googleapis.com`. `fields` are
+/// further fields of the form, like `Content-Type`, which the policy requires to have these
+/// values, and `conditions` are further conditions, like `["content-length-range", 0, 1048576]`.
+#[allow(clippy::too_many_arguments)]
+pub async fn sign_post_policy(
+    signer: &dyn SignBlob,
+    base_url: &str,
+    bucket: &str,
+    key: &str,
+    expires_in: Duration,
+    fields: &[(&str, &str)],
+    conditions: &[Value],
+    now: DateTime<Utc>,
+) -> Result<PostPolicy> {
+    if expires_in > crate::signed_url::MAX_EXPIRATION {
+        return Err(Error::Signing(
+            format!(
+                "policy documents may expire in at most {} seconds",
+                crate::signed_url::MAX_EXPIRATION.as_secs()
+            )
+            .into(),
+        ));
+    }
+    let expiration = now
+        + chrono::Duration::from_std(expires_in).map_err(|err| Error::Signing(Box::new(err)))?;
+    let credential = format!(
+        "{}/{}/auto/storage/goog4_request",
+        signer.client_email(),
+        now.format("%Y%m%d")
+    );
+
+    let mut form_fields: Vec<(String, String)> = vec![
+        ("key".into(), key.into()),
+        ("x-goog-algorithm".into(), SIGNING_ALGORITHM.into()),
+        ("x-goog-credential".into(), credential),
+        (
+            "x-goog-date".into(),
+            now.format("%Y%m%dT%H%M%SZ").to_string(),
+        ),
+    ];
+    form_fields.extend(fields.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+
+    let policy_conditions: Vec<Value> = Some(json!({ "bucket": bucket }))
+        .into_iter()
+        .chain(form_fields.iter().map(|(k, v)| {
+            let mut condition = serde_json::Map::new();
+            condition.insert(k.clone(), Value::from(v.as_str()));
+            Value::Object(condition)
+        }))
+        .chain(conditions.iter().cloned())
+        .collect();
+    let policy = json!({
+        "conditions": policy_conditions,
+        "expiration": expiration.to_rfc3339_opts(SecondsFormat::Secs, true),
+    });
+    let policy = base64::encode(policy.to_string());
+    let signature = signer
+        .sign_blob(policy.as_bytes())
+        .await
+        .map_err(Error::Signing)?;
+
+    form_fields.push(("x-goog-signature".into(), hex(&signature)));
+    form_fields.push(("policy".into(), policy));
+    Ok(PostPolicy {
+        url: format!("{}/{}/", base_url.trim_end_matches('/'), bucket),
+        fields: form_fields,
+    })
+}
+
+fn base64_url(data: &[u8]) -> String {
+    base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::signed_url::SignBlobOutput;
+    use chrono::TimeZone;
+
+    struct FixedSigner;
+
+    impl SignBlob for FixedSigner {
+        fn client_email(&self) -> &str {
+            "signer@project.iam.gserviceaccount.com"
+        }
+
+        fn sign_blob<'a>(&'a self, _blob: &'a [u8]) -> SignBlobOutput<'a> {
+            Box::pin(async { Ok(vec![0xde, 0xad, 0xbe, 0xef]) })
+        }
+    }
+
+    fn decode_part(part: &str) -> Value {
+        serde_json::from_slice(&base64::decode_config(part, base64::URL_SAFE_NO_PAD).unwrap())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn audience_jwts_assert_the_service_account() {
+        let now = Utc.with_ymd_and_hms(2019, 2, 1, 9, 0, 0).unwrap();
+        let jwt = sign_audience_jwt(
+            &FixedSigner,
+            "https://my-service.example.com",
+            Duration::from_secs(600),
+            now,
+        )
+        .await
+        .unwrap();
+        let parts: Vec<_> = jwt.split('.').collect();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(decode_part(parts[0])["alg"], "RS256");
+        let claims = decode_part(parts[1]);
+        assert_eq!(claims["sub"], "signer@project.iam.gserviceaccount.com");
+        assert_eq!(claims["aud"], "https://my-service.example.com");
+        assert_eq!(claims["exp"], now.timestamp() + 600);
+        assert_eq!(parts[2], base64_url(&[0xde, 0xad, 0xbe, 0xef]));
+
+        let res = sign_audience_jwt(
+            &FixedSigner,
+            "aud",
+            MAX_JWT_LIFETIME + Duration::from_secs(1),
+            now,
+        )
+        .await;
+        assert!(matches!(res, Err(Error::Signing(_))));
+    }
+
+    #[tokio::test]
+    async fn post_policies_require_their_fields() {
+        let now = Utc.with_ymd_and_hms(2019, 2, 1, 9, 0, 0).unwrap();
+        let policy = sign_post_policy(
+            &FixedSigner,
+            "https://storage.googleapis.com/",
+            "bucket",
+            "uploads/file.txt",
+            Duration::from_secs(3600),
+            &[("Content-Type", "text/plain")],
+            &[json!(["content-length-range", 0, 1024])],
+            now,
+        )
+        .await
+        .unwrap();
+        assert_eq!(policy.url, "https://storage.googleapis.com/bucket/");
+        let field = |name: &str| {
+            policy
+                .fields
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(field("key"), Some("uploads/file.txt"));
+        assert_eq!(field("x-goog-date"), Some("20190201T090000Z"));
+        assert_eq!(field("x-goog-signature"), Some("deadbeef"));
+
+        let document: Value =
+            serde_json::from_slice(&base64::decode(field("policy").unwrap()).unwrap()).unwrap();
+        assert_eq!(document["expiration"], "2019-02-01T10:00:00Z");
+        let conditions = document["conditions"].as_array().unwrap();
+        assert_eq!(conditions[0], json!({"bucket": "bucket"}));
+        assert!(conditions.contains(&json!({"Content-Type": "text/plain"})));
+        assert!(conditions.contains(&json!(["content-length-range", 0, 1024])));
+    }
+}
@@ -0,0 +1,294 @@
+//! Typed values of the standard query parameters most APIs accept
+//!
+//! Call builders set them through their `alt()`, `xgafv()`, `upload_protocol()`, `quota_user()`,
+//! `user_ip()` and `callback()` methods, if the API declares the respective parameter, instead of
+//! passing strings to `param()`. Values with constraints, like the length of `quotaUser`, are
+//! checked when they are created, before any request is made.
+use std::error::Error as StdError;
+use std::fmt::{self, Display};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::url::Params;
+
+/// The data format of the response, the `alt` parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Alt {
+    /// Responses with Content-Type of `application/json`, which are decoded into the response
+    /// type of the method.
+    Json,
+    /// Media download with context-dependent Content-Type.
+    Media,
+    /// Responses with Content-Type of `application/x-protobuf`.
+    Proto,
+}
+
+impl Alt {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Alt::Json => "json",
+            Alt::Media => "media",
+            Alt::Proto => "proto",
+        }
+    }
+}
+
+/// The format of errors, the `$.xgafv` parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Xgafv {
+    /// v1 error format
+    V1,
+    /// v2 error format
+    V2,
+}
+
+impl Xgafv {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Xgafv::V1 => "1",
+            Xgafv::V2 => "2",
+        }
+    }
+}
+
+/// The protocol of media uploads, the `upload_protocol` parameter.
+///
+/// Not to be confused with [`crate::UploadProtocol`], which selects the upload method.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UploadProtocol {
+    Raw,
+    Multipart,
+    Resumable,
+}
+
+impl UploadProtocol {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UploadProtocol::Raw => "raw",
+            UploadProtocol::Multipart => "multipart",
+            UploadProtocol::Resumable => "resumable",
+        }
+    }
+}
+
+macro_rules! impl_display {
+    ($($ty:ident),*) => {
+        $(impl Display for $ty {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.as_str())
+            }
+        })*
+    };
+}
+
+/// The error of creating a standard parameter from an invalid value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidStandardParam {
+    /// The name of the parameter, like `quotaUser`
+    pub name: &'static str,
+    /// Why the value isn't valid
+    pub reason: String,
+}
+
+impl InvalidStandardParam {
+    fn new(name: &'static str, reason: impl Into<String>) -> Self {
+        InvalidStandardParam {
+            name,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl Display for InvalidStandardParam {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid value of '{}': {}", self.name, self.reason)
+    }
+}
+
+impl StdError for InvalidStandardParam {}
+
+/// The user quota is attributed to, the `quotaUser` parameter, which lets server-side
+/// applications enforce per-user limits.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct QuotaUser(String);
+
+impl QuotaUser {
+    /// The maximum number of characters of a quota user.
+    pub const MAX_LEN: usize = 40;
+
+    /// Returns the quota user `value`, which is an arbitrary, non-empty string of at most
+    /// [`Self::MAX_LEN`] characters.
+    pub fn new(value: impl Into<String>) -> Result<Self, InvalidStandardParam> {
+        let value = value.into();
+        if value.is_empty() {
+            return Err(InvalidStandardParam::new("quotaUser", "it is empty"));
+        }
+        let len = value.chars().count();
+        if len > Self::MAX_LEN {
+            return Err(InvalidStandardParam::new(
+                "quotaUser",
+                format!("it has {} characters, more than {}", len, Self::MAX_LEN),
+            ));
+        }
+        Ok(QuotaUser(value))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// The IP address of the end user quota is attributed to, the legacy `userIp` parameter.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UserIp(String);
+
+impl UserIp {
+    /// Returns the IP address `value`, which is an IPv4 or IPv6 address like `203.0.113.7`.
+    pub fn new(value: &str) -> Result<Self, InvalidStandardParam> {
+        IpAddr::from_str(value)
+            .map(UserIp::from)
+            .map_err(|err| InvalidStandardParam::new("userIp", err.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<IpAddr> for UserIp {
+    fn from(ip: IpAddr) -> Self {
+        UserIp(ip.to_string())
+    }
+}
+
+/// The name of the JavaScript function JSONP responses are wrapped in, the `callback` parameter.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Callback(String);
+
+impl Callback {
+    /// Returns the callback `value`, which is a JavaScript identifier like `handle_response`,
+    /// possibly qualified like `app.handle_response`.
+    pub fn new(value: impl Into<String>) -> Result<Self, InvalidStandardParam> {
+        let value = value.into();
+        let is_identifier = |name: &str| {
+            let mut chars = name.chars();
+            matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '$')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+        };
+        if !value.split('.').all(is_identifier) {
+            return Err(InvalidStandardParam::new(
+                "callback",
+                format!("'{}' isn't a JavaScript identifier", value),
+            ));
+        }
+        Ok(Callback(value))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl_display!(Alt, Xgafv, UploadProtocol, QuotaUser, UserIp, Callback);
+
+/// The standard parameters set on a call builder.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StandardParams {
+    pub alt: Option<Alt>,
+    pub xgafv: Option<Xgafv>,
+    pub upload_protocol: Option<UploadProtocol>,
+    pub quota_user: Option<QuotaUser>,
+    pub user_ip: Option<UserIp>,
+    pub callback: Option<Callback>,
+}
+
+impl StandardParams {
+    /// Returns the names of the parameters which are set.
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names = Vec::new();
+        if self.alt.is_some() {
+            names.push("alt");
+        }
+        if self.xgafv.is_some() {
+            names.push("$.xgafv");
+        }
+        if self.upload_protocol.is_some() {
+            names.push("upload_protocol");
+        }
+        if self.quota_user.is_some() {
+            names.push("quotaUser");
+        }
+        if self.user_ip.is_some() {
+            names.push("userIp");
+        }
+        if self.callback.is_some() {
+            names.push("callback");
+        }
+        names
+    }
+
+    /// Appends the parameters which are set to `params`.
+    pub fn push_to<'a>(&'a self, params: &mut Params<'a>) {
+        if let Some(alt) = self.alt {
+            params.push("alt", alt.as_str());
+        }
+        if let Some(xgafv) = self.xgafv {
+            params.push("$.xgafv", xgafv.as_str());
+        }
+        if let Some(protocol) = self.upload_protocol {
+            params.push("upload_protocol", protocol.as_str());
+        }
+        if let Some(quota_user) = &self.quota_user {
+            params.push("quotaUser", quota_user.as_str());
+        }
+        if let Some(user_ip) = &self.user_ip {
+            params.push("userIp", user_ip.as_str());
+        }
+        if let Some(callback) = &self.callback {
+            params.push("callback", callback.as_str());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn set_params_are_pushed() {
+        let standard = StandardParams {
+            alt: Some(Alt::Proto),
+            xgafv: Some(Xgafv::V2),
+            quota_user: Some(QuotaUser::new("user-1").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(standard.names(), ["alt", "$.xgafv", "quotaUser"]);
+
+        let mut params = Params::with_capacity(3);
+        standard.push_to(&mut params);
+        assert_eq!(
+            params.into_vec(),
+            [
+                ("alt".to_string(), "proto".to_string()),
+                ("$.xgafv".to_string(), "2".to_string()),
+                ("quotaUser".to_string(), "user-1".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        assert!(QuotaUser::new("a".repeat(QuotaUser::MAX_LEN)).is_ok());
+        assert!(QuotaUser::new("a".repeat(QuotaUser::MAX_LEN + 1)).is_err());
+        assert!(QuotaUser::new("").is_err());
+
+        assert_eq!(UserIp::new("203.0.113.7").unwrap().as_str(), "203.0.113.7");
+        assert_eq!(UserIp::new("2001:db8::1").unwrap().as_str(), "2001:db8::1");
+        assert_eq!(UserIp::new("localhost").unwrap_err().name, "userIp");
+
+        assert!(Callback::new("app.handle_response$1").is_ok());
+        assert!(Callback::new("alert(1)").is_err());
+        assert!(Callback::new("app.").is_err());
+        assert!(Callback::new("1st").is_err());
+    }
+}
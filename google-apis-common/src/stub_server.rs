@@ -0,0 +1,378 @@
+//! A fake server for any API, driven by its discovery document
+//!
+//! Requires the `stub-server` feature.
+//!
+//! [`StubApi`] matches requests against the paths of the methods in the document, and answers
+//! them with a canned response which is valid according to the response schema of the method.
+//! This allows integration tests of code using generated crates to run without network access.
+//!
+//! # Example
+//! ```ignore
+//! let stub = StubApi::from_discovery(include_str!("storage-api.json"))?
+//!     .respond("storage.buckets.get", json!({"name": "my-bucket"}));
+//! let server = stub.serve().await?;
+//! let mut hub = Storage::new(client, NoToken);
+//! hub.base_url(server.base_url());
+//! hub.root_url(server.root_url());
+//! ```
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serde_json::{json, Map, Value};
+use tokio::sync::oneshot;
+
+/// A method of the API, reachable at one of its paths.
+struct Route {
+    method_id: String,
+    http_method: Method,
+    /// The path template, relative to the root URL, like `storage/v1/b/{bucket}/o`
+    path: String,
+    response: Option<String>,
+}
+
+/// A fake implementation of an API.
+pub struct StubApi {
+    service_path: String,
+    routes: Vec<Route>,
+    schemas: Map<String, Value>,
+    responses: HashMap<String, Value>,
+}
+
+impl StubApi {
+    /// Creates the fake implementation of the API described by the discovery document `doc`.
+    pub fn from_discovery(doc: &str) -> serde_json::Result<Self> {
+        let doc: Value = serde_json::from_str(doc)?;
+        let service_path = doc["servicePath"].as_str().unwrap_or_default().to_string();
+        let mut routes = Vec::new();
+        add_routes(&doc, &service_path, &mut routes);
+        let schemas = doc["schemas"].as_object().cloned().unwrap_or_default();
+        Ok(StubApi {
+            service_path,
+            routes,
+            schemas,
+            responses: HashMap::new(),
+        })
+    }
+
+    /// Answer calls to the method with the given id, like `storage.buckets.get`, with `response`
+    /// instead of the generated one.
+    pub fn respond(mut self, method_id: &str, response: Value) -> Self {
+        self.responses.insert(method_id.to_string(), response);
+        self
+    }
+
+    /// Returns the response to a request to `path`, an absolute path without query.
+    pub fn handle(&self, http_method: &Method, path: &str) -> Response<Body> {
+        let path = path.trim_start_matches('/');
+        let route = self
+            .routes
+            .iter()
+            .find(|r| r.http_method == *http_method && matches_template(&r.path, path));
+        let route = match route {
+            Some(route) => route,
+            None => {
+                let error = json!({
+                    "error": {
+                        "code": 404,
+                        "message": format!("No method of the API handles {} /{}", http_method, path),
+                        "status": "NOT_FOUND",
+                    }
+                });
+                return json_response(StatusCode::NOT_FOUND, &error);
+            }
+        };
+        match (self.responses.get(&route.method_id), &route.response) {
+            (Some(value), _) => json_response(StatusCode::OK, value),
+            (None, Some(schema)) => {
+                let value = self.example_object(schema, &mut HashSet::new());
+                json_response(StatusCode::OK, &value)
+            }
+            (None, None) => Response::builder()
+                .status(StatusCode::NO_CONTENT)
+                .body(Body::empty())
+                .unwrap(),
+        }
+    }
+
+    /// Serves the API on a random port of the loopback interface, until the returned server
+    /// is dropped.
+    pub async fn serve(self) -> io::Result<StubServer> {
+        let service_path = self.service_path.clone();
+        let api = Arc::new(self);
+        let make_service = make_service_fn(move |_| {
+            let api = api.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let res = api.handle(req.method(), req.uri().path());
+                    async move { Ok::<_, Infallible>(res) }
+                }))
+            }
+        });
+        let server = hyper::Server::try_bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?
+            .serve(make_service);
+        let addr = server.local_addr();
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            stopped.await.ok();
+        }));
+        Ok(StubServer {
+            addr,
+            service_path,
+            _shutdown: shutdown,
+        })
+    }
+
+    fn example_object(&self, schema: &str, seen: &mut HashSet<String>) -> Value {
+        let schema_value = match self.schemas.get(schema) {
+            Some(schema_value) => schema_value,
+            None => return json!({}),
+        };
+        // recursive schemas end with an empty object
+        if !seen.insert(schema.to_string()) {
+            return json!({});
+        }
+        let value = self.example_value(schema_value, seen);
+        seen.remove(schema);
+        value
+    }
+
+    /// Returns a value of the given schema, which parses into the types of generated crates.
+    fn example_value(&self, schema: &Value, seen: &mut HashSet<String>) -> Value {
+        if let Some(schema) = schema["$ref"].as_str() {
+            return self.example_object(schema, seen);
+        }
+        match (schema["type"].as_str(), schema["format"].as_str()) {
+            (Some("object"), _) => {
+                let properties = schema["properties"].as_object();
+                let object = properties
+                    .into_iter()
+                    .flatten()
+                    .map(|(name, property)| (name.clone(), self.example_value(property, seen)))
+                    .collect();
+                Value::Object(object)
+            }
+            (Some("array"), _) => json!([]),
+            (Some("boolean"), _) => json!(false),
+            (Some("integer"), Some("int64" | "uint64")) => json!("0"),
+            (Some("string"), Some("int64" | "uint64")) => json!("0"),
+            (Some("integer"), _) => json!(0),
+            (Some("number"), _) => json!(0.0),
+            (Some("string"), Some("date-time" | "google-datetime")) => {
+                json!("1970-01-01T00:00:00Z")
+            }
+            (Some("string"), Some("date")) => json!("1970-01-01"),
+            (Some("string"), Some("google-duration")) => json!("0s"),
+            (Some("string"), _) => match schema["enum"].get(0) {
+                Some(first) => first.clone(),
+                None => json!(""),
+            },
+            _ => Value::Null,
+        }
+    }
+}
+
+/// A running [`StubApi`], which stops once dropped.
+pub struct StubServer {
+    addr: SocketAddr,
+    service_path: String,
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl StubServer {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The URL to pass to the `root_url()` method of a hub.
+    pub fn root_url(&self) -> String {
+        format!("http://{}/", self.addr)
+    }
+
+    /// The URL to pass to the `base_url()` method of a hub.
+    pub fn base_url(&self) -> String {
+        format!("http://{}/{}", self.addr, self.service_path)
+    }
+}
+
+fn add_routes(resource: &Value, service_path: &str, routes: &mut Vec<Route>) {
+    for method in resource["methods"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(_, m)| m)
+    {
+        let (method_id, http_method) = match (method["id"].as_str(), method["httpMethod"].as_str())
+        {
+            (Some(id), Some(http_method)) => (id, http_method),
+            _ => continue,
+        };
+        let http_method = match http_method.parse::<Method>() {
+            Ok(http_method) => http_method,
+            Err(_) => continue,
+        };
+        let response = method["response"]["$ref"].as_str().map(str::to_string);
+        let upload_paths = method["mediaUpload"]["protocols"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter_map(|(_, protocol)| protocol["path"].as_str())
+            .map(|path| path.trim_start_matches('/').to_string());
+        let path = method["path"]
+            .as_str()
+            .map(|path| format!("{}{}", service_path, path));
+        for path in path.into_iter().chain(upload_paths) {
+            routes.push(Route {
+                method_id: method_id.to_string(),
+                http_method: http_method.clone(),
+                path,
+                response: response.clone(),
+            });
+        }
+    }
+    for sub_resource in resource["resources"].as_object().into_iter().flatten() {
+        add_routes(sub_resource.1, service_path, routes);
+    }
+}
+
+/// Returns true if `path` matches the URI `template`, in which `{name}` matches a single
+/// segment, and `{+name}` any amount of them.
+fn matches_template(template: &str, path: &str) -> bool {
+    let start = match template.find('{') {
+        Some(start) => start,
+        None => return template == path,
+    };
+    let (literal, rest) = template.split_at(start);
+    let path = match path.strip_prefix(literal) {
+        Some(path) => path,
+        None => return false,
+    };
+    let end = match rest.find('}') {
+        Some(end) => end,
+        None => return false,
+    };
+    let (variable, template) = (&rest[1..end], &rest[end + 1..]);
+    let reserved = variable.starts_with('+');
+    // the variable matches at least one character, backtracking from the longest match
+    (1..=path.len()).rev().any(|len| {
+        path.is_char_boundary(len)
+            && (reserved || !path[..len].contains('/'))
+            && matches_template(template, &path[len..])
+    })
+}
+
+fn json_response(status: StatusCode, value: &Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(value.to_string()))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DISCOVERY: &str = r#"{
+        "servicePath": "storage/v1/",
+        "resources": {
+            "objects": {
+                "methods": {
+                    "get": {
+                        "id": "storage.objects.get",
+                        "httpMethod": "GET",
+                        "path": "b/{bucket}/o/{object}",
+                        "response": {"$ref": "Object"}
+                    },
+                    "insert": {
+                        "id": "storage.objects.insert",
+                        "httpMethod": "POST",
+                        "path": "b/{bucket}/o",
+                        "mediaUpload": {"protocols": {"simple": {"path": "/upload/storage/v1/b/{bucket}/o"}}},
+                        "response": {"$ref": "Object"}
+                    },
+                    "delete": {
+                        "id": "storage.objects.delete",
+                        "httpMethod": "DELETE",
+                        "path": "{+name}:delete"
+                    }
+                }
+            }
+        },
+        "schemas": {
+            "Object": {
+                "type": "object",
+                "properties": {
+                    "name": {"type": "string"},
+                    "size": {"type": "string", "format": "uint64"},
+                    "updated": {"type": "string", "format": "date-time"},
+                    "acl": {"type": "array", "items": {"$ref": "Object"}},
+                    "owner": {"$ref": "Object"}
+                }
+            }
+        }
+    }"#;
+
+    async fn body(res: Response<Body>) -> Value {
+        serde_json::from_slice(&hyper::body::to_bytes(res.into_body()).await.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn templates_match_paths() {
+        assert!(matches_template("b/{bucket}/o", "b/my-bucket/o"));
+        assert!(!matches_template("b/{bucket}/o", "b/my/bucket/o"));
+        assert!(!matches_template("b/{bucket}/o", "b//o"));
+        assert!(matches_template(
+            "v1/{+name}:cancel",
+            "v1/projects/p/ops/1:cancel"
+        ));
+        assert!(!matches_template(
+            "v1/{+name}:cancel",
+            "v1/projects/p/ops/1"
+        ));
+    }
+
+    #[tokio::test]
+    async fn requests_are_answered_from_the_discovery_document() {
+        let api = StubApi::from_discovery(DISCOVERY)
+            .unwrap()
+            .respond("storage.objects.insert", json!({"name": "uploaded"}));
+
+        let res = api.handle(&Method::GET, "/storage/v1/b/bucket/o/object");
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            body(res).await,
+            json!({"name": "", "size": "0", "updated": "1970-01-01T00:00:00Z", "acl": [], "owner": {}})
+        );
+
+        let res = api.handle(&Method::POST, "/upload/storage/v1/b/bucket/o");
+        assert_eq!(body(res).await, json!({"name": "uploaded"}));
+
+        let res = api.handle(&Method::DELETE, "/storage/v1/a/b:delete");
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let res = api.handle(&Method::PUT, "/storage/v1/b/bucket/o/object");
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn stub_api_is_served() {
+        let server = StubApi::from_discovery(DISCOVERY)
+            .unwrap()
+            .serve()
+            .await
+            .unwrap();
+        let url = format!("{}b/bucket/o/object", server.base_url());
+        let res = hyper::Client::new()
+            .get(url.parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(body(res).await["name"], "");
+    }
+}
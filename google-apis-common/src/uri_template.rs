@@ -0,0 +1,227 @@
+//! Expansion of the URI templates of method paths
+//!
+//! Paths of methods are URI templates like `b/{bucket}/o/{object}` or `v1/{+name}:cancel`, whose
+//! expressions [`expand()`] replaces with the percent-encoded values of the path parameters, as
+//! RFC 6570 demands: simple expressions like `{name}` encode all but the unreserved characters,
+//! while reserved expressions like `{+name}` keep reserved characters like `/`, so that names of
+//! resources stay readable. Unlike RFC 6570, reserved expressions encode `?` and `#`, which would
+//! end the path, and `[` and `]`, which are only valid in hosts.
+use std::error::Error as StdError;
+use std::fmt::{self, Display};
+
+use ::url::percent_encoding::{percent_encode, EncodeSet};
+
+use crate::url::Params;
+
+/// The error of expanding a URI template, or of parsing the URL it was expanded into.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UriTemplateError {
+    /// The value of the parameter of an expression, like `name` of `{+name}`, wasn't set
+    MissingParam(String),
+    /// The value of the parameter of an expression is empty, which would address another resource
+    EmptyParam(String),
+    /// An expression starting at the byte offset isn't closed, or has no name
+    InvalidExpression(String, usize),
+    /// The URL isn't valid, with the reason
+    InvalidUrl(String, String),
+}
+
+impl Display for UriTemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UriTemplateError::MissingParam(name) => {
+                write!(f, "the path parameter '{}' isn't set", name)
+            }
+            UriTemplateError::EmptyParam(name) => {
+                write!(f, "the path parameter '{}' is empty", name)
+            }
+            UriTemplateError::InvalidExpression(template, offset) => write!(
+                f,
+                "the URI template '{}' has an invalid expression at {}",
+                template, offset
+            ),
+            UriTemplateError::InvalidUrl(url, err) => {
+                write!(f, "the URL '{}' isn't valid: {}", url, err)
+            }
+        }
+    }
+}
+
+impl StdError for UriTemplateError {}
+
+/// Encodes all bytes but the unreserved characters of RFC 3986.
+#[derive(Clone, Copy, Debug)]
+#[allow(non_camel_case_types)]
+pub(crate) struct UNRESERVED_ENCODE_SET;
+
+impl EncodeSet for UNRESERVED_ENCODE_SET {
+    fn contains(&self, byte: u8) -> bool {
+        !(byte.is_ascii_alphanumeric() || b"-._~".contains(&byte))
+    }
+}
+
+/// Encodes all bytes but the unreserved characters and the reserved characters which can appear
+/// in a path. `%` is handled by [`encode_reserved()`].
+#[derive(Clone, Copy, Debug)]
+#[allow(non_camel_case_types)]
+struct PATH_ENCODE_SET;
+
+impl EncodeSet for PATH_ENCODE_SET {
+    fn contains(&self, byte: u8) -> bool {
+        UNRESERVED_ENCODE_SET.contains(byte) && !b"/:@!$&'()*+,;=".contains(&byte)
+    }
+}
+
+/// Returns `value` percent-encoded for a simple expression like `{name}`.
+pub fn encode_simple(value: &str) -> String {
+    percent_encode(value.as_bytes(), UNRESERVED_ENCODE_SET).to_string()
+}
+
+/// Returns `value` percent-encoded for a reserved expression like `{+name}`, which keeps the
+/// percent-encoded triplets of the value, and encodes all other `%`.
+pub fn encode_reserved(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut encoded = String::with_capacity(value.len());
+    let mut start = 0;
+    for (i, &byte) in bytes.iter().enumerate() {
+        if byte != b'%' {
+            continue;
+        }
+        encoded.extend(percent_encode(&bytes[start..i], PATH_ENCODE_SET));
+        let is_triplet = bytes.len() > i + 2
+            && bytes[i + 1].is_ascii_hexdigit()
+            && bytes[i + 2].is_ascii_hexdigit();
+        encoded.push_str(if is_triplet { "%" } else { "%25" });
+        start = i + 1;
+    }
+    encoded.extend(percent_encode(&bytes[start..], PATH_ENCODE_SET));
+    encoded
+}
+
+/// Returns `template` with each of its expressions replaced by the encoded value of its parameter
+/// in `params`.
+///
+/// Supported are simple expressions like `{name}`, reserved expressions like `{+name}` and
+/// path segment expressions like `{/name*}`, the only ones of method paths. Values are
+/// substituted in one pass, so expressions in values stay as they are.
+pub fn expand(template: &str, params: &Params) -> Result<String, UriTemplateError> {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        expanded.push_str(&rest[..open]);
+        let offset = template.len() - rest.len() + open;
+        let invalid = || UriTemplateError::InvalidExpression(template.to_string(), offset);
+        let close = rest[open..].find('}').ok_or_else(invalid)? + open;
+        let expression = &rest[open + 1..close];
+        let (operator, name) = match expression.as_bytes().first() {
+            Some(b'+') => ('+', &expression[1..]),
+            Some(b'/') => ('/', expression[1..].trim_end_matches('*')),
+            _ => (' ', expression),
+        };
+        if name.is_empty() {
+            return Err(invalid());
+        }
+        let value = params
+            .get(name)
+            .ok_or_else(|| UriTemplateError::MissingParam(name.to_string()))?;
+        if value.is_empty() {
+            return Err(UriTemplateError::EmptyParam(name.to_string()));
+        }
+        match operator {
+            '+' => expanded.push_str(&encode_reserved(value)),
+            '/' => {
+                expanded.push('/');
+                expanded.push_str(&encode_simple(value));
+            }
+            _ => expanded.push_str(&encode_simple(value)),
+        }
+        rest = &rest[close + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Returns the names of the parameters of the expressions of `template`, like `name` of
+/// `{+name}`, in the order they appear. Invalid expressions are skipped.
+pub fn param_names(template: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let close = match rest[open..].find('}') {
+            Some(close) => close + open,
+            None => break,
+        };
+        let name = rest[open + 1..close]
+            .trim_start_matches(|c| c == '+' || c == '/')
+            .trim_end_matches('*');
+        if !name.is_empty() {
+            names.push(name);
+        }
+        rest = &rest[close + 1..];
+    }
+    names
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn params<'a>(values: &[(&'a str, &'a str)]) -> Params<'a> {
+        let mut params = Params::with_capacity(values.len());
+        for &(name, value) in values {
+            params.push(name, value);
+        }
+        params
+    }
+
+    #[test]
+    fn templates_are_expanded_with_encoded_values() {
+        let params = params(&[
+            ("bucket", "my bucket"),
+            ("object", "a/b%c?d#e~f"),
+            ("name", "projects/p/ops/a b%2Fc%zz?#[]"),
+            ("id", "{bucket}"),
+        ]);
+        assert_eq!(
+            expand("b/{bucket}/o/{object}", &params).unwrap(),
+            "b/my%20bucket/o/a%2Fb%25c%3Fd%23e~f"
+        );
+        assert_eq!(
+            expand("v1/{+name}:cancel", &params).unwrap(),
+            "v1/projects/p/ops/a%20b%2Fc%25zz%3F%23%5B%5D:cancel"
+        );
+        assert_eq!(expand("v1{/id*}", &params).unwrap(), "v1/%7Bbucket%7D");
+        assert_eq!(expand("v1/static", &params).unwrap(), "v1/static");
+    }
+
+    #[test]
+    fn invalid_templates_are_errors() {
+        assert_eq!(
+            expand("v1/{name}", &params(&[("name", "")])),
+            Err(UriTemplateError::EmptyParam("name".into()))
+        );
+        let params = params(&[("name", "n")]);
+        assert_eq!(
+            expand("v1/{project}", &params),
+            Err(UriTemplateError::MissingParam("project".into()))
+        );
+        assert_eq!(
+            expand("v1/{name", &params),
+            Err(UriTemplateError::InvalidExpression("v1/{name".into(), 3))
+        );
+        assert_eq!(
+            expand("v1/{+}", &params),
+            Err(UriTemplateError::InvalidExpression("v1/{+}".into(), 3))
+        );
+        assert_eq!(encode_reserved("100%"), "100%25");
+    }
+
+    #[test]
+    fn param_names_are_found() {
+        assert_eq!(
+            param_names("v1/{+name}:cancel/{id}{/path*}"),
+            ["name", "id", "path"]
+        );
+        assert!(param_names("v1/static/{}").is_empty());
+    }
+}
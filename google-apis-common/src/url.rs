@@ -1,38 +1,101 @@
 use std::borrow::Cow;
 
-use ::url::percent_encoding::{percent_encode, DEFAULT_ENCODE_SET};
 use ::url::Url;
+use smallvec::SmallVec;
 
+use crate::uri_template::{self, UriTemplateError};
+
+/// The number of parameters kept inline, which suffices for most calls, like getting a resource
+/// by its name.
+const INLINE_PARAMS: usize = 8;
+
+/// A parameter of a call, with its name and value.
+struct Param<'a> {
+    name: &'a str,
+    value: Cow<'a, str>,
+    /// Whether the parameter is substituted into the path, which leaves it out of the query
+    in_path: bool,
+}
+
+/// The parameters of a call, which are path parameters, substituted into the URI template of its
+/// path, or query parameters, in the order they were added.
+#[derive(Default)]
 pub struct Params<'a> {
-    params: Vec<(&'a str, Cow<'a, str>)>,
+    params: SmallVec<[Param<'a>; INLINE_PARAMS]>,
 }
 
 impl<'a> Params<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
-            params: Vec::with_capacity(capacity),
+            params: SmallVec::with_capacity(capacity),
         }
     }
 
+    /// Appends the query parameter `param`.
     pub fn push<I: Into<Cow<'a, str>>>(&mut self, param: &'a str, value: I) {
-        self.params.push((param, value.into()))
+        self.params.push(Param {
+            name: param,
+            value: value.into(),
+            in_path: false,
+        })
+    }
+
+    /// Appends the path parameter `param`, whose value is only used to expand the path.
+    pub fn push_path<I: Into<Cow<'a, str>>>(&mut self, param: &'a str, value: I) {
+        self.params.push(Param {
+            name: param,
+            value: value.into(),
+            in_path: true,
+        })
+    }
+
+    /// Sets the value of the parameter `param`, or appends it as query parameter if it isn't set.
+    pub fn set<I: Into<Cow<'a, str>>>(&mut self, param: &'a str, value: I) {
+        match self.params.iter_mut().find(|p| p.name == param) {
+            Some(p) => p.value = value.into(),
+            None => self.push(param, value),
+        }
     }
 
+    /// Appends `params` sorted by name, which makes URLs independent of the iteration order of
+    /// maps holding them.
     pub fn extend<I: Iterator<Item = (&'a String, IC)>, IC: Into<Cow<'a, str>>>(
         &mut self,
         params: I,
     ) {
-        self.params
-            .extend(params.map(|(k, v)| (k.as_str(), v.into())))
+        let start = self.params.len();
+        for (name, value) in params {
+            self.push(name.as_str(), value);
+        }
+        self.params[start..].sort_by_key(|p| p.name);
     }
 
     pub fn get(&self, param_name: &str) -> Option<&str> {
         self.params
             .iter()
-            .find(|(name, _)| name == &param_name)
-            .map(|(_, param)| param.as_ref())
+            .find(|p| p.name == param_name)
+            .map(|p| p.value.as_ref())
+    }
+
+    /// Returns the names and values of the query parameters.
+    pub fn query(&self) -> impl Iterator<Item = (&str, &str)> + '_ {
+        // Shortening the lifetime of the values keeps it out of the returned type
+        let params: &[Param<'_>] = &self.params;
+        params
+            .iter()
+            .filter(|p| !p.in_path)
+            .map(|p| (p.name, p.value.as_ref()))
     }
 
+    /// Substitutes the URI template expression `from`, like `{name}` or `{+name}`, in `url` with
+    /// the value of `param`, which is empty if it isn't set.
+    ///
+    /// With `url_encode`, values are percent-encoded like [`uri_template::expand()`] does, which
+    /// crates generated since replaced this with. Otherwise, values are substituted verbatim.
     pub fn uri_replacement(
         &self,
         url: String,
@@ -40,32 +103,36 @@ impl<'a> Params<'a> {
         from: &str,
         url_encode: bool,
     ) -> String {
-        if url_encode {
-            let mut replace_with: Cow<str> = self.get(param).unwrap_or_default().into();
-            if from.as_bytes()[1] == b'+' {
-                replace_with = percent_encode(replace_with.as_bytes(), DEFAULT_ENCODE_SET)
-                    .to_string()
-                    .into();
-            }
-            url.replace(from, &replace_with)
-        } else {
-            let replace_with = self
-                .get(param)
-                .expect("to find substitution value in params");
-
-            url.replace(from, replace_with)
+        let value = self.get(param).unwrap_or_default();
+        if !url_encode {
+            return url.replace(from, value);
         }
+        let replace_with = if from.starts_with("{+") {
+            uri_template::encode_reserved(value)
+        } else {
+            uri_template::encode_simple(value)
+        };
+        url.replace(from, &replace_with)
     }
 
+    /// Removes the parameters named `to_remove`, which crates generated before path parameters
+    /// were added with [`Params::push_path()`] did with them once the path was expanded.
     pub fn remove_params(&mut self, to_remove: &[&str]) {
-        self.params.retain(|(n, _)| !to_remove.contains(n))
+        self.params.retain(|p| !to_remove.contains(&p.name))
     }
 
-    pub fn inner_mut(&mut self) -> &mut Vec<(&'a str, Cow<'a, str>)> {
-        self.params.as_mut()
+    /// Returns the query parameters, in the order they were added.
+    pub fn into_vec(self) -> Vec<(String, String)> {
+        self.params
+            .into_iter()
+            .filter(|p| !p.in_path)
+            .map(|p| (p.name.to_string(), p.value.into_owned()))
+            .collect()
     }
 
-    pub fn parse_with_url(&self, url: &str) -> Url {
-        Url::parse_with_params(url, &self.params).unwrap()
+    /// Returns `url` with the query parameters as query, or an error if it isn't valid.
+    pub fn parse_with_url(&self, url: &str) -> Result<Url, UriTemplateError> {
+        Url::parse_with_params(url, self.query())
+            .map_err(|err| UriTemplateError::InvalidUrl(url.to_string(), err.to_string()))
     }
 }
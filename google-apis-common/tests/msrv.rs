@@ -0,0 +1,166 @@
+//! Verifies that the generated crates and the common crates declare the `rust_version` of
+//! `etc/api/shared.yaml`, and that the templates of the generator and the common crates only use
+//! std APIs and syntax available in it, without needing that toolchain.
+//!
+//! The checks of the sources are textual, so they only know about the items listed in
+//! [`FEATURES`]. Add an item there when a review finds a new one. The `msrv` job of the CI builds
+//! everything with the toolchain itself.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Patterns of std APIs or syntax, with the version of Rust they became stable in.
+const FEATURES: &[(&str, (u32, u32))] = &[
+    // 1.63
+    ("std::thread::scope", (1, 63)),
+    ("OwnedFd", (1, 63)),
+    // 1.64
+    ("std::ffi::c_char", (1, 64)),
+    ("IntoFuture", (1, 64)),
+    // 1.65
+    ("std::backtrace::Backtrace", (1, 65)),
+    // 1.66
+    ("std::hint::black_box", (1, 66)),
+    (".checked_add_signed(", (1, 66)),
+    // 1.67
+    (".checked_ilog", (1, 67)),
+    // 1.70
+    (".is_some_and(", (1, 70)),
+    (".is_ok_and(", (1, 70)),
+    ("OnceLock", (1, 70)),
+    ("IsTerminal", (1, 70)),
+    // 1.73
+    (".div_ceil(", (1, 73)),
+    // 1.74
+    ("io::Error::other", (1, 74)),
+    // 1.76
+    (".inspect_err(", (1, 76)),
+    (".inspect_ok(", (1, 76)),
+    // 1.77
+    (".first_chunk(", (1, 77)),
+    // 1.80
+    ("LazyLock", (1, 80)),
+    ("LazyCell", (1, 80)),
+    (".take_if(", (1, 80)),
+    // 1.82
+    (".is_none_or(", (1, 82)),
+    ("std::iter::repeat_n", (1, 82)),
+    // 1.83
+    (".get_or_insert_default(", (1, 83)),
+];
+
+fn repo_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .to_owned()
+}
+
+fn parse_version(version: &str) -> (u32, u32) {
+    let mut parts = version.trim().trim_matches('"').split('.');
+    let major = parts.next().unwrap().parse().unwrap();
+    let minor = parts.next().unwrap().parse().unwrap();
+    (major, minor)
+}
+
+/// Returns the `rust_version` of `etc/api/shared.yaml`.
+fn rust_version() -> (u32, u32) {
+    let shared = fs::read_to_string(repo_root().join("etc/api/shared.yaml")).unwrap();
+    let line = shared
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("rust_version:"))
+        .expect("`cargo.rust_version` in etc/api/shared.yaml");
+    parse_version(line)
+}
+
+fn sources(dir: &Path, extensions: &[&str], files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            sources(&path, extensions, files);
+        } else if path
+            .extension()
+            .is_some_and(|ext| extensions.iter().any(|e| ext == *e))
+        {
+            files.push(path);
+        }
+    }
+}
+
+#[test]
+fn common_crates_declare_the_rust_version() {
+    let (major, minor) = rust_version();
+    for krate in [
+        "google-apis-common",
+        "google-clis-common",
+        "google-types-common",
+    ] {
+        let manifest = fs::read_to_string(repo_root().join(krate).join("Cargo.toml")).unwrap();
+        let declared = manifest
+            .lines()
+            .find_map(|line| line.strip_prefix("rust-version ="))
+            .unwrap_or_else(|| panic!("{} has no rust-version", krate));
+        assert_eq!(parse_version(declared), (major, minor), "{}", krate);
+    }
+}
+
+#[test]
+fn generated_crates_declare_the_rust_version() {
+    let (major, minor) = rust_version();
+    let root = repo_root();
+    let template =
+        fs::read_to_string(root.join("src/generator/templates/Cargo.toml.mako")).unwrap();
+    assert!(
+        template.contains("rust-version = \"${cargo.rust_version}\""),
+        "Cargo.toml.mako doesn't write the rust-version of the generated crates"
+    );
+    for entry in fs::read_dir(root.join("gen")).unwrap() {
+        let path = entry.unwrap().path().join("Cargo.toml");
+        let Ok(manifest) = fs::read_to_string(&path) else {
+            continue;
+        };
+        // Crates generated before the version was pinned have none, until they are regenerated.
+        if let Some(declared) = manifest
+            .lines()
+            .find_map(|line| line.strip_prefix("rust-version ="))
+        {
+            assert_eq!(
+                parse_version(declared),
+                (major, minor),
+                "{}",
+                path.display()
+            );
+        }
+    }
+}
+
+#[test]
+fn sources_only_use_features_of_the_rust_version() {
+    let msrv = rust_version();
+    let root = repo_root();
+    let mut files = Vec::new();
+    sources(&root.join("src/generator/templates"), &["mako"], &mut files);
+    sources(&root.join("google-apis-common/src"), &["rs"], &mut files);
+    sources(&root.join("google-clis-common/src"), &["rs"], &mut files);
+    sources(&root.join("google-types-common/src"), &["rs"], &mut files);
+
+    let mut violations = Vec::new();
+    for file in &files {
+        let contents = fs::read_to_string(file).unwrap();
+        for (line_no, line) in contents.lines().enumerate() {
+            let code = line.split("//").next().unwrap();
+            for (pattern, since) in FEATURES {
+                if *since > msrv && code.contains(pattern) {
+                    violations.push(format!(
+                        "{}:{}: `{}` needs Rust {}.{}",
+                        file.strip_prefix(&root).unwrap().display(),
+                        line_no + 1,
+                        pattern,
+                        since.0,
+                        since.1
+                    ));
+                }
+            }
+        }
+    }
+    assert!(violations.is_empty(), "{}", violations.join("\n"));
+}
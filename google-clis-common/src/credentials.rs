@@ -0,0 +1,174 @@
+//! Credentials of the CLIs
+//!
+//! Unless credentials are given on the command-line or in the profile, the CLIs use the
+//! Application Default Credentials, like the Cloud SDK: the file named by the
+//! `GOOGLE_APPLICATION_CREDENTIALS` environment variable, the user credentials of
+//! `gcloud auth application-default login`, or the service account of the instance the CLI runs
+//! on. Only without any of them, the user is asked to authorize the CLI in the browser.
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json as json;
+use serde_json::value::Value;
+use yup_oauth2::authorized_user::AuthorizedUserSecret;
+use yup_oauth2::{ApplicationSecret, ConsoleApplicationSecret, ServiceAccountKey};
+
+use crate::profile::Profile;
+use crate::{expand_home_dir, CLIError, ConfigurationError};
+
+/// The environment variable with the path of the credentials file to use by default.
+pub const CREDENTIALS_ENV: &str = "GOOGLE_APPLICATION_CREDENTIALS";
+
+/// The environment variable with the configuration directory of gcloud, if it's not the default.
+pub const GCLOUD_CONFIG_ENV: &str = "CLOUDSDK_CONFIG";
+
+/// The file with the credentials of `gcloud auth application-default login`, in the
+/// configuration directory of gcloud.
+pub const GCLOUD_CREDENTIALS_FILE: &str = "application_default_credentials.json";
+
+/// The environment variable with the host of the metadata server, if it's not the default.
+pub const METADATA_HOST_ENV: &str = "GCE_METADATA_HOST";
+
+/// The host of the metadata server of Compute Engine instances, Cloud Run services and the like.
+pub const DEFAULT_METADATA_HOST: &str = "169.254.169.254";
+
+/// The credentials of all requests of an invocation.
+pub enum Credentials {
+    /// An API key, sent instead of a token
+    ApiKey(String),
+    /// The key of a service account, from a file like those of `--key-file`
+    ServiceAccount(ServiceAccountKey),
+    /// The credentials of a user, like those of `gcloud auth application-default login`
+    AuthorizedUser(AuthorizedUserSecret),
+    /// The application secret the user authorizes in the browser
+    InstalledFlow(ApplicationSecret),
+    /// No credentials were found, which leaves the service account of the instance, if there is
+    /// a metadata server, or the installed flow with the application secret of the configuration
+    /// directory
+    Default,
+}
+
+impl Credentials {
+    /// Returns the credentials to use, which are `api_key` or those of `key_file`, both given on
+    /// the command-line, those of the profile, or the Application Default Credentials, in that
+    /// order.
+    pub fn resolve(
+        api_key: Option<&str>,
+        key_file: Option<&str>,
+        profile: &Profile,
+    ) -> Result<Credentials, CLIError> {
+        if let Some(key) = api_key {
+            return Ok(Credentials::ApiKey(key.to_string()));
+        }
+        if let Some(path) = key_file.or(profile.credentials.as_deref()) {
+            return Credentials::from_file(&expand_home_dir(path)?);
+        }
+        if let Some(path) = env::var(CREDENTIALS_ENV)
+            .ok()
+            .filter(|path| !path.is_empty())
+        {
+            return Credentials::from_file(&path);
+        }
+        match gcloud_credentials_path() {
+            Some(path) if path.is_file() => Credentials::from_file(&path.to_string_lossy()),
+            _ => Ok(Credentials::Default),
+        }
+    }
+
+    /// Returns the credentials of the file at `path`, which is a service account key, the
+    /// credentials of a user, or an application secret.
+    pub fn from_file(path: &str) -> Result<Credentials, CLIError> {
+        match fs::read_to_string(path) {
+            Ok(content) => Credentials::parse(path, &content),
+            Err(err) => Err(CLIError::Configuration(ConfigurationError::Io((
+                path.to_string(),
+                err,
+            )))),
+        }
+    }
+
+    fn parse(path: &str, content: &str) -> Result<Credentials, CLIError> {
+        let invalid = |err: String| {
+            CLIError::Configuration(ConfigurationError::InvalidCredentials((
+                path.to_string(),
+                err,
+            )))
+        };
+        let value: Value = json::from_str(content).map_err(|err| invalid(err.to_string()))?;
+        let credentials = match value["type"].as_str() {
+            Some("service_account") => json::from_value(value).map(Credentials::ServiceAccount),
+            Some("authorized_user") => json::from_value(value).map(Credentials::AuthorizedUser),
+            Some(other) => return Err(invalid(format!("unsupported type '{}'", other))),
+            None => json::from_value::<ConsoleApplicationSecret>(value).map(|secret| {
+                let secret = secret.installed.or(secret.web);
+                secret.map_or(Credentials::Default, Credentials::InstalledFlow)
+            }),
+        };
+        match credentials {
+            Ok(Credentials::Default) => {
+                Err(invalid("no installed or web application secret".into()))
+            }
+            Ok(credentials) => Ok(credentials),
+            Err(err) => Err(invalid(err.to_string())),
+        }
+    }
+}
+
+/// Returns the path of the credentials file of `gcloud auth application-default login`.
+pub fn gcloud_credentials_path() -> Option<PathBuf> {
+    let config_dir = match env::var(GCLOUD_CONFIG_ENV) {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ if cfg!(windows) => Path::new(&env::var("APPDATA").ok()?).join("gcloud"),
+        _ => PathBuf::from(expand_home_dir("~/.config/gcloud").ok()?),
+    };
+    Some(config_dir.join(GCLOUD_CREDENTIALS_FILE))
+}
+
+/// Returns the host of the metadata server.
+pub fn metadata_host() -> String {
+    env::var(METADATA_HOST_ENV)
+        .ok()
+        .filter(|host| !host.is_empty())
+        .unwrap_or_else(|| DEFAULT_METADATA_HOST.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn credentials_files_are_told_apart() {
+        let user = r#"{
+            "type": "authorized_user",
+            "client_id": "id",
+            "client_secret": "secret",
+            "refresh_token": "token"
+        }"#;
+        assert!(matches!(
+            Credentials::parse("adc.json", user),
+            Ok(Credentials::AuthorizedUser(_))
+        ));
+
+        let installed = r#"{"installed": {
+            "client_id": "id",
+            "client_secret": "secret",
+            "auth_uri": "https://accounts.google.com/o/oauth2/auth",
+            "token_uri": "https://oauth2.googleapis.com/token"
+        }}"#;
+        assert!(matches!(
+            Credentials::parse("secret.json", installed),
+            Ok(Credentials::InstalledFlow(_))
+        ));
+
+        assert!(Credentials::parse("key.json", r#"{"type": "external_account"}"#).is_err());
+        assert!(Credentials::parse("key.json", r#"{"type": "service_account"}"#).is_err());
+        assert!(Credentials::parse("key.json", "{}").is_err());
+
+        let profile = Profile::default();
+        assert!(matches!(
+            Credentials::resolve(Some("key"), None, &profile),
+            Ok(Credentials::ApiKey(key)) if key == "key"
+        ));
+    }
+}
@@ -1,3 +1,6 @@
+pub mod credentials;
+pub mod profile;
+
 use mime::Mime;
 use yup_oauth2::{ApplicationSecret, ConsoleApplicationSecret};
 use serde_json as json;
@@ -8,15 +11,16 @@ use std::env;
 use std::fmt;
 use std::fs;
 use std::io;
-use std::io::{stdout, Write};
+use std::io::{stdout, BufRead, IsTerminal, Read, Write};
 use std::path::Path;
 use std::str::FromStr;
-use std::string::ToString;
+use std::time::Duration;
 
 use std::default::Default;
 
 const FIELD_SEP: char = '.';
 
+#[derive(Clone, Copy)]
 pub enum ComplexType {
     Pod,
     Vec,
@@ -30,6 +34,7 @@ pub enum ComplexType {
 // F64(f64),
 // String(String),
 
+#[derive(Clone, Copy)]
 pub enum JsonType {
     Boolean,
     Int,
@@ -38,6 +43,7 @@ pub enum JsonType {
     String,
 }
 
+#[derive(Clone, Copy)]
 pub struct JsonTypeInfo {
     pub jtype: JsonType,
     pub ctype: ComplexType,
@@ -125,9 +131,9 @@ impl AsRef<str> for CallType {
 #[derive(Clone, Default)]
 pub struct FieldCursor(Vec<String>);
 
-impl ToString for FieldCursor {
-    fn to_string(&self) -> String {
-        self.0.join(".")
+impl fmt::Display for FieldCursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0.join("."))
     }
 }
 
@@ -419,6 +425,324 @@ pub fn writer_from_opts(arg: Option<&str>) -> Result<Box<dyn Write>, io::Error>
     }
 }
 
+enum PathToken<'a> {
+    Field(&'a str),
+    Index(usize),
+    All,
+}
+
+/// Returns the field of `value` at `path`, like `state` or `items[0].name`, where `[*]`, like in
+/// `items[*].name`, selects the rest of the path of all items of an array.
+pub fn select_field(value: &Value, path: &str) -> Result<Value, CLIError> {
+    let unknown = || CLIError::Field(FieldError::Unknown(path.to_string(), None, None));
+    let mut tokens = Vec::new();
+    for segment in path.split(FIELD_SEP) {
+        let (name, mut rest) = segment.split_at(segment.find('[').unwrap_or(segment.len()));
+        if name.is_empty() && rest.is_empty() {
+            return Err(CLIError::Field(FieldError::Empty));
+        }
+        if !name.is_empty() {
+            tokens.push(PathToken::Field(name));
+        }
+        while !rest.is_empty() {
+            let end = rest.find(']').ok_or_else(unknown)?;
+            tokens.push(match &rest[1..end] {
+                "*" => PathToken::All,
+                index => PathToken::Index(index.parse().map_err(|_| unknown())?),
+            });
+            rest = &rest[end + 1..];
+            if !rest.is_empty() && !rest.starts_with('[') {
+                return Err(unknown());
+            }
+        }
+    }
+    select_tokens(value, &tokens).ok_or_else(unknown)
+}
+
+fn select_tokens(value: &Value, tokens: &[PathToken]) -> Option<Value> {
+    match tokens.split_first() {
+        None => Some(value.clone()),
+        Some((PathToken::Field(name), rest)) => select_tokens(value.get(*name)?, rest),
+        Some((PathToken::Index(index), rest)) => select_tokens(value.get(*index)?, rest),
+        Some((PathToken::All, rest)) => value
+            .as_array()?
+            .iter()
+            .map(|item| select_tokens(item, rest))
+            .collect::<Option<Vec<_>>>()
+            .map(Value::Array),
+    }
+}
+
+/// Writes `value` as pretty JSON, or only its field at `field`, as selected by [`select_field()`].
+/// Selected strings are written without quotes, to be used by scripts as they are.
+pub fn write_json_output(
+    ostream: &mut dyn Write,
+    value: &Value,
+    field: Option<&str>,
+) -> Result<(), CLIError> {
+    let field = match field {
+        Some(path) => select_field(value, path)?,
+        None => {
+            json::to_writer_pretty(ostream, value).expect("write to be complete");
+            return Ok(());
+        }
+    };
+    match field {
+        Value::String(s) => writeln!(ostream, "{}", s),
+        field => json::to_writer_pretty(&mut *ostream, &field)
+            .map_err(io::Error::from)
+            .and_then(|_| writeln!(ostream)),
+    }
+    .expect("write to be complete");
+    Ok(())
+}
+
+/// Parses a duration like `60s`, `500ms`, `2m` or `1h`, or `30` for seconds, of the argument
+/// `arg_name`.
+pub fn parse_duration(arg_name: &str, value: &str) -> Result<Duration, CLIError> {
+    let parse_error = |desc: &str| {
+        CLIError::ParseError(
+            arg_name.to_string(),
+            "duration".to_string(),
+            value.to_string(),
+            desc.to_string(),
+        )
+    };
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| parse_error("expected a number, followed by a unit"))?;
+    let seconds = |factor: u64| {
+        amount
+            .checked_mul(factor)
+            .map(Duration::from_secs)
+            .ok_or_else(|| parse_error("the duration is too long"))
+    };
+    match unit {
+        "ms" => Ok(Duration::from_millis(amount)),
+        "" | "s" => Ok(Duration::from_secs(amount)),
+        "m" => seconds(60),
+        "h" => seconds(60 * 60),
+        _ => Err(parse_error("the unit must be one of 'ms', 's', 'm' or 'h'")),
+    }
+}
+
+/// How many requests of a batch file are made at the same time, unless given on the command-line.
+pub const DEFAULT_BATCH_CONCURRENCY: usize = 8;
+
+/// A request of a batch file, which has the JSON value of one request per line.
+#[derive(Debug, PartialEq)]
+pub struct BatchRequest {
+    /// The number of the line of the request, starting at 1
+    pub line: usize,
+    /// The value of the request, or why the line isn't valid JSON
+    pub value: Result<Value, String>,
+}
+
+/// Returns the requests of the batch file at `path`, or of the standard input if it's `-`.
+pub fn read_batch_file(path: &str) -> Result<Vec<BatchRequest>, io::Error> {
+    let content = match path {
+        "-" => {
+            let mut content = String::new();
+            io::stdin().read_to_string(&mut content)?;
+            content
+        }
+        _ => fs::read_to_string(path)?,
+    };
+    Ok(parse_batch_requests(&content))
+}
+
+/// Returns the requests of the lines of a batch file, skipping empty lines.
+pub fn parse_batch_requests(content: &str) -> Vec<BatchRequest> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| BatchRequest {
+            line: index + 1,
+            value: json::from_str(line).map_err(|err| err.to_string()),
+        })
+        .collect()
+}
+
+/// Sets the fields of `value` in `base`, keeping the fields of objects in `base` which `value`
+/// doesn't have.
+pub fn merge_json_values(base: &mut Value, value: Value) {
+    match (base, value) {
+        (Value::Object(base), Value::Object(value)) => {
+            for (key, value) in value {
+                match base.get_mut(&key) {
+                    Some(field) => merge_json_values(field, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, value) => *base = value,
+    }
+}
+
+/// Returns the line of the output of a batch, for the request of batch file line `line`.
+pub fn batch_result(line: usize, result: Result<Value, String>) -> Value {
+    match result {
+        Ok(response) => json::json!({ "line": line, "response": response }),
+        Err(err) => json::json!({ "line": line, "error": err }),
+    }
+}
+
+/// Adds `page`, the response of a call returning one page of results, to `results`, the responses
+/// of the pages before, which start as null. The lists of both are concatenated, while the other
+/// fields are those of the last page, except for `nextPageToken`, which is removed.
+pub fn append_page(results: &mut Value, page: Value) {
+    match (&mut *results, page) {
+        (Value::Object(results), Value::Object(page)) => {
+            for (key, value) in page {
+                match (results.get_mut(&key), value) {
+                    (Some(Value::Array(items)), Value::Array(more)) => items.extend(more),
+                    (_, value) => {
+                        results.insert(key, value);
+                    }
+                }
+            }
+        }
+        (results, page) => *results = page,
+    }
+    if let Value::Object(results) = results {
+        results.remove("nextPageToken");
+    }
+}
+
+/// Returns the exit code of a call which failed with the canonical error code `status`, like
+/// `NOT_FOUND`, so that scripts can tell the errors they handle apart.
+pub fn exit_code_of_status(status: &str) -> i32 {
+    match status {
+        "NOT_FOUND" => 3,
+        "PERMISSION_DENIED" | "UNAUTHENTICATED" => 4,
+        "RESOURCE_EXHAUSTED" => 5,
+        "INVALID_ARGUMENT" | "FAILED_PRECONDITION" | "OUT_OF_RANGE" => 6,
+        "ABORTED" | "ALREADY_EXISTS" => 7,
+        "UNAVAILABLE" | "DEADLINE_EXCEEDED" => 8,
+        _ => 1,
+    }
+}
+
+/// A failed call, as written to standard error as one line of JSON.
+#[derive(Debug, Default)]
+pub struct ErrorReport {
+    /// The HTTP status of the response, if there was one
+    pub code: Option<u16>,
+    /// The canonical error code, like `NOT_FOUND`
+    pub status: String,
+    /// The reason the server gave, like `rateLimitExceeded`
+    pub reason: Option<String>,
+    /// How long the server asked to wait before retrying
+    pub retry_after: Option<Duration>,
+    pub message: String,
+}
+
+impl ErrorReport {
+    /// Returns the exit code of the program, by the canonical error code.
+    pub fn exit_code(&self) -> i32 {
+        exit_code_of_status(&self.status)
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut value = json::json!({
+            "code": self.code,
+            "status": self.status,
+            "reason": self.reason,
+            "retry_after": self.retry_after.map(|delay| delay.as_secs_f64()),
+            "message": self.message.trim(),
+        });
+        remove_json_null_values(&mut value);
+        value
+    }
+}
+
+impl fmt::Display for ErrorReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", json::json!({ "error": self.to_json() }))
+    }
+}
+
+/// A field which the method requires to be set in its request.
+pub struct RequiredField {
+    /// The path of the field on the command-line, like `test-spec.test-timeout`
+    pub name: &'static str,
+    /// The path of the field in the request, like `testSpec.testTimeout`
+    pub json_path: &'static str,
+    pub type_info: JsonTypeInfo,
+    /// The values of the field, if it's an enumeration, or none
+    pub choices: &'static [&'static str],
+}
+
+impl RequiredField {
+    /// Returns `object` with the field set to `value`, if it's valid.
+    fn set(&self, object: &Value, value: &str) -> Result<Value, InvalidOptionsError> {
+        let mut err = InvalidOptionsError::new();
+        if !self.choices.is_empty() && !self.choices.contains(&value) {
+            err.issues.push(CLIError::ParseError(
+                self.name.to_string(),
+                format!("one of {}", self.choices.join(", ")),
+                value.to_string(),
+                "unknown value".to_string(),
+            ));
+            return Err(err);
+        }
+        let mut object = object.clone();
+        FieldCursor::from(self.json_path).set_json_value(
+            &mut object,
+            value,
+            self.type_info,
+            &mut err,
+            &FieldCursor::from(self.name),
+        );
+        if err.issues.is_empty() {
+            Ok(object)
+        } else {
+            Err(err)
+        }
+    }
+}
+
+/// Asks for the value of each of `fields` which isn't set in `object` yet on the terminal, until
+/// it's valid. Does nothing if the standard input isn't a terminal.
+pub fn prompt_missing_fields(object: &mut Value, fields: &[RequiredField]) -> io::Result<()> {
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        return Ok(());
+    }
+    let mut input = stdin.lock();
+    for field in fields {
+        if select_field(object, field.json_path).is_ok() {
+            continue;
+        }
+        loop {
+            if field.choices.is_empty() {
+                eprint!("{}: ", field.name);
+            } else {
+                eprint!("{} ({}): ", field.name, field.choices.join("|"));
+            }
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            match field.set(object, line.trim()) {
+                Ok(value) => {
+                    *object = value;
+                    break;
+                }
+                Err(err) => eprint!("{}", err),
+            }
+        }
+    }
+    Ok(())
+}
+
 pub fn arg_from_str<'a, T>(
     arg: &str,
     err: &mut InvalidOptionsError,
@@ -473,6 +797,10 @@ pub enum ConfigurationError {
     HomeExpansionFailed(String),
     Secret(ApplicationSecretError),
     Io((String, io::Error)),
+    InvalidConfig((String, String)),
+    InvalidCredentials((String, String)),
+    UnknownProfile(String),
+    ProjectUnset,
 }
 
 impl fmt::Display for ConfigurationError {
@@ -495,6 +823,24 @@ impl fmt::Display for ConfigurationError {
                 "IO operation failed on path '{}' with error: {}.",
                 path, err
             ),
+            ConfigurationError::InvalidConfig((ref path, ref err)) => writeln!(
+                f,
+                "Could not parse configuration file at '{}' with error: {}.",
+                path, err
+            ),
+            ConfigurationError::InvalidCredentials((ref path, ref err)) => writeln!(
+                f,
+                "Could not read credentials at '{}' with error: {}.",
+                path, err
+            ),
+            ConfigurationError::UnknownProfile(ref name) => {
+                writeln!(f, "Profile '{}' is not in the configuration file.", name)
+            }
+            ConfigurationError::ProjectUnset => writeln!(
+                f,
+                "The project '{}' stands for is not set in the profile.",
+                profile::PROFILE_PROJECT
+            ),
         }
     }
 }
@@ -657,7 +1003,23 @@ pub fn assure_config_dir_exists(dir: &str) -> Result<String, CLIError> {
         return Err(CLIError::Configuration(ConfigurationError::DirectoryUnset));
     }
 
-    let expanded_config_dir = if trdir.as_bytes()[0] == b'~' {
+    let expanded_config_dir = expand_home_dir(trdir)?;
+
+    if let Err(err) = fs::create_dir(&expanded_config_dir) {
+        if err.kind() != io::ErrorKind::AlreadyExists {
+            return Err(CLIError::Configuration(
+                ConfigurationError::DirectoryCreationFailed((expanded_config_dir, err)),
+            ));
+        }
+    }
+
+    Ok(expanded_config_dir)
+}
+
+/// Returns `path` with a leading `~` replaced by the home directory of the current user.
+pub fn expand_home_dir(path: &str) -> Result<String, CLIError> {
+    let trdir = path.trim();
+    Ok(if trdir.as_bytes().first() == Some(&b'~') {
         match env::var("HOME")
             .ok()
             .or_else(|| env::var("UserProfile").ok())
@@ -674,17 +1036,27 @@ pub fn assure_config_dir_exists(dir: &str) -> Result<String, CLIError> {
         }
     } else {
         trdir.to_string()
-    };
+    })
+}
 
-    if let Err(err) = fs::create_dir(&expanded_config_dir) {
-        if err.kind() != io::ErrorKind::AlreadyExists {
-            return Err(CLIError::Configuration(
-                ConfigurationError::DirectoryCreationFailed((expanded_config_dir, err)),
-            ));
+/// Returns the application secret of the file at `path`, like the `credentials` of a profile.
+pub fn application_secret_from_file(path: &str) -> Result<ApplicationSecret, CLIError> {
+    let path = expand_home_dir(path)?;
+    let f = fs::File::open(&path).map_err(|io_err| {
+        CLIError::Configuration(ConfigurationError::Io((path.clone(), io_err)))
+    })?;
+    match json::de::from_reader::<_, ConsoleApplicationSecret>(f) {
+        Err(json_err) => Err(CLIError::Configuration(ConfigurationError::Secret(
+            ApplicationSecretError::DecoderError((path, json_err)),
+        ))),
+        Ok(console_secret) => {
+            console_secret
+                .installed
+                .ok_or(CLIError::Configuration(ConfigurationError::Secret(
+                    ApplicationSecretError::FormatError(path),
+                )))
         }
     }
-
-    Ok(expanded_config_dir)
 }
 
 pub fn application_secret_from_directory(
@@ -750,7 +1122,6 @@ pub fn application_secret_from_directory(
     unreachable!();
 }
 
-
 #[cfg(test)]
 mod test_cli {
     use super::*;
@@ -793,4 +1164,158 @@ mod test_cli {
         assert_eq!(c.num_fields(), 3);
         assert_eq!(c.to_string(), "one.beer.one");
     }
+
+    #[test]
+    fn fields_are_selected() {
+        let value = json::json!({
+            "state": "FINISHED",
+            "items": [{ "name": "a", "size": 1 }, { "name": "b", "size": 2 }],
+        });
+
+        assert_eq!(select_field(&value, "state").unwrap(), "FINISHED");
+        assert_eq!(select_field(&value, "items[1].size").unwrap(), 2);
+        assert_eq!(
+            select_field(&value, "items[*].name").unwrap(),
+            json::json!(["a", "b"])
+        );
+        assert!(select_field(&value, "items[2]").is_err());
+        assert!(select_field(&value, "items[x]").is_err());
+        assert!(select_field(&value, "missing").is_err());
+        assert!(select_field(&value, "state..x").is_err());
+
+        let mut out = Vec::new();
+        write_json_output(&mut out, &value, Some("state")).unwrap();
+        assert_eq!(out, b"FINISHED\n");
+    }
+
+    #[test]
+    fn batch_requests_are_merged_into_the_base_request() {
+        let requests =
+            parse_batch_requests("{\"name\": \"a\"}\n\n{\"labels\": {\"env\": \"ci\"}}\nnope\n");
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].line, 1);
+        assert_eq!(requests[1].line, 3);
+        assert_eq!(requests[2].line, 4);
+        assert!(requests[2].value.is_err());
+
+        let mut base = json::json!({ "name": "base", "labels": { "team": "apis" } });
+        merge_json_values(&mut base, requests[1].value.clone().unwrap());
+        merge_json_values(&mut base, requests[0].value.clone().unwrap());
+        assert_eq!(
+            base,
+            json::json!({ "name": "a", "labels": { "team": "apis", "env": "ci" } })
+        );
+
+        assert_eq!(
+            batch_result(4, Err("invalid".into())),
+            json::json!({ "line": 4, "error": "invalid" })
+        );
+    }
+
+    #[test]
+    fn errors_are_reported_as_json() {
+        let report = ErrorReport {
+            code: Some(429),
+            status: "RESOURCE_EXHAUSTED".into(),
+            reason: Some("rateLimitExceeded".into()),
+            retry_after: Some(Duration::from_millis(1500)),
+            message: "Quota exceeded\n".into(),
+        };
+        assert_eq!(report.exit_code(), 5);
+        assert_eq!(
+            report.to_string(),
+            r#"{"error":{"code":429,"message":"Quota exceeded","reason":"rateLimitExceeded","retry_after":1.5,"status":"RESOURCE_EXHAUSTED"}}"#
+        );
+
+        let report = ErrorReport {
+            status: "NOT_FOUND".into(),
+            message: "Not Found".into(),
+            ..ErrorReport::default()
+        };
+        assert_eq!(report.exit_code(), 3);
+        assert_eq!(
+            report.to_json(),
+            json::json!({ "status": "NOT_FOUND", "message": "Not Found" })
+        );
+        assert_eq!(exit_code_of_status("PERMISSION_DENIED"), 4);
+        assert_eq!(exit_code_of_status("INTERNAL"), 1);
+    }
+
+    #[test]
+    fn pages_are_concatenated() {
+        let mut results = Value::Null;
+        append_page(
+            &mut results,
+            json::json!({ "items": [1, 2], "kind": "list", "nextPageToken": "a" }),
+        );
+        append_page(
+            &mut results,
+            json::json!({ "items": [3], "unreachable": ["r"], "nextPageToken": "b" }),
+        );
+        append_page(&mut results, json::json!({ "kind": "last" }));
+        assert_eq!(
+            results,
+            json::json!({ "items": [1, 2, 3], "kind": "last", "unreachable": ["r"] })
+        );
+    }
+
+    #[test]
+    fn required_fields_are_validated() {
+        let field = RequiredField {
+            name: "test-spec.test-timeout",
+            json_path: "testSpec.testTimeout",
+            type_info: JsonTypeInfo {
+                jtype: JsonType::String,
+                ctype: ComplexType::Pod,
+            },
+            choices: &[],
+        };
+        let object = json::json!({});
+        assert_eq!(
+            field.set(&object, "300s").unwrap(),
+            json::json!({ "testSpec": { "testTimeout": "300s" } })
+        );
+
+        let state = RequiredField {
+            name: "state",
+            json_path: "state",
+            type_info: field.type_info,
+            choices: &["ENABLED", "DISABLED"],
+        };
+        assert!(state.set(&object, "ENABLED").is_ok());
+        assert!(state.set(&object, "UNKNOWN").is_err());
+
+        let count = RequiredField {
+            name: "count",
+            json_path: "count",
+            type_info: JsonTypeInfo {
+                jtype: JsonType::Int,
+                ctype: ComplexType::Pod,
+            },
+            choices: &[],
+        };
+        assert!(count.set(&object, "many").is_err());
+    }
+
+    #[test]
+    fn durations_are_parsed() {
+        assert_eq!(
+            parse_duration("timeout", "500ms").unwrap(),
+            Duration::from_millis(500)
+        );
+        assert_eq!(
+            parse_duration("timeout", "60").unwrap(),
+            Duration::from_secs(60)
+        );
+        assert!(parse_duration("timeout", "2d").is_err());
+        assert!(parse_duration("timeout", "s").is_err());
+        assert_eq!(
+            parse_duration("timeout", "2h").unwrap(),
+            Duration::from_secs(7200)
+        );
+        assert!(matches!(
+            parse_duration("timeout", &format!("{}h", u64::MAX / 60)),
+            Err(CLIError::ParseError(..))
+        ));
+    }
 }
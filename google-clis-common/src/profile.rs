@@ -0,0 +1,164 @@
+//! Configuration profiles of the CLIs
+//!
+//! The configuration file, `~/.config/google-apis-rs/config.toml` by default, holds named
+//! profiles like
+//!
+//! ```toml
+//! [profiles.default]
+//! project = "my-project"
+//! credentials = "~/secrets/service-account.json"
+//! quota_project = "my-billing-project"
+//! endpoint = "https://private.googleapis.com/storage/v1/"
+//! ```
+//!
+//! The CLIs use the profile given with `--profile`, or the `default` one if there is one.
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+
+use serde::Deserialize;
+
+use crate::{expand_home_dir, CLIError, ConfigurationError};
+
+/// The path of the configuration file.
+pub const CONFIG_FILE: &str = "~/.config/google-apis-rs/config.toml";
+
+/// The profile used if none is given.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The environment variable with the base URL of all requests, unless given on the command-line.
+pub const ENDPOINT_OVERRIDE_ENV: &str = "GOOGLE_API_ENDPOINT_OVERRIDE";
+
+/// The value of a project argument which stands for the project of the profile.
+pub const PROFILE_PROJECT: &str = "_";
+
+/// The defaults of a profile for all invocations of the CLIs using it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// The project id used for project arguments given as [`PROFILE_PROJECT`]
+    pub project: Option<String>,
+    /// The path of the credentials, like a service account key, the credentials of a user or an
+    /// application secret, instead of the Application Default Credentials
+    pub credentials: Option<String>,
+    /// The project billed for the quota of all requests, sent as `x-goog-user-project` header
+    pub quota_project: Option<String>,
+    /// The base URL of all requests, instead of the one of the API
+    pub endpoint: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct Config {
+    #[serde(default)]
+    profiles: BTreeMap<String, Profile>,
+}
+
+impl Profile {
+    /// Returns the profile `name` of the configuration file at `path`.
+    ///
+    /// Without `name`, it's the [`DEFAULT_PROFILE`], or an empty profile if there is no such
+    /// profile or no configuration file. A profile which was named has to exist.
+    pub fn load(path: &str, name: Option<&str>) -> Result<Profile, CLIError> {
+        let path = expand_home_dir(path)?;
+        let config = match fs::read_to_string(&path) {
+            Ok(content) => Profile::parse(&path, &content)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Config::default(),
+            Err(err) => return Err(CLIError::Configuration(ConfigurationError::Io((path, err)))),
+        };
+        Profile::select(config, name)
+    }
+
+    fn parse(path: &str, content: &str) -> Result<Config, CLIError> {
+        toml::from_str(content).map_err(|err| {
+            CLIError::Configuration(ConfigurationError::InvalidConfig((
+                path.to_string(),
+                err.to_string(),
+            )))
+        })
+    }
+
+    fn select(mut config: Config, name: Option<&str>) -> Result<Profile, CLIError> {
+        match name {
+            Some(name) => config.profiles.remove(name).ok_or_else(|| {
+                CLIError::Configuration(ConfigurationError::UnknownProfile(name.to_string()))
+            }),
+            None => Ok(config.profiles.remove(DEFAULT_PROFILE).unwrap_or_default()),
+        }
+    }
+
+    /// Returns the base URL of all requests, which is `endpoint` given on the command-line, the
+    /// one of the [`ENDPOINT_OVERRIDE_ENV`] environment variable, or the one of this profile, in
+    /// that order, or None to use the one of the API.
+    pub fn endpoint(&self, endpoint: Option<&str>) -> Option<String> {
+        endpoint
+            .map(str::to_string)
+            .or_else(|| env::var(ENDPOINT_OVERRIDE_ENV).ok())
+            .filter(|endpoint| !endpoint.is_empty())
+            .or_else(|| self.endpoint.clone())
+    }
+
+    /// Returns the project `value` of an argument, which is the project of this profile if
+    /// `value` is [`PROFILE_PROJECT`].
+    pub fn project<'a>(&'a self, value: &'a str) -> Result<&'a str, CLIError> {
+        if value != PROFILE_PROJECT {
+            return Ok(value);
+        }
+        self.project
+            .as_deref()
+            .ok_or(CLIError::Configuration(ConfigurationError::ProjectUnset))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        [profiles.default]
+        project = "my-project"
+
+        [profiles.ci]
+        credentials = "/secrets/ci.json"
+        quota_project = "billing"
+        endpoint = "http://localhost:8085/"
+    "#;
+
+    #[test]
+    fn profiles_are_selected_by_name() {
+        let config = || Profile::parse("config.toml", CONFIG).unwrap();
+
+        let default = Profile::select(config(), None).unwrap();
+        assert_eq!(default.project.as_deref(), Some("my-project"));
+        assert_eq!(default.project("_").unwrap(), "my-project");
+        assert_eq!(default.project("other").unwrap(), "other");
+
+        let ci = Profile::select(config(), Some("ci")).unwrap();
+        assert_eq!(ci.quota_project.as_deref(), Some("billing"));
+        assert_eq!(ci.endpoint.as_deref(), Some("http://localhost:8085/"));
+        assert!(ci.project("_").is_err());
+
+        assert!(Profile::select(config(), Some("unknown")).is_err());
+        assert_eq!(
+            Profile::select(Config::default(), None).unwrap(),
+            Profile::default()
+        );
+        assert!(Profile::parse("config.toml", "[profiles.default]\nproject = 1").is_err());
+    }
+
+    #[test]
+    fn endpoints_are_overridden() {
+        let profile = Profile {
+            endpoint: Some("http://localhost:8085/".into()),
+            ..Profile::default()
+        };
+        assert_eq!(
+            profile.endpoint(Some("https://private.googleapis.com/")),
+            Some("https://private.googleapis.com/".into())
+        );
+        if env::var(ENDPOINT_OVERRIDE_ENV).is_err() {
+            assert_eq!(profile.endpoint(None), profile.endpoint);
+            assert_eq!(Profile::default().endpoint(None), None);
+        }
+    }
+}
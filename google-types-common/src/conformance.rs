@@ -0,0 +1,120 @@
+//! Checks of the schema types of generated APIs against their discovery documents
+//!
+//! The generator emits a test per schema of an API, which calls [`assert_round_trip`] with an
+//! example JSON value built from the types, formats and enums the discovery document declares for
+//! its properties.
+use core::fmt::Debug;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::prelude::*;
+
+/// Asserts that `example` deserializes as `T`, and that serializing the result keeps all its keys
+/// and deserializes to an equal instance again.
+///
+/// Scalars are not compared with `example`, as their serialization may differ, for instance in
+/// the precision of durations.
+///
+/// # Panics
+/// If `example` isn't JSON, or any of the above doesn't hold.
+pub fn assert_round_trip<T>(type_name: &str, example: &str)
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    let example: Value = serde_json::from_str(example)
+        .unwrap_or_else(|err| panic!("invalid example of {}: {}", type_name, err));
+    let value: T = serde_json::from_value(example.clone())
+        .unwrap_or_else(|err| panic!("{} doesn't deserialize {}: {}", type_name, example, err));
+    let serialized = serde_json::to_value(&value)
+        .unwrap_or_else(|err| panic!("{} doesn't serialize: {}", type_name, err));
+    if let Some(path) = missing_key(&example, &serialized, String::new()) {
+        panic!(
+            "{} loses {} of {} when serialized as {}",
+            type_name, path, example, serialized
+        );
+    }
+    let serialized = serialized.to_string();
+    let again: T = serde_json::from_str(&serialized).unwrap_or_else(|err| {
+        panic!(
+            "{} doesn't deserialize its own {}: {}",
+            type_name, serialized, err
+        )
+    });
+    assert_eq!(
+        again, value,
+        "{} doesn't round-trip through JSON",
+        type_name
+    );
+}
+
+/// Returns the path of the first key of `expected` which isn't in `actual`.
+/// Keys of `expected` whose value is `null` are as good as missing, and are skipped.
+pub(crate) fn missing_key(expected: &Value, actual: &Value, path: String) -> Option<String> {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => expected
+            .iter()
+            .filter(|(_, v)| !v.is_null())
+            .find_map(|(k, v)| {
+                let path = format!("{}.{}", path, k);
+                match actual.get(k) {
+                    Some(a) => missing_key(v, a, path),
+                    None => Some(path),
+                }
+            }),
+        (Value::Array(expected), Value::Array(actual)) => {
+            expected.iter().enumerate().find_map(|(i, v)| {
+                let path = format!("{}[{}]", path, i);
+                match actual.get(i) {
+                    Some(a) => missing_key(v, a, path),
+                    None => Some(path),
+                }
+            })
+        }
+        (Value::Object(_), _) | (Value::Array(_), _) => Some(path),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::Deserialize;
+
+    #[serde_with::serde_as]
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Sample {
+        #[serde(rename = "sizeBytes")]
+        #[serde_as(as = "Option<serde_with::DisplayFromStr>")]
+        size_bytes: Option<i64>,
+        #[serde_as(as = "Option<crate::serde::duration::Wrapper>")]
+        ttl: Option<chrono::Duration>,
+        labels: Option<std::collections::HashMap<String, String>>,
+    }
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Misnamed {
+        size_bytes: Option<String>,
+    }
+
+    #[test]
+    fn complete_types_round_trip() {
+        assert_round_trip::<Sample>(
+            "Sample",
+            r#"{"sizeBytes": "42", "ttl": "3.5s", "labels": {"key": "value"}}"#,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Misnamed loses .sizeBytes")]
+    fn missing_renames_are_detected() {
+        assert_round_trip::<Misnamed>("Misnamed", r#"{"sizeBytes": "42"}"#);
+    }
+
+    #[test]
+    #[should_panic(expected = "Sample doesn't deserialize")]
+    fn wrong_formats_are_detected() {
+        assert_round_trip::<Sample>("Sample", r#"{"sizeBytes": 42}"#);
+    }
+}
@@ -0,0 +1,165 @@
+//! Conversions between the schemas of different versions of an API
+//!
+//! Where two versions of an API have schemas of the same name and with fields of the same types,
+//! the generator implements `TryFrom` between them by means of [`convert`] and [`try_convert`],
+//! which go through the JSON both schemas share.
+use core::fmt::{self, Display, Formatter};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::conformance::missing_key;
+use crate::prelude::*;
+
+/// The error of a conversion between the schemas of two versions of an API.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConversionError {
+    /// The name of the schema which was converted.
+    pub type_name: &'static str,
+    /// The path of the first field which was set but couldn't be converted, like `.labels`, or
+    /// empty if the value as a whole couldn't be.
+    pub path: String,
+    /// Why the conversion failed.
+    pub kind: ConversionErrorKind,
+}
+
+/// Why a conversion between the schemas of two versions of an API failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConversionErrorKind {
+    /// The field is set, but the schema of the other version doesn't have it.
+    Lost,
+    /// The JSON of the value doesn't fit the schema of the other version, with the message of
+    /// `serde_json`.
+    Json(String),
+}
+
+impl ConversionError {
+    fn json(type_name: &'static str, err: serde_json::Error) -> Self {
+        ConversionError {
+            type_name,
+            path: String::new(),
+            kind: ConversionErrorKind::Json(err.to_string()),
+        }
+    }
+}
+
+impl Display for ConversionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ConversionErrorKind::Lost => write!(
+                f,
+                "{}{} has no counterpart in the other version",
+                self.type_name, self.path
+            ),
+            ConversionErrorKind::Json(msg) => {
+                write!(f, "{} doesn't convert: {}", self.type_name, msg)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ConversionError {}
+
+/// Converts `value` into a schema whose fields include all of the fields of `value`, with the
+/// same types.
+///
+/// Fails if the JSON of `value` doesn't fit `T` all the same, which the generator makes sure of
+/// unless the schemas changed since.
+pub fn convert<S, T>(type_name: &'static str, value: S) -> Result<T, ConversionError>
+where
+    S: Serialize,
+    T: DeserializeOwned,
+{
+    let json = serde_json::to_value(value).map_err(|err| ConversionError::json(type_name, err))?;
+    serde_json::from_value(json).map_err(|err| ConversionError::json(type_name, err))
+}
+
+/// Converts `value` into a schema which has the fields of `value` with the same types, but may lack
+/// some of them.
+///
+/// Fails if one of the missing fields is set in `value`, nested fields included, or like
+/// [`convert`].
+pub fn try_convert<S, T>(type_name: &'static str, value: S) -> Result<T, ConversionError>
+where
+    S: Serialize,
+    T: Serialize + DeserializeOwned,
+{
+    let json = serde_json::to_value(value).map_err(|err| ConversionError::json(type_name, err))?;
+    let converted: T = serde_json::from_value(json.clone())
+        .map_err(|err| ConversionError::json(type_name, err))?;
+    let converted_json =
+        serde_json::to_value(&converted).map_err(|err| ConversionError::json(type_name, err))?;
+    match missing_key(&json, &converted_json, String::new()) {
+        Some(path) => Err(ConversionError {
+            type_name,
+            path,
+            kind: ConversionErrorKind::Lost,
+        }),
+        None => Ok(converted),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Label {
+        key: Option<String>,
+        color: Option<String>,
+    }
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct BetaItem {
+        name: Option<String>,
+        labels: Option<Vec<Label>>,
+    }
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct StableLabel {
+        key: Option<String>,
+    }
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct StableItem {
+        name: Option<String>,
+        labels: Option<Vec<StableLabel>>,
+        size: Option<i64>,
+    }
+
+    fn item(color: Option<&str>) -> BetaItem {
+        BetaItem {
+            name: Some("a".into()),
+            labels: Some(vec![Label {
+                key: Some("k".into()),
+                color: color.map(Into::into),
+            }]),
+        }
+    }
+
+    #[test]
+    fn conversion_keeps_shared_fields() {
+        let stable: StableItem = convert("StableItem", StableItem::default()).unwrap();
+        assert_eq!(stable, StableItem::default());
+
+        let stable: StableItem = try_convert("BetaItem", item(None)).unwrap();
+        assert_eq!(stable.name.as_deref(), Some("a"));
+        assert_eq!(stable.labels.unwrap()[0].key.as_deref(), Some("k"));
+    }
+
+    #[test]
+    fn conversion_losing_fields_fails() {
+        let err = try_convert::<_, StableItem>("BetaItem", item(Some("red"))).unwrap_err();
+        assert_eq!(err.path, ".labels[0].color");
+        assert_eq!(err.kind, ConversionErrorKind::Lost);
+    }
+
+    #[test]
+    fn conversion_of_mismatching_json_fails() {
+        let err = convert::<_, StableItem>("BetaItem", "a").unwrap_err();
+        assert!(matches!(err.kind, ConversionErrorKind::Json(_)));
+        assert!(err.path.is_empty());
+    }
+}
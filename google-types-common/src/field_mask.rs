@@ -1,8 +1,10 @@
-use std::fmt::{Display, Formatter};
-use std::str::FromStr;
+use core::fmt::{Display, Formatter};
+use core::str::FromStr;
 
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+use crate::prelude::*;
+
 fn titlecase(source: &str, dest: &mut String) {
     let mut underscore = false;
     for c in source.chars() {
@@ -54,12 +56,12 @@ impl<'de> Deserialize<'de> for FieldMask {
 }
 
 impl FromStr for FieldMask {
-    type Err = std::convert::Infallible;
+    type Err = core::convert::Infallible;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut in_quotes = false;
         let mut prev_ind = 0;
         let mut paths = Vec::new();
-        for (i, c) in s.chars().enumerate() {
+        for (i, c) in s.char_indices() {
             if c == '`' {
                 in_quotes = !in_quotes;
             } else if in_quotes {
@@ -75,7 +77,7 @@ impl FromStr for FieldMask {
 }
 
 impl Display for FieldMask {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         let mut repr = String::new();
         for path in &self.0 {
             titlecase(path, &mut repr);
@@ -117,6 +119,13 @@ mod test {
         );
     }
 
+    #[test]
+    fn paths_after_multibyte_characters_are_split() {
+        let mask: FieldMask = "a.`ü`,photo".parse().unwrap();
+        assert_eq!(mask.0.len(), 2);
+        assert_eq!(mask.0[1], "photo");
+    }
+
     #[test]
     fn test_empty_wrapper() {
         assert_eq!(
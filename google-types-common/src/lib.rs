@@ -0,0 +1,55 @@
+//! What the schema types of generated crates need, without any of the dependencies of making
+//! calls.
+//!
+//! It is used by the `-types` crates, which only contain the schemas of an API, and re-exported by
+//! `google-apis-common` for the full API crates.
+//!
+//! Without the default `std` feature, it only needs `alloc`, and is `#![no_std]`.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod conformance;
+pub mod convert;
+pub mod field_mask;
+pub mod serde;
+
+pub use chrono;
+pub use field_mask::FieldMask;
+pub use serde_with;
+
+/// What the prelude of `std` would provide without `#![no_std]`.
+mod prelude {
+    pub use alloc::format;
+    pub use alloc::string::{String, ToString};
+    pub use alloc::vec::Vec;
+}
+
+use prelude::*;
+
+/// Identifies types which can be inserted and deleted.
+/// Types with this trait are most commonly used by clients of this API.
+pub trait Resource {}
+
+/// Identifies types which are used in API responses.
+pub trait ResponseResult {}
+
+/// Identifies types which are used in API requests.
+pub trait RequestValue {}
+
+/// Identifies types which are not actually used by the API
+/// This might be a bug within the google API schema.
+pub trait UnusedType {}
+
+/// Identifies types which are only used as part of other types, which
+/// usually are carrying the `Resource` trait.
+pub trait Part {}
+
+/// Identifies types which are only used by other types internally.
+/// They have no special meaning, this trait just marks them for completeness.
+pub trait NestedType {}
+
+/// A trait for all types that can convert themselves into a *parts* string
+pub trait ToParts {
+    fn to_parts(&self) -> String;
+}
@@ -1,30 +1,32 @@
 pub mod duration {
+    use core::fmt::Formatter;
+    use core::str::FromStr;
     use serde::{Deserialize, Deserializer};
     use serde_with::{DeserializeAs, SerializeAs};
-    use std::fmt::Formatter;
-    use std::str::FromStr;
 
     use chrono::Duration;
 
+    use crate::prelude::*;
+
     const MAX_SECONDS: i64 = 315576000000i64;
 
     #[derive(Debug)]
     enum ParseDurationError {
         MissingSecondSuffix,
         NanosTooSmall,
-        ParseIntError(std::num::ParseIntError),
+        ParseIntError(core::num::ParseIntError),
         SecondOverflow { seconds: i64, max_seconds: i64 },
         SecondUnderflow { seconds: i64, min_seconds: i64 },
     }
 
-    impl From<std::num::ParseIntError> for ParseDurationError {
-        fn from(pie: std::num::ParseIntError) -> Self {
+    impl From<core::num::ParseIntError> for ParseDurationError {
+        fn from(pie: core::num::ParseIntError) -> Self {
             ParseDurationError::ParseIntError(pie)
         }
     }
 
-    impl std::fmt::Display for ParseDurationError {
-        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    impl core::fmt::Display for ParseDurationError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
             match self {
                 ParseDurationError::MissingSecondSuffix => write!(f, "'s' suffix was not present"),
                 ParseDurationError::NanosTooSmall => {
@@ -51,6 +53,7 @@ pub mod duration {
         }
     }
 
+    #[cfg(feature = "std")]
     impl std::error::Error for ParseDurationError {}
 
     fn duration_from_str(s: &str) -> Result<Duration, ParseDurationError> {
@@ -130,8 +133,8 @@ pub mod duration {
         where
             D: Deserializer<'de>,
         {
-            let s = Deserialize::deserialize(deserializer)?;
-            duration_from_str(s).map_err(serde::de::Error::custom)
+            let s: String = Deserialize::deserialize(deserializer)?;
+            duration_from_str(&s).map_err(serde::de::Error::custom)
         }
     }
 }
@@ -140,6 +143,8 @@ pub mod urlsafe_base64 {
     use serde::{Deserialize, Deserializer, Serializer};
     use serde_with::{DeserializeAs, SerializeAs};
 
+    use crate::prelude::*;
+
     pub struct Wrapper;
 
     pub fn to_string(bytes: &Vec<u8>) -> String {
@@ -160,12 +165,14 @@ pub mod urlsafe_base64 {
         where
             D: Deserializer<'de>,
         {
-            let s: &str = Deserialize::deserialize(deserializer)?;
-            base64::decode_config(s, base64::URL_SAFE).map_err(serde::de::Error::custom)
+            let s: String = Deserialize::deserialize(deserializer)?;
+            base64::decode_config(&s, base64::URL_SAFE).map_err(serde::de::Error::custom)
         }
     }
 }
 
+use crate::prelude::*;
+
 pub fn datetime_to_string(datetime: &chrono::DateTime<chrono::offset::Utc>) -> String {
     datetime.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
 }
@@ -0,0 +1,58 @@
+import json
+import unittest
+
+from generator.lib.conversions import version_key, latest_stable_version, conversions, used_schemas, FROM, TRY_FROM
+from .test_data.discovery_document import DISCOVERY_DOC
+
+
+class ConversionsTest(unittest.TestCase):
+    def setUp(self):
+        self.schemas = {
+            'Item': {'id': 'Item', 'type': 'object', 'properties': {
+                'name': {'type': 'string'},
+                'size': {'type': 'string', 'format': 'int64'},
+                'labels': {'type': 'array', 'items': {'$ref': 'Label'}},
+            }},
+            'Label': {'id': 'Label', 'type': 'object', 'properties': {
+                'key': {'type': 'string'},
+            }},
+        }
+        self.used = set(self.schemas)
+
+    def test_versions_sort_by_release(self):
+        versions = ['v2', 'v1', 'v1beta2', 'v1alpha', 'v1.3', 'v1p1beta1', 'v1beta1']
+        self.assertEqual(sorted(versions, key=version_key),
+                         ['v1alpha', 'v1beta1', 'v1beta2', 'v1', 'v1p1beta1', 'v1.3', 'v2'])
+        self.assertIsNone(version_key('directory_v1'))
+
+    def test_latest_stable_version_skips_pre_releases(self):
+        self.assertEqual(latest_stable_version(['v1', 'v2beta1', 'v1.3', 'directory_v1']), 'v1.3')
+        self.assertIsNone(latest_stable_version(['v1beta1', 'v1alpha']))
+
+    def test_equal_schemas_convert_both_ways(self):
+        self.assertEqual(conversions(self.schemas, self.used, self.schemas, self.used),
+                         [('Item', FROM), ('Label', FROM)])
+
+    def test_missing_fields_need_try_from(self):
+        other = json.loads(json.dumps(self.schemas))
+        del other['Label']['properties']['key']
+        self.assertEqual(conversions(self.schemas, self.used, other, self.used),
+                         [('Item', TRY_FROM), ('Label', TRY_FROM)])
+        self.assertEqual(conversions(other, self.used, self.schemas, self.used),
+                         [('Item', FROM), ('Label', FROM)])
+
+    def test_changed_types_do_not_convert(self):
+        other = json.loads(json.dumps(self.schemas))
+        other['Item']['properties']['size']['format'] = 'int32'
+        self.assertEqual(conversions(self.schemas, self.used, other, self.used),
+                         [('Item', None), ('Label', FROM)])
+
+    def test_only_schemas_used_by_methods_are_generated(self):
+        doc = json.loads(DISCOVERY_DOC)
+        used = used_schemas(doc)
+        self.assertIn('Album', used)
+        self.assertTrue(used <= set(doc['schemas']))
+
+
+if __name__ == '__main__':
+    unittest.main()
@@ -0,0 +1,47 @@
+import io
+import json
+import os
+import tarfile
+import tempfile
+import unittest
+
+from generator.lib.discovery_source import list_documents, read_document
+
+DOCS = {
+    'storage.v1.json': {'discoveryVersion': 'v1', 'name': 'storage', 'version': 'v1', 'revision': '20230101'},
+    'pubsub/v1/pubsub-api.json': {'discoveryVersion': 'v1', 'name': 'pubsub', 'version': 'v1'},
+    'index.json': {'items': []},
+}
+
+
+class DiscoverySourceTest(unittest.TestCase):
+    def setUp(self):
+        self.tmp = tempfile.TemporaryDirectory()
+        self.dir = os.path.join(self.tmp.name, 'discoveries')
+        self.tarball = os.path.join(self.tmp.name, 'discoveries.tar.gz')
+        with tarfile.open(self.tarball, 'w:gz') as tar:
+            for path, doc in DOCS.items():
+                contents = json.dumps(doc).encode('utf-8')
+                os.makedirs(os.path.dirname(os.path.join(self.dir, path)), exist_ok=True)
+                with open(os.path.join(self.dir, path), 'wb') as fh:
+                    fh.write(contents)
+                info = tarfile.TarInfo(path)
+                info.size = len(contents)
+                tar.addfile(info, io.BytesIO(contents))
+
+    def tearDown(self):
+        self.tmp.cleanup()
+
+    def test_documents_are_listed(self):
+        for source in (self.dir, self.tarball):
+            self.assertEqual(sorted(d['name'] for d in list_documents(source)), ['pubsub', 'storage'])
+
+    def test_documents_are_read_by_name_and_version(self):
+        for source in (self.dir, self.tarball):
+            self.assertEqual(read_document(source, 'storage', 'v1')['revision'], '20230101')
+            with self.assertRaises(KeyError):
+                read_document(source, 'storage', 'v2')
+
+
+if __name__ == '__main__':
+    unittest.main()
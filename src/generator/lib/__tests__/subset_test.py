@@ -0,0 +1,39 @@
+import json
+import unittest
+
+from generator.lib.subset import apply_subset, process_data
+from .test_data.discovery_document import DISCOVERY_DOC
+
+
+class SubsetTest(unittest.TestCase):
+    def setUp(self):
+        self.discovery_doc = json.loads(DISCOVERY_DOC)
+
+    def test_only_listed_methods_are_kept(self):
+        apply_subset(self.discovery_doc, ['photoslibrary.albums.get', 'photoslibrary.albums.create'])
+        self.assertEqual(list(self.discovery_doc['resources'].keys()), ['albums'])
+        self.assertEqual(sorted(self.discovery_doc['resources']['albums']['methods'].keys()), ['create', 'get'])
+
+    def test_schemas_used_by_kept_methods_are_kept(self):
+        apply_subset(self.discovery_doc, ['photoslibrary.albums.create'])
+        schemas = set(self.discovery_doc['schemas'].keys())
+        self.assertIn('CreateAlbumRequest', schemas)
+        self.assertIn('Album', schemas)
+        self.assertIn('SharedAlbumOptions', schemas)
+        self.assertNotIn('MediaItem', schemas)
+
+    def test_listed_schemas_are_kept(self):
+        apply_subset(self.discovery_doc, ['photoslibrary.albums.get'], ['MediaItem'])
+        self.assertIn('MediaItem', self.discovery_doc['schemas'])
+
+    def test_unknown_methods_are_rejected(self):
+        with self.assertRaises(ValueError):
+            apply_subset(self.discovery_doc, ['photoslibrary.albums.frobnicate'])
+
+    def test_data_without_subset_is_unchanged(self):
+        expected = json.loads(DISCOVERY_DOC)
+        self.assertEqual(process_data(self.discovery_doc), expected)
+
+
+if __name__ == '__main__':
+    unittest.main()
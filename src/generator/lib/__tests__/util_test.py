@@ -3,7 +3,8 @@
 import unittest
 import json
 
-from generator.lib.util import to_api_version, library_name, re_find_replacements, to_rust_type
+from generator.lib.util import to_api_version, library_name, re_find_replacements, to_rust_type, method_default_scope, \
+    deprecated_attribute
 from .test_data.discovery_document import DISCOVERY_DOC
 
 
@@ -106,6 +107,28 @@ class UtilsTest(unittest.TestCase):
         rust_type = to_rust_type(schemas, class_name, property_name, property_value, allow_optionals=True)
         self.assertEqual(rust_type, 'Option<Vec<HashMap<String, String>>>')
 
+    def test_method_default_scope(self):
+        class Method(dict):
+            __getattr__ = dict.__getitem__
+
+        auth = 'https://www.googleapis.com/auth/'
+        scopes = [auth + 'cloud-platform', auth + 'cloud-platform.read-only', auth + 'devstorage.full_control',
+                  auth + 'devstorage.read_only']
+        self.assertEqual(method_default_scope(Method(httpMethod='GET', scopes=scopes)), auth + 'devstorage.read_only')
+        self.assertEqual(method_default_scope(Method(httpMethod='POST', scopes=scopes)),
+                         auth + 'devstorage.full_control')
+        self.assertEqual(method_default_scope(Method(httpMethod='POST', scopes=[auth + 'cloud-platform'])),
+                         auth + 'cloud-platform')
+        self.assertIsNone(method_default_scope(Method(httpMethod='GET')))
+
+    def test_deprecated_attribute(self):
+        self.assertIsNone(deprecated_attribute({'description': 'Lists buckets.'}))
+        self.assertEqual(deprecated_attribute({'deprecated': True, 'description': 'The "legacy" ID.'}),
+                         '#[deprecated(note = "Deprecated by the API, and may be removed from it in the future.")]')
+        self.assertEqual(deprecated_attribute({'deprecated': True,
+                                               'description': 'The zone.\nUse the "location" field\ninstead.'}),
+                         '#[deprecated(note = "Use the \\"location\\" field instead.")]')
+
 
 def main():
     unittest.main()
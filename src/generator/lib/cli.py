@@ -20,6 +20,20 @@ KEY_VALUE_ARG = 'kv'
 SCOPE_FLAG = 'scope'
 CONFIG_DIR_FLAG = 'config-dir'
 DEBUG_FLAG = 'debug'
+PROFILE_FLAG = 'profile'
+OUTPUT_FIELD_FLAG = 'output-field'
+RETRIES_FLAG = 'retries'
+RETRY_BACKOFF_FLAG = 'retry-backoff'
+TIMEOUT_FLAG = 'timeout'
+ENDPOINT_FLAG = 'endpoint'
+INTERACTIVE_FLAG = 'interactive'
+BATCH_FILE_FLAG = 'batch-file'
+BATCH_CONCURRENCY_FLAG = 'batch-concurrency'
+ALL_PAGES_FLAG = 'all-pages'
+PAGE_SIZE_FLAG = 'page-size'
+DRY_RUN_FLAG = 'dry-run'
+KEY_FILE_FLAG = 'key-file'
+API_KEY_FLAG = 'api-key'
 DEFAULT_MIME = 'application/octet-stream'
 
 MODE_ARG = 'mode'
@@ -31,10 +45,28 @@ OUT_ARG = 'out'
 
 SCOPE_ARG = 'url'
 CONFIG_DIR_ARG = 'folder'
+PROFILE_ARG = 'name'
+OUTPUT_FIELD_ARG = 'path'
+RETRIES_ARG = 'count'
+RETRY_BACKOFF_ARG = 'backoff'
+TIMEOUT_ARG = 'duration'
+ENDPOINT_ARG = 'base-url'
+BATCH_FILE_ARG = 'requests'
+BATCH_CONCURRENCY_ARG = 'jobs'
+PAGE_SIZE_ARG = 'size'
+KEY_FILE_ARG = 'key-path'
+API_KEY_ARG = 'key'
+ENDPOINT_OVERRIDE_ENV = 'GOOGLE_API_ENDPOINT_OVERRIDE'
 
 FIELD_SEP = '.'
 
 CONFIG_DIR = '~/.google-service-cli'
+CONFIG_FILE = '~/.config/google-apis-rs/config.toml'
+
+# required parameters of these names may be given as `_`, standing for the project of the profile
+PROJECT_PARAMS = ('project', 'projectId')
+PAGE_TOKEN_PARAM = 'pageToken'
+PAGE_SIZE_PARAMS = ('pageSize', 'maxResults')
 
 POD_TYPES = set(('boolean', 'integer', 'number', 'uint32', 'double', 'float', 'int32', 'int64', 'uint64', 'string'))
 
@@ -105,6 +137,39 @@ def is_request_value_property(mc, p):
     return mc.request_value and mc.request_value.id == p.get(util.TREF)
 
 
+# Returns True if the method of the given context can make the requests of a batch file, which is
+# the case for methods with a request, but without uploads or downloads of media
+def supports_batch(mc):
+    return bool(mc.request_value) and not mc.media_params and not mc.m.get('supportsMediaDownload', False)
+
+
+# Returns the page token parameter of the method of the given context if it returns pages of results, with the token
+# of the next one in the `nextPageToken` field of its response, or None
+def page_token_param(mc):
+    if mc.media_params or mc.m.get('supportsMediaDownload', False) or not mc.response_schema:
+        return None
+    if 'nextPageToken' not in mc.response_schema.get('properties', dict()):
+        return None
+    for p in mc.optional_props:
+        if p.name == PAGE_TOKEN_PARAM:
+            return p
+    return None
+
+
+# Returns the parameter setting the size of the pages of results of the method of the given context, or None
+def page_size_param(mc):
+    for p in mc.optional_props:
+        if p.name in PAGE_SIZE_PARAMS and p.get('type') == 'integer':
+            return p
+    return None
+
+
+# Returns True if the engine has a function making a call of the method of the given context which returns the
+# response as JSON value, for batches and pages
+def has_value_call(mc):
+    return supports_batch(mc) or page_token_param(mc) is not None
+
+
 # transform name to be a suitable subcommand
 def mangle_subcommand(name):
     return util.camel_to_under(name).replace('_', '-').replace('.', '-')
@@ -144,6 +209,18 @@ def docopt_mode(protocols):
 def actual_json_type(name, type):
     return type
 
+def is_project_param(p):
+    return p.name in PROJECT_PARAMS and p.get('type') == 'string' and not p.get('repeated', False)
+
+
+# Returns True if the property `p` of a request is required by method `m`, which discovery documents
+# say in its annotations, or, more often, only in its description
+def is_required_request_field(p, m):
+    if m.id in p.get('annotations', dict()).get('required', list()):
+        return True
+    return re.match(r'^\s*\[?Required\]?[.:]', p.get('description', '')) is not None
+
+
 # return a string representing property `p` suitable for docopt argument parsing
 def to_docopt_arg(p):
     return '<%s>%s' % (mangle_subcommand(p.name), p.get('repeated', False) and '...' or '')
@@ -0,0 +1,170 @@
+# Finds the schemas two versions of an API have in common, to generate `TryFrom` implementations
+# between them, like between `google-accessapproval1` and
+# `google-accessapproval1_beta1`.
+#
+# The conversions of a pair of versions are generated into the crate of the later version, which
+# depends on the crate of the earlier one optionally. As later versions only depend on earlier
+# ones, crates never depend on each other.
+#
+# A schema converts into its namesake of the other version losslessly (`FROM`) if every field of it
+# exists in the other schema with the same type, and may lose fields (`TRY_FROM`) if the types of
+# the fields both schemas have are the same, but some of its fields are missing in the other one.
+# Both are generated as `TryFrom`, as a lossless conversion still fails if the JSON of a value
+# doesn't fit the other schema, but only the latter checks for fields which would be lost.
+import json
+import os
+import re
+from typing import Any, Dict, Iterable, List, Optional, Set, Tuple
+
+import yaml
+
+from generator.lib.subset import apply_subset, reachable_schemas
+
+REF = '$ref'
+FROM = 'From'
+TRY_FROM = 'TryFrom'
+
+_VERSION = re.compile(r'^v(\d+)(?:\.(\d+))?(?:p(\d+))?(?:(alpha|beta)(\d*))?$')
+_STAGES = {'alpha': 0, 'beta': 1, None: 2}
+
+
+def version_key(version: str) -> Optional[Tuple[int, ...]]:
+    """Returns a key sorting versions by their release, with pre-releases before their stable version,
+    or None if the version doesn't follow the usual scheme, like `directory_v1`"""
+    m = _VERSION.match(version)
+    if m is None:
+        return None
+    major, minor, point, stage, stage_number = m.groups()
+    return (int(major), int(minor or 0), int(point or 0), _STAGES[stage], int(stage_number or 0))
+
+
+def latest_stable_version(versions: Iterable[str]) -> Optional[str]:
+    """Returns the newest of `versions` which isn't an alpha or beta, or None if there is none"""
+    stable = [v for v in versions if version_key(v) is not None and version_key(v)[3] == _STAGES[None]]
+    return max(stable, key=version_key, default=None)
+
+
+def earlier_versions(api_base: str, name: str, version: str, blacklist: Iterable[str] = ()) -> List[str]:
+    """Returns the versions of API `name` released before `version` which are generated, oldest first"""
+    key = version_key(version)
+    blacklist = set(blacklist)
+    if key is None or name in blacklist:
+        return []
+    api_dir = os.path.join(api_base, name)
+    res = []
+    for other in os.listdir(api_dir):
+        other_key = version_key(other)
+        if other_key is None or other_key >= key or name + '-' + other in blacklist:
+            continue
+        if os.path.isfile(os.path.join(api_dir, other, name + '-api.json')):
+            res.append(other)
+    return sorted(res, key=version_key)
+
+
+def load_document(api_base: str, name: str, version: str) -> Dict[str, Any]:
+    """Loads the discovery document of an API version with the subset its crate is generated from, if any"""
+    version_dir = os.path.join(api_base, name, version)
+    with open(os.path.join(version_dir, name + '-api.json')) as fh:
+        doc = json.load(fh)
+    subset_file = os.path.join(version_dir, name + '-api_subset.yaml')
+    if os.path.isfile(subset_file):
+        with open(subset_file) as fh:
+            subset = (yaml.safe_load(fh) or {}).get('api', {}).get('subset')
+        if subset:
+            apply_subset(doc, subset.get('methods', []), subset.get('schemas', []))
+    return doc
+
+
+def used_schemas(doc: Dict[str, Any]) -> Set[str]:
+    """Returns the ids of the schemas a generated crate has, which are the ones its methods use"""
+    roots: Set[str] = set()
+    todo = [doc]
+    while todo:
+        container = todo.pop()
+        for m in container.get('methods', {}).values():
+            for io in ('request', 'response'):
+                if REF in m.get(io, {}):
+                    roots.add(m[io][REF])
+        todo.extend(container.get('resources', {}).values())
+    return reachable_schemas(doc.get('schemas', {}), roots)
+
+
+def conversions(schemas: Dict[str, Any], used: Set[str],
+                other_schemas: Dict[str, Any], other_used: Set[str]) -> List[Tuple[str, Optional[str]]]:
+    """Returns (schema id, FROM|TRY_FROM|None) of each schema both versions have, sorted by id, describing how it
+    converts into its namesake in `other_schemas`"""
+    common = sorted(set(schemas) & set(other_schemas) & used & other_used)
+    kinds: Dict[str, Optional[str]] = {}
+    for sid in common:
+        kinds[sid] = _schema_conversion(sid, schemas, other_schemas, {})
+    return [(sid, kinds[sid]) for sid in common]
+
+
+def _schema_conversion(sid: str, src: Dict[str, Any], dst: Dict[str, Any],
+                       seen: Dict[str, Optional[str]]) -> Optional[str]:
+    # Recursive schemas are assumed to convert until proven otherwise
+    if sid in seen:
+        return seen[sid]
+    if sid not in src or sid not in dst:
+        return None
+    seen[sid] = FROM
+    kind = _conversion(src[sid], dst[sid], src, dst, seen)
+    seen[sid] = kind
+    return kind
+
+
+def _worst(kinds: Iterable[Optional[str]]) -> Optional[str]:
+    res = FROM
+    for kind in kinds:
+        if kind is None:
+            return None
+        if kind == TRY_FROM:
+            res = TRY_FROM
+    return res
+
+
+def _conversion(s: Dict[str, Any], d: Dict[str, Any], src: Dict[str, Any], dst: Dict[str, Any],
+                seen: Dict[str, Optional[str]]) -> Optional[str]:
+    if REF in s or REF in d:
+        if s.get(REF) != d.get(REF):
+            return None
+        return _schema_conversion(s[REF], src, dst, seen)
+    if s.get('type') != d.get('type') or s.get('format') != d.get('format'):
+        return None
+    if 'variant' in s or 'variant' in d:
+        return s.get('variant') == d.get('variant') and FROM or None
+
+    kinds: List[Optional[str]] = []
+    for key in ('items', 'additionalProperties'):
+        if (key in s) != (key in d):
+            return None
+        if key in s:
+            kinds.append(_conversion(s[key], d[key], src, dst, seen))
+
+    sp, dp = s.get('properties', {}), d.get('properties', {})
+    for pn, p in sp.items():
+        if pn in dp:
+            kinds.append(_conversion(p, dp[pn], src, dst, seen))
+        else:
+            kinds.append(TRY_FROM)
+    return _worst(kinds)
+
+
+def version_conversions(api_base: str, name: str, version: str, blacklist: Iterable[str] = ()) \
+        -> List[Tuple[str, str, List[Tuple[str, Optional[str], Optional[str]]]]]:
+    """Returns (earlier version, its revision, conversions) of each earlier version of API `name` sharing
+    schemas with `version`, where conversions are (schema id, FROM|TRY_FROM|None into the earlier version,
+    FROM|TRY_FROM|None from the earlier version) of each schema which converts in at least one direction"""
+    doc = load_document(api_base, name, version)
+    used = used_schemas(doc)
+    res = []
+    for other_version in earlier_versions(api_base, name, version, blacklist):
+        other = load_document(api_base, name, other_version)
+        other_used = used_schemas(other)
+        into_other = conversions(doc.get('schemas', {}), used, other.get('schemas', {}), other_used)
+        from_other = dict(conversions(other.get('schemas', {}), other_used, doc.get('schemas', {}), used))
+        schema_conversions = [(sid, into, from_other[sid]) for sid, into in into_other
+                              if into is not None or from_other[sid] is not None]
+        if schema_conversions:
+            res.append((other_version, other.get('revision', '00000000'), schema_conversions))
+    return res
@@ -0,0 +1,68 @@
+# Reads discovery documents from a vendored copy instead of discovery.googleapis.com, for reproducible
+# and offline regeneration, like:
+#
+#   make update-json DISCOVERY_SOURCE=vendor/discoveries.tar.gz
+#
+# The source is a directory or a tarball. All JSON files in it which are discovery documents are used,
+# no matter their layout, as each document names its API and version.
+import json
+import os
+import sys
+import tarfile
+from typing import Any, Dict, Iterator, List, Tuple
+
+ENV_VAR = 'DISCOVERY_SOURCE'
+
+
+def _json_files(source: str) -> Iterator[Tuple[str, bytes]]:
+    """Yields the path and contents of each JSON file in the directory or tarball `source`"""
+    if os.path.isdir(source):
+        for root, dirs, files in os.walk(source):
+            dirs.sort()
+            for name in sorted(files):
+                if name.endswith('.json'):
+                    path = os.path.join(root, name)
+                    with open(path, 'rb') as fh:
+                        yield path, fh.read()
+    elif tarfile.is_tarfile(source):
+        with tarfile.open(source) as tar:
+            for member in tar.getmembers():
+                if member.isfile() and member.name.endswith('.json'):
+                    yield member.name, tar.extractfile(member).read()
+    else:
+        raise ValueError("discovery source '%s' is neither a directory nor a tarball" % source)
+
+
+def _documents(source: str) -> Iterator[Dict[str, Any]]:
+    for path, contents in _json_files(source):
+        try:
+            doc = json.loads(contents)
+        except ValueError:
+            continue
+        if isinstance(doc, dict) and 'discoveryVersion' in doc and 'name' in doc and 'version' in doc:
+            yield doc
+
+
+def list_documents(source: str) -> List[Dict[str, str]]:
+    """Returns the APIs of all documents in `source`, like the `items` of the discovery service's directory"""
+    return [{'name': doc['name'], 'version': doc['version']} for doc in _documents(source)]
+
+
+def read_document(source: str, name: str, version: str) -> Dict[str, Any]:
+    """Returns the discovery document of API `name` in `version` from `source`"""
+    for doc in _documents(source):
+        if doc['name'] == name and doc['version'] == version:
+            return doc
+    raise KeyError("no discovery document of %s %s in '%s'" % (name, version, source))
+
+
+def main(source: str, name: str, version: str, target: str):
+    doc = read_document(source, name, version)
+    with open(target, 'w') as fh:
+        json.dump(doc, fh, indent=4, sort_keys=True)
+
+
+if __name__ == '__main__':
+    if len(sys.argv) != 5:
+        sys.exit('usage: discovery_source.py <directory-or-tarball> <name> <version> <target-file>')
+    main(*sys.argv[1:])
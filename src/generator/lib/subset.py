@@ -0,0 +1,92 @@
+# Restricts the data of an API to the methods listed in `api.subset`, to generate a "mini" crate
+# with only the methods a team needs, like:
+#
+#   api:
+#     subset:
+#       methods:
+#         - storage.objects.get
+#         - storage.objects.insert
+#       # schemas to keep even though no kept method refers to them
+#       schemas: []
+#
+# The subset is read from `<name>-api_subset.yaml`, next to the discovery document.
+# Schemas are kept if a kept method or kept schema refers to them.
+from typing import Any, Dict, Iterable, List, Set
+
+REF = '$ref'
+
+
+def process_data(data: Dict[str, Any]) -> Dict[str, Any]:
+    """Called by mako-render with the merged data-files, before rendering any template"""
+    subset = data.get('api', {}).get('subset')
+    if subset:
+        apply_subset(data, subset.get('methods', []), subset.get('schemas', []))
+    return data
+
+
+def apply_subset(doc: Dict[str, Any], method_ids: Iterable[str], schema_ids: Iterable[str] = ()):
+    """Removes all methods not in `method_ids` from the discovery document `doc`, along with the
+    resources and schemas only they used."""
+    wanted = set(method_ids)
+    kept: List[Dict[str, Any]] = []
+    _prune_methods(doc, wanted, kept)
+    unknown = wanted - {m['id'] for m in kept}
+    if unknown:
+        raise ValueError("methods of the subset are not part of the API: %s" % ', '.join(sorted(unknown)))
+
+    schemas = doc.get('schemas', {})
+    roots = set(schema_ids)
+    for m in kept:
+        for io in ('request', 'response'):
+            if REF in m.get(io, {}):
+                roots.add(m[io][REF])
+    reachable = reachable_schemas(schemas, roots)
+    for sid in list(schemas.keys()):
+        if sid not in reachable:
+            del schemas[sid]
+
+
+def _prune_methods(container: Dict[str, Any], wanted: Set[str], kept: List[Dict[str, Any]], top: bool = True):
+    methods = container.get('methods')
+    if methods is not None:
+        for name in list(methods.keys()):
+            if methods[name]['id'] in wanted:
+                kept.append(methods[name])
+            else:
+                del methods[name]
+        if not methods:
+            del container['methods']
+
+    resources = container.get('resources')
+    if resources is not None:
+        for name in list(resources.keys()):
+            _prune_methods(resources[name], wanted, kept, top=False)
+            if not resources[name].get('methods') and not resources[name].get('resources'):
+                del resources[name]
+        # templates expect the resources of the API to exist, even if there are none
+        if not resources and not top:
+            del container['resources']
+
+
+def reachable_schemas(schemas: Dict[str, Any], roots: Set[str]) -> Set[str]:
+    seen: Set[str] = set()
+    todo = list(roots)
+    while todo:
+        sid = todo.pop()
+        if sid in seen or sid not in schemas:
+            continue
+        seen.add(sid)
+        todo.extend(_refs(schemas[sid]))
+    return seen
+
+
+def _refs(value: Any) -> Iterable[str]:
+    if isinstance(value, dict):
+        for k, v in value.items():
+            if k == REF and isinstance(v, str):
+                yield v
+            else:
+                yield from _refs(v)
+    elif isinstance(value, list):
+        for v in value:
+            yield from _refs(v)
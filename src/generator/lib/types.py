@@ -52,6 +52,28 @@ RUST_TYPE_MAP = {
     "google-fieldmask": Base("client::FieldMask")
 }
 
+# Values of each type and format, as they appear in JSON, for the schema conformance tests
+JSON_EXAMPLE_MAP = {
+    'boolean': True,
+    'integer': 7,
+    'number': 1.5,
+    'uint32': 7,
+    'int32': -7,
+    'double': 1.5,
+    'float': 1.5,
+    'any': "example",
+    'int64': "-42",
+    'uint64': "42",
+    'string': "example",
+    'google-datetime': "2023-01-02T03:04:05.678Z",
+    'date-time': "2023-01-02T03:04:05.678Z",
+    'date': "2023-01-02",
+    'google-duration': "3.5s",
+    # url-safe, without padding
+    'byte': "aGVsbG8_d29ybGQh",
+    'google-fieldmask': "name,labels.key",
+}
+
 RUST_TYPE_RND_MAP = {
     'bool': lambda: str(bool(randint(0, 1))).lower(),
     'u32': lambda: randint(0, 100),
@@ -24,7 +24,11 @@ ${util.library_name(name, version)}\
 </%def>
 
 <%def name="crate_name()" buffered="True">\
+% if cargo.get('is_alias', False):
+${util.alias_crate_name(name)}\
+% else:
 ${util.library_to_crate_name(util.library_name(name, version), make.target_suffix)}\
+% endif
 </%def>
 
 <%def name="doc_base_url()" buffered="True">\
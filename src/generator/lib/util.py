@@ -7,7 +7,7 @@ from dataclasses import dataclass
 from typing import Any, Dict, List, Mapping, Tuple
 from copy import deepcopy
 from .rust_type import Base, Box, HashMap, Vec, Option, RustType
-from .types import RUST_TYPE_MAP, RUST_TYPE_RND_MAP
+from .types import RUST_TYPE_MAP, RUST_TYPE_RND_MAP, JSON_EXAMPLE_MAP
 
 re_linestart = re.compile('^', flags=re.MULTILINE)
 re_spaces_after_newline = re.compile('^ {4}', flags=re.MULTILINE)
@@ -42,7 +42,17 @@ ADD_SCOPE_FN = "add_scope"
 ADD_SCOPES_FN = "add_scopes"
 CLEAR_SCOPES_FN = "clear_scopes"
 
-ADD_PARAM_MEDIA_EXAMPLE = "." + ADD_PARAM_FN + '("alt", "media")'
+PATH_PARAMS_MODULE = 'path_params'
+ADD_PARAM_MEDIA_EXAMPLE = '.alt(client::standard_params::Alt::Media)'
+# standard parameter -> (setter and field of client::standard_params::StandardParams, its type)
+STANDARD_PARAMS = {
+    'alt': ('alt', 'Alt'),
+    '$.xgafv': ('xgafv', 'Xgafv'),
+    'upload_protocol': ('upload_protocol', 'UploadProtocol'),
+    'quotaUser': ('quota_user', 'QuotaUser'),
+    'userIp': ('user_ip', 'UserIp'),
+    'callback': ('callback', 'Callback'),
+}
 
 SPACES_PER_TAB = 4
 
@@ -58,6 +68,8 @@ PART_MARKER_TRAIT = 'client::Part'
 NESTED_MARKER_TRAIT = 'client::NestedType'
 REQUEST_VALUE_PROPERTY_NAME = 'request'
 DELEGATE_PROPERTY_NAME = 'delegate'
+CHUNK_SIZE_PROPERTY_NAME = 'chunkSize'
+KEEP_CONTENT_ENCODING_PROPERTY_NAME = 'keepContentEncoding'
 TO_PARTS_MARKER = 'client::ToParts'
 UNUSED_TYPE_MARKER = 'client::UnusedType'
 
@@ -65,10 +77,12 @@ PROTOCOL_TYPE_INFO = {
     'simple': {
         'arg_name': 'stream',
         'description': """Upload media all at once.
-If the upload fails for whichever reason, all progress is lost.""",
+If the upload fails for whichever reason, all progress is lost.
+If the method takes a request value, it is sent along with the media in a single
+`multipart/related` request.""",
         'default': 'fs::File',
         'suffix': '',
-        'example_value': 'fs::File::open("file.ext").unwrap(), "application/octet-stream".parse().unwrap()'
+        'example_value': '%(lib)s::client::ReadSeekAdapter::new(fs::File::open("file.ext").unwrap()), "application/octet-stream".parse().unwrap()'
     },
     'resumable': {
         'arg_name': 'resumeable_stream',
@@ -82,7 +96,7 @@ may specify the `chunk_size()` and may cancel the operation before each chunk is
 `cancel_chunk_upload(...)`.""",
         'default': 'fs::File',
         'suffix': '_resumable',
-        'example_value': 'fs::File::open("file.ext").unwrap(), "application/octet-stream".parse().unwrap()'
+        'example_value': '%(lib)s::client::ReadSeekAdapter::new(fs::File::open("file.ext").unwrap()), "application/octet-stream".parse().unwrap()'
     }
 }
 
@@ -238,6 +252,11 @@ def rust_doc_test_norun(s):
     return "```test_harness,no_run\n%s```" % trailing_newline(s)
 
 
+# a rust test that runs
+def rust_doc_test(s):
+    return "```\n%s```" % trailing_newline(s)
+
+
 # a rust code block in (github) markdown
 def markdown_rust_block(s):
     return "```Rust\n%s```" % trailing_newline(s)
@@ -248,6 +267,11 @@ def rust_test_fn_invisible(s):
     return "# async fn dox() {\n%s# }" % trailing_newline(s)
 
 
+# wraps s into an invisible main function of a doc test, run by tokio.
+def rust_test_main_invisible(s):
+    return "# #[tokio::main]\n# async fn main() {\n%s# }" % trailing_newline(s)
+
+
 # markdown comments
 def markdown_comment(s):
     return "<!---\n%s-->" % trailing_newline(s)
@@ -449,6 +473,31 @@ def to_rust_type_inner(
         raise AssertionError("%s: unknown dict layout: %s" % (str(err), t))
 
 
+# Return the (expression, parameter name) pairs to substitute in the URI template `path`, like ('{+name}', 'name').
+# NOTE: We only handle the cases that are actually used in the schemas. If this shouldn't
+# be worth it anymore (i.e. too many cases), then we should use a uri-template library
+# to handle this at runtime, possibly, or use a python uri-template library, to more easily
+# handle the required cases. Whatever is less work, I guess.
+def url_replacements(path, params):
+    replacements = list()
+    all_required_param_name = set(p.name for p in params if is_required_property(p))
+    for s in re_find_replacements.findall(path):
+        if s in (r[0] for r in replacements):
+            continue
+        sn = s[1:-1]
+        if sn.startswith('/') and sn.endswith('*'):
+            sn = sn[1:-1]
+        elif sn.startswith('+'):
+            sn = sn[1:]
+        assert sn in all_required_param_name, "Expected param '%s' to be in required parameter list for substitution" % sn
+        replacements.append((s, sn))
+    # Assure we can substitue everything
+    for s, d in replacements:
+        path = path.replace(s, d)
+    assert '{' not in path, "Failed to replace all fields in '%s', have to parse expressions" % path
+    return replacements
+
+
 # return True if this property is actually a nested type
 def is_nested_type_property(t):
     return 'type' in t and t['type'] == 'object' and 'properties' in t or ('items' in t and 'properties' in t['items'])
@@ -459,6 +508,43 @@ def is_nested_type(s):
     return len(s.parents) > 0
 
 
+# return the name of the newtype of path parameter `p` in the `path_params` module, like `ProjectId` for `projectId`,
+# or None if it is no single string
+def path_param_type(p):
+    if p.get('location') != 'path' or p.get('type') != 'string' or 'format' in p or p.get('repeated', False):
+        return None
+    return canonical_type_name(p.name.replace('.', '_').replace('$', ''))
+
+
+# as activity_input_type(), but accepting the newtype of path parameters as well as strings
+def builder_input_type(schemas, p):
+    tn = path_param_type(p)
+    if tn is None:
+        return activity_input_type(schemas, p)
+    return 'impl std::convert::TryInto<%s::%s>' % (PATH_PARAMS_MODULE, tn)
+
+
+# as rust_copy_value_s(), for values of the type returned by builder_input_type(). Empty strings, which don't convert,
+# are kept empty, and fail the call with `UriTemplateError::EmptyParam` when its URL is built.
+def builder_copy_value_s(schemas, n, p):
+    tn = path_param_type(p)
+    if tn is None:
+        return rust_copy_value_s(n, activity_input_type(schemas, p), p)
+    return 'std::convert::TryInto::<%s::%s>::try_into(%s).map(%s::%s::into_string).unwrap_or_default()' \
+           % (PATH_PARAMS_MODULE, tn, n, PATH_PARAMS_MODULE, tn)
+
+
+# return sorted (newtype name, parameter name) of all path parameters of the methods in context `c`
+def path_param_types(c):
+    res = dict()
+    for m in c.fqan_map.values():
+        for p in _method_params(m, location='path'):
+            tn = path_param_type(p)
+            if tn is not None:
+                res.setdefault(tn, p.name)
+    return sorted(res.items())
+
+
 # convert a rust-type to something that would be taken as input of a function
 # even though our storage type is different
 def activity_input_type(schemas, p):
@@ -548,6 +634,37 @@ def is_schema_with_optionals(schema_markers):
     return True
 
 
+# Return an example of the JSON value of a schema or property t, with every property set, or None if there is
+# no value which deserializes. Referenced schemas are expanded up to the given depth, and omitted beyond, or
+# where they would recurse.
+def json_example(schemas, t, depth=2, _seen=()):
+    if TREF in t:
+        tn = t[TREF]
+        if tn in _seen or depth == 0 or tn not in schemas:
+            return None
+        return json_example(schemas, schemas[tn], depth - 1, _seen + (tn,))
+    if 'variant' in t:
+        return None
+    if 'properties' in t:
+        res = dict()
+        for pn, p in items(t['properties']):
+            v = json_example(schemas, p, depth, _seen)
+            if v is not None:
+                res[pn] = v
+        return res
+    if t.get('type') == 'array':
+        v = json_example(schemas, t['items'], depth, _seen)
+        return [] if v is None else [v]
+    if is_map_prop(t):
+        v = json_example(schemas, t['additionalProperties'], depth, _seen)
+        return {} if v is None else {'key': v}
+    if t.get('type') == 'object':
+        return {}
+    if 'enum' in t:
+        return t['enum'][0]
+    return JSON_EXAMPLE_MAP.get(t.get('format', t.get('type')))
+
+
 # -------------------------
 ## @name Activity Utilities
 # @{
@@ -729,6 +846,30 @@ def build_all_params(c, m):
                       
                       It should be used to handle progress information, and to implement a certain level of resilience."""})
     params.append(dp)
+    if any(mp.protocol == 'resumable' for mp in method_media_params(m)):
+        params.append(type(m)({'name': CHUNK_SIZE_PROPERTY_NAME,
+                               'type': 'string',
+                               'format': 'uint64',
+                               'skip_example': True,
+                               'priority': 0,
+                               'is_query_param': False,
+                               'description':
+                                   """The size of each chunk of a resumable upload, overriding the one provided by the delegate.
+                                   It will be rounded up to a multiple of 256 KiB, the smallest chunk size accepted by the server.
+                                   Chunks are sent one after the other, as an upload session only accepts the chunk following
+                                   the bytes it has. Larger chunks make better use of fast links."""}))
+    # end add chunk size for resumable uploads
+    if m.get('supportsMediaDownload', False):
+        params.append(type(m)({'name': KEEP_CONTENT_ENCODING_PROPERTY_NAME,
+                               'type': 'boolean',
+                               'skip_example': True,
+                               'priority': 0,
+                               'is_query_param': False,
+                               'description':
+                                   """If set, media downloads are requested with `Accept-Encoding: gzip` and gzip-compressed media are kept as is.
+                                   Otherwise media served with `Content-Encoding: gzip` are decompressed by `download_to_file()` and `doit_download()`,
+                                   with the `gzip` feature."""}))
+    # end add content encoding for media downloads
     return params, request_value
 
 
@@ -935,6 +1076,11 @@ def library_to_crate_name(name, suffix=''):
     return 'google-' + name + suffix
 
 
+# return the name of the crate re-exporting the newest stable version of API `name`, like `google-testing`
+def alias_crate_name(name):
+    return library_to_crate_name(normalize_library_name(name))
+
+
 # return version like 0.1.0+2014031421
 def crate_version(build_version, revision):
     return '%s+%s' % (build_version, isinstance(revision, str) and revision or '00000000')
@@ -1006,10 +1152,12 @@ def supports_scopes(auth):
 def method_default_scope(m):
     if 'scopes' not in m:
         return None
-    default_scope = sorted(m.scopes)[0]
+    # Scopes of the API itself grant less than the ones of all of Google Cloud, and are preferred
+    scopes = sorted(m.scopes, key=lambda s: ('/auth/cloud-platform' in s, s))
+    default_scope = scopes[0]
     if m.httpMethod in ('HEAD', 'GET', 'OPTIONS', 'TRACE'):
-        for scope in m.scopes:
-            if 'readonly' in scope:
+        for scope in scopes:
+            if is_read_only_scope(scope):
                 default_scope = scope
                 break
         # end for each scope
@@ -1017,6 +1165,56 @@ def method_default_scope(m):
     return default_scope
 
 
+# Scopes like `.../auth/drive.readonly`, `.../auth/cloud-platform.read-only` or `.../auth/devstorage.read_only`
+def is_read_only_scope(url):
+    return any(marker in url for marker in ('readonly', 'read-only', 'read_only'))
+
+
+# Returns the name of the setter of the timeout of a call, which is `call_timeout` if the method has a `timeout`
+# parameter, whose setter is called `timeout` already.
+def timeout_setter_fn(params):
+    return 'call_timeout' if any(p.name == 'timeout' for p in params) else 'timeout'
+
+
+# Returns the `#[deprecated]` attribute of a method or parameter the discovery document flags as deprecated,
+# or None. Sentences of its description telling what to use instead become the note of the attribute.
+def deprecated_attribute(m_or_p):
+    if not m_or_p.get('deprecated', False):
+        return None
+    description = ' '.join(m_or_p.get('description', '').split())
+    migration = [s for s in re.split(r'(?<=\.)\s+', description)
+                 if re.search(r'\b(use|instead|replaced|migrate)\b', s, re.IGNORECASE)]
+    note = ' '.join(migration) or 'Deprecated by the API, and may be removed from it in the future.'
+    return '#[deprecated(note = "%s")]' % note.replace('\\', '\\\\').replace('"', '\\"')
+
+
+# The feature compiling out the methods changing data, see read_only_cfg_attribute()
+READ_ONLY_FEATURE = 'readonly-only'
+
+
+# A method changes data unless it's a GET, or one of its scopes is read-only
+def is_mutating_method(m):
+    return m.get('httpMethod', 'GET') != 'GET' and not any(is_read_only_scope(s) for s in m.get('scopes', []))
+
+
+# return the attribute removing a method with the read-only feature, or None if it doesn't change data
+def read_only_cfg_attribute(m):
+    if not is_mutating_method(m):
+        return None
+    return '#[cfg(not(feature = "%s"))]' % READ_ONLY_FEATURE
+
+
+# return the filter wrapping a doc test of a method changing data like `test_fn_filter`, but only without the
+# read-only feature. Doc tests with a `main()` get an empty one with the feature instead.
+def rust_test_fn_read_only_gated(test_fn_filter):
+    def gated(s):
+        res = '# #[cfg(not(feature = "%s"))]\n%s' % (READ_ONLY_FEATURE, test_fn_filter(s))
+        if test_fn_filter is rust_test_main_invisible:
+            res += '\n# #[cfg(feature = "%s")]\n# fn main() {}' % READ_ONLY_FEATURE
+        return res
+    return gated
+
+
 _rb_type_params = ("'a",) + HUB_TYPE_PARAMETERS
 
 
@@ -1,4 +1,8 @@
-<%! from generator.lib.util import (estr, enclose_in, hash_comment, library_to_crate_name, to_extern_crate_name) %>\
+<%!
+    from generator.lib.util import (estr, enclose_in, hash_comment, library_to_crate_name, to_extern_crate_name,
+                                    library_name, crate_version)
+    from generator.lib.conversions import version_conversions
+%>\
 <%namespace name="util" file="../lib/util.mako"/>\
 <%block filter="hash_comment">\
 <%util:gen_info source="${self.uri}" />\
@@ -8,7 +12,13 @@
 name = "${util.crate_name()}"
 version = "${util.crate_version()}"
 authors = [${",\n           ".join('"%s"' % a for a in cargo.authors)}]
+% if cargo.get('is_types_only', False):
+description = "The schema types of ${util.canonical_name()} (protocol ${version}), without a client to make calls"
+% elif cargo.get('is_alias', False):
+description = "The newest stable version of ${util.canonical_name()}, which is protocol ${version} currently"
+% else:
 description = "A complete library to interact with ${util.canonical_name()} (protocol ${version})"
+% endif
 repository = "${util.github_source_root_url()}"
 % if documentationLink is not UNDEFINED and documentationLink:
 homepage = "${documentationLink}"
@@ -18,6 +28,9 @@ license = "${copyright.license_abbrev}"
 keywords = ["${name[:20]}", ${", ".join(estr(cargo.keywords))}]
 autobins = false
 edition = "2018"
+% if cargo.get('rust_version'):
+rust-version = "${cargo.rust_version}"
+% endif
 
 % if cargo.get('is_executable', False):
 [[bin]]
@@ -26,8 +39,18 @@ path = "src/main.rs"
 % endif
 
 [dependencies]
+% if cargo.get('is_types_only', False):
+## Only what the schemas need, to keep the dependency tree of users of the types small
+serde = { version = "^ 1.0", default-features = false, features = ["derive", "alloc"] }
+serde_json = { version = "^ 1.0", default-features = false, features = ["alloc"] }
+google-types-common = { path = "../../google-types-common", version = "5.0.2", default-features = false }
+% elif cargo.get('is_alias', False):
+## Only the crate of the API version it stands for
+% else:
 anyhow = "^ 1.0"
+% if cargo.get('is_executable', False):
 hyper-rustls = "0.23.0"
+% endif
 ## Must match the one hyper uses, otherwise there are duplicate similarly named `Mime` structs
 mime = "^ 0.3.0"
 serde = { version = "^ 1.0", features = ["derive"] }
@@ -38,6 +61,7 @@ google-clis-common = { path = "../../google-clis-common", version = "5.0" }
 % else:
 google-apis-common = { path = "../../google-apis-common", version = "5.0.1" }
 % endif
+% endif
 % for dep in cargo.get('dependencies', list()):
 ${dep}
 % endfor
@@ -55,10 +79,88 @@ ${dep}
 [dependencies.${crate_name_we_depend_on}]
 path = "../${api_name}"
 version = "${util.crate_version()}"
+% if cargo.get('is_alias', False):
+default-features = false
+% endif
 % endif
+% if make.id == 'api':
+## Earlier versions of the API to convert schemas from and into, see conversions.rs
+% for other_version, other_revision, _ in version_conversions(directories.api_base, name, version, api.get('blacklist', list())):
 
-% if not cargo.get("is_executable", False):
+[dependencies.${library_to_crate_name(library_name(name, other_version))}]
+path = "../${library_name(name, other_version)}"
+version = "${crate_version(cargo.build_version, other_revision)}"
+optional = true
+default-features = false
+% endfor
+
+## TLS doesn't build for wasm targets, where the runtime provides it
+[target.'cfg(not(target_arch = "wasm32"))'.dependencies]
+hyper-rustls = "0.23.0"
+% endif
+
+% if cargo.get("is_types_only", False):
+[features]
+## Without it, the crate is `#![no_std]` and only needs `alloc`
+std = ["google-types-common/std", "serde/std", "serde_json/std"]
+default = ["std"]
+% elif cargo.get("is_alias", False):
+[features]
+% for feature in ('yup-oauth2', 'client-builder', 'indicatif', 'prometheus', 'tracing', 'simd-json', 'proto', 'arbitrary-precision', 'float-roundtrip', 'preserve-order', 'openapi', 'stub-server', 'cassette', 'chaos', 'mock', 'emulator', 'live', 'audit', 'gzip', 'readonly-only'):
+${feature} = ["${crate_name_we_depend_on}/${feature}"]
+% endfor
+% if api.get('grpc_endpoint'):
+grpc = ["${crate_name_we_depend_on}/grpc"]
+% endif
+% if 'signed_url' in api.get('helpers', list()):
+signing = ["${crate_name_we_depend_on}/signing"]
+% endif
+default = ["yup-oauth2", "gzip"]
+% elif not cargo.get("is_executable", False):
 [features]
 yup-oauth2 = ["google-apis-common/yup-oauth2"]
-default = ["yup-oauth2"]
+## Creates the client and authenticator once, for the hubs of all APIs a program uses
+client-builder = ["google-apis-common/client-builder"]
+indicatif = ["google-apis-common/indicatif"]
+prometheus = ["google-apis-common/prometheus"]
+tracing = ["google-apis-common/tracing"]
+simd-json = ["google-apis-common/simd-json"]
+## Adds `doit_proto()` to the methods of APIs accepting `alt=proto`, decoding responses with `prost`
+proto = ["google-apis-common/proto"]
+% if api.get('grpc_endpoint'):
+## Adds `grpc_transport()` to the hub, to make calls with the `tonic` clients of the protos of the API
+grpc = ["google-apis-common/grpc"]
+% endif
+% if 'signed_url' in api.get('helpers', list()):
+## Adds `signed_url()` to the hub, signing with `ring` or the IAM credentials API
+signing = ["google-apis-common/signing"]
+% endif
+## Options of serde_json for all JSON of the build, see `client::json_serializer`
+arbitrary-precision = ["google-apis-common/arbitrary-precision"]
+float-roundtrip = ["google-apis-common/float-roundtrip"]
+preserve-order = ["google-apis-common/preserve-order"]
+## Embeds the discovery document, to convert it into an OpenAPI document
+openapi = []
+stub-server = ["google-apis-common/stub-server"]
+cassette = ["google-apis-common/cassette"]
+chaos = ["google-apis-common/chaos"]
+mock = ["google-apis-common/mock"]
+emulator = ["google-apis-common/emulator"]
+live = ["google-apis-common/live"]
+## Adds `audit()` to the hub, recording calls changing resources along with the SHA-256 of their bodies
+audit = ["google-apis-common/audit"]
+## Decompresses gzip-compressed media downloads with `flate2`
+gzip = ["google-apis-common/gzip"]
+## Compiles out the methods which change data, which are those neither using `GET` nor read-only scopes
+readonly-only = []
+default = ["yup-oauth2", "gzip"]
+
+[[example]]
+name = "quickstart"
+required-features = ["mock"]
+
+[dev-dependencies]
+tokio = { version = "^1.0", features = ["macros", "rt-multi-thread"] }
+## The examples of the documentation run against a `client::mock::MockConnector`
+google-apis-common = { path = "../../google-apis-common", version = "5.0.1", features = ["mock"] }
 % endif
\ No newline at end of file
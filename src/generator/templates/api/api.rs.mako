@@ -3,11 +3,14 @@
 <%namespace name="rbuild" file="lib/rbuild.mako"/>\
 <%namespace name="mbuild" file="lib/mbuild.mako"/>\
 <%namespace name="schema" file="lib/schema.mako"/>\
+<%namespace name="helpers" file="lib/helpers.mako"/>\
+<%namespace name="apitrait" file="lib/apitrait.mako"/>\
 <%
     from generator.lib.util import (new_context, rust_comment, rust_doc_comment, rust_module_doc_comment,
                       rb_type, hub_type, mangle_ident, hub_type_params_s,
                       rb_type_params_s, find_fattest_resource, HUB_TYPE_PARAMETERS, METHODS_RESOURCE,
-                      UNUSED_TYPE_MARKER, schema_markers)
+                      UNUSED_TYPE_MARKER, schema_markers, path_param_types, PATH_PARAMS_MODULE, supports_scopes,
+                      read_only_cfg_attribute)
 
     c = new_context(schemas, resources)
     hub_type = hub_type(c.schemas, util.canonical_name())
@@ -18,10 +21,11 @@
 use std::collections::HashMap;
 use std::cell::RefCell;
 use std::default::Default;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::error::Error as StdError;
 use serde_json as json;
 use std::io;
+#[cfg(not(target_arch = "wasm32"))]
 use std::fs;
 use std::mem;
 
@@ -58,12 +62,89 @@ pub struct ${hub_type}${ht_params} {
     pub client: hyper::Client<S, hyper::body::Body>,
     pub auth: Box<dyn client::GetToken>,
     _user_agent: String,
+    _api_client: String,
     _base_url: String,
     _root_url: String,
+    _metrics: Option<std::sync::Arc<dyn client::Metrics>>,
+    _events: Option<client::events::EventSender>,
+    _har: Option<client::har::HarRecorder>,
+    #[cfg(feature = "audit")]
+    _audit: Option<client::audit::Auditor>,
+    _api_key: Option<String>,
+    _max_response_bytes: Option<u64>,
+    _json_serializer: Option<std::sync::Arc<dyn client::json_serializer::JsonSerializer>>,
+    _failover: Option<std::sync::Arc<client::failover::Failover>>,
+    _retry_policy: Option<client::retry::RetryPolicy>,
+    _retry_after_limit: u32,
 }
 
 impl<'a, ${', '.join(HUB_TYPE_PARAMETERS)}> client::Hub for ${hub_type}${ht_params} {}
 
+/// A [`${hub_type}`] whose connector type is erased, to compile the hub and its call builders only once
+/// for all connectors used with it.
+pub type Dyn${hub_type} = ${hub_type}<client::DynConnector>;
+
+impl<S> ${hub_type}${ht_params}
+where
+    S: tower_service::Service<http::Uri> + Clone + Send + Sync + 'static,
+    S::Response: hyper::client::connect::Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    /// The parts of this hub which `client::exec::execute()` needs to make calls.
+    fn transport(&self) -> client::exec::Transport<'_> {
+        client::exec::Transport {
+            client: &self.client,
+            auth: &*self.auth,
+            user_agent: &self._user_agent,
+            api_client: &self._api_client,
+            metrics: self._metrics.as_deref(),
+            har: self._har.as_ref(),
+            #[cfg(feature = "audit")]
+            audit: self._audit.as_ref(),
+            max_response_bytes: self._max_response_bytes,
+            failover: self._failover.as_deref(),
+        }
+    }
+
+    /// Connect to the server and obtain a token for the default scope, concurrently, so that the first call,
+    /// often latency-critical at the start of a process, doesn't wait for the TLS handshake and authentication.
+    /// Calls requiring other scopes obtain their token when they are made.
+    ///
+    /// Any response of the server will do. Fails with `client::Error::MissingToken` if the token couldn't be obtained,
+    /// and otherwise with `client::Error::HttpError` if the connection failed.
+    pub async fn warm_up(&self) -> client::Result<()> {
+        % if supports_scopes(auth):
+        let scopes = [Scope::default().as_ref()];
+        client::exec::warm_up(&self.transport(), &self._base_url, Some(&scopes)).await
+        % else:
+        client::exec::warm_up(&self.transport(), &self._base_url, None).await
+        % endif
+    }
+}
+
+impl<S> client::dyn_hub::DynHub for ${hub_type}${ht_params}
+where
+    S: tower_service::Service<http::Uri> + Clone + Send + Sync + 'static,
+    S::Response: hyper::client::connect::Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    fn methods(&self) -> &'static [client::methods::MethodMetadata] {
+        METHODS
+    }
+
+    fn call_method<'a>(&'a self, method_id: &'a str, params: &'a [(&'a str, &'a str)], request: Option<&'a json::Value>)
+                       -> client::BoxFuture<'a, client::Result<json::Value>> {
+        Box::pin(async move {
+            let method = client::methods::find(METHODS, method_id)
+                .ok_or_else(|| client::Error::UnknownMethod(method_id.to_string()))?;
+            client::dyn_hub::call(&self.transport(), &self._base_url, "${servicePath}", self._api_key.as_deref(),
+                                  method, params, request).await
+        })
+    }
+}
+
 impl<'a, ${', '.join(HUB_TYPE_PARAMETERS)}> ${hub_type}${ht_params} {
 
     pub fn new<A: 'static + client::GetToken>(client: hyper::Client<S, hyper::body::Body>, auth: A) -> ${hub_type}${ht_params} {
@@ -71,11 +152,31 @@ impl<'a, ${', '.join(HUB_TYPE_PARAMETERS)}> ${hub_type}${ht_params} {
             client,
             auth: Box::new(auth),
             _user_agent: "${default_user_agent}".to_string(),
+            _api_client: client::api_client_header(env!("CARGO_PKG_VERSION")),
             _base_url: "${baseUrl}".to_string(),
             _root_url: "${rootUrl}".to_string(),
+            _metrics: None,
+            _events: None,
+            _har: None,
+            #[cfg(feature = "audit")]
+            _audit: None,
+            _api_key: None,
+            _max_response_bytes: None,
+            _json_serializer: None,
+            _failover: None,
+            _retry_policy: None,
+            _retry_after_limit: client::retry::DEFAULT_RETRY_AFTER_LIMIT,
         }
     }
 
+    /// Returns a hub for methods which are authorized with an API key alone, without an authenticator.
+    /// Methods requiring scopes are sent without token, and will usually be rejected by the server.
+    pub fn with_api_key(client: hyper::Client<S, hyper::body::Body>, key: String) -> ${hub_type}${ht_params} {
+        let mut hub = ${hub_type}::new(client, client::NoToken);
+        hub._api_key = Some(key);
+        hub
+    }
+
     % for resource in sorted(c.rta_map.keys()):
     pub fn ${mangle_ident(resource)}(&'a self) -> ${rb_type(resource)}${rb_type_params_s(resource, c)} {
         ${rb_type(resource)} { hub: &self }
@@ -90,6 +191,22 @@ impl<'a, ${', '.join(HUB_TYPE_PARAMETERS)}> ${hub_type}${ht_params} {
         mem::replace(&mut self._user_agent, agent_name)
     }
 
+    /// Set the value of the `x-goog-api-client` header of all requests, which identifies the client to the server.
+    /// It defaults to the versions of the compiler and of this crate, like `gl-rust/1.70.0 gdcl/${cargo.build_version}`.
+    ///
+    /// Returns the previously set value.
+    pub fn api_client(&mut self, value: String) -> String {
+        mem::replace(&mut self._api_client, value)
+    }
+
+    /// Append `token`, like `my-library/1.2.0`, to the `x-goog-api-client` header and the user-agent of all
+    /// requests, to identify libraries and applications built on this crate. Tokens appended before are kept,
+    /// and appending a token again does nothing.
+    pub fn append_client_token(&mut self, token: &str) {
+        client::append_client_token(&mut self._api_client, token);
+        client::append_client_token(&mut self._user_agent, token);
+    }
+
     /// Set the base url to use in all requests to the server.
     /// It defaults to `${baseUrl}`.
     ///
@@ -98,6 +215,17 @@ impl<'a, ${', '.join(HUB_TYPE_PARAMETERS)}> ${hub_type}${ht_params} {
         mem::replace(&mut self._base_url, new_base_url)
     }
 
+    /// Fail over between the base urls of `failover`, like a regional endpoint followed by the global one, which
+    /// replace the base url. Calls are sent to the first healthy endpoint, and to the next one at once if it can't
+    /// be connected to, or once it answered with several server errors in a row. Calls with media use the preferred
+    /// endpoint, and hubs cloned afterwards share the health of the endpoints.
+    ///
+    /// Returns the previously set failover.
+    pub fn failover(&mut self, failover: client::failover::Failover) -> Option<std::sync::Arc<client::failover::Failover>> {
+        self._base_url = failover.preferred().to_string();
+        self._failover.replace(std::sync::Arc::new(failover))
+    }
+
     /// Set the root url to use in all requests to the server.
     /// It defaults to `${rootUrl}`.
     ///
@@ -105,8 +233,191 @@ impl<'a, ${', '.join(HUB_TYPE_PARAMETERS)}> ${hub_type}${ht_params} {
     pub fn root_url(&mut self, new_root_url: String) -> String {
         mem::replace(&mut self._root_url, new_root_url)
     }
+
+    /// Set the metrics implementation to inform about every request made to the server.
+    /// By default, no metrics are collected. With the `prometheus` feature enabled,
+    /// `client::prometheus_metrics::PrometheusMetrics` exports them to a `prometheus::Registry`.
+    ///
+    /// Returns the previously set metrics implementation.
+    pub fn metrics<M: client::Metrics + 'static>(&mut self, metrics: M) -> Option<std::sync::Arc<dyn client::Metrics>> {
+        self._metrics.replace(std::sync::Arc::new(metrics))
+    }
+
+    /// Subscribe to the [events](client::events::HubEvent) of all calls made through this hub from now on,
+    /// and through its clones made afterwards.
+    /// Events are only produced once there was a subscription.
+    pub fn events(&mut self) -> client::events::EventReceiver {
+        self._events.get_or_insert_with(client::events::event_channel).subscribe()
+    }
+
+    /// Capture all requests made to the server, and their responses, with `recorder`,
+    /// to be saved as HAR file. Credentials are redacted. Requests of resumable uploads
+    /// after the first are not captured.
+    ///
+    /// Returns the previously set recorder.
+    pub fn capture_har(&mut self, recorder: client::har::HarRecorder) -> Option<client::har::HarRecorder> {
+        self._har.replace(recorder)
+    }
+
+    /// Record every call changing resources, that is every call not using `GET`, with `auditor`.
+    ///
+    /// Returns the previously set auditor.
+    #[cfg(feature = "audit")]
+    pub fn audit(&mut self, auditor: client::audit::Auditor) -> Option<client::audit::Auditor> {
+        self._audit.replace(auditor)
+    }
+
+    /// Set the API key appended to the requests of methods which don't need a token, unless the delegate
+    /// of a call provides one.
+    ///
+    /// Returns the previously set key.
+    pub fn api_key(&mut self, key: String) -> Option<String> {
+        self._api_key.replace(key)
+    }
+
+    /// Set the size the bodies of responses may have at most, which calls fail with `client::Error::ResponseTooLarge`
+    /// as soon as it's exceeded while reading them, instead of buffering the rest. Bodies of downloads are returned
+    /// to be read by the caller, and aren't limited.
+    /// By default, the size of responses isn't limited.
+    ///
+    /// Returns the previously set maximum size.
+    pub fn max_response_bytes(&mut self, max_bytes: u64) -> Option<u64> {
+        self._max_response_bytes.replace(max_bytes)
+    }
+
+    /// Retry transient failures of all calls, like `503 Service Unavailable` responses, with exponential backoff as
+    /// `policy` says, unless their delegate retries them itself. Call builders can override it with their own
+    /// `retry_policy()`.
+    /// By default, calls are only retried if their delegate asks for it.
+    ///
+    /// Returns the previously set policy.
+    pub fn retry_policy(&mut self, policy: client::retry::RetryPolicy) -> Option<client::retry::RetryPolicy> {
+        self._retry_policy.replace(policy)
+    }
+
+    /// Set how often calls answered with `429 Too Many Requests` or `503 Service Unavailable` are retried at most
+    /// after the delay the server asked for, with a `Retry-After` header or the `RetryInfo` of the error, if it's at
+    /// most `client::retry::MAX_RETRY_AFTER`. A limit of 0 turns it off. Retry policies take precedence for the
+    /// statuses they retry.
+    /// It defaults to `client::retry::DEFAULT_RETRY_AFTER_LIMIT`.
+    ///
+    /// Returns the previously set limit.
+    pub fn retry_after_limit(&mut self, limit: u32) -> u32 {
+        mem::replace(&mut self._retry_after_limit, limit)
+    }
+
+    /// Set the serializer of the request values of all calls, like a closure taking a `serde_json::Value`, for
+    /// special needs like writing floats with a fixed number of decimals.
+    /// By default, request values are serialized compactly with serde_json, whose handling of numbers is set by
+    /// the `arbitrary-precision` and `float-roundtrip` features.
+    ///
+    /// Returns the previously set serializer.
+    pub fn json_serializer<J: client::json_serializer::JsonSerializer + 'static>(&mut self, serializer: J) -> Option<std::sync::Arc<dyn client::json_serializer::JsonSerializer>> {
+        self._json_serializer.replace(std::sync::Arc::new(serializer))
+    }
+}
+
+#[cfg(feature = "mock")]
+impl ${hub_type}<hyper::client::HttpConnector> {
+    /// Returns a hub sending all requests to the mock server at `server_uri`, like the `uri()` of a
+    /// `wiremock::MockServer`, over plain HTTP and with a `client::StaticToken`.
+    pub fn for_mock_server(server_uri: &str) -> ${hub_type}<hyper::client::HttpConnector> {
+        let mut hub = ${hub_type}::new(client::mock::http_client(), client::StaticToken::default());
+        hub.base_url(client::mock::base_url(server_uri, "${servicePath}"));
+        hub.root_url(client::mock::root_url(server_uri));
+        hub
+    }
+
+    /// Returns the HTTP method and path of the requests of the method with the given id, to match them on a mock server.
+    pub fn method_route(method_id: &str) -> Option<&'static client::mock::MethodRoute> {
+        METHOD_ROUTES.iter().find(|route| route.method_id == method_id)
+    }
 }
 
+#[cfg(feature = "emulator")]
+impl ${hub_type}<hyper::client::HttpConnector> {
+    /// Returns a hub sending all requests to the emulator at `host`, like `localhost:8085`, over plain HTTP
+    /// and without authentication.
+    pub fn for_emulator(host: &str) -> ${hub_type}<hyper::client::HttpConnector> {
+        let mut hub = ${hub_type}::new(client::emulator::http_client(), client::NoToken);
+        hub.base_url(client::emulator::base_url(host, "${servicePath}"));
+        hub.root_url(client::emulator::root_url(host));
+        hub
+    }
+    % if api.get('emulator_host_env'):
+
+    /// Returns a hub for the emulator at the host in the `${api.emulator_host_env}` environment variable, if it is set.
+    pub fn from_emulator_env() -> Option<${hub_type}<hyper::client::HttpConnector>> {
+        client::emulator::host_from_env("${api.emulator_host_env}").map(|host| ${hub_type}::for_emulator(&host))
+    }
+    % endif
+}
+% if api.get('grpc_endpoint'):
+
+#[cfg(feature = "grpc")]
+impl<S> ${hub_type}${ht_params} {
+    /// Connects a transport to the gRPC endpoint of the API, `${api.grpc_endpoint}`, which authorizes calls with
+    /// the tokens of this hub and retries them with its retry policy.
+    pub async fn grpc_transport(&self) -> client::Result<client::grpc::GrpcTransport> {
+        self.grpc_transport_at("${api.grpc_endpoint}").await
+    }
+
+    /// Like `grpc_transport()`, but connects to `endpoint`, like the one of a regional endpoint or an emulator.
+    pub async fn grpc_transport_at(&self, endpoint: &str) -> client::Result<client::grpc::GrpcTransport> {
+        let transport = client::grpc::GrpcTransport::connect(endpoint, self.auth.clone()).await?
+        % if supports_scopes(auth):
+            .scopes([Scope::default().as_ref().to_string()])
+        % endif
+            .api_client(self._api_client.clone());
+        Ok(match self._retry_policy.clone() {
+            Some(policy) => transport.retry_policy(policy),
+            None => transport,
+        })
+    }
+}
+% endif
+
+#[cfg(feature = "mock")]
+const METHOD_ROUTES: &[client::mock::MethodRoute] = &[
+% for m in sorted(c.fqan_map.values(), key=lambda m: m.id):
+    % if read_only_cfg_attribute(m):
+    ${read_only_cfg_attribute(m)}
+    % endif
+    client::mock::MethodRoute { method_id: "${m.id}", http_method: "${m.httpMethod}", path: "/${servicePath}${m.path.lstrip('/')}" },
+% endfor
+];
+
+/// All methods of this API, sorted by id. Use `client::methods::find(METHODS, id)` to look one up.
+/// With the `readonly-only` feature, the methods changing data are left out.
+pub const METHODS: &[client::methods::MethodMetadata] = &[
+% for m in sorted(c.fqan_map.values(), key=lambda m: m.id):
+<%
+    required_params = [pn for pn in m.get('parameterOrder', []) if m.get('parameters', {}).get(pn, {}).get('required', False)]
+%>\
+    % if read_only_cfg_attribute(m):
+    ${read_only_cfg_attribute(m)}
+    % endif
+    client::methods::MethodMetadata {
+        id: "${m.id}",
+        http_method: "${m.httpMethod}",
+        path: "/${servicePath}${m.path.lstrip('/')}",
+        required_params: &[${', '.join('"%s"' % pn for pn in required_params)}],
+        scopes: &[${', '.join('"%s"' % s for s in sorted(m.get('scopes', [])))}],
+    },
+% endfor
+];
+
+
+/// The types of the path parameters of the methods of this API, which the methods creating call builders take,
+/// along with strings converting into them.
+pub mod ${PATH_PARAMS_MODULE} {
+% for type_name, param_name in path_param_types(c):
+    crate::client::path_param!(
+        /// The *${param_name}* path parameter.
+        ${type_name}, "${param_name}"
+    );
+% endfor
+}
 
 % if c.schemas:
 // ############
@@ -117,6 +428,8 @@ impl<'a, ${', '.join(HUB_TYPE_PARAMETERS)}> ${hub_type}${ht_params} {
 ${schema.new(s, c)}
 % endif
 % endfor
+
+${schema.conformance_tests(c)}
 % endif
 
 // ###################
@@ -140,3 +453,31 @@ ${mbuild.new(resource, method, c)}
 
 % endfor ## method in methods
 % endfor ## resource, methods
+
+${mbuild.url_tests(c)}
+
+// ##########
+// TRAIT ###
+// ########
+
+${apitrait.new(c)}
+
+% if api.get('helpers'):
+
+// ############
+// HELPERS ###
+// ##########
+
+% if 'composite_upload' in api.helpers:
+${helpers.composite_upload(c)}
+% endif
+% if 'signed_url' in api.helpers:
+${helpers.signed_url(c)}
+% endif
+% if 'test_lab_fixtures' in api.helpers:
+${helpers.test_lab_fixtures(c)}
+% endif
+% if 'matrix_watcher' in api.helpers:
+${helpers.matrix_watcher(c)}
+% endif
+% endif
@@ -0,0 +1,43 @@
+<%namespace name="util" file="../../lib/util.mako"/>\
+<%
+    from generator.lib.util import (rust_comment, library_name, library_to_crate_name, to_extern_crate_name)
+    from generator.lib.conversions import version_conversions, FROM, TRY_FROM
+
+    versions = version_conversions(directories.api_base, name, version, api.get('blacklist', list()))
+%>\
+<%block filter="rust_comment">\
+<%util:gen_info source="${self.uri}" />\
+</%block>
+//! Conversions between the schemas of this and earlier versions of the API.
+//!
+//! Each earlier version sharing schemas with this one is an optional dependency. Enabling it implements
+//! `TryFrom` between the schemas of the same name which have the same fields, and between those where the
+//! target lacks some of the fields, which also fails if one of them is set.
+% for other_version, _, schema_conversions in versions:
+<%
+    other_library = library_name(name, other_version)
+    other_crate = library_to_crate_name(other_library)
+%>\
+
+#[cfg(feature = "${other_crate}")]
+mod ${other_library} {
+    use std::convert::TryFrom;
+
+    use crate::api;
+    use ${to_extern_crate_name(other_crate)}::api as other;
+% for sid, into_other, from_other in schema_conversions:
+% for kind, src, dst in ((into_other, 'api', 'other'), (from_other, 'other', 'api')):
+% if kind in (FROM, TRY_FROM):
+
+    impl TryFrom<${src}::${sid}> for ${dst}::${sid} {
+        type Error = client::convert::ConversionError;
+
+        fn try_from(value: ${src}::${sid}) -> std::result::Result<Self, Self::Error> {
+            client::convert::${kind == FROM and 'convert' or 'try_convert'}("${sid}", value)
+        }
+    }
+% endif
+% endfor
+% endfor
+}
+% endfor
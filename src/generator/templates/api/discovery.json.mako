@@ -0,0 +1,16 @@
+<%
+    import json
+
+    # The parts of the discovery document describing the API, after the subset of the crate was applied.
+    # It's rendered before the other templates, as those add their own information to the schemas.
+    DISCOVERY_KEYS = ('kind', 'discoveryVersion', 'id', 'name', 'version', 'revision', 'title', 'description',
+                      'documentationLink', 'rootUrl', 'servicePath', 'baseUrl', 'batchPath', 'parameters', 'auth',
+                      'schemas', 'resources', 'methods')
+    doc = dict()
+    for key in DISCOVERY_KEYS:
+        value = context.get(key, UNDEFINED)
+        if value is UNDEFINED:
+            continue
+        doc[key] = value.to_dict(recursive=True) if hasattr(value, 'to_dict') else value
+%>\
+${json.dumps(doc, indent=1, sort_keys=True)}
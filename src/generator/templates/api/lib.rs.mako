@@ -42,13 +42,26 @@ ${lib.docs(c)}
 
 // Re-export the hyper and hyper_rustls crate, they are required to build the hub
 pub use hyper;
+#[cfg(not(target_arch = "wasm32"))]
 pub use hyper_rustls;
 pub extern crate google_apis_common as client;
 pub use client::chrono;
 pub mod api;
+mod conversions;
 
-// Re-export the hub type and some basic client structs
-pub use api::${hub_type};
+/// The discovery document this crate was generated from, restricted to its methods.
+#[cfg(feature = "openapi")]
+pub const DISCOVERY_DOCUMENT: &str = include_str!("../discovery.json");
+
+/// Returns the OpenAPI 3 document of this API, converted from its discovery document.
+#[cfg(feature = "openapi")]
+pub fn openapi() -> serde_json::Value {
+    let doc = serde_json::from_str(DISCOVERY_DOCUMENT).expect("discovery document to be valid JSON");
+    client::openapi::from_discovery(&doc)
+}
+
+// Re-export the hub type, its trait for mocking, and some basic client structs
+pub use api::{${hub_type}, ${hub_type}Api};
 pub use client::{Result, Error, Delegate, FieldMask};
 
 // Re-export the yup_oauth2 crate, that is required to call some methods of the hub and the client
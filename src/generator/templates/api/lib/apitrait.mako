@@ -0,0 +1,86 @@
+<%!
+    from generator.lib.util import (hub_type, hub_type_params_s, mangle_ident, to_fqan, activity_input_type,
+                      build_all_params, organize_params, method_response, method_media_params,
+                      deprecated_attribute, read_only_cfg_attribute)
+%>\
+<%namespace name="util" file="../../../lib/util.mako"/>\
+## A trait implemented by the hub, with one function per method, which can be implemented
+## by mocks in tests of applications.
+###############################################################################################
+###############################################################################################
+<%def name="new(c)">\
+<%
+    hub_type_name = hub_type(schemas, util.canonical_name())
+    TraitType = hub_type_name + 'Api'
+
+    # (fn name, args, arg names, rtype, resource, method), for all methods which can be called without media
+    fns = []
+    for resource in sorted(c.rta_map.keys()):
+        for a in c.rta_map[resource]:
+            m = c.fqan_map[to_fqan(c.rtc_map[resource], resource, a)]
+            if method_media_params(m):
+                continue
+            params, request_value = build_all_params(c, m)
+            required_props, optional_props, part_prop = organize_params(params, request_value)
+            args = ''.join(', %s: %s' % (mangle_ident(p.name), activity_input_type(schemas, p)) for p in required_props)
+            arg_names = ', '.join(mangle_ident(p.name) for p in required_props)
+            rtype = 'client::Result<hyper::Response<hyper::body::Body>>'
+            response_schema = method_response(c, m)
+            if response_schema:
+                rtype = 'client::Result<(hyper::Response<hyper::body::Body>, %s)>' % response_schema.id
+            fns.append((mangle_ident('%s_%s' % (resource, a)), args, arg_names, rtype, resource, a,
+                        deprecated_attribute(m), read_only_cfg_attribute(m)))
+%>\
+/// All methods of the [`${hub_type_name}`] hub which don't upload media, as a trait.
+///
+/// Code which uses `&dyn ${TraitType}` instead of the hub can be tested with a mock implementing this
+/// trait, without making requests to a server. Each function corresponds to calling `doit()` on
+/// the call builder of the method, without setting any optional parameters.
+///
+% if fns:
+/// # Example
+///
+/// ```ignore
+/// struct Mock;
+///
+/// impl ${TraitType} for Mock {
+///     fn ${fns[0][0]}(&self${fns[0][1]}) -> client::BoxFuture<'_, ${fns[0][3]}> {
+///         Box::pin(async { Err(client::Error::Cancelled) })
+///     }
+///     // ...
+/// }
+/// ```
+% endif
+pub trait ${TraitType}: Send + Sync {
+% for fn_name, args, arg_names, rtype, resource, a, deprecated, cfg_read_only in fns:
+    /// Performs `${mangle_ident(resource)}().${mangle_ident(a)}(...).doit()`.
+    % if cfg_read_only:
+    ${cfg_read_only}
+    % endif
+    % if deprecated:
+    ${deprecated}
+    % endif
+    fn ${fn_name}(&self${args}) -> client::BoxFuture<'_, ${rtype}>;
+% endfor
+}
+
+impl<S> ${TraitType} for ${hub_type_name}${hub_type_params_s()}
+where
+    S: tower_service::Service<http::Uri> + Clone + Send + Sync + 'static,
+    S::Response: hyper::client::connect::Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+% for fn_name, args, arg_names, rtype, resource, a, deprecated, cfg_read_only in fns:
+    % if cfg_read_only:
+    ${cfg_read_only}
+    % endif
+    % if deprecated:
+    #[allow(deprecated)]
+    % endif
+    fn ${fn_name}(&self${args}) -> client::BoxFuture<'_, ${rtype}> {
+        Box::pin(self.${mangle_ident(resource)}().${mangle_ident(a)}(${arg_names}).doit())
+    }
+% endfor
+}
+</%def>
@@ -0,0 +1,539 @@
+<%!
+    from generator.lib.util import (rb_type, rb_type_params_s, hub_type, hub_type_params_s, to_fqan,
+                                    read_only_cfg_attribute)
+
+    # Returns the attribute compiling out a helper with the read-only feature if one of `methods` of `resource`,
+    # which it calls, changes data, or None.
+    def read_only_helper_attribute(c, resource, methods):
+        for method in methods:
+            attribute = read_only_cfg_attribute(c.fqan_map[to_fqan(c.rtc_map[resource], resource, method)])
+            if attribute:
+                return attribute
+        return None
+%>\
+<%namespace name="util" file="../../../lib/util.mako"/>\
+## Hand-written helpers which only make sense for particular APIs.
+## They are enabled by listing them in `api.helpers` within the API's overrides file.
+
+## Parallel composite uploads for the storage API, see
+## https://cloud.google.com/storage/docs/parallel-composite-uploads
+###############################################################################################
+###############################################################################################
+<%def name="composite_upload(c)">\
+<%
+    ThisType = rb_type('objects') + rb_type_params_s('objects', c)
+%>\
+/// The maximum amount of source objects that can be composed into one object with a single request.
+pub const MAX_COMPOSE_COMPONENTS: usize = 32;
+
+impl${rb_type_params_s('objects', c)} ${ThisType}
+where
+    S: tower_service::Service<http::Uri> + Clone + Send + Sync + 'static,
+    S::Response: hyper::client::connect::Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    /// Upload the file at `path` as object `object` of `bucket` using a *parallel composite upload*,
+    /// similar to what `gsutil` does for large files.
+    ///
+    /// The file is split into at most `parts` sections (but no more than [`MAX_COMPOSE_COMPONENTS`]),
+    /// which are uploaded concurrently as temporary objects. These are then composed into the final object,
+    /// whose metadata is taken from `request`, and deleted afterwards - even if the upload failed.
+    ///
+    /// Please note that composite objects don't have an MD5 hash, only a CRC32C checksum.
+    #[cfg(not(target_arch = "wasm32"))]
+    % if read_only_helper_attribute(c, 'objects', ('insert', 'compose', 'delete')):
+    ${read_only_helper_attribute(c, 'objects', ('insert', 'compose', 'delete'))}
+    % endif
+    pub async fn composite_upload<P>(&self, request: Object, bucket: &str, object: &str, path: P, mime_type: mime::Mime, parts: usize) -> client::Result<(hyper::Response<hyper::body::Body>, Object)>
+        where P: AsRef<std::path::Path> {
+        let path = path.as_ref();
+        let size = fs::metadata(path)?.len();
+        let parts = parts.clamp(1, MAX_COMPOSE_COMPONENTS) as u64;
+        let part_size = ((size + parts - 1) / parts).max(1);
+
+        // (name, start, len) of each temporary object
+        let mut sections = Vec::new();
+        let mut start = 0;
+        while start < size || sections.is_empty() {
+            let len = part_size.min(size - start);
+            sections.push((format!("{}.{}.part", object, sections.len()), start, len));
+            start += len;
+        }
+
+        let mime_type = &mime_type;
+        let uploads = sections.iter().map(|(part_name, start, len)| async move {
+            let section = match client::FileSection::open(path, *start, *len) {
+                Ok(section) => section,
+                Err(err) => return Err(client::Error::Io(err)),
+            };
+            let part = Object {
+                name: Some(part_name.clone()),
+                ..Default::default()
+            };
+            self.insert(part, bucket).upload_resumable(client::ReadSeekAdapter::new(section), mime_type.clone()).await
+        });
+        let uploaded = client::futures::future::join_all(uploads).await;
+
+        let result = match uploaded.into_iter().find_map(|r| r.err()) {
+            Some(err) => Err(err),
+            None => {
+                let compose_request = ComposeRequest {
+                    destination: Some(request),
+                    source_objects: Some(sections.iter().map(|(part_name, _, _)| ComposeRequestSourceObjects {
+                        name: Some(part_name.clone()),
+                        ..Default::default()
+                    }).collect()),
+                    ..Default::default()
+                };
+                self.compose(compose_request, bucket, object).doit().await
+            }
+        };
+
+        for (part_name, _, _) in &sections {
+            // Parts which failed to upload don't exist, which is why errors are ignored here.
+            self.delete(bucket, part_name).doit().await.ok();
+        }
+        result
+    }
+}
+</%def>
+
+## V4 signed URLs for the storage API, see
+## https://cloud.google.com/storage/docs/access-control/signed-urls
+###############################################################################################
+###############################################################################################
+<%def name="signed_url(c)">\
+<%
+    hub_type_name = hub_type(c.schemas, util.canonical_name())
+%>\
+#[cfg(feature = "signing")]
+impl<S> ${hub_type_name}${hub_type_params_s()}
+where
+    S: tower_service::Service<http::Uri> + Clone + Send + Sync + 'static,
+    S::Response: hyper::client::connect::Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    /// Create a V4 signed URL which allows anyone holding it to perform `method` (like `GET` or `PUT`)
+    /// on `object` in `bucket`, until `expires_in` elapsed. It may be valid for at most 7 days.
+    ///
+    /// The URL is signed by `signer`, which is either a `client::signed_url::ServiceAccountSigner` created from a
+    /// service account key, or an [`iam_signer()`](Self::iam_signer) if the key is not available locally.
+    /// Use `client::signed_url::sign_url(...)` to sign additional headers or query parameters as well.
+    pub async fn signed_url(&self, signer: &dyn client::signed_url::SignBlob, method: &str, bucket: &str, object: &str, expires_in: std::time::Duration) -> client::Result<String> {
+        client::signed_url::sign_url(signer, &self._root_url, method, bucket, object, expires_in, &[], &[], client::chrono::Utc::now()).await
+    }
+
+    /// Returns a signer for [`signed_url()`](Self::signed_url) which uses the IAM credentials API to sign on behalf of
+    /// the service account identified by `service_account_email`, using the client and authenticator of this hub.
+    pub fn iam_signer(&self, service_account_email: &str) -> client::signed_url::IamSigner<S> {
+        client::signed_url::IamSigner {
+            client: self.client.clone(),
+            auth: self.auth.clone(),
+            service_account_email: service_account_email.to_string(),
+        }
+    }
+}
+</%def>
+
+## Valid test matrices for the testing API, to be used in tests of applications
+###############################################################################################
+###############################################################################################
+<%def name="test_lab_fixtures(c)">\
+<%
+    hub_type_name = hub_type(c.schemas, util.canonical_name())
+%>\
+/// Valid [`TestMatrix`] values, to be used in tests of code creating test matrices.
+///
+/// The files they reference, like `gs://my-bucket/app.apk`, don't exist. Replace them with the
+/// `with_*(...)` methods of the schemas where a test needs real ones, as their fields can't be
+/// given with struct literals outside of this crate:
+///
+/// ```ignore
+/// let apk = FileReference::default().with_gcs_path("gs://real-bucket/app.apk".to_string());
+/// let matrix = fixtures::robo_matrix().with_test_specification(
+///     TestSpecification::default().with_android_robo_test(AndroidRoboTest::default().with_app_apk(apk)),
+/// );
+/// ```
+pub mod fixtures {
+    use super::*;
+
+    /// The bucket which results are written to.
+    pub const RESULTS_GCS_PATH: &str = "gs://my-bucket/results";
+
+    /// A matrix running the instrumentation tests of an APK on a single Android device.
+    pub fn minimal_instrumentation_matrix() -> TestMatrix {
+        android_matrix(TestSpecification {
+            android_instrumentation_test: Some(AndroidInstrumentationTest {
+                app_apk: Some(file_reference("gs://my-bucket/app.apk")),
+                test_apk: Some(file_reference("gs://my-bucket/app-test.apk")),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    }
+
+    /// A matrix crawling the UI of an APK with Robo on a single Android device.
+    pub fn robo_matrix() -> TestMatrix {
+        android_matrix(TestSpecification {
+            android_robo_test: Some(AndroidRoboTest {
+                app_apk: Some(file_reference("gs://my-bucket/app.apk")),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    }
+
+    /// A matrix running the game loop of an APK on a single Android device.
+    pub fn game_loop_matrix() -> TestMatrix {
+        android_matrix(TestSpecification {
+            android_test_loop: Some(AndroidTestLoop {
+                app_apk: Some(file_reference("gs://my-bucket/app.apk")),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    }
+
+    /// A matrix running XCTests on a single iOS device.
+    pub fn xctest_matrix() -> TestMatrix {
+        TestMatrix {
+            test_specification: Some(TestSpecification {
+                ios_xc_test: Some(IosXcTest {
+                    tests_zip: Some(file_reference("gs://my-bucket/tests.zip")),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            environment_matrix: Some(EnvironmentMatrix {
+                ios_device_list: Some(IosDeviceList {
+                    ios_devices: Some(vec![ios_device()]),
+                }),
+                ..Default::default()
+            }),
+            result_storage: Some(result_storage()),
+            ..Default::default()
+        }
+    }
+
+    /// A Pixel 5 running Android 11 in portrait orientation.
+    pub fn android_device() -> AndroidDevice {
+        AndroidDevice {
+            android_model_id: Some("redfin".to_string()),
+            android_version_id: Some("30".to_string()),
+            locale: Some("en".to_string()),
+            orientation: Some("portrait".to_string()),
+        }
+    }
+
+    /// An iPhone 13 Pro running iOS 15.7 in portrait orientation.
+    pub fn ios_device() -> IosDevice {
+        IosDevice {
+            ios_model_id: Some("iphone13pro".to_string()),
+            ios_version_id: Some("15.7".to_string()),
+            locale: Some("en".to_string()),
+            orientation: Some("portrait".to_string()),
+        }
+    }
+
+    /// Stores results below [`RESULTS_GCS_PATH`].
+    pub fn result_storage() -> ResultStorage {
+        ResultStorage {
+            google_cloud_storage: Some(GoogleCloudStorage {
+                gcs_path: Some(RESULTS_GCS_PATH.to_string()),
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn file_reference(gcs_path: &str) -> FileReference {
+        FileReference {
+            gcs_path: Some(gcs_path.to_string()),
+        }
+    }
+
+    fn android_matrix(test_specification: TestSpecification) -> TestMatrix {
+        TestMatrix {
+            test_specification: Some(test_specification),
+            environment_matrix: Some(EnvironmentMatrix {
+                android_device_list: Some(AndroidDeviceList {
+                    android_devices: Some(vec![android_device()]),
+                }),
+                ..Default::default()
+            }),
+            result_storage: Some(result_storage()),
+            ..Default::default()
+        }
+    }
+
+    #[cfg(all(test, feature = "stub-server"))]
+    mod test {
+        use super::*;
+
+        #[tokio::test]
+        % if read_only_helper_attribute(c, 'projects', ('testMatrices.create',)):
+        ${read_only_helper_attribute(c, 'projects', ('testMatrices.create',))}
+        % endif
+        async fn fixtures_create_test_matrices() {
+            let doc = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/../../etc/api/testing/v1/testing-api.json"));
+            let server = client::stub_server::StubApi::from_discovery(doc).unwrap().serve().await.unwrap();
+            let mut hub = ${hub_type_name}::new(hyper::Client::builder().build(hyper::client::HttpConnector::new()), client::NoToken);
+            hub.base_url(server.base_url());
+            hub.root_url(server.root_url());
+
+            for matrix in [minimal_instrumentation_matrix(), robo_matrix(), game_loop_matrix(), xctest_matrix()] {
+                let spec = matrix.test_specification.as_ref().unwrap();
+                let tests = [spec.android_instrumentation_test.is_some(), spec.android_robo_test.is_some(),
+                             spec.android_test_loop.is_some(), spec.ios_xc_test.is_some()];
+                assert_eq!(tests.iter().filter(|t| **t).count(), 1);
+                let (res, _) = hub.projects().test_matrices_create(matrix, "my-project").doit().await.unwrap();
+                assert!(res.status().is_success());
+            }
+        }
+    }
+}
+</%def>
+
+## Incremental progress of test matrices for the testing API, for CI integrations
+###############################################################################################
+###############################################################################################
+<%def name="matrix_watcher(c)">\
+<%
+    hub_type_name = hub_type(c.schemas, util.canonical_name())
+%>\
+/// Polling of test matrices which reports what changed between two polls.
+///
+/// ```ignore
+/// let watcher = matrix_watcher::MatrixWatcher::new(&hub, "my-project", &matrix_id);
+/// let matrix = watcher.watch(|event| println!("{:?}", event)).await?;
+/// ```
+pub mod matrix_watcher {
+    #[cfg(not(target_arch = "wasm32"))]
+    use std::collections::VecDeque;
+    use std::time::Duration;
+
+    use super::*;
+
+    /// The time between two polls, unless set with [`MatrixWatcher::interval()`].
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+
+    /// A change of a test matrix between two polls.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum MatrixEvent {
+        /// The state of the matrix changed, like from `PENDING` to `RUNNING`
+        MatrixState(String),
+        /// The state of a test execution changed, identified by its id and the index of its shard, if the test is sharded
+        ExecutionState { execution_id: String, shard_index: Option<i32>, state: String },
+        /// A progress message was appended to the details of a test execution
+        ProgressMessage { execution_id: String, message: String },
+        /// The matrix finished, with its outcome summary like `SUCCESS`, or its final state if it didn't run its tests,
+        /// like `INVALID`
+        Finished(String),
+    }
+
+    /// Returns true if the matrix or execution `state` won't change anymore.
+    pub fn is_final_state(state: &str) -> bool {
+        !matches!(state, "" | "TEST_STATE_UNSPECIFIED" | "VALIDATING" | "PENDING" | "RUNNING")
+    }
+
+    /// Returns the events of the changes from `previous` to `current`. Without `previous`, all states and messages of
+    /// `current` are new.
+    pub fn diff(previous: Option<&TestMatrix>, current: &TestMatrix) -> Vec<MatrixEvent> {
+        let mut events = Vec::new();
+        let state = current.state.as_deref().unwrap_or_default();
+        let previous_state = previous.and_then(|matrix| matrix.state.as_deref());
+        if previous_state != Some(state) && !state.is_empty() {
+            events.push(MatrixEvent::MatrixState(state.to_string()));
+        }
+
+        let previous_executions = previous.and_then(|matrix| matrix.test_executions.as_deref()).unwrap_or_default();
+        for execution in current.test_executions.iter().flatten() {
+            let execution_id = execution.id.clone().unwrap_or_default();
+            let previous_execution = previous_executions.iter().find(|e| e.id == execution.id);
+            let execution_state = execution.state.as_deref().unwrap_or_default();
+            if previous_execution.and_then(|e| e.state.as_deref()) != Some(execution_state) && !execution_state.is_empty() {
+                events.push(MatrixEvent::ExecutionState {
+                    execution_id: execution_id.clone(),
+                    shard_index: execution.shard.as_ref().and_then(|shard| shard.shard_index),
+                    state: execution_state.to_string(),
+                });
+            }
+            // Messages are only ever appended, so those beyond the previous ones are new
+            let seen = previous_execution.map_or(0, |e| progress_messages(e).len());
+            for message in progress_messages(execution).iter().skip(seen) {
+                events.push(MatrixEvent::ProgressMessage {
+                    execution_id: execution_id.clone(),
+                    message: message.clone(),
+                });
+            }
+        }
+
+        if is_final_state(state) && !previous_state.map_or(false, is_final_state) {
+            let outcome = match state {
+                "FINISHED" => current.outcome_summary.clone().unwrap_or_default(),
+                _ => state.to_string(),
+            };
+            events.push(MatrixEvent::Finished(outcome));
+        }
+        events
+    }
+
+    fn progress_messages(execution: &TestExecution) -> &[String] {
+        execution.test_details.as_ref().and_then(|details| details.progress_messages.as_deref()).unwrap_or_default()
+    }
+
+    /// Polls a test matrix with `test_matrices_get`, and reports the changes since the previous poll as [`MatrixEvent`]s,
+    /// through a callback with [`watch()`](Self::watch) or as a stream with [`events()`](Self::events).
+    pub struct MatrixWatcher<'a, S> {
+        hub: &'a ${hub_type_name}<S>,
+        project_id: String,
+        test_matrix_id: String,
+        interval: Duration,
+        last: Option<TestMatrix>,
+    }
+
+    impl<'a, S> MatrixWatcher<'a, S>
+    where
+        S: tower_service::Service<http::Uri> + Clone + Send + Sync + 'static,
+        S::Response: hyper::client::connect::Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+        S::Future: Send + Unpin + 'static,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    {
+        /// Returns a watcher of the test matrix `test_matrix_id` of the project `project_id`, which polls every
+        /// [`DEFAULT_INTERVAL`].
+        pub fn new(hub: &'a ${hub_type_name}<S>, project_id: &str, test_matrix_id: &str) -> Self {
+            MatrixWatcher {
+                hub,
+                project_id: project_id.to_string(),
+                test_matrix_id: test_matrix_id.to_string(),
+                interval: DEFAULT_INTERVAL,
+                last: None,
+            }
+        }
+
+        /// Sets the time between two polls.
+        pub fn interval(mut self, interval: Duration) -> Self {
+            self.interval = interval;
+            self
+        }
+
+        /// Returns the matrix of the last poll, if there was one.
+        pub fn matrix(&self) -> Option<&TestMatrix> {
+            self.last.as_ref()
+        }
+
+        /// Returns true if the matrix of the last poll was in a final state.
+        pub fn is_finished(&self) -> bool {
+            self.last.as_ref().map_or(false, |matrix| is_final_state(matrix.state.as_deref().unwrap_or_default()))
+        }
+
+        /// Gets the matrix once, and returns the changes since the previous poll.
+        pub async fn poll(&mut self) -> client::Result<Vec<MatrixEvent>> {
+            let (_, matrix) = self.hub.projects().test_matrices_get(&self.project_id, &self.test_matrix_id).doit().await?;
+            let events = diff(self.last.as_ref(), &matrix);
+            self.last = Some(matrix);
+            Ok(events)
+        }
+
+        /// Polls the matrix until it's in a final state, calls `on_event` with each change, and returns the final matrix.
+        /// It isn't available on wasm targets, which have no timer to wait between polls. Call `poll()` with a timer of your own there.
+        #[cfg(not(target_arch = "wasm32"))]
+        pub async fn watch<F: FnMut(&MatrixEvent)>(mut self, mut on_event: F) -> client::Result<TestMatrix> {
+            loop {
+                for event in self.poll().await? {
+                    on_event(&event);
+                }
+                if self.is_finished() {
+                    return Ok(self.last.take().expect("polled"));
+                }
+                sleep(self.interval).await;
+            }
+        }
+
+        /// Returns the changes of the matrix as a stream, which ends after [`MatrixEvent::Finished`]. Failed polls are
+        /// errors of the stream, which polls again after the interval if it's polled further. Like `watch()`, it isn't
+        /// available on wasm targets.
+        #[cfg(not(target_arch = "wasm32"))]
+        pub fn events(self) -> impl client::futures::Stream<Item = client::Result<MatrixEvent>> + 'a {
+            client::futures::stream::unfold((self, VecDeque::new(), false), |(mut watcher, mut pending, mut polled)| async move {
+                loop {
+                    if let Some(event) = pending.pop_front() {
+                        return Some((Ok(event), (watcher, pending, polled)));
+                    }
+                    if watcher.is_finished() {
+                        return None;
+                    }
+                    if polled {
+                        sleep(watcher.interval).await;
+                    }
+                    polled = true;
+                    match watcher.poll().await {
+                        Ok(events) => pending.extend(events),
+                        Err(err) => return Some((Err(err), (watcher, pending, polled))),
+                    }
+                }
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        fn execution(id: &str, shard_index: i32, state: &str, messages: &[&str]) -> TestExecution {
+            TestExecution {
+                id: Some(id.to_string()),
+                shard: Some(Shard { shard_index: Some(shard_index), ..Default::default() }),
+                state: Some(state.to_string()),
+                test_details: Some(TestDetails {
+                    progress_messages: Some(messages.iter().map(|m| m.to_string()).collect()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }
+        }
+
+        fn matrix(state: &str, executions: Vec<TestExecution>) -> TestMatrix {
+            TestMatrix {
+                state: Some(state.to_string()),
+                test_executions: Some(executions),
+                outcome_summary: Some("SUCCESS".to_string()).filter(|_| state == "FINISHED"),
+                ..Default::default()
+            }
+        }
+
+        #[test]
+        fn changes_between_polls_are_events() {
+            let pending = matrix("PENDING", vec![execution("a", 0, "PENDING", &[]), execution("b", 1, "PENDING", &[])]);
+            assert_eq!(diff(None, &pending), [
+                MatrixEvent::MatrixState("PENDING".into()),
+                MatrixEvent::ExecutionState { execution_id: "a".into(), shard_index: Some(0), state: "PENDING".into() },
+                MatrixEvent::ExecutionState { execution_id: "b".into(), shard_index: Some(1), state: "PENDING".into() },
+            ]);
+
+            let running = matrix("RUNNING", vec![execution("a", 0, "RUNNING", &["Starting Test"]), execution("b", 1, "PENDING", &[])]);
+            assert_eq!(diff(Some(&pending), &running), [
+                MatrixEvent::MatrixState("RUNNING".into()),
+                MatrixEvent::ExecutionState { execution_id: "a".into(), shard_index: Some(0), state: "RUNNING".into() },
+                MatrixEvent::ProgressMessage { execution_id: "a".into(), message: "Starting Test".into() },
+            ]);
+            assert!(diff(Some(&running), &running).is_empty());
+
+            let finished = matrix("FINISHED", vec![execution("a", 0, "FINISHED", &["Starting Test", "Done"]), execution("b", 1, "FINISHED", &[])]);
+            assert_eq!(diff(Some(&running), &finished), [
+                MatrixEvent::MatrixState("FINISHED".into()),
+                MatrixEvent::ExecutionState { execution_id: "a".into(), shard_index: Some(0), state: "FINISHED".into() },
+                MatrixEvent::ProgressMessage { execution_id: "a".into(), message: "Done".into() },
+                MatrixEvent::ExecutionState { execution_id: "b".into(), shard_index: Some(1), state: "FINISHED".into() },
+                MatrixEvent::Finished("SUCCESS".into()),
+            ]);
+            assert!(diff(Some(&finished), &finished).is_empty());
+            assert_eq!(diff(None, &matrix("INVALID", vec![])), [
+                MatrixEvent::MatrixState("INVALID".into()),
+                MatrixEvent::Finished("INVALID".into()),
+            ]);
+        }
+    }
+}
+</%def>
@@ -7,7 +7,7 @@
                       REQUEST_MARKER_TRAIT, RESPONSE_MARKER_TRAIT, supports_scopes, to_api_version,
                       to_fqan, METHODS_RESOURCE, ADD_PARAM_MEDIA_EXAMPLE, PROTOCOL_TYPE_INFO, enclose_in,
                       upload_action_fn, METHODS_BUILDER_MARKER_TRAIT, DELEGATE_TYPE,
-                      to_extern_crate_name, rust_doc_sanitize)  
+                      to_extern_crate_name, rust_doc_sanitize, is_mutating_method)  
 
     def pretty_name(name):
         return ' '.join(split_camelcase_s(name).split('.'))
@@ -41,6 +41,16 @@
                 return lf % (name, url)
         return lf % (name, doc_base_url + url)
 
+    # Call builders of methods changing data don't exist with the read-only feature, so rustdoc links them by URL,
+    # which it doesn't resolve, rather than by path
+    def method_link(name, m, builder_type):
+        if rust_doc and is_mutating_method(m):
+            return '[%s](api/struct.%s.html)' % (name, builder_type)
+        return link(name, 'api::%s' % builder_type)
+
+    def method_of(resource, method):
+        return c.fqan_map[to_fqan(c.rtc_map[resource], resource, method)]
+
 
     api_version = to_api_version(version)
     if api_version[0].isdigit():
@@ -83,7 +93,7 @@ It seems there is nothing you can do here ... .
 <%
     md_methods = list()
     for method in sorted(c.rta_map[r]):
-        md_methods.append(link('*%s*' % pretty_name(method), "api::%s" % mb_type(r, method)))
+        md_methods.append(method_link('*%s*' % pretty_name(method), method_of(r, method), mb_type(r, method)))
     md_resource = pretty_name(r)
     sn = singular(canonical_type_name(r))
 
@@ -100,7 +110,7 @@ Other activities are ...
 
 % endif
 % for method in sorted(c.rta_map[METHODS_RESOURCE]):
-* ${link(pretty_name(method), "api::%s" % mb_type(METHODS_RESOURCE, method))}
+* ${method_link(pretty_name(method), method_of(METHODS_RESOURCE, method), mb_type(METHODS_RESOURCE, method))}
 % endfor
 % endif
 
@@ -115,7 +125,7 @@ ${method_type} supported by ...
     if resource != METHODS_RESOURCE:
         name_parts.append(pretty_name(resource))
 %>\
-* ${link('*%s*' % ' '.join(name_parts), "api::%s" % mb_type(resource, method))}
+* ${method_link('*%s*' % ' '.join(name_parts), m, mb_type(resource, method))}
 % endfor ## for each method
 
 % endif  ## if methods
@@ -192,6 +202,12 @@ ${link('Hub Delegate', delegate_url)}, or the ${link('Authenticator Delegate', u
 
 When delegates handle errors or intermediate values, they may have a chance to instruct the system to retry. This 
 makes the system potentially resilient to all kinds of errors.
+Without a custom delegate, transient failures like `503 Service Unavailable` are retried with exponential backoff
+by setting a `client::retry::RetryPolicy` with `retry_policy(...)`, on the hub for all calls, or on a call builder.
+Responses with `429 Too Many Requests` or `503 Service Unavailable` which say when to retry, like rate limited calls, are
+retried after that delay in any case, up to the `retry_after_limit(...)` of the hub.
+Calls which must not take longer than a given time, including their retries, are given a `timeout(...)` on their call
+builder, after which they fail with `client::Error::Deadline`.
 
 ${'##'} Uploads and Downloads
 If a method supports downloads, the response body, which is part of the ${link('Result', 'client::Result')}, should be
@@ -203,6 +219,13 @@ this call: `${ADD_PARAM_MEDIA_EXAMPLE}`.
 Methods supporting uploads can do so using up to ${len(PROTOCOL_TYPE_INFO)} different protocols: 
 ${put_and(md_italic(PROTOCOL_TYPE_INFO.keys()))}. The distinctiveness of each is represented by customized 
 `${api.terms.action}(...)` methods, which are then named ${put_and(enclose_in('`', ("%s(...)" % upload_action_fn(api.terms.upload_action, v['suffix']) for v in PROTOCOL_TYPE_INFO.values())))} respectively.
+The media is read from any `AsyncRead + AsyncSeek` source, like `tokio::fs::File`. Blocking sources like `std::fs::File`
+can be passed by wrapping them into a `client::ReadSeekAdapter`.
+
+The ${link('delegate', delegate_url)} is informed about the progress of uploads through `upload_progress(...)`.
+Media downloads can be written to any `Write` implementation with `client::download_with_progress(...)`, which
+reports each received chunk to `download_progress(...)`.
+With the `indicatif` feature enabled, `client::progress::ProgressBarDelegate` shows this progress as progress bars.
 
 ${'##'} Customization and Callbacks
 
@@ -213,6 +236,88 @@ retry on failure.
 
 The ${link('delegate trait', delegate_url)} is default-implemented, allowing you to customize it with minimal effort.
 
+To attach the requests made to a bug report, they can be captured into a HAR file by handing a
+`client::har::HarRecorder` to the `capture_har(...)` method of the ${link('hub', hub_url)}. Credentials are redacted, and bodies are truncated.
+
+With the `audit` feature, environments which need to prove what was changed through the API can hand a
+`client::audit::Auditor` to the `audit(...)` method of the hub, which records every call not using `GET`, with its outcome, to a sink of your choice.
+
+To unit-test code using this library without a server, let it use the `${hub_url}Api` trait, which is implemented by
+the ${link('hub', hub_url)}, and implement it for a mock in your tests.
+Integration tests can instead run against `client::stub_server::StubApi`, which the `stub-server` feature provides.
+It serves canned responses for all methods of an API, given its discovery document, and is used by pointing
+the `base_url(...)` and `root_url(...)` of the hub to it.
+With the `cassette` feature, `client::cassette::CassetteConnector` records the requests of a hub to a cassette file,
+and replays them in later runs, without network access.
+To verify how your code handles errors, the `chaos` feature provides `client::chaos::ChaosConnector`, which makes
+a given share of requests fail with `429` or `500`, time out, or return malformed JSON.
+Tests using mock servers like `wiremock` can enable the `mock` feature, and get a hub sending its requests to the
+server with `${hub_url}::for_mock_server(...)`. `${hub_url}::method_route(...)` returns the HTTP method and path
+regex to match the requests of a method by its id.
+The examples of this documentation run the same way, against a hub answering with examples of the responses of
+their methods.
+The `examples/quickstart.rs` of this crate lists and creates resources against `client::mock::MockConnector`, which
+answers requests in memory, and is a tested starting point for your own code.
+Local emulators, like those of Pub/Sub, Firestore or Spanner, are used with a hub from `${hub_url}::for_emulator(...)`,
+which the `emulator` feature provides. It sends requests over plain HTTP and without authentication.
+% if api.get('emulator_host_env'):
+`${hub_url}::from_emulator_env()` uses the emulator in the `${api.emulator_host_env}` environment variable.
+% endif
+Services which must never change data can enable the `readonly-only` feature, which compiles out the call builders
+of all methods not using `GET` or a read-only scope, like `.../auth/drive.readonly`.
+All API crates share the `Error` of the `client` module, so programs calling several APIs handle the errors of all of
+them in one place. `status()` and `canonical_code()` tell how the server answered a failed call, if it did.
+Programs using more than one connector can wrap each of them into a `client::DynConnector`, and use the
+`Dyn${hub_url}` hub, which compiles the hub and its call builders only once for all of them.
+Programs calling several APIs can create the client and the authenticator once with the `client::client_builder::ClientBuilder`
+of the `client-builder` feature, and create the hubs of all of them with the `client()` and `auth()` of the `SharedClient` it
+builds, so that they share connections and tokens.
+To compose calls with tower middleware, like buffering or load shedding, `client::service::CallService` turns a call
+into a `tower_service::Service`, which makes the call through a hub shared in an `Arc` for each request it gets.
+Plugin systems and scripting layers can call any method by its id, with `serde_json::Value`s for parameters and results,
+through the `client::dyn_hub::DynHub` trait the hub implements, as `Box<dyn DynHub>` if they hold hubs of several APIs.
+Groups of independent calls, like fetching many resources by their ids, are made with `client::execute_all(...)`, which
+keeps at most a given number of their `doit()` futures in flight, optionally shares a `client::RateLimiter` between them,
+and returns the values and errors of all calls as `client::PartialResult`.
+Methods which need no scopes are authorized with an API key alone, and don't ask the authenticator for a token.
+The key is set with `api_key(...)` on the hub, or provided by the delegate of a call. Programs using only such methods
+can create the hub with `${hub_url}::with_api_key(...)`, without an authenticator.
+The read-only calls in `tests/live.rs` run against the real API with the `live` feature, if `GOOGLE_APIS_LIVE_PROJECT`
+names a project, and authenticate with the application default credentials.
+To check how a call was built without making it, the `into_parts()` method of call builders returns its parameters,
+request and scopes as `client::CallParts`, or `Error::InvalidUrl` if they don't make up the URL.
+Its `to_http_request()` turns them into an `http::Request`, which executors other than hyper, like `reqwest`, can send
+once they authorized it.
+`build_request()` does the same, and also returns a `client::ResponseParser` turning the response into the result
+`doit()` would have returned, to make calls through any transport, like message queues.
+The methods creating call builders take path parameters, like ids of resources, as the newtypes of the `path_params`
+module, or as strings, which convert into them with `TryFrom`. Like their `new(...)`, it rejects empty values, and calls
+given an empty one fail with `Error::InvalidUrl` rather than addressing another resource.
+Their values are percent-encoded into the path of the URL by `client::uri_template::expand()`, so that ids containing
+`/`, `%` or spaces address the right resource, and calls with URLs which can't be built fail with `Error::InvalidUrl`.
+The `api::METHODS` table describes every method, with its HTTP method, path template, required parameters and
+scopes, for tools which inspect the API programmatically.
+With the `openapi` feature, `openapi()` returns an OpenAPI 3 document of the API, converted from the discovery
+document the crate embeds then, to generate gateway configurations or clients in other languages.
+
+With the `tracing` feature enabled, each call is wrapped by `client::instrument()`, see its documentation for the span.
+The `simd-json` feature decodes response bodies with simd-json, which parses large responses, like long lists,
+several times faster. Bodies it can't handle are decoded with serde_json as usual.
+% if parameters and 'proto' in parameters.get('alt', {}).get('enum', []):
+With the `proto` feature, calls returning a resource have `doit_proto()`, which requests the response with `alt=proto`
+and decodes it into a `prost` message of your own, like one generated from the protos of the API. Responses are smaller
+and decode faster that way.
+% endif
+% if api.get('grpc_endpoint'):
+With the `grpc` feature, `${hub_url}::grpc_transport()` connects to the gRPC endpoint of the API, `${api.grpc_endpoint}`,
+to make calls with the `tonic` clients generated from the protos of the API, including streaming ones. The transport
+authorizes and retries calls like the hub does, see `client::grpc`.
+% endif
+% if api.get('boxed_futures'):
+The `doit()` methods of calls without media uploads return their future boxed, as `client::BoxFuture`, which keeps
+binaries using many calls considerably smaller at the cost of one allocation per call.
+% endif
+
 ${'##'} Optional Parts in Server-Requests
 
 All structures provided by this library are made to be ${link('encodable', request_trait_url)} and 
@@ -220,6 +325,8 @@ ${link('decodable', response_trait_url)} via *json*. Optionals are used to indic
 are valid.
 Most optionals are are considered ${link('Parts', part_trait_url)} which are identifiable by name, which will be sent to 
 the server to indicate either the set parts of the request or the desired parts in the response.
+As new revisions of the API may add fields, structures are `#[non_exhaustive]` and built starting from `default()`,
+either by assigning their fields or by chaining their `with_*(...)` methods.
 
 ${'##'} Builder Arguments
 
@@ -241,7 +348,18 @@ Arguments will always be copied or cloned into the builder, to make them indepen
 ## Needs test_prelude.
 ###############################################################################################
 ###############################################################################################
-<%def name="test_hub(hub_type, comments=True)">\
+<%def name="test_hub(hub_type, comments=True, mock_response=None)">\
+% if mock_response is not None:
+use ${util.library_name()}::{${hub_type}, client, hyper, chrono, FieldMask};
+
+% if comments:
+// Instantiate the hub with a connector answering all requests in memory, and without authentication.
+// Programs calling the real API use a `hyper_rustls` connector and an authenticator, like the
+// `InstalledFlowAuthenticator` of the `oauth2` module, instead.
+% endif
+let connector = client::mock::MockConnector::json(serde_json::from_str(r#"${mock_response}"#).unwrap());
+let mut hub = ${hub_type}::new(hyper::Client::builder().build(connector), client::NoToken);\
+% else:
 use std::default::Default;
 use ${util.library_name()}::{${hub_type}, oauth2, hyper, hyper_rustls, chrono, FieldMask};
 
@@ -262,6 +380,7 @@ let auth = oauth2::InstalledFlowAuthenticator::builder(
         oauth2::InstalledFlowReturnMethod::HTTPRedirect,
     ).build().await.unwrap();
 let mut hub = ${hub_type}::new(hyper::Client::builder().build(hyper_rustls::HttpsConnectorBuilder::new().with_native_roots().https_or_http().enable_http1().enable_http2().build()), auth);\
+% endif
 </%def>
 
 ## You will still have to set the filter for your comment type - either nothing, or rust_doc_comment !
@@ -279,7 +398,7 @@ let mut hub = ${hub_type}::new(hyper::Client::builder().build(hyper_rustls::Http
         fr = find_fattest_resource(c)
     if fr is not None:
         fqan = None
-        last_param_count = None
+        last_preference = None
         for fqan in c.sta_map[fr.id]:
             category, aresource, amethod = activity_split(fqan)
             # Cannot use fqan directly, as it might need remapping thanks to 'special case' resource.
@@ -288,15 +407,17 @@ let mut hub = ${hub_type}::new(hyper::Client::builder().build(hyper_rustls::Http
             build_all_params(c, am)
             aparams, arequest_value = build_all_params(c, am)
 
-            if last_param_count is None or len(aparams) > last_param_count:
+            # prefer methods which don't change data, which exist with the read-only feature as well
+            preference = (not is_mutating_method(am), len(aparams))
+            if last_preference is None or preference > last_preference:
                 m, resource, method, params, request_value = am, aresource or category, amethod, aparams, arequest_value
-                last_param_count = len(aparams)
+                last_preference = preference
         # end for each fn to test
         part_prop, parts = parts_from_params(params)
     # end fill in values
 %>\
 % if fr:
-${mbuild.usage(resource, method, m, params, request_value, parts, show_all=True, rust_doc=rust_doc, handle_result=True)}\
+${mbuild.usage(resource, method, m, params, request_value, parts, show_all=True, rust_doc=rust_doc, handle_result=True, gate_read_only=True)}\
 % else:
 <%block filter="main_filter">\
 ${util.test_prelude()}\
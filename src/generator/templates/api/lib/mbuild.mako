@@ -1,5 +1,6 @@
 <%!
     from generator.lib.util import (put_and, rust_test_fn_invisible, rust_doc_test_norun, rust_doc_comment,
+                      rust_test_main_invisible, rust_doc_test, json_example,
                       rb_type, mb_type, singular, hub_type, to_fqan, indent_all_but_first_by,
                       activity_rust_type, mangle_ident, activity_input_type, get_word,
                       split_camelcase_s, property, is_pod_property, TREF, IO_REQUEST,
@@ -9,10 +10,12 @@
                       hub_type_params_s, method_media_params, enclose_in, method_response,
                       CALL_BUILDER_MARKERT_TRAIT, pass_through, markdown_rust_block, parts_from_params,
                       DELEGATE_PROPERTY_NAME, struct_type_bounds_s, scope_url_to_variant,
-                      re_find_replacements, ADD_PARAM_FN, ADD_PARAM_MEDIA_EXAMPLE, upload_action_fn, METHODS_RESOURCE,
+                      url_replacements, ADD_PARAM_FN, ADD_PARAM_MEDIA_EXAMPLE, upload_action_fn, METHODS_RESOURCE,
                       method_name_to_variant, size_to_bytes, method_default_scope,
                       is_repeated_property, setter_fn_name, ADD_SCOPE_FN, ADD_SCOPES_FN, rust_doc_sanitize,
-                      CLEAR_SCOPES_FN, items, string_impl)
+                      CLEAR_SCOPES_FN, CHUNK_SIZE_PROPERTY_NAME, KEEP_CONTENT_ENCODING_PROPERTY_NAME, STANDARD_PARAMS, items,
+                      string_impl, path_param_type, builder_input_type, builder_copy_value_s,
+                      read_only_cfg_attribute, is_mutating_method, rust_test_fn_read_only_gated, timeout_setter_fn)
 
     SIMPLE = "simple"
     RESUMABLE = "resumable"
@@ -61,9 +64,18 @@
         # end
     # end
 
+    # the standard parameters of the API with a typed setter, unless the method has a parameter of the same name
+    param_names = [p.name for p in params]
+    typed_params = dict((pn, v) for pn, v in items(STANDARD_PARAMS)
+                        if parameters and pn in parameters and pn not in param_names and v[0] not in param_names)
+    timeout_fn = timeout_setter_fn(params)
+
     part_prop, parts = parts_from_params(params)
     part_desc = make_parts_desc(part_prop)
     parts = get_parts(part_prop)
+
+    # the builders of methods changing data don't exist with the read-only feature
+    cfg_read_only = read_only_cfg_attribute(m)
 %>\
 % if 'description' in m:
 ${m.description | rust_doc_sanitize, rust_doc_comment}
@@ -81,6 +93,10 @@ ${m.description | rust_doc_sanitize, rust_doc_comment}
 /// but not the `${response_schema.id}` structure that you would usually get. The latter will be a default value.
 % endif
 ///
+/// Large media can be written straight to disk using `download_to_file(...)`, which resumes interrupted
+/// downloads from the current size of the file. To process the media while it arrives instead, use
+/// `doit_download()`, which provides the response body as `AsyncRead`.
+///
 % endif ## supports media download
 % if resource == METHODS_RESOURCE:
 /// A builder for the *${method}* method.
@@ -99,7 +115,7 @@ ${part_desc | rust_doc_sanitize, rust_doc_comment}
 % if len(m.scopes) > 1:
 at least one of the following scopes to make a valid call, possibly depending on *parts*:
 ///
-% for s in m.scopes:
+% for s in sorted(m.scopes):
 /// * *${s}*
 % endfor
 % else:
@@ -117,6 +133,9 @@ the *${m.scopes[0]}* scope to make a valid call.
 <%block filter="rust_doc_comment">\
 ${self.usage(resource, method, m, params, request_value, parts)}\
 </%block>
+% if cfg_read_only:
+${cfg_read_only}
+% endif
 pub struct ${ThisType}
     where ${struct_type_bounds_s()} {
 
@@ -131,15 +150,24 @@ pub struct ${ThisType}
     % endif
 % endfor
 ## A generic map for additinal parameters. Sometimes you can set some that are documented online only
-    ${api.properties.params}: HashMap<String, String>,
+    ${api.properties.params}: BTreeMap<String, String>,
+    ${api.properties.standard_params}: client::standard_params::StandardParams,
+    ${api.properties.retry_policy}: Option<client::retry::RetryPolicy>,
+    ${api.properties.timeout}: Option<std::time::Duration>,
     % if method_default_scope(m):
 ## We need the scopes sorted, to not unnecessarily query new tokens
     ${api.properties.scopes}: BTreeSet<String>
     % endif
 }
 
+% if cfg_read_only:
+${cfg_read_only}
+% endif
 impl${mb_tparams} ${CALL_BUILDER_MARKERT_TRAIT} for ${ThisType} {}
 
+% if cfg_read_only:
+${cfg_read_only}
+% endif
 impl${mb_tparams} ${ThisType}
 where
     S: tower_service::Service<http::Uri> + Clone + Send + Sync + 'static,
@@ -152,6 +180,18 @@ ${self._action_fn(c, resource, method, m, params, request_value, parts, doit_wit
 % endif
 
 ${self._action_fn(c, resource, method, m, params, request_value, parts)}\
+% if m.get('supportsMediaDownload', False):
+
+${self._action_fn(c, resource, method, m, params, request_value, parts, doit_download = True)}\
+
+${self._action_fn(c, resource, method, m, params, request_value, parts, download_to_file = True)}\
+% endif
+% if method_response(c, m) and parameters and 'proto' in parameters.get('alt', {}).get('enum', []):
+
+${self._action_fn(c, resource, method, m, params, request_value, parts, doit_proto = True)}\
+% endif
+
+${self._into_parts_fn(c, m, params, request_value)}\
 
 ## SETTERS ###############
 % for p in params:
@@ -168,7 +208,7 @@ ${self._setter_fn(resource, method, m, p, part_prop, ThisType, c)}\
     ///
     /// # Additional Parameters
     ///
-    % for opn, op in list((opn, op) for (opn, op) in parameters.items() if opn not in [p.name for p in params]):
+    % for opn, op in list((opn, op) for (opn, op) in parameters.items() if opn not in [p.name for p in params] + list(typed_params)):
     /// * *${opn}* (${op.location}-${op.type}) - ${op.description}
     % endfor
     % endif
@@ -177,6 +217,31 @@ ${self._setter_fn(resource, method, m, p, part_prop, ThisType, c)}\
         self.${api.properties.params}.insert(name.as_ref().to_string(), value.as_ref().to_string());
         self
     }
+    % for pn, (field, type_name) in items(typed_params):
+
+    ${parameters[pn].get('description', 'no description provided') | rust_doc_sanitize, rust_doc_comment, indent_all_but_first_by(1)}
+    ///
+    /// Sets the *${pn}* query parameter, which must not be set with [`Self::${ADD_PARAM_FN}()`] as well.
+    pub fn ${field}(mut self, new_value: client::standard_params::${type_name}) -> ${ThisType} {
+        self.${api.properties.standard_params}.${field} = Some(new_value);
+        self
+    }
+    % endfor
+
+    /// Retry transient failures of this call as `policy` says, instead of as the retry policy of the hub.
+    /// See `client::retry` for which failures are retried.
+    pub fn retry_policy(mut self, policy: client::retry::RetryPolicy) -> ${ThisType} {
+        self.${api.properties.retry_policy} = Some(policy);
+        self
+    }
+
+    /// Fail the call with `client::Error::Deadline` unless it completed within `timeout`, including all retries,
+    /// so that hung connections don't stall the caller. The delegate is told that a call which timed out finished
+    /// without success.
+    pub fn ${timeout_fn}(mut self, timeout: std::time::Duration) -> ${ThisType} {
+        self.${api.properties.timeout} = Some(timeout);
+        self
+    }
 
     % if method_default_scope(m):
     /// Identifies the authorization scope for the method you are building.
@@ -189,7 +254,8 @@ ${self._setter_fn(resource, method, m, p, part_prop, ThisType, c)}\
     ///
     /// Usually there is more than one suitable scope to authorize an operation, some of which may
     /// encompass more rights than others. For example, for listing resources, a *read-only* scope will be
-    /// sufficient, a read-write scope will do as well.
+    /// sufficient, a read-write scope will do as well. The [`Scope`] variants of this method are returned by
+    /// [`Self::required_scopes()`], and can be passed directly.
     pub fn ${ADD_SCOPE_FN}<St>(mut self, scope: St) -> ${ThisType}
                                                         where St: AsRef<str> {
         self.${api.properties.scopes}.insert(String::from(scope.as_ref()));
@@ -213,6 +279,11 @@ ${self._setter_fn(resource, method, m, p, part_prop, ThisType, c)}\
         self.${api.properties.scopes}.clear();
         self
     }
+
+    /// Returns the scopes of which at least one is needed to authorize this method.
+    pub fn required_scopes(&self) -> &'static [Scope] {
+        &[${', '.join(scope_url_to_variant(name, url, fully_qualified=True) for url in sorted(m.scopes) if url in auth.oauth2.scopes)}]
+    }
     % endif
 }
 </%def>
@@ -239,6 +310,9 @@ ${self._setter_fn(resource, method, m, p, part_prop, ThisType, c)}\
 
     value_name = 'new_value'
     new_value_copied = rust_copy_value_s(value_name, InType, p)
+    if path_param_type(p):
+        InType = builder_input_type(schemas, p)
+        new_value_copied = builder_copy_value_s(schemas, value_name, p)
     if not is_required_property(p) and not is_repeated_property(p):
         new_value_copied = 'Some(%s)' % new_value_copied
 
@@ -274,6 +348,9 @@ ${self._setter_fn(resource, method, m, p, part_prop, ThisType, c)}\
     ///
     ${part_desc | rust_doc_sanitize, rust_doc_comment, indent_all_but_first_by(1)}
     % endif
+    % if deprecated_attribute(p):
+    ${deprecated_attribute(p)}
+    % endif
     pub fn ${mangle_ident(setter_fn_name(p))}(mut self, ${value_name}: ${InType}) -> ${ThisType} {
         % if p.get('repeated', False):
         self.${property(p.name)}.push(${new_value_copied});
@@ -290,7 +367,7 @@ ${self._setter_fn(resource, method, m, p, part_prop, ThisType, c)}\
 ## documented example for a given method.
 ###############################################################################################
 ###############################################################################################
-<%def name="usage(resource, method, m, params, request_value, parts=None, show_all=False, rust_doc=True, handle_result=False)">\
+<%def name="usage(resource, method, m, params, request_value, parts=None, show_all=False, rust_doc=True, handle_result=False, gate_read_only=False)">\
 <%
     hub_type_name = hub_type(schemas, util.canonical_name())
     required_props, optional_props, part_prop = organize_params(params, request_value)
@@ -332,7 +409,7 @@ ${self._setter_fn(resource, method, m, p, part_prop, ThisType, c)}\
         action_name = upload_action_fn(api.terms.upload_action, media_params[index].type.suffix)
     else:
         action_name = api.terms.action
-    action_args = media_params and media_params[-1].type.example_value or ''
+    action_args = media_params and media_params[-1].type.example_value % dict(lib=util.library_name()) or ''
 
     random_value_warning = "Values shown here are possibly random and not representative !"
 
@@ -340,6 +417,18 @@ ${self._setter_fn(resource, method, m, p, part_prop, ThisType, c)}\
     test_block_filter = rust_doc and rust_doc_test_norun or markdown_rust_block
     test_fn_filter = rust_doc and rust_test_fn_invisible or pass_through
 
+    # Doc tests run against a hub answering with an example of the response, unless they need files to upload
+    mock_response = None
+    if rust_doc and not media_params:
+        import json
+        mock_response = json.dumps(json_example(schemas, m.get('response', dict())) or dict(), sort_keys=True)
+        test_block_filter = rust_doc_test
+        test_fn_filter = rust_test_main_invisible
+
+    # Examples outside of the call builder, which the read-only feature removes along with them, must still compile
+    if rust_doc and gate_read_only and is_mutating_method(m):
+        test_fn_filter = rust_test_fn_read_only_gated(test_fn_filter)
+
     if request_value:
         request_value_type = request_value.id
 %>\
@@ -355,7 +444,7 @@ use ${util.library_name()}::{Result, Error};
 use std::fs;
 % endif
 <%block filter="test_fn_filter">\
-${capture(lib.test_hub, hub_type_name, comments=show_all) | hide_filter}
+${capture(lib.test_hub, hub_type_name, comments=show_all, mock_response=mock_response) | hide_filter}
 % if request_value:
 // As the method needs a request, you would usually fill it with the desired information
 // into the respective structure. Some of the parts shown here might not be applicable !
@@ -401,18 +490,26 @@ ${'.' + action_name | indent_by(13)}(${action_args}).await;
 
 match result {
     Err(e) => match e {
-        // The Error enum provides details about what exactly happened.
+        // The Error enum, which all API crates share, provides details about what exactly happened.
         // You can also just use its `Debug`, `Display` or `Error` traits
+         Error::Failure(_)
+        |Error::BadRequest(_) => println!("{}: {}", e.canonical_code(), e),
          Error::HttpError(_)
         |Error::Io(_)
         |Error::MissingAPIKey
         |Error::MissingToken(_)
         |Error::Cancelled
         |Error::UploadSizeLimitExceeded(_, _)
-        |Error::Failure(_)
-        |Error::BadRequest(_)
         |Error::FieldClash(_)
-        |Error::JsonDecodeError(_, _) => println!("{}", e),
+        |Error::JsonDecodeError(_, _)
+        |Error::Signing(_)
+        |Error::InvalidUrl(_)
+        |Error::InvalidRequest(_)
+        |Error::ResponseTooLarge(_)
+        |Error::ProtoDecodeError(_)
+        |Error::UnknownMethod(_)
+        |Error::Deadline(_)
+        |Error::Grpc(_) => println!("{}", e),
     },
     Ok(res) => println!("Success: {:?}", res),
 }
@@ -425,11 +522,11 @@ match result {
 ## create an entire 'api.terms.action' method
 ###############################################################################################
 ###############################################################################################
-<%def name="_action_fn(c, resource, method, m, params, request_value, parts, doit_without_upload = False)">\
+<%def name="_action_fn(c, resource, method, m, params, request_value, parts, doit_without_upload = False, download_to_file = False, doit_download = False, doit_proto = False)">\
 <%
     import os.path
     join_url = lambda b, e: b.strip('/') + e
-    if doit_without_upload:
+    if doit_without_upload or download_to_file or doit_download or doit_proto:
         media_params = []
     else:
         media_params = method_media_params(m)
@@ -450,13 +547,12 @@ match result {
 
     mtype_param = 'RS'
 
-    possible_urls = [m.path]
     simple_media_param = None
     resumable_media_param = None
     if media_params:
         type_params = '<%s>' % mtype_param
         qualifier = ''
-        where = '\n\t\twhere ' + mtype_param + ': client::ReadSeek'
+        where = '\n\t\twhere ' + mtype_param + ': client::AsyncReadSeek'
         add_args = (', mut reader: %s, reader_mime_type: mime::Mime' % mtype_param) + ", protocol: client::UploadProtocol"
         for p in media_params:
             if p.protocol == SIMPLE:
@@ -465,12 +561,39 @@ match result {
                 resumable_media_param = p
     # end handle media params
 
-    if doit_without_upload:
-        action_fn = qualifier + 'async fn ' + "doit_without_upload" + type_params + '(mut self)' + ' -> ' + rtype + where
+    if download_to_file:
+        # the media is written to the file, there is no resource to decode
+        response_schema = None
+        rtype = 'client::Result<hyper::Response<hyper::body::Body>>'
+    elif doit_download:
+        # the media is handed out as is, there is no resource to decode
+        response_schema = None
+        rtype = 'client::Result<hyper::Response<client::BodyReader>>'
+    elif doit_proto:
+        # the response is decoded into the message type of the caller
+        type_params = '<M>'
+        where = '\n\t\twhere M: client::prost::Message + Default'
+        rtype = 'client::Result<(hyper::Response<hyper::body::Body>, M)>'
+
+    # the state machines of calls are large, returning them boxed makes binaries with many calls smaller
+    boxed = api.get('boxed_futures', False) and not (media_params or download_to_file or doit_proto)
+    fn_rtype = boxed and "client::BoxFuture<'a, %s>" % rtype or rtype
+    qualifier += boxed and 'fn ' or 'async fn '
+
+    if download_to_file:
+        action_fn = qualifier + 'download_to_file<P>(mut self, path: P) -> ' + fn_rtype + '\n\t\twhere P: AsRef<std::path::Path>'
+    elif doit_download:
+        action_fn = qualifier + 'doit_download(mut self) -> ' + fn_rtype
+    elif doit_proto:
+        action_fn = qualifier + 'doit_proto' + type_params + '(mut self) -> ' + fn_rtype + where
+    elif doit_without_upload:
+        action_fn = qualifier + "doit_without_upload" + type_params + '(mut self)' + ' -> ' + fn_rtype + where
     else:
-        action_fn = qualifier + 'async fn ' + api.terms.action + type_params + ('(mut self%s)' % add_args) + ' -> ' + rtype + where
+        action_fn = qualifier + api.terms.action + type_params + ('(mut self%s)' % add_args) + ' -> ' + fn_rtype + where
 
     field_params = [p for p in params if p.get('is_query_param', True)]
+    # calls without media leave sending the request to the loop shared by all calls
+    use_exec = not (media_params or download_to_file or doit_download)
 
     paddfields = 'self.' + api.properties.params
 
@@ -481,61 +604,81 @@ match result {
     default_scope = method_default_scope(m)
 
     # s = '{foo}' -> ('{foo}', 'foo') -> (find_this, replace_with)
-    seen = set()
-    replacements = list()
-    all_required_param_name = set(p.name for p in params if is_required_property(p))
-    MULTI_SLASH = 'multi-slash-prefix'
-    URL_ENCODE = 'url-encode'
+    replacements = url_replacements(m.path, params)
 
-    READER_SEEK = "let size = reader.seek(io::SeekFrom::End(0)).unwrap();\nreader.seek(io::SeekFrom::Start(0)).unwrap();\n"
+    READER_SEEK = "let size = client::media_size(&mut reader).await?;\n"
     if media_params:
         max_size = media_params[0].max_size
         if max_size > 0:
             READER_SEEK += "if size > %i {\n\treturn Err(client::Error::UploadSizeLimitExceeded(size, %i))\n}" % (max_size, max_size)
-
-    special_cases = set()
-    for possible_url in possible_urls:
-        for s in re_find_replacements.findall(possible_url):
-            if s in seen: continue
-            seen.add(s)
-            sn = s[1:-1]
-
-            # NOTE: We only handle the cases that are actually used in the schemas. If this shouldn't
-            # be worth it anymore (i.e. too many cases), then we should use a uri-template library
-            # to handle this at runtime, possibly, or use a python uri-template library, to more easily
-            # handle the required cases. Whatever is less work, I guess.
-            if sn.startswith('/') and sn.endswith('*'):
-                sn = sn[1:-1]
-                special_cases.add(MULTI_SLASH)
-            elif sn.startswith('+'):
-                sn = sn[1:]
-                special_cases.add(URL_ENCODE)
-            assert sn in all_required_param_name, "Expected param '%s' to be in required parameter list for substitution" % sn
-            replacements.append((s, sn))
-        # end for each found substitution
-        # Assure we can substitue everything
-        for s, d in replacements:
-            possible_url = possible_url.replace(s, d)
-        assert '{' not in possible_url, "Failed to replace all fields in '%s', have to parse expressions" % possible_url
-    # end for each possible url
-    del seen
 %>
-    % if doit_without_upload:
+    % if download_to_file:
+    /// Perform the operation you have build so far, writing the downloaded media to the file at `path`.
+    ///
+    /// If the file exists, only the missing bytes are requested from the server using a `Range` header.
+    /// Should the connection fail, the download is resumed from the current size of the file if the delegate
+    /// allows to retry. The body of the returned response was consumed already.
+    /// With the `gzip` feature, media served with `Content-Encoding: gzip` are written decompressed, unless
+    /// `keep_content_encoding(true)` was set.
+    % elif doit_download:
+    /// Perform the operation you have build so far, requesting the media and providing the response body as
+    /// [`AsyncRead`](tokio::io::AsyncRead), without buffering it in memory.
+    ///
+    /// This allows to pipe the media straight into files, decompressors or hashing sinks. Use
+    /// `client::content_length(...)` on the response headers to learn the size of the media, if known.
+    /// With the `gzip` feature, media served with `Content-Encoding: gzip` are decompressed, unless
+    /// `keep_content_encoding(true)` was set.
+    % elif doit_proto:
+    /// Perform the operation you have build so far, requesting the response protobuf-encoded with `alt=proto`, and
+    /// decoding it into the message `M`, like the type `prost` generated from the protos of the API.
+    ///
+    /// Protobuf responses are smaller than JSON ones, and faster to decode. An `alt` parameter set on this builder
+    /// is ignored.
+    % elif doit_without_upload:
     /// Perform the operation you have build so far, but without uploading. This is used to e.g. renaming or updating the description for a file
     % else:
     /// Perform the operation you have build so far.
     % endif
+    % if download_to_file:
+    #[cfg(not(target_arch = "wasm32"))]
+    % elif doit_proto:
+    #[cfg(feature = "proto")]
+    % endif
     ${action_fn} {
-        use std::io::{Read, Seek};
+        ${boxed and 'Box::pin(' or ''}client::instrument("${m.id}", async move {
+<%block filter="indent_by(4)">\
+        use std::io::Read;
         use hyper::header::{CONTENT_TYPE, CONTENT_LENGTH, AUTHORIZATION, USER_AGENT, LOCATION};
         use client::{ToParts, url::Params};
         use std::borrow::Cow;
 
         let mut dd = client::DefaultDelegate;
+        let mut ed;
         let mut dlg: &mut dyn client::Delegate = ${delegate}.unwrap_or(&mut dd);
-        dlg.begin(client::MethodInfo { id: "${m.id}",
-                               http_method: ${method_name_to_variant(m.httpMethod)} });
-
+        if let Some(events) = self.hub._events.as_ref() {
+            ed = client::events::EventDelegate::new(dlg, events.clone());
+            dlg = &mut ed;
+        }
+        let retry_policy = self.${api.properties.retry_policy}.as_ref().or(self.hub._retry_policy.as_ref());
+        let mut rd = client::retry::RetryDelegate::new(dlg, retry_policy, self.hub._retry_after_limit);
+        dlg = &mut rd;
+<%
+    request_id = 'None'
+    for p in field_params:
+        if p.name == 'requestId' and p.get('type') == 'string' and not p.get('repeated', False):
+            if is_required_property(p):
+                request_id = 'Some(self.%s.clone())' % property(p.name)
+            else:
+                request_id = 'self.%s.clone()' % property(p.name)
+%>        dlg.begin(client::MethodInfo { id: "${m.id}",
+                               http_method: ${method_name_to_variant(m.httpMethod)},
+                               request_id: ${request_id} });
+
+        ## The delegate is reborrowed by the call, so that it can still be told the call finished if it timed out
+        let result = client::with_timeout(self.${api.properties.timeout}, async {
+            let ${'mut ' if not use_exec and m.httpMethod != 'GET' else ''}dlg: &mut dyn client::Delegate = &mut *dlg;
+</%block>\
+<%block filter="indent_by(8)">\
         ## TODO: Should go into validation function?
         ## Additional params - may not overlap with optional params
         for &field in [${', '.join(enclose_in('"', reserved_params + [p.name for p in field_params]))}].iter() {
@@ -544,8 +687,14 @@ match result {
                 return Err(client::Error::FieldClash(field));
             }
         }
+        for field in self.${api.properties.standard_params}.names() {
+            if ${paddfields}.contains_key(field) {
+                ${delegate_finish}(false);
+                return Err(client::Error::FieldClash(field));
+            }
+        }
 
-        let mut params = Params::with_capacity(${len(params) + len(reserved_params)} + ${paddfields}.len());
+        let mut params = Params::new();
 <%
     if media_params and 'mediaUpload' in m:
         upload_type_map = dict()
@@ -556,48 +705,17 @@ match result {
         # for each media param
     # end build media param map
 %>\
-        % for p in field_params:
-<%
-    pname = 'self.' + property(p.name)    # property identifier
-    to_string_impl = string_impl(p)
-%>\
-        ## parts can also be derived from the request, but we do that only if it's not set
-        % if p.name == 'part' and request_value:
-        % if is_repeated_property(p):
-        if ${pname}.is_empty() {
-            ${pname}.push(self.${property(REQUEST_VALUE_PROPERTY_NAME)}.to_parts());
-        }
-        % else:
-        % if not is_required_property(p):
-        if ${pname}.is_none() {
-            ${pname} = Some(self.${property(REQUEST_VALUE_PROPERTY_NAME)}.to_parts());
-        }
-        % else:
-        if ${pname}.len() == 0 {
-            ${pname} = self.${property(REQUEST_VALUE_PROPERTY_NAME)}.to_parts();
-        }
-        % endif ## not is_required_property(p)
-        % endif is_repeated_property(p):
-        % endif ## p.name == 'part' and request_value:
-        % if p.get('repeated', False):
-        if ${pname}.len() > 0 {
-            for f in ${pname}.iter() {
-                params.push("${p.name}", ${to_string_impl("f")});
-            }
-        }
-        % elif not is_required_property(p):
-        if let Some(value) = ${pname}.as_ref() {
-            params.push("${p.name}", ${to_string_impl("value")});
-        }
-        % else:
-        params.push("${p.name}", ${to_string_impl(pname)});
-        % endif
-        % endfor
-
+${self._push_field_params(field_params, request_value, replacements)}
+        self.${api.properties.standard_params}.push_to(&mut params);
         params.extend(${paddfields}.iter());
+        % if download_to_file or doit_download:
+        params.set("alt", "media");
+        % elif doit_proto:
+        params.set("alt", "proto");
+        % endif
 
-        % if response_schema:
-        % if supports_download:
+        % if response_schema and not doit_proto:
+        ## Responses in other formats, like media or protobuf, are returned as they are
         let (alt_field_missing, enable_resource_parsing) = {
             if let Some(value) = params.get("alt") {
                 (false, value == "json")
@@ -608,12 +726,9 @@ match result {
         if alt_field_missing {
             params.push("alt", "json");
         }
-        % else:
-        params.push("alt", "json");
-        % endif ## supportsMediaDownload
         % endif ## response schema
         % if media_params:
-        let (mut url, upload_type) =
+        let (base_url, path, upload_type) =
             % for mp in media_params:
             % if loop.first:
             if \
@@ -621,7 +736,7 @@ match result {
 else if \
             % endif
 protocol == ${PROTOCOL_TYPE_MAP[mp.protocol]} {
-                (self.hub._root_url.clone() + "${mp.path.lstrip('/')}", "${upload_type_map.get(mp.protocol, mp.protocol)}")
+                (&self.hub._root_url, "${mp.path.lstrip('/')}", "${upload_type_map.get(mp.protocol, mp.protocol)}")
             } \
             % endfor
 else {
@@ -629,14 +744,14 @@ else {
             };
         params.push("uploadType", upload_type);
         % else:
-        let mut url = self.hub._base_url.clone() + "${m.path}";
+        let (base_url, path) = (&self.hub._base_url, "${m.path}");
         % endif
         % if not default_scope:
         % if no_auth is UNDEFINED:
         <%
             assert 'key' in parameters, "Expected 'key' parameter if there are no scopes"
         %>
-        match dlg.api_key() {
+        match dlg.api_key().or_else(|| self.hub._api_key.clone()) {
             Some(value) => params.push("key", value),
             None => {
                 ${delegate_finish}(false);
@@ -651,31 +766,77 @@ else {
         % endif
 
         ## Handle URI Templates
-        % if replacements:
-        for &(find_this, param_name) in [${', '.join('("%s", "%s")' % r for r in replacements)}].iter() {
-            url = params.uri_replacement(url, param_name, find_this, ${"true" if URL_ENCODE in special_cases else "false"});
-        }
-        ## Remove all used parameters
-        {
-            let to_remove = [${', '.join(reversed(['"%s"' % r[1] for r in replacements]))}];
-            params.remove_params(&to_remove);
-        }
-        % endif
+        let url = match client::uri_template::expand(path, &params) {
+            Ok(path) => base_url.clone() + &path,
+            Err(err) => {
+                ${delegate_finish}(false);
+                return Err(client::Error::InvalidUrl(err));
+            }
+        };
+        let url = match params.parse_with_url(&url) {
+            Ok(url) => url,
+            Err(err) => {
+                ${delegate_finish}(false);
+                return Err(client::Error::InvalidUrl(err));
+            }
+        };
 
-        let url = params.parse_with_url(&url);
+        % if use_exec:
+        % if request_value:
+        let request_value = {
+            let mut value = json::value::to_value(&self.${property(REQUEST_VALUE_PROPERTY_NAME)}).expect("serde to work");
+            client::remove_json_null_values(&mut value);
+            client::sort_json_keys(&mut value);
+            hyper::body::Bytes::from(client::json_serializer::serialize(self.hub._json_serializer.as_deref(), &value))
+        };
+        % endif
+        % if default_scope:
+        let scopes = self.${api.properties.scopes}.iter().map(String::as_str).collect::<Vec<_>>();
+        % endif
+        let call = client::exec::Call {
+            method_id: "${m.id}",
+            http_method: ${method_name_to_variant(m.httpMethod)},
+            url: &url,
+            scopes: ${default_scope and 'Some(&scopes)' or 'None'},
+            request: ${request_value and 'Some(&request_value)' or 'None'},
+            read_body: ${doit_proto and 'true' or response_schema and 'enable_resource_parsing' or 'false'},
+        };
+        let (res, body) = client::exec::execute(&self.hub.transport(), call, dlg).await?;
+        % if doit_proto:
+        let result_value = client::proto::decode(res, body, dlg)?;
+        % elif response_schema:
+        let result_value = client::exec::decode(res, body, dlg)?;
+        % else:
+        let result_value = res;
+        % endif
+        ${delegate_finish}(true);
+        Ok(result_value)
+        % else:
 
         % if request_value:
         let mut json_mime_type = mime::APPLICATION_JSON;
+        ## The request is serialized once, and all attempts share its bytes
         let mut request_value_reader =
             {
                 let mut value = json::value::to_value(&self.${property(REQUEST_VALUE_PROPERTY_NAME)}).expect("serde to work");
                 client::remove_json_null_values(&mut value);
-                let mut dst = io::Cursor::new(Vec::with_capacity(128));
-                json::to_writer(&mut dst, &value).unwrap();
-                dst
+                client::sort_json_keys(&mut value);
+                let dst = client::json_serializer::serialize(self.hub._json_serializer.as_deref(), &value);
+                io::Cursor::new(hyper::body::Bytes::from(dst))
             };
-        let request_size = request_value_reader.seek(io::SeekFrom::End(0)).unwrap();
-        request_value_reader.seek(io::SeekFrom::Start(0)).unwrap();
+        let request_size = request_value_reader.get_ref().len() as u64;
+        dlg.request_serialized(request_size);
+        % endif
+
+        % if m.httpMethod != 'GET':
+        #[cfg(feature = "audit")]
+        let mut ad;
+        #[cfg(feature = "audit")]
+        if let Some(auditor) = self.hub._audit.as_ref() {
+            ad = client::audit::AuditDelegate::new(dlg, auditor, "${m.id}", ${method_name_to_variant(m.httpMethod)}, &url,
+                                                   ${request_value and 'Some(&request_value_reader.get_ref()[..])' or 'None'});
+            dlg = &mut ad;
+        }
         % endif
 
         % if resumable_media_param:
@@ -683,9 +844,20 @@ else {
         let mut upload_url_from_server;
         let mut upload_url: Option<String> = None;
         % endif
+        % if download_to_file:
+        let mut file = match fs::OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => file,
+            Err(err) => {
+                ${delegate_finish}(false);
+                return Err(client::Error::Io(err));
+            }
+        };
+        % endif
 
+        let mut retries = 0;
         loop {
             % if default_scope:
+            let token_started = std::time::Instant::now();
             let token = match ${auth_call}.get_token(&self.${api.properties.scopes}.iter().map(String::as_str).collect::<Vec<_>>()[..]).await {
                 Ok(token) => token,
                 Err(e) => {
@@ -698,10 +870,22 @@ else {
                     }
                 }
             };
+            dlg.token_obtained(token_started.elapsed());
             % endif
             % if request_value:
-            request_value_reader.seek(io::SeekFrom::Start(0)).unwrap();
+            request_value_reader.set_position(0);
+            % endif
+            % if download_to_file:
+            let offset = match file.metadata() {
+                Ok(metadata) => metadata.len(),
+                Err(err) => {
+                    ${delegate_finish}(false);
+                    return Err(client::Error::Io(err));
+                }
+            };
             % endif
+            let request_started = std::time::Instant::now();
+            let mut har_entry = None;
             let mut req_result = {
             % if resumable_media_param:
                 if should_ask_dlg_for_url && (upload_url = dlg.upload_url()) == () && upload_url.is_some() {
@@ -716,13 +900,15 @@ else {
             % endif
 <%block filter="indent_by(resumable_media_param and 4 or 0)">\
             % if request_value and simple_media_param:
+                let mut media_reader = io::Cursor::new(Vec::new());
                 let mut mp_reader: client::MultiPartReader = Default::default();
                 let (mut body_reader, content_type) = match protocol {
                     ${PROTOCOL_TYPE_MAP[simple_media_param.protocol]} => {
                         mp_reader.reserve_exact(2);
                         ${READER_SEEK | indent_all_but_first_by(5)}
+                        media_reader = io::Cursor::new(client::read_media(&mut reader).await?);
                         mp_reader.add_part(&mut request_value_reader, request_size, json_mime_type.clone())
-                                 .add_part(&mut reader, size, reader_mime_type.clone());
+                                 .add_part(&mut media_reader, size, reader_mime_type.clone());
                         (&mut mp_reader as &mut (dyn io::Read + Send), client::MultiPartReader::mime_type())
                     },
                     _ => (&mut request_value_reader as &mut (dyn io::Read + Send), json_mime_type.clone()),
@@ -733,13 +919,24 @@ else {
                 let mut req_builder = hyper::Request::builder()
                     .method(${method_name_to_variant(m.httpMethod)})
                     .uri(url.as_str())
-                    .header(USER_AGENT, self.hub._user_agent.clone());
+                    .header(USER_AGENT, self.hub._user_agent.clone())
+                    .header(client::API_CLIENT_HEADER, self.hub._api_client.clone());
 
                 % if default_scope:
                 if let Some(token) = token.as_ref() {
                     req_builder = req_builder.header(AUTHORIZATION, format!("Bearer {}", token));
                 }
                 % endif
+                % if download_to_file:
+                if offset > 0 {
+                    req_builder = req_builder.header(hyper::header::RANGE, format!("bytes={}-", offset));
+                }
+                % endif
+                % if supports_download:
+                if self.${property(KEEP_CONTENT_ENCODING_PROPERTY_NAME)}.unwrap_or(false) {
+                    req_builder = req_builder.header(hyper::header::ACCEPT_ENCODING, "gzip");
+                }
+                % endif
 
                 % if resumable_media_param:
                 upload_url_from_server = true;
@@ -747,6 +944,7 @@ else {
                     req_builder = req_builder.header("X-Upload-Content-Type", format!("{}", reader_mime_type));
                 }
                 % endif
+                req_builder = client::prepare_request(dlg, req_builder);
 
                 % if request_value:
                     % if not simple_media_param:
@@ -756,7 +954,10 @@ else {
                         .body(hyper::body::Body::from(request_value_reader.get_ref().clone()))\
                     % else:
                         let mut body_reader_bytes = vec![];
-                        body_reader.read_to_end(&mut body_reader_bytes).unwrap();
+                        if let Err(err) = body_reader.read_to_end(&mut body_reader_bytes) {
+                            ${delegate_finish}(false);
+                            return Err(client::Error::Io(err));
+                        }
                         let request = req_builder
                             .header(CONTENT_TYPE, content_type.to_string())
                             .body(hyper::body::Body::from(body_reader_bytes))\
@@ -765,8 +966,7 @@ else {
                     % if simple_media_param:
                         let request = if protocol == ${PROTOCOL_TYPE_MAP[simple_media_param.protocol]} {
                             ${READER_SEEK | indent_all_but_first_by(4)}
-                            let mut bytes = Vec::with_capacity(size as usize);
-                            reader.read_to_end(&mut bytes)?;
+                            let bytes = client::read_media(&mut reader).await?;
                             req_builder.header(CONTENT_TYPE, reader_mime_type.to_string())
                                      .header(CONTENT_LENGTH, size)
                                      .body(hyper::body::Body::from(bytes))
@@ -780,17 +980,38 @@ else {
                 % endif
 ;
 
-                client.request(request.unwrap()).await
+                let request = match request {
+                    Ok(request) => request,
+                    Err(err) => {
+                        ${delegate_finish}(false);
+                        return Err(client::Error::InvalidRequest(err));
+                    }
+                };
+                let (request, entry) = client::har::capture_request(self.hub._har.as_ref(), client::log_request(request).await).await;
+                har_entry = entry;
+                client.request(request).await
 
 </%block>\
                 % if resumable_media_param:
             }
                 % endif
             };
+            let request_duration = request_started.elapsed();
+            dlg.attempt_completed(req_result.as_ref().ok().map(|res| res.status()), request_duration);
+            if let Some(metrics) = self.hub._metrics.as_ref() {
+                let status = client::StatusClass::of(&req_result);
+                metrics.increment_request_count("${m.id}", status);
+                metrics.record_request_latency("${m.id}", status, request_duration);
+                if status.is_error() {
+                    metrics.increment_error_count("${m.id}", client::canonical_code(req_result.as_ref().ok().map(|res| res.status())));
+                }
+            }
 
             match req_result {
                 Err(err) => {
                     if let client::Retry::After(d) = dlg.http_error(&err) {
+                        retries += 1;
+                        dlg.retry_scheduled(retries, d);
                         sleep(d).await;
                         continue;
                     }
@@ -798,8 +1019,30 @@ else {
                     return Err(client::Error::HttpError(err))
                 }
                 Ok(mut res) => {
+                    client::log_response(&res);
+                    if let Some(entry) = har_entry.as_mut() {
+                        entry.response(&res);
+                    }
+                    % if download_to_file:
+                    ## The file is complete already
+                    if offset > 0 && res.status() == hyper::StatusCode::RANGE_NOT_SATISFIABLE {
+                        ${delegate_finish}(true);
+                        return Ok(res)
+                    }
+                    % endif
                     if !res.status().is_success() {
-                        let res_body_string = client::get_body_as_string(res.body_mut()).await;
+                        let res_body = match client::get_body_as_bytes_limited(res.body_mut(), self.hub._max_response_bytes).await {
+                            Ok(res_body) => res_body,
+                            Err(err) => {
+                                ${delegate_finish}(false);
+                                return Err(err);
+                            }
+                        };
+                        let res_body_string = String::from_utf8_lossy(&res_body).into_owned();
+                        client::log_response_body(&res_body_string);
+                        if let Some(entry) = har_entry.as_mut() {
+                            entry.response_body(&res_body_string);
+                        }
                         let (parts, _) = res.into_parts();
                         let body = hyper::Body::from(res_body_string.clone());
                         let restored_response = hyper::Response::from_parts(parts, body);
@@ -807,6 +1050,8 @@ else {
                         let server_response = json::from_str::<serde_json::Value>(&res_body_string).ok();
 
                         if let client::Retry::After(d) = dlg.http_failure(&restored_response, server_response.clone()) {
+                            retries += 1;
+                            dlg.retry_scheduled(retries, d);
                             sleep(d).await;
                             continue;
                         }
@@ -818,6 +1063,13 @@ else {
                             None => Err(client::Error::Failure(restored_response)),
                         }
                     }
+                    % if simple_media_param:
+                    if protocol == ${PROTOCOL_TYPE_MAP[simple_media_param.protocol]} {
+                        if let Ok(size) = client::media_size(&mut reader).await {
+                            dlg.upload_progress(size, size);
+                        }
+                    }
+                    % endif
                     % if resumable_media_param:
                     if protocol == ${PROTOCOL_TYPE_MAP[resumable_media_param.protocol]} {
                         ${READER_SEEK | indent_all_but_first_by(6)}
@@ -833,12 +1085,14 @@ else {
                                 start_at: if upload_url_from_server { Some(0) } else { None },
                                 auth: &${auth_call},
                                 user_agent: &self.hub._user_agent,
+                                api_client: &self.hub._api_client,
                                 // TODO: Check this assumption
                                 auth_header: format!("Bearer {}", token.ok_or_else(|| client::Error::MissingToken("resumable upload requires token".into()))?.as_str()),
                                 url: url_str,
                                 reader: &mut reader,
                                 media_type: reader_mime_type.clone(),
-                                content_length: size
+                                content_length: size,
+                                chunk_size: self.${property(CHUNK_SIZE_PROPERTY_NAME)},
                             }.upload().await
                         };
                         match upload_result {
@@ -849,11 +1103,13 @@ else {
                             Some(Err(err)) => {
                                 ## Do not ask the delgate again, as it was asked by the helper !
                                 ${delegate_finish}(false);
-                                return Err(client::Error::HttpError(err))
+                                return Err(err)
                             }
                             ## Now the result contains the actual resource, if any ... it will be
                             ## decoded next
                             Some(Ok(upload_result)) => {
+                                ## The body belongs to the last chunk, not to the captured initial request
+                                har_entry = None;
                                 res = upload_result;
                                 if !res.status().is_success() {
                                     ## delegate was called in upload() already - don't tell him again
@@ -867,27 +1123,72 @@ else {
                     % endif
                 % if response_schema:
                     ## If 'alt' is not json, we cannot attempt to decode the response
-                    let result_value = \
-                    % if supports_download:
-if enable_resource_parsing \
-                    % endif
-{
-                        let res_body_string = client::get_body_as_string(res.body_mut()).await;
+                    let result_value = if enable_resource_parsing {
+                        let res_body = match client::get_body_as_bytes_limited(res.body_mut(), self.hub._max_response_bytes).await {
+                            Ok(res_body) => res_body,
+                            Err(err) => {
+                                ${delegate_finish}(false);
+                                return Err(err);
+                            }
+                        };
+                        client::log_response_body(&res_body);
+                        if let Some(entry) = har_entry.as_mut() {
+                            entry.response_body(&res_body);
+                        }
 
-                        match json::from_str(&res_body_string) {
-                            Ok(decoded) => (res, decoded),
+                        let decode_started = std::time::Instant::now();
+                        match client::decode_json_slice(&res_body) {
+                            Ok(decoded) => {
+                                dlg.response_decoded(decode_started.elapsed());
+                                (res, decoded)
+                            },
                             Err(err) => {
+                                let res_body_string = String::from_utf8_lossy(&res_body).into_owned();
                                 dlg.response_json_decode_error(&res_body_string, &err);
                                 return Err(client::Error::JsonDecodeError(res_body_string, err));
                             }
                         }
-                    }\
-                    % if supports_download:
- else { (res, Default::default()) }\
-                    % endif
-;
+                    } else { (res, Default::default()) };
                 % else:
+                    % if download_to_file:
+                    let keep_encoding = self.${property(KEEP_CONTENT_ENCODING_PROPERTY_NAME)}.unwrap_or(false);
+                    ## The range refers to the compressed media, which can't be appended to the decompressed file
+                    if res.status() == hyper::StatusCode::PARTIAL_CONTENT && client::decodes_gzip(res.headers(), keep_encoding) {
+                        if let Err(err) = file.set_len(0) {
+                            ${delegate_finish}(false);
+                            return Err(client::Error::Io(err));
+                        }
+                        continue;
+                    }
+                    ## The server ignored our range and sends the entire media
+                    let offset = if res.status() == hyper::StatusCode::PARTIAL_CONTENT { offset } else { 0 };
+                    if let Err(err) = file.set_len(offset) {
+                        ${delegate_finish}(false);
+                        return Err(client::Error::Io(err));
+                    }
+                    match client::append_download(&mut res, &mut file, dlg, offset, keep_encoding).await {
+                        Ok(_) => {},
+                        Err(client::Error::HttpError(err)) => {
+                            if let client::Retry::After(d) = dlg.http_error(&err) {
+                                retries += 1;
+                                dlg.retry_scheduled(retries, d);
+                                sleep(d).await;
+                                continue;
+                            }
+                            ${delegate_finish}(false);
+                            return Err(client::Error::HttpError(err))
+                        }
+                        Err(err) => {
+                            ${delegate_finish}(false);
+                            return Err(err)
+                        }
+                    }
+                    % endif
+                    % if doit_download:
+                    let result_value = client::download_reader(res, self.${property(KEEP_CONTENT_ENCODING_PROPERTY_NAME)}.unwrap_or(false));
+                    % else:
                     let result_value = res;
+                    % endif
                 % endif
 
                     ${delegate_finish}(true);
@@ -895,6 +1196,16 @@ if enable_resource_parsing \
                 }
             }
         }
+        % endif ## use_exec
+</%block>\
+<%block filter="indent_by(4)">\
+        }).await;
+        if let Err(client::Error::Deadline(_)) = result {
+            ${delegate_finish}(false);
+        }
+        result
+</%block>\
+        })${boxed and ')' or '.await'}
     }
 
     % for p in media_params:
@@ -904,8 +1215,192 @@ if enable_resource_parsing \
     /// * *${split_camelcase_s(item_name)}*: ${isinstance(item, (list, tuple)) and put_and(enclose_in("'", item)) or str(item)}
     % endfor
     pub async fn ${upload_action_fn(api.terms.upload_action, p.type.suffix)}<${mtype_param}>(self, ${p.type.arg_name}: ${mtype_param}, mime_type: mime::Mime) -> ${rtype}
-                where ${mtype_param}: client::ReadSeek {
+                where ${mtype_param}: client::AsyncReadSeek {
         self.${api.terms.action}(${p.type.arg_name}, mime_type, ${PROTOCOL_TYPE_MAP[p.protocol]}).await
     }
     % endfor
 </%def>
+
+
+## pushes the values of all field parameters of a call builder to `params`, those substituted into the path as
+## path parameters
+###############################################################################################
+###############################################################################################
+<%def name="_push_field_params(field_params, request_value, replacements)">\
+        % for p in field_params:
+<%
+    pname = 'self.' + property(p.name)    # property identifier
+    to_string_impl = string_impl(p)
+    push = 'push_path' if p.name in [r[1] for r in replacements] else 'push'
+%>\
+        ## parts can also be derived from the request, but we do that only if it's not set
+        % if p.name == 'part' and request_value:
+        % if is_repeated_property(p):
+        if ${pname}.is_empty() {
+            ${pname}.push(self.${property(REQUEST_VALUE_PROPERTY_NAME)}.to_parts());
+        }
+        % else:
+        % if not is_required_property(p):
+        if ${pname}.is_none() {
+            ${pname} = Some(self.${property(REQUEST_VALUE_PROPERTY_NAME)}.to_parts());
+        }
+        % else:
+        if ${pname}.len() == 0 {
+            ${pname} = self.${property(REQUEST_VALUE_PROPERTY_NAME)}.to_parts();
+        }
+        % endif ## not is_required_property(p)
+        % endif is_repeated_property(p):
+        % endif ## p.name == 'part' and request_value:
+        % if p.get('repeated', False):
+        if ${pname}.len() > 0 {
+            for f in ${pname}.iter() {
+                params.${push}("${p.name}", ${to_string_impl("f")});
+            }
+        }
+        % elif not is_required_property(p):
+        if let Some(value) = ${pname}.as_ref() {
+            params.${push}("${p.name}", ${to_string_impl("value")});
+        }
+        % else:
+        params.${push}("${p.name}", ${to_string_impl(pname)});
+        % endif
+        % endfor
+</%def>
+
+
+## creates a function returning the state of a call builder, without making the call
+###############################################################################################
+###############################################################################################
+<%def name="_into_parts_fn(c, m, params, request_value)">\
+<%
+    field_params = [p for p in params if p.get('is_query_param', True)]
+    replacements = url_replacements(m.path, params)
+    paddfields = 'self.' + api.properties.params
+    default_scope = method_default_scope(m)
+    derives_part = request_value and any(p.name == 'part' for p in field_params)
+    response_schema = method_response(c, m)
+%>\
+    /// Returns the values this call was built with, without making it, to check them in tests.
+    ///
+    /// The URL and query parameters don't include those set by `doit()` to implement the protocol, like `alt`.
+    /// Fails with `client::Error::InvalidUrl` if the path parameters don't expand into the path of the URL.
+    pub fn into_parts(${'mut ' if default_scope or derives_part else ''}self) -> client::Result<client::CallParts> {
+        use client::{ToParts, url::Params};
+
+        let mut params = Params::new();
+${self._push_field_params(field_params, request_value, replacements)}\
+        self.${api.properties.standard_params}.push_to(&mut params);
+        params.extend(${paddfields}.iter());
+        % if default_scope:
+        if self.${api.properties.scopes}.is_empty() {
+            self.${api.properties.scopes}.insert(${scope_url_to_variant(name, default_scope, fully_qualified=True)}.as_ref().to_string());
+        }
+        % endif
+        ## An invalid base URL is kept as it is, for `to_http_request()` to reject it
+        let path = client::uri_template::expand("${m.path}", &params).map_err(client::Error::InvalidUrl)?;
+        let url = self.hub._base_url.clone() + &path;
+        let ${'mut ' if replacements else ''}path_params = Vec::new();
+        % for find_this, param_name in replacements:
+        path_params.push(("${param_name}", params.get("${param_name}").unwrap_or_default().to_string()));
+        % endfor
+        let ${'mut ' if request_value else ''}parts = client::CallParts {
+            method_id: "${m.id}",
+            http_method: ${method_name_to_variant(m.httpMethod)},
+            url: params.parse_with_url(&url).map(|url| url.to_string()).unwrap_or(url),
+            path_params,
+            query_params: params.into_vec(),
+            request: None,
+            scopes: ${'std::mem::take(&mut self.%s)' % api.properties.scopes if default_scope else 'BTreeSet::new()'},
+        };
+        % if request_value:
+        let mut value = json::value::to_value(&self.${property(REQUEST_VALUE_PROPERTY_NAME)}).expect("serde to work");
+        client::remove_json_null_values(&mut value);
+        client::sort_json_keys(&mut value);
+        parts.request = Some(value);
+        % endif
+        Ok(parts)
+    }
+
+    /// Builds the request of this call without making it, along with the parser of its response, to make the call
+    /// through other transports than the hub. See `client::CallParts::to_http_request()` for what the transport
+    /// has to add to the request. Fails like `into_parts()`, or with `client::Error::InvalidRequest` if the URL isn't
+    /// valid.
+    pub fn build_request(self) -> client::Result<(http::Request<hyper::body::Bytes>, client::ResponseParser<${response_schema and response_schema.id or 'json::Value'}>)> {
+        let request = self.into_parts()?.to_http_request().map_err(client::Error::InvalidRequest)?;
+        Ok((request.map(Into::into), client::ResponseParser::default()))
+    }
+</%def>
+
+
+## Tests checking the URLs of all call builders, with reserved characters in their string parameters
+###############################################################################################
+###############################################################################################
+<%def name="url_tests(c)">\
+<%
+    from urllib.parse import quote, urlparse
+
+    hub_type_name = hub_type(schemas, util.canonical_name())
+    VALUE = 'a/b c?d#e~f'
+    LITERALS = {'&str': ('"%s"' % VALUE, VALUE), 'bool': ('true', 'true')}
+    for int_type in ('i32', 'i64', 'u32', 'u64'):
+        LITERALS[int_type] = ('7', '7')
+
+    # like DEFAULT_ENCODE_SET of the url crate, which is used for reserved expansions like {+name}
+    def encode_reserved(v):
+        return ''.join(ch if 0x20 < ord(ch) < 0x7f and ch not in '"#<>`?{}' else quote(ch, safe='') for ch in v)
+
+    # (test name, call expression, expected path, [(query param, value)])
+    tests = []
+    base_path = urlparse(baseUrl).path
+    for resource in sorted(c.rta_map.keys()):
+        for a in sorted(c.rta_map[resource]):
+            m = c.fqan_map[to_fqan(c.rtc_map[resource], resource, a)]
+            params, request_value = build_all_params(c, m)
+            required_props, _, _ = organize_params(params, request_value)
+            args = []
+            values = dict()
+            for p in required_props:
+                if request_value and p.name == REQUEST_VALUE_PROPERTY_NAME:
+                    args.append('%s::default()' % request_value.id)
+                    continue
+                literal = LITERALS.get(activity_input_type(schemas, p))
+                if literal is None or is_repeated_property(p):
+                    break
+                args.append(literal[0])
+                values[p.name] = literal[1]
+            else:
+                path = m.path
+                for find_this, param_name in url_replacements(m.path, params):
+                    encode = encode_reserved if find_this.startswith('{+') else lambda v: quote(v, safe='')
+                    path = path.replace(find_this, encode(values[param_name]))
+                query = [(p.name, values[p.name]) for p in required_props
+                         if p.name in values and p.get('location') == 'query']
+                call = 'hub.%s().%s(%s)' % (mangle_ident(resource), mangle_ident(a), ', '.join(args))
+                tests.append((mangle_ident('%s_%s' % (resource, a)), call, base_path + path, query,
+                              read_only_cfg_attribute(m)))
+%>\
+#[cfg(test)]
+mod url_tests {
+    use super::*;
+
+    fn hub() -> ${hub_type_name}<hyper::client::HttpConnector> {
+        ${hub_type_name}::new(hyper::Client::builder().build(hyper::client::HttpConnector::new()), client::NoToken)
+    }
+% for test_name, call, expected_path, query, cfg_read_only in tests:
+
+    #[test]
+    % if cfg_read_only:
+    ${cfg_read_only}
+    % endif
+    fn ${test_name}() {
+        let hub = hub();
+        let parts = ${call}.into_parts().unwrap();
+        let url = ::url::Url::parse(&parts.url).unwrap();
+        assert_eq!(url.path(), r#"${expected_path}"#);
+    % for name, value in query:
+        assert_eq!(parts.query_param("${name}"), Some(r#"${value}"#));
+    % endfor
+    }
+% endfor
+}
+</%def>
@@ -6,7 +6,8 @@
                       rust_copy_value_s, organize_params, REQUEST_VALUE_PROPERTY_NAME,
                       build_all_params, rb_type_params_s, hub_type_params_s, mb_type_params_s, mb_additional_type_params, 
                       struct_type_bounds_s, METHODS_RESOURCE, SPACES_PER_TAB, prefix_all_but_first_with,
-                      METHODS_BUILDER_MARKER_TRAIT, remove_empty_lines, method_default_scope, rust_doc_sanitize)
+                      METHODS_BUILDER_MARKER_TRAIT, remove_empty_lines, method_default_scope, rust_doc_sanitize,
+                      deprecated_attribute, builder_input_type, builder_copy_value_s, read_only_cfg_attribute)
 %>\
 <%namespace name="util" file="../../../lib/util.mako"/>\
 <%namespace name="lib" file="lib.mako"/>\
@@ -65,12 +66,12 @@ impl${rb_params} ${ThisType} {
 
     method_args = ''
     if required_props:
-        method_args = ', ' + ', '.join('%s: %s' % (mangle_ident(p.name), activity_input_type(schemas, p)) for p in required_props)
+        method_args = ', ' + ', '.join('%s: %s' % (mangle_ident(p.name), builder_input_type(schemas, p)) for p in required_props)
 
     mb_tparams = mb_type_params_s(m)
     # we would could have information about data requirements for each property in it's dict.
     # for now, we just hardcode it, and treat the entries as way to easily change param names
-    assert len(api.properties) == 2, "Hardcoded for now, thanks to scope requirements"
+    assert len(api.properties) == 5, "Hardcoded for now, thanks to scope requirements"
 
     type_params = ''
     if mb_additional_type_params(m):
@@ -94,6 +95,12 @@ impl${rb_params} ${ThisType} {
         | remove_empty_lines, prefix_all_but_first_with(' ' * SPACES_PER_TAB + '///'  + ' ' * (len(arg_prefix) - len('///')))}
     % endfor
     % endif
+    % if read_only_cfg_attribute(m):
+    ${read_only_cfg_attribute(m)}
+    % endif
+    % if deprecated_attribute(m):
+    ${deprecated_attribute(m)}
+    % endif
     pub fn ${mangle_ident(a)}${type_params}(&self${method_args}) -> ${RType}${mb_tparams} {
         % if part_prop and request_value:
         use client::ToParts;
@@ -110,7 +117,7 @@ impl${rb_params} ${ThisType} {
         ${RType} {
             hub: self.hub,
             % for p in required_props:
-            ${property(p.name)}: ${rust_copy_value_s(mangle_ident(p.name), activity_input_type(schemas, p), p)},
+            ${property(p.name)}: ${builder_copy_value_s(schemas, mangle_ident(p.name), p)},
             % endfor
             ## auto-generate parts from request resources
             % if part_prop and request_value:
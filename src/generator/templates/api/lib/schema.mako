@@ -3,7 +3,8 @@
                       IO_TYPES, activity_split, enclose_in, REQUEST_MARKER_TRAIT, mb_type, indent_all_but_first_by,
                       NESTED_TYPE_SUFFIX, RESPONSE_MARKER_TRAIT, split_camelcase_s, METHODS_RESOURCE,
                       PART_MARKER_TRAIT, canonical_type_name, TO_PARTS_MARKER, UNUSED_TYPE_MARKER, is_schema_with_optionals,
-                      rust_doc_sanitize, items)
+                      rust_doc_sanitize, items, json_example)
+    import json
 %>\
 ## Build a schema which must be an object
 ###################################################################################################################
@@ -11,6 +12,8 @@
 <%def name="_new_object(s, properties, c, allow_optionals)">\
 <% struct = 'pub struct ' + s.id %>\
 % if properties:
+## Fields added by future revisions of the API don't break code constructing schemas with `with_*()` calls
+#[non_exhaustive]
 ${struct} {
 % for pn, p in items(properties):
     ${p.get('description', 'no description provided') | rust_doc_sanitize, rust_doc_comment, indent_all_but_first_by(1)}
@@ -27,6 +30,22 @@ ${struct} {
     pub ${mangle_ident(pn)}: ${rust_ty},
 % endfor
 }
+
+impl ${s.id} {
+% for pn, p in items(properties):
+<%
+    rust_ty = to_rust_type(schemas, s.id, pn, p, allow_optionals=allow_optionals)
+    in_ty, new_value = rust_ty, 'new_value'
+    if rust_ty.startswith('Option<'):
+        in_ty, new_value = rust_ty[len('Option<'):-1], 'Some(new_value)'
+%>\
+    /// Sets the *${split_camelcase_s(pn)}* field to the given value.
+    pub fn ${mangle_ident('with_' + pn)}(mut self, new_value: ${in_ty}) -> ${s.id} {
+        self.${mangle_ident(pn)} = ${new_value};
+        self
+    }
+% endfor
+}
 % elif 'additionalProperties' in s:
 ${struct}(pub ${to_rust_type(schemas, s.id, NESTED_TYPE_SUFFIX, s, allow_optionals=allow_optionals)});
 % elif 'variant' in s:
@@ -65,7 +84,7 @@ ${struct} { _never_set: Option<bool> }
     # We always need Serialization support, as others might want to serialize the response, even though we will 
     # only deserialize it.
     # And since we don't know what others want to do, we implement Deserialize as well by default ... 
-    traits = ['Clone', 'Debug', 'Serialize', 'Deserialize']
+    traits = ['Clone', 'Debug', 'PartialEq', 'Serialize', 'Deserialize']
 
     # default only works for structs, and 'variant' will be an enum
     if 'variant' not in s:
@@ -158,7 +177,11 @@ The list links the activity name, along with information about where it is used
     method_name = ' '.join(split_camelcase_s(method).split('.')) + name_suffix
     value_type = '|'.join(iot) or 'none'
 %>\
+% if make.id == 'types':
+* ${method_name} (${value_type})
+% else:
 * [${method_name}](${struct_url}) (${value_type})
+% endif
 % endfor
 % else:
 
@@ -170,3 +193,27 @@ This type is not used in any activity, and only used as *part* of another schema
 The contained type is `${to_rust_type(schemas, s.id, s.id, s)}`.
 %endif
 </%def>
+
+## Tests checking that every struct round-trips an example built from its discovery document
+###################################################################################################################
+###################################################################################################################
+<%def name="conformance_tests(c)">\
+<%
+    examples = []
+    for s in c.schemas.values():
+        if UNUSED_TYPE_MARKER in schema_markers(s, c, transitive=True) or s.type != 'object' or not s.get('properties'):
+            continue
+        examples.append((s.id, json.dumps(json_example(c.schemas, s, _seen=(s.id,)), sort_keys=True)))
+%>\
+#[cfg(test)]
+mod schema_conformance {
+    use super::*;
+% for sid, example in examples:
+
+    #[test]
+    fn ${mangle_ident(sid)}_round_trips() {
+        client::conformance::assert_round_trip::<${sid}>("${sid}", r###"${example}"###);
+    }
+% endfor
+}
+</%def>
@@ -0,0 +1,74 @@
+<%namespace name="util" file="../../lib/util.mako"/>\
+<%
+    from generator.lib.util import (new_context, rust_comment, hub_type, mangle_ident, to_fqan,
+                      build_all_params, organize_params, method_media_params, to_extern_crate_name,
+                      deprecated_attribute)
+
+    c = new_context(schemas, resources)
+    hub_type_name = hub_type(c.schemas, util.canonical_name())
+    crate = to_extern_crate_name(util.crate_name())
+
+    # The most calls made, to keep the tests fast and within quotas
+    MAX_CALLS = 3
+
+    # Returns the code of the arguments to call `m` with in project `project`, or None if they
+    # can't be derived from the project alone.
+    def project_args(m):
+        params, request_value = build_all_params(c, m)
+        required_props, optional_props, part_prop = organize_params(params, request_value)
+        args = []
+        for p in required_props:
+            if p.get('type') != 'string' or 'format' in p:
+                return None
+            if p.name in ('project', 'projectId'):
+                args.append('&project')
+            elif p.get('pattern') == '^projects/[^/]+$':
+                args.append('&format!("projects/{}", project)')
+            else:
+                return None
+        return ', '.join(args)
+
+    # (fn name, resource, method, args) of read-only calls
+    calls = []
+    for resource in sorted(c.rta_map.keys()):
+        for a in c.rta_map[resource]:
+            m = c.fqan_map[to_fqan(c.rtc_map[resource], resource, a)]
+            if len(calls) == MAX_CALLS or m.httpMethod != 'GET' or method_media_params(m) or deprecated_attribute(m):
+                continue
+            args = project_args(m)
+            if args is not None:
+                calls.append((mangle_ident('%s_%s' % (resource, a)), mangle_ident(resource), mangle_ident(a), args))
+%>\
+<%block filter="rust_comment">\
+<%util:gen_info source="${self.uri}" />\
+</%block>
+//! Read-only calls against the real ${util.canonical_name()} API, to detect breakage between
+//! regenerations of this crate.
+//!
+//! They only run if `GOOGLE_APIS_LIVE_PROJECT` names the project to use, authenticating with the
+//! application default credentials:
+//! `GOOGLE_APIS_LIVE_PROJECT=my-project cargo test --features live --test live`
+#![cfg(feature = "live")]
+% if calls:
+use ${crate}::{client, ${hub_type_name}};
+
+/// Returns a hub for the real API and the project to use, if live tests are enabled.
+async fn live_hub() -> Option<(${hub_type_name}<client::live::Connector>, String)> {
+    let project = client::live::project_id()?;
+    let auth = client::live::application_default_credentials()
+        .await
+        .expect("application default credentials");
+    Some((${hub_type_name}::new(client::live::https_client(), auth), project))
+}
+% for fn_name, resource, method, args in calls:
+
+#[tokio::test]
+async fn ${fn_name}() {
+    let (hub, ${'project' in args and 'project' or '_project'}) = match live_hub().await {
+        Some(live) => live,
+        None => return,
+    };
+    hub.${resource}().${method}(${args}).doit().await.unwrap();
+}
+% endfor
+% endif
@@ -0,0 +1,82 @@
+<%namespace name="util" file="../../lib/util.mako"/>\
+<%
+    from generator.lib.util import (new_context, rust_comment, hub_type, mangle_ident, to_fqan,
+                      activity_input_type, build_all_params, organize_params, method_media_params,
+                      is_pod_property, to_extern_crate_name, deprecated_attribute, read_only_cfg_attribute, TREF)
+
+    c = new_context(schemas, resources)
+    hub_type_name = hub_type(c.schemas, util.canonical_name())
+    crate = to_extern_crate_name(util.crate_name())
+
+    # Returns the code of the arguments to call the trait function of `m` with, or None if they
+    # can't be made up.
+    def example_args(m):
+        params, request_value = build_all_params(c, m)
+        required_props, optional_props, part_prop = organize_params(params, request_value)
+        args = []
+        for p in required_props:
+            if p.get(TREF):
+                args.append('%s::api::%s::default()' % (crate, activity_input_type(schemas, p)))
+            elif is_pod_property(p):
+                args.append('Default::default()')
+            elif activity_input_type(schemas, p) == '&str':
+                args.append('"my-%s"' % p.name)
+            else:
+                return None
+        return ', '.join(args)
+
+    # (trait fn name, args) of the first method listing, and the first method creating resources
+    list_call, create_call = None, None
+    for resource in sorted(c.rta_map.keys()):
+        for a in c.rta_map[resource]:
+            m = c.fqan_map[to_fqan(c.rtc_map[resource], resource, a)]
+            if method_media_params(m) or deprecated_attribute(m):
+                continue
+            if list_call is None and a == 'list':
+                args = example_args(m)
+                if args is not None:
+                    list_call = (mangle_ident('%s_%s' % (resource, a)), args)
+            elif create_call is None and a in ('create', 'insert'):
+                args = example_args(m)
+                if args is not None:
+                    create_call = (mangle_ident('%s_%s' % (resource, a)), args, read_only_cfg_attribute(m))
+%>\
+<%block filter="rust_comment">\
+<%util:gen_info source="${self.uri}" />\
+</%block>
+//! A starting point for using the ${util.canonical_name()} API.
+//!
+//! The calls are made through the `${hub_type_name}Api` trait, against a `client::mock::MockConnector`
+//! answering all requests with an empty object, which is why this example runs without network access
+//! or credentials. To use the real API, build the hub with a `hyper_rustls` connector and an
+//! authenticator instead, as shown in the crate documentation.
+//!
+//! Run it with `cargo run --example quickstart --features mock`.
+use ${crate}::{client, ${hub_type_name}, ${hub_type_name}Api};
+
+async fn run(hub: &dyn ${hub_type_name}Api) -> client::Result<()> {
+% if list_call:
+    let result = hub.${list_call[0]}(${list_call[1]}).await?;
+    println!("${list_call[0]}: {:?}", result);
+% endif
+% if create_call:
+% if create_call[2]:
+    ${create_call[2]}
+    {
+        let result = hub.${create_call[0]}(${create_call[1]}).await?;
+        println!("${create_call[0]}: {:?}", result);
+    }
+% else:
+    let result = hub.${create_call[0]}(${create_call[1]}).await?;
+    println!("${create_call[0]}: {:?}", result);
+% endif
+% endif
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> client::Result<()> {
+    let connector = client::mock::MockConnector::json(serde_json::json!({}));
+    let hub = ${hub_type_name}::new(hyper::Client::builder().build(connector), client::NoToken);
+    run(&hub).await
+}
@@ -1,6 +1,10 @@
 <%
     from generator.lib.util import (markdown_comment, new_context)
-    from generator.lib.cli import (CONFIG_DIR, CONFIG_DIR_FLAG, SCOPE_FLAG, application_secret_path, DEBUG_FLAG)
+    from generator.lib.cli import (CONFIG_DIR, CONFIG_DIR_FLAG, SCOPE_FLAG, application_secret_path, DEBUG_FLAG,
+                                   CONFIG_FILE, PROFILE_FLAG, OUTPUT_FIELD_FLAG, RETRIES_FLAG, RETRY_BACKOFF_FLAG,
+                                   TIMEOUT_FLAG, ENDPOINT_FLAG, ENDPOINT_OVERRIDE_ENV, INTERACTIVE_FLAG, STRUCT_FLAG,
+                                   BATCH_FILE_FLAG, BATCH_CONCURRENCY_FLAG, ALL_PAGES_FLAG, PAGE_SIZE_FLAG, DRY_RUN_FLAG,
+                                   KEY_FILE_FLAG, API_KEY_FLAG)
 
     c = new_context(schemas, resources)
 %>\
@@ -45,8 +49,37 @@ The program will store all persistent data in the `${CONFIG_DIR}` directory in *
 
 More information about the various kinds of persistent data are given in the following paragraphs.
 
+## Profiles
+
+Settings shared by many invocations can be kept in profiles of the `${CONFIG_FILE}` file, which are selected
+with the `--${PROFILE_FLAG}` flag, or the `default` profile if there is one.
+
+```toml
+[profiles.default]
+# used for project arguments given as `_`
+project = "my-project"
+# a service account key, user credentials or application secret, instead of the Application Default Credentials
+credentials = "~/secrets/service-account.json"
+# the project billed for the quota of all requests
+quota_project = "my-billing-project"
+# the base URL of all requests, like that of an emulator or a private endpoint
+endpoint = "http://localhost:8085/"
+```
+
 # Authentication
 
+Requests are authorized with the first credentials found of
+
+* the API key given with `--${API_KEY_FLAG}`, for methods which accept one instead of a token,
+* the service account key, user credentials or application secret given with `--${KEY_FILE_FLAG}`,
+* the credentials of the profile,
+* the file named by the `GOOGLE_APPLICATION_CREDENTIALS` environment variable,
+* the user credentials of `gcloud auth application-default login`,
+* the service account of the instance the program runs on, if it has a metadata server,
+
+like the *Application Default Credentials* of other Google tools. Without any of them, the user is asked to
+authorize the program in the browser, with the application secret described below.
+
 Most APIs require a user to authenticate any request. If this is the case, the [scope][scopes] determines the 
 set of permissions granted. The granularity of these is usually no more than *read-only* or *full-access*.
 
@@ -74,7 +107,8 @@ To allow more comfortable usage of the CLI without forcing anyone to register an
 comes with a default application secret that is configured accordingly. This also means that heavy usage
 all around the world may deplete the daily quota.
 
-You can workaround this limitation by putting your own secrets file at this location: 
+You can workaround this limitation by using any other credentials, as described above, or by putting your own
+secrets file at this location: 
 `${CONFIG_DIR}/${application_secret_path(util.program_name())}`, assuming that the required *${name}* API 
 was enabled for it. Such a secret file can be downloaded in the *Google Developer Console* at 
 *APIs & auth -> Credentials -> Download JSON* and used as is.
@@ -82,8 +116,83 @@ was enabled for it. Such a secret file can be downloaded in the *Google Develope
 Learn more about how to setup Google projects and enable APIs using the [official documentation][google-project-new].
 
 
+# Output
+
+Responses are written as pretty-printed *JSON*. To use a single value of them in scripts, select it with the
+`--${OUTPUT_FIELD_FLAG}` flag, like `--${OUTPUT_FIELD_FLAG} state`, `--${OUTPUT_FIELD_FLAG} items[0].name`,
+or `--${OUTPUT_FIELD_FLAG} items[*].name` for the names of all items. Selected strings are written without quotes.
+
+# Endpoints
+
+To target an emulator, a test server, or a private or restricted endpoint, set the base URL of all requests with
+`--${ENDPOINT_FLAG} <url>`, like `--${ENDPOINT_FLAG} http://localhost:8085/`. Without the flag, the
+`${ENDPOINT_OVERRIDE_ENV}` environment variable is used, and then the `endpoint` of the profile.
+
+# Interactive Use
+
+With `--${INTERACTIVE_FLAG}`, the required fields of the request which weren't given with `-${STRUCT_FLAG}` are asked for
+on the terminal, listing the possible values of enumerations, and asked for again if the given value isn't valid.
+Without a terminal, like in scripts, nothing is asked for.
+
+% if 'test_matrices_wait' in api.get('helpers', list()):
+# Waiting for Test Matrices
+
+`${util.program_name()} projects test-matrices-wait <project-id> <test-matrix-id>` polls a test matrix every 10 seconds, or
+as often as `-i 30s` says, until it finished, writing its state to standard error whenever it changes, and the final test
+matrix to the output. Its exit code is 0 if the outcome of the matrix is `SUCCESS`, 1 if it's `FAILURE`, and 2 otherwise,
+like if the tests were inconclusive or skipped, or the matrix was invalid or cancelled, so that CI pipelines can
+gate on it. `--${TIMEOUT_FLAG}` limits how long it waits in total.
+
+% endif
+# Pages
+
+Methods returning pages of results, like most `list` methods, return one page at a time, with the `nextPageToken` to
+pass as `-p page-token=...` for the next one. With `--${ALL_PAGES_FLAG}`, the pages are requested one after another until
+the last one, and the lists of all pages are output as one response. `--${PAGE_SIZE_FLAG} <size>` sets the maximum number of
+results of each page.
+
+# Batches
+
+With `--${BATCH_FILE_FLAG} requests.jsonl`, methods with a request make one call for each line of the file, which holds
+the JSON of a request, like `{"name": "my-bucket"}`. Its fields are set on the request given with `-${STRUCT_FLAG}`, which
+so holds what all requests have in common. Up to `--${BATCH_CONCURRENCY_FLAG}` calls, 8 by default, are made at the same
+time, each retried on its own as described below. The result of each call is written as one line of JSON, in the order
+of the file, like `{"line": 1, "response": {...}}` or `{"line": 2, "error": "..."}`. If any call failed, the exit code
+is 1.
+
+# Errors and Exit Codes
+
+A failed call is written to standard error as one line of JSON, like
+`{"error":{"code":404,"message":"...","reason":"notFound","status":"NOT_FOUND"}}`, with the HTTP status of the
+response, its [canonical error code][errors], the reason and message the server gave, and the seconds it asked to wait
+before retrying as `retry_after`, if it did. The exit code tells the canonical error codes scripts usually handle apart:
+
+| Exit code | Canonical error codes |
+|-----------|-----------------------|
+| 3 | `NOT_FOUND` |
+| 4 | `PERMISSION_DENIED`, `UNAUTHENTICATED` |
+| 5 | `RESOURCE_EXHAUSTED`, like exceeded rate limits |
+| 6 | `INVALID_ARGUMENT`, `FAILED_PRECONDITION`, `OUT_OF_RANGE` |
+| 7 | `ABORTED`, `ALREADY_EXISTS` |
+| 8 | `UNAVAILABLE`, `DEADLINE_EXCEEDED`, also for calls which didn't finish within `--${TIMEOUT_FLAG}` |
+
+All other errors exit with 1. Invalid arguments or configuration are reported as text before any call is made, and
+may exit with 3 to 5 as well.
+
+# Retries and Timeouts
+
+Calls failing with network errors, `429 Too Many Requests` or transient server errors like `503 Service Unavailable` are
+retried up to `--${RETRIES_FLAG}` times, like `--${RETRIES_FLAG} 3 --${RETRY_BACKOFF_FLAG} 2s`, waiting twice as long before
+each retry as before the previous one, up to 32s, shortened by a random jitter. Without retries, calls the server asked to
+retry later, with `Retry-After`, are still retried after that delay.
+`--${TIMEOUT_FLAG} 60s` fails calls which didn't finish in time, including their retries.
+
 # Debugging
 
+To check which request a command would send, like which fields of the request `-${STRUCT_FLAG}` arguments set, use
+`--${DRY_RUN_FLAG}`. It outputs the method, URL, headers and JSON body of the request instead of sending it, with credentials
+redacted.
+
 Even though the CLI does its best to provide usable error messages, sometimes it might be desirable to know
 what exactly led to a particular issue. This is done by allowing all client-server communication to be 
 output to standard error *as-is*.
@@ -95,5 +204,6 @@ You may consider redirecting standard error into a file for ease of use, e.g. `$
 
 [scopes]: https://developers.google.com/+/api/oauth#scopes
 [revoke-access]: http://webapps.stackexchange.com/a/30849
+[errors]: https://cloud.google.com/apis/design/errors#handling_errors
 [google-dev-console]: https://console.developers.google.com/
 [google-project-new]: https://developers.google.com/console/help/new/
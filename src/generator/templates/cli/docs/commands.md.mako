@@ -33,7 +33,7 @@ You will need authorization for \
 % if len(mc.m.scopes) > 1:
 at least one of the following scopes to make a valid call:
 
-% for s in mc.m.scopes:
+% for s in sorted(mc.m.scopes):
 * *${s}*
 % endfor
 % else:
@@ -1,4 +1,5 @@
 <%namespace name="util" file="../../../lib/util.mako"/>\
+<%namespace name="helpers" file="helpers.mako"/>\
 <%!
     import os
 
@@ -6,7 +7,12 @@
     from generator.lib.cli import (mangle_subcommand, new_method_context, PARAM_FLAG, STRUCT_FLAG, UPLOAD_FLAG, OUTPUT_FLAG, VALUE_ARG,
                      CONFIG_DIR, SCOPE_FLAG, is_request_value_property, FIELD_SEP, docopt_mode, FILE_ARG, MIME_ARG, OUT_ARG,
                      CONFIG_DIR_FLAG, KEY_VALUE_ARG, to_docopt_arg, DEBUG_FLAG, MODE_ARG, SCOPE_ARG,
-                     CONFIG_DIR_ARG, FILE_FLAG, MIME_FLAG, subcommand_md_filename)
+                     CONFIG_DIR_ARG, FILE_FLAG, MIME_FLAG, subcommand_md_filename, PROFILE_FLAG, PROFILE_ARG,
+                     CONFIG_FILE, is_project_param, OUTPUT_FIELD_FLAG, OUTPUT_FIELD_ARG, RETRIES_FLAG, RETRIES_ARG,
+                     RETRY_BACKOFF_FLAG, RETRY_BACKOFF_ARG, TIMEOUT_FLAG, TIMEOUT_ARG, ENDPOINT_FLAG, ENDPOINT_ARG,
+                     ENDPOINT_OVERRIDE_ENV, INTERACTIVE_FLAG, BATCH_FILE_FLAG, BATCH_FILE_ARG, BATCH_CONCURRENCY_FLAG,
+                     BATCH_CONCURRENCY_ARG, ALL_PAGES_FLAG, PAGE_SIZE_FLAG, PAGE_SIZE_ARG, DRY_RUN_FLAG,
+                     KEY_FILE_FLAG, KEY_FILE_ARG, API_KEY_FLAG, API_KEY_ARG)
 
     def rust_boolean(v):
         return v and 'true' or 'false'
@@ -57,6 +63,9 @@ ${util.program_name()} [options]
 %>\
                 ${mangle_subcommand(method)} ${' '.join(args)}
     % endfor # each method
+    % if resource == 'projects' and 'test_matrices_wait' in api.get('helpers', list()):
+                test-matrices-wait <project-id> <test-matrix-id> [-i <interval>] [-${OUTPUT_FLAG} <${OUT_ARG}>]
+    % endif
 % endfor # end for each resource
   ${util.program_name()} --help
 
@@ -71,6 +80,54 @@ Configuration:
             A directory into which we will store our persistent data. Defaults to
             a user-writable directory that we will create during the first invocation.
             [default: ${CONFIG_DIR}]
+  --${PROFILE_FLAG} <${PROFILE_ARG}>
+            The profile of ${CONFIG_FILE} to use, which provides
+            the project used for project arguments given as `_`, the credentials, the
+            quota project and the endpoint. Defaults to the `default` profile, if there is one.
+  --${KEY_FILE_FLAG} <${KEY_FILE_ARG}>
+            The service account key, user credentials or application secret to
+            authorize requests with, instead of the credentials of the profile or the
+            Application Default Credentials.
+  --${API_KEY_FLAG} <${API_KEY_ARG}>
+            Authorize requests with the given API key instead of a token, for methods
+            which accept it.
+  --${OUTPUT_FIELD_FLAG} <${OUTPUT_FIELD_ARG}>
+            Output only the field at the given path of the response, like `state`,
+            `items[0].name`, or `items[*].name` for the names of all items. Strings are
+            written without quotes.
+  --${RETRIES_FLAG} <${RETRIES_ARG}>
+            Retry calls failing with network errors, `429` or transient server errors up
+            to the given number of times. [default: 0]
+  --${RETRY_BACKOFF_FLAG} <${RETRY_BACKOFF_ARG}>
+            The delay before the first retry, like `2s` or `500ms`, which doubles with
+            each further retry. [default: 1s]
+  --${TIMEOUT_FLAG} <${TIMEOUT_ARG}>
+            Fail calls which didn't finish in the given time, like `60s` or `2m`,
+            including their retries.
+  --${ENDPOINT_FLAG} <${ENDPOINT_ARG}>
+            The base URL of all requests, like that of an emulator, a test server or a
+            private endpoint, instead of ${baseUrl}.
+            Defaults to the ${ENDPOINT_OVERRIDE_ENV} environment variable, or
+            the endpoint of the profile.
+  --${INTERACTIVE_FLAG}
+            Ask for the required fields of the request which weren't given with
+            -${STRUCT_FLAG}, if the standard input is a terminal.
+  --${BATCH_FILE_FLAG} <${BATCH_FILE_ARG}>
+            Make one call for each line of the given file, or of the standard input
+            if it's `-`, which holds the JSON of a request. Its fields are set on the
+            request given with -${STRUCT_FLAG}. The result of each call is written as one
+            line of JSON, with the number of the line of its request.
+  --${BATCH_CONCURRENCY_FLAG} <${BATCH_CONCURRENCY_ARG}>
+            The number of calls of a batch file made at the same time. [default: 8]
+  --${ALL_PAGES_FLAG}
+            Call methods returning pages of results until the last page, and output
+            the results of all pages as one response.
+  --${PAGE_SIZE_FLAG} <${PAGE_SIZE_ARG}>
+            The maximum number of results of each page, for methods returning pages
+            of results.
+  --${DRY_RUN_FLAG}
+            Output the method, URL, headers and body of the request instead of sending
+            it, with credentials redacted. Batches and further pages aren't requested.
 </%def>
 
 
@@ -106,6 +163,117 @@ Configuration:
         False,
     ))
 
+    global_args.append((
+        PROFILE_FLAG,
+        "The profile of %s to use, which provides the project used for project arguments "
+        "given as `_`, the credentials, the quota project and the endpoint. "
+        "Defaults to the `default` profile, if there is one." % CONFIG_FILE,
+        PROFILE_ARG,
+        False,
+    ))
+
+    global_args.append((
+        KEY_FILE_FLAG,
+        "The service account key, user credentials or application secret to authorize requests with, "
+        "instead of the credentials of the profile or the Application Default Credentials.",
+        KEY_FILE_ARG,
+        False,
+    ))
+
+    global_args.append((
+        API_KEY_FLAG,
+        "Authorize requests with the given API key instead of a token, for methods which accept it.",
+        API_KEY_ARG,
+        False,
+    ))
+
+    global_args.append((
+        OUTPUT_FIELD_FLAG,
+        "Output only the field at the given path of the response, like `state`, `items[0].name`, "
+        "or `items[*].name` for the names of all items. Strings are written without quotes.",
+        OUTPUT_FIELD_ARG,
+        False,
+    ))
+
+    global_args.append((
+        RETRIES_FLAG,
+        "Retry calls failing with network errors, `429` or transient server errors up to the given number of times. "
+        "[default: 0]",
+        RETRIES_ARG,
+        False,
+    ))
+
+    global_args.append((
+        RETRY_BACKOFF_FLAG,
+        "The delay before the first retry, like `2s` or `500ms`, which doubles with each further retry. "
+        "[default: 1s]",
+        RETRY_BACKOFF_ARG,
+        False,
+    ))
+
+    global_args.append((
+        TIMEOUT_FLAG,
+        "Fail calls which didn't finish in the given time, like `60s` or `2m`, including their retries.",
+        TIMEOUT_ARG,
+        False,
+    ))
+
+    global_args.append((
+        ENDPOINT_FLAG,
+        "The base URL of all requests, like that of an emulator, a test server or a private endpoint, "
+        "instead of %s. Defaults to the %s environment variable, or the endpoint of the profile."
+        % (baseUrl, ENDPOINT_OVERRIDE_ENV),
+        ENDPOINT_ARG,
+        False,
+    ))
+
+    global_args.append((
+        INTERACTIVE_FLAG,
+        "Ask for the required fields of the request which weren't given with -%s, "
+        "if the standard input is a terminal." % STRUCT_FLAG,
+        None,
+        False,
+    ))
+
+    global_args.append((
+        BATCH_FILE_FLAG,
+        "Make one call for each line of the given file, or of the standard input if it's `-`, which holds "
+        "the JSON of a request. Its fields are set on the request given with -%s. The result of each call is "
+        "written as one line of JSON, with the number of the line of its request." % STRUCT_FLAG,
+        BATCH_FILE_ARG,
+        False,
+    ))
+
+    global_args.append((
+        BATCH_CONCURRENCY_FLAG,
+        "The number of calls of a batch file made at the same time. [default: 8]",
+        BATCH_CONCURRENCY_ARG,
+        False,
+    ))
+
+    global_args.append((
+        ALL_PAGES_FLAG,
+        "Call methods returning pages of results until the last page, and output the results of all pages "
+        "as one response.",
+        None,
+        False,
+    ))
+
+    global_args.append((
+        PAGE_SIZE_FLAG,
+        "The maximum number of results of each page, for methods returning pages of results.",
+        PAGE_SIZE_ARG,
+        False,
+    ))
+
+    global_args.append((
+        DRY_RUN_FLAG,
+        "Output the method, URL, headers and body of the request instead of sending it, with credentials "
+        "redacted. Batches and further pages aren't requested.",
+        None,
+        False,
+    ))
+
     global_args.append((
         DEBUG_FLAG,
         "Debug print all errors",
@@ -149,9 +317,12 @@ let arg_data = [
     for p in mc.required_props:
         if is_request_value_property(mc, p):
             continue
+        desc = p.get('description')
+        if is_project_param(p):
+            desc = (desc or '') + ' Given as `_`, it is the project of the profile.'
         args.append((
             None,
-            p.get('description'),
+            desc,
             mangle_subcommand(p.name),
             True,
             False,
@@ -214,6 +385,9 @@ let arg_data = [
             % endfor
           ]),
     % endfor # each method
+    % if resource == 'projects' and 'test_matrices_wait' in api.get('helpers', list()):
+${helpers.test_matrices_wait_arg_data()}\
+    % endif
     ]),
 </%block>
 % endfor # end for each resource
@@ -1,15 +1,20 @@
 <%namespace name="util" file="../../../lib/util.mako"/>\
+<%namespace name="helpers" file="helpers.mako"/>\
 <%!
     from generator.lib.util import (hub_type, mangle_ident, indent_all_but_first_by, activity_rust_type, setter_fn_name, ADD_PARAM_FN,
                       upload_action_fn, is_schema_with_optionals, schema_markers, indent_by, method_default_scope,
-                      ADD_SCOPE_FN, TREF, enclose_in)
+                      ADD_SCOPE_FN, TREF, enclose_in, timeout_setter_fn)
     from generator.lib.cli import (mangle_subcommand, new_method_context, PARAM_FLAG, STRUCT_FLAG, OUTPUT_FLAG, VALUE_ARG,
                      CONFIG_DIR, SCOPE_FLAG, is_request_value_property, FIELD_SEP, docopt_mode, FILE_ARG, MIME_ARG, OUT_ARG,
                      call_method_ident, POD_TYPES, opt_value, ident,
                      KEY_VALUE_ARG, to_cli_schema, SchemaEntry, CTYPE_POD, actual_json_type, CTYPE_MAP, CTYPE_ARRAY,
                      application_secret_path, CONFIG_DIR_FLAG, req_value, MODE_ARG,
                      opt_values, SCOPE_ARG, CONFIG_DIR_ARG, DEFAULT_MIME, field_vec, comma_sep_fields, JSON_TYPE_TO_ENUM_MAP,
-                     CTYPE_TO_ENUM_MAP)
+                     CTYPE_TO_ENUM_MAP, PROFILE_ARG, is_project_param, OUTPUT_FIELD_ARG, RETRIES_ARG,
+                     RETRY_BACKOFF_ARG, TIMEOUT_ARG, RETRIES_FLAG, RETRY_BACKOFF_FLAG, TIMEOUT_FLAG, ENDPOINT_ARG,
+                     INTERACTIVE_FLAG, is_required_request_field, supports_batch, BATCH_FILE_ARG,
+                     BATCH_CONCURRENCY_ARG, BATCH_CONCURRENCY_FLAG, page_token_param, page_size_param, has_value_call,
+                     ALL_PAGES_FLAG, PAGE_SIZE_ARG, PAGE_SIZE_FLAG, DRY_RUN_FLAG, KEY_FILE_ARG, API_KEY_ARG)
     from generator.lib.types import JSON_TO_RUST_DEFAULT
     v_arg = '<%s>' % VALUE_ARG
     SOPT = 'self.opt'
@@ -31,16 +36,23 @@
 <%
     hub_type_name = 'api::' + hub_type(c.schemas, util.canonical_name())
 %>\
-use client::{InvalidOptionsError, CLIError, arg_from_str, writer_from_opts, parse_kv_arg,
+use client::{InvalidOptionsError, CLIError, arg_from_str, writer_from_opts, write_json_output, parse_kv_arg,
+          parse_duration, RequiredField, prompt_missing_fields,
           input_file_from_opts, input_mime_from_opts, FieldCursor, FieldError, CallType, UploadProtocol,
-          calltype_from_str, remove_json_null_values, ComplexType, JsonType, JsonTypeInfo};
+          calltype_from_str, remove_json_null_values, ComplexType, JsonType, JsonTypeInfo,
+          read_batch_file, merge_json_values, batch_result, select_field, DEFAULT_BATCH_CONCURRENCY, append_page,
+          ConfigurationError, ErrorReport};
+use client::credentials::{self, Credentials};
 
 use std::default::Default;
 use std::error::Error as StdError;
+use std::future::Future;
 use std::str::FromStr;
+use std::time::Duration;
 
 use serde_json as json;
 use clap::ArgMatches;
+use futures::stream::{self, StreamExt};
 use http::Uri;
 use hyper::client::connect;
 use tokio::io::{AsyncRead, AsyncWrite};
@@ -49,11 +61,50 @@ use tower_service;
 enum DoitError {
     IoError(String, io::Error),
     ApiError(Error),
+    OutputError(CLIError),
+    TimeoutError(Duration),
+    /// The number of failed requests of a batch, and of all its requests
+    BatchError(usize, usize),
+    /// Why the request of a batch file isn't valid for the method
+    RequestError(String),
+% if 'test_matrices_wait' in api.get('helpers', list()):
+    /// The outcome of a call which succeeded, and the exit code it results in
+    OutcomeError(String, i32),
+% endif
+}
+
+/// Returns the report of a call which failed with `err`, as written to standard error.
+fn error_report(err: &Error) -> ErrorReport {
+    ErrorReport {
+        code: err.status().map(|status| status.as_u16()),
+        status: err.canonical_code().to_string(),
+        reason: err.reason().map(str::to_string),
+        retry_after: err.retry_after(),
+        message: err.message().map(str::to_string).unwrap_or_else(|| err.to_string()),
+    }
+}
+
+/// Adds the quota project of the profile to all requests.
+struct CallDelegate {
+    quota_project: Option<String>,
+}
+
+impl Delegate for CallDelegate {
+    fn prepare_request(&mut self, builder: http::request::Builder) -> http::request::Builder {
+        match self.quota_project.as_ref() {
+            Some(project) => builder.header("x-goog-user-project", project.as_str()),
+            None => builder,
+        }
+    }
 }
 
 struct Engine<'n, S> {
     opt: ArgMatches<'n>,
     hub: ${hub_type_name}<S>,
+    profile: client::profile::Profile,
+    timeout: Option<Duration>,
+    batch_concurrency: usize,
+    api_key: Option<String>,
     gp: ${"Vec<&'static str>"},
     gpm: Vec<(&'static str, &'static str)>,
 }
@@ -68,13 +119,128 @@ where
 {
 % for resource in sorted(c.rta_map.keys()):
     % for method in sorted(c.rta_map[resource]):
+    ## deprecated methods and parameters remain available on the command-line
+    #[allow(deprecated)]
     async fn ${call_method_ident(resource, method)}(&self, opt: &ArgMatches<'n>, dry_run: bool, err: &mut InvalidOptionsError)
                                                     -> Result<(), DoitError> {
         ${self._method_call_impl(c, resource, method) | indent_all_but_first_by(2)}
     }
 
+<%
+    mc = new_method_context(resource, method, c)
+    value_call_args = ["opt: &ArgMatches<'n>"]
+    if mc.request_value:
+        value_call_args.append('request_value: json::Value')
+    if page_token_param(mc):
+        value_call_args.append('page_token: Option<String>')
+%>\
+    % if has_value_call(mc):
+    /// Makes the call, with the request of a batch file or for a page of results, and returns its response.
+    #[allow(deprecated)]
+    async fn ${call_method_ident(resource, method)}_value(&self, ${', '.join(value_call_args)})
+                                                          -> Result<json::Value, DoitError> {
+        ${self._method_call_impl(c, resource, method, value=True) | indent_all_but_first_by(2)}
+    }
+
+    % endif
     % endfor # each method
 % endfor
+% if 'test_matrices_wait' in api.get('helpers', list()):
+    ${helpers.test_matrices_wait_impl(c) | indent_all_but_first_by(1)}
+% endif
+    /// Makes a call with `call` for each request of the batch file, with `base` being the request
+    /// given on the command-line, and writes the result of each as one line of JSON, in order.
+    async fn batch<F, Fut>(&self, opt: &ArgMatches<'n>, base: json::Value, call: F) -> Result<(), DoitError>
+    where
+        F: Fn(json::Value) -> Fut,
+        Fut: Future<Output = Result<json::Value, DoitError>>,
+    {
+        let batch_file = ${SOPT}.value_of("${BATCH_FILE_ARG}").unwrap_or("-");
+        let requests = match read_batch_file(batch_file) {
+            Ok(requests) => requests,
+            Err(io_err) => return Err(DoitError::IoError(batch_file.to_string(), io_err)),
+        };
+        let mut ostream = match writer_from_opts(opt.value_of("${(OUT_ARG)}")) {
+            Ok(f) => f,
+            Err(io_err) => return Err(DoitError::IoError(${opt_value(OUT_ARG, default='-')}.to_string(), io_err)),
+        };
+        let field = ${SOPT}.value_of("${OUTPUT_FIELD_ARG}");
+        let total = requests.len();
+        let mut failed = 0;
+        let call = &call;
+        let mut results = stream::iter(requests)
+            .map(|request| {
+                let mut value = base.clone();
+                async move {
+                    let result = match request.value {
+                        Ok(request_value) => {
+                            merge_json_values(&mut value, request_value);
+                            call(value).await.map_err(|err| match err {
+                                DoitError::ApiError(err) => err.to_string(),
+                                DoitError::TimeoutError(timeout) => format!("The call didn't finish within {:?}", timeout),
+                                DoitError::RequestError(err) => err,
+                                DoitError::IoError(path, err) => format!("{}: {}", path, err),
+                                DoitError::OutputError(err) => err.to_string(),
+                                DoitError::BatchError(failed, total) => format!("{} of {} requests failed", failed, total),
+                                % if 'test_matrices_wait' in api.get('helpers', list()):
+                                DoitError::OutcomeError(outcome, _) => format!("The outcome is {}", outcome),
+                                % endif
+                            })
+                        }
+                        Err(err) => Err(err),
+                    };
+                    let result = match (result, field) {
+                        (Ok(response), Some(path)) => select_field(&response, path)
+                            .map_err(|err| err.to_string().trim_end().to_string()),
+                        (result, _) => result,
+                    };
+                    batch_result(request.line, result)
+                }
+            })
+            .buffered(self.batch_concurrency);
+        while let Some(result) = results.next().await {
+            if result.get("error").is_some() {
+                failed += 1;
+            }
+            if let Err(io_err) = writeln!(ostream, "{}", result).and_then(|_| ostream.flush()) {
+                return Err(DoitError::IoError(${opt_value(OUT_ARG, default='-')}.to_string(), io_err));
+            }
+        }
+        if failed > 0 {
+            Err(DoitError::BatchError(failed, total))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Makes calls with `call`, given the token of the page of results to return, until the last
+    /// page, and writes the results of all pages as one response.
+    async fn pages<F, Fut>(&self, opt: &ArgMatches<'n>, call: F) -> Result<(), DoitError>
+    where
+        F: Fn(Option<String>) -> Fut,
+        Fut: Future<Output = Result<json::Value, DoitError>>,
+    {
+        let mut ostream = match writer_from_opts(opt.value_of("${(OUT_ARG)}")) {
+            Ok(f) => f,
+            Err(io_err) => return Err(DoitError::IoError(${opt_value(OUT_ARG, default='-')}.to_string(), io_err)),
+        };
+        let mut results = json::Value::Null;
+        let mut page_token = None;
+        loop {
+            let page = call(page_token.take()).await?;
+            page_token = page["nextPageToken"].as_str().filter(|token| !token.is_empty()).map(str::to_string);
+            append_page(&mut results, page);
+            if page_token.is_none() {
+                break;
+            }
+        }
+        if let Err(err) = write_json_output(&mut ostream, &results, ${SOPT}.value_of("${OUTPUT_FIELD_ARG}")) {
+            return Err(DoitError::OutputError(err));
+        }
+        ostream.flush().unwrap();
+        Ok(())
+    }
+
     async fn _doit(&self, dry_run: bool) -> Result<Result<(), DoitError>, Option<InvalidOptionsError>> {
         let mut err = InvalidOptionsError::new();
         let mut call_result: Result<(), DoitError> = Ok(());
@@ -89,6 +255,9 @@ where
                         call_result = self.${call_method_ident(resource, method)}(opt, dry_run, &mut err).await;
                     },
                     % endfor # each method
+                    % if resource == 'projects' and 'test_matrices_wait' in api.get('helpers', list()):
+                    ${helpers.test_matrices_wait_dispatch() | indent_all_but_first_by(5)}
+                    % endif
                     _ => {
                         err.issues.push(CLIError::MissingMethodError("${mangle_subcommand(resource)}".to_string()));
                         writeln!(io::stderr(), "{}\n", opt.usage()).ok();
@@ -112,33 +281,145 @@ where
         }
     }
 
+    /// Returns true if the metadata server of the instance the CLI runs on answers, which provides the tokens of
+    /// its service account.
+    async fn has_metadata_server(client: &hyper::Client<S, hyper::body::Body>) -> bool {
+        let request = hyper::Request::get(format!("http://{}/", credentials::metadata_host()))
+            .header("Metadata-Flavor", "Google")
+            .body(hyper::body::Body::empty());
+        let request = match request {
+            Ok(request) => request,
+            Err(_) => return false,
+        };
+        match tokio::time::timeout(Duration::from_millis(500), client.request(request)).await {
+            Ok(Ok(response)) => response.headers().get("Metadata-Flavor").map_or(false, |flavor| flavor == "Google"),
+            _ => false,
+        }
+    }
+
     // Please note that this call will fail if any part of the opt can't be handled
     async fn new(opt: ArgMatches<'n>, connector: S) -> Result<Engine<'n, S>, InvalidOptionsError> {
-        let (config_dir, secret) = {
-            let config_dir = match client::assure_config_dir_exists(opt.value_of("${CONFIG_DIR_ARG}").unwrap_or("${CONFIG_DIR}")) {
-                Err(e) => return Err(InvalidOptionsError::single(e, 3)),
-                Ok(p) => p,
+        let profile = match client::profile::Profile::load(client::profile::CONFIG_FILE, opt.value_of("${PROFILE_ARG}")) {
+            Err(e) => return Err(InvalidOptionsError::single(e, 5)),
+            Ok(p) => p,
+        };
+
+        ## Without retries, calls are only retried after the delay the server asked for, as by the hub by default
+        let mut retry_policy = None;
+        if let Some(retries) = opt.value_of("${RETRIES_ARG}") {
+            retry_policy = match retries.parse::<u32>() {
+                Ok(0) => None,
+                Ok(retries) => Some(RetryPolicy::new().max_attempts(retries.saturating_add(1))),
+                Err(e) => return Err(InvalidOptionsError::single(CLIError::ParseError("${RETRIES_FLAG}".to_string(),
+                    "u32".to_string(), retries.to_string(), format!("{}", e)), 1)),
+            };
+        }
+        if let Some(backoff) = opt.value_of("${RETRY_BACKOFF_ARG}") {
+            let backoff = match parse_duration("${RETRY_BACKOFF_FLAG}", backoff) {
+                Ok(backoff) => backoff,
+                Err(e) => return Err(InvalidOptionsError::single(e, 1)),
             };
+            retry_policy = retry_policy.map(|policy| policy.base_delay(backoff));
+        }
+        let timeout = match opt.value_of("${TIMEOUT_ARG}").map(|timeout| parse_duration("${TIMEOUT_FLAG}", timeout)) {
+            Some(Err(e)) => return Err(InvalidOptionsError::single(e, 1)),
+            Some(Ok(timeout)) => Some(timeout),
+            None => None,
+        };
+        let batch_concurrency = match opt.value_of("${BATCH_CONCURRENCY_ARG}") {
+            Some(jobs) => match jobs.parse::<usize>() {
+                Ok(jobs) if jobs > 0 => jobs,
+                Ok(_) => return Err(InvalidOptionsError::single(CLIError::ParseError("${BATCH_CONCURRENCY_FLAG}".to_string(),
+                    "usize".to_string(), jobs.to_string(), "must be at least 1".to_string()), 1)),
+                Err(e) => return Err(InvalidOptionsError::single(CLIError::ParseError("${BATCH_CONCURRENCY_FLAG}".to_string(),
+                    "usize".to_string(), jobs.to_string(), format!("{}", e)), 1)),
+            },
+            None => DEFAULT_BATCH_CONCURRENCY,
+        };
 
-            match client::application_secret_from_directory(&config_dir, "${application_secret_path(util.program_name())}",
-                                                         "${api.credentials.replace('"', r'\"')}") {
-                Ok(secret) => (config_dir, secret),
-                Err(e) => return Err(InvalidOptionsError::single(e, 4))
-            }
+        let config_dir = match client::assure_config_dir_exists(opt.value_of("${CONFIG_DIR_ARG}").unwrap_or("${CONFIG_DIR}")) {
+            Err(e) => return Err(InvalidOptionsError::single(e, 3)),
+            Ok(p) => p,
+        };
+        let credentials = match Credentials::resolve(opt.value_of("${API_KEY_ARG}"), opt.value_of("${KEY_FILE_ARG}"), &profile) {
+            Err(e) => return Err(InvalidOptionsError::single(e, 4)),
+            Ok(c) => c,
+        };
+        let api_key = match credentials {
+            Credentials::ApiKey(ref key) => Some(key.clone()),
+            _ => None,
         };
 
         let client = hyper::Client::builder().build(connector);
+        let tokens_path = format!("{}/${util.program_name()}", config_dir);
+        let auth_failed = |what: &str, e: io::Error| {
+            InvalidOptionsError::single(CLIError::Configuration(ConfigurationError::InvalidCredentials((what.to_string(),
+                                                                                                          e.to_string()))), 4)
+        };
 
-        let auth = oauth2::InstalledFlowAuthenticator::with_client(
-            secret,
-            oauth2::InstalledFlowReturnMethod::HTTPRedirect,
-            client.clone(),
-        ).persist_tokens_to_disk(format!("{}/${util.program_name()}", config_dir)).build().await.unwrap();
+        let auth = match credentials {
+            Credentials::ApiKey(_) => None,
+            Credentials::ServiceAccount(key) => {
+                let auth = oauth2::ServiceAccountAuthenticator::with_client(key, client.clone()).build().await;
+                Some(auth.map_err(|e| auth_failed("the service account key", e))?)
+            },
+            Credentials::AuthorizedUser(secret) => {
+                let auth = oauth2::AuthorizedUserAuthenticator::with_client(secret, client.clone())
+                    .persist_tokens_to_disk(tokens_path).build().await;
+                Some(auth.map_err(|e| auth_failed("the user credentials", e))?)
+            },
+            Credentials::InstalledFlow(secret) => {
+                let auth = oauth2::InstalledFlowAuthenticator::with_client(
+                    secret,
+                    oauth2::InstalledFlowReturnMethod::HTTPRedirect,
+                    client.clone(),
+                ).persist_tokens_to_disk(tokens_path).build().await;
+                Some(auth.map_err(|e| auth_failed("the application secret", e))?)
+            },
+            Credentials::Default if Self::has_metadata_server(&client).await => {
+                let opts = oauth2::ApplicationDefaultCredentialsFlowOpts::default();
+                let auth = match oauth2::ApplicationDefaultCredentialsAuthenticator::with_client(opts, client.clone()).await {
+                    oauth2::authenticator::ApplicationDefaultCredentialsTypes::InstanceMetadata(builder) => builder.build().await,
+                    oauth2::authenticator::ApplicationDefaultCredentialsTypes::ServiceAccount(builder) => builder.build().await,
+                };
+                Some(auth.map_err(|e| auth_failed("the metadata server", e))?)
+            },
+            Credentials::Default => {
+                // Without any credentials, the user authorizes the CLI with its own application secret
+                let secret = match client::application_secret_from_directory(&config_dir, "${application_secret_path(util.program_name())}",
+                                                                             "${api.credentials.replace('"', r'\"')}") {
+                    Err(e) => return Err(InvalidOptionsError::single(e, 4)),
+                    Ok(secret) => secret,
+                };
+                let auth = oauth2::InstalledFlowAuthenticator::with_client(
+                    secret,
+                    oauth2::InstalledFlowReturnMethod::HTTPRedirect,
+                    client.clone(),
+                ).persist_tokens_to_disk(tokens_path).build().await;
+                Some(auth.map_err(|e| auth_failed("the application secret", e))?)
+            },
+        };
+
+        let mut hub = match auth {
+            Some(auth) => ${hub_type_name}::new(client, auth),
+            None => ${hub_type_name}::with_api_key(client, api_key.clone().unwrap_or_default()),
+        };
+        hub.append_client_token(concat!("${util.program_name()}/", env!("CARGO_PKG_VERSION")));
+        if let Some(endpoint) = profile.endpoint(opt.value_of("${ENDPOINT_ARG}")) {
+            hub.base_url(endpoint);
+        }
+        if let Some(retry_policy) = retry_policy {
+            hub.retry_policy(retry_policy);
+        }
 
 <% gpm = gen_global_parameter_names(parameters) %>\
         let engine = Engine {
             opt: opt,
-            hub: ${hub_type_name}::new(client, auth),
+            hub,
+            profile,
+            timeout,
+            batch_concurrency,
+            api_key,
             gp: ${field_vec(gpm)},
             gpm: vec![
                 % for pn in list(pn for pn in gpm if mangle_subcommand(pn) != pn):
@@ -163,7 +444,9 @@ where
 }
 </%def>
 
-<%def name="_method_call_impl(c, resource, method)" buffered="True">\
+## With `value`, the implementation of a call returning the response as JSON value, which is made with the request
+## of a batch file or for one page of results, after the options were checked
+<%def name="_method_call_impl(c, resource, method, value=False)" buffered="True">\
 <%
     mc = new_method_context(resource, method, c)
     supports_media_download = mc.m.get('supportsMediaDownload', False)
@@ -178,9 +461,16 @@ where
     if mc.request_value:
         request_cli_schema = to_cli_schema(c, mc.request_value)
 
+    request_prop_name = None
     request_prop_type = None
     global_parameter_names = gen_global_parameter_names(parameters)
+    page_token_prop = page_token_param(mc)
+    page_size_prop = page_size_param(mc)
 %>\
+% if value:
+let dry_run = false;
+let err = &mut InvalidOptionsError::new();
+% endif
     ## REQUIRED PARAMETERS
 % for p in mc.required_props:
 <%
@@ -188,8 +478,26 @@ where
     prop_type = activity_rust_type(c.schemas, p, allow_optionals=False)
 %>\
     % if is_request_value_property(mc, p):
-<% request_prop_type = prop_type %>\
-${self._request_value_impl(c, request_cli_schema, prop_name, request_prop_type)}\
+<%
+    request_prop_name = prop_name
+    request_prop_type = prop_type
+%>\
+    % if value:
+let ${prop_name}: api::${prop_type} = match json::value::from_value(request_value) {
+    Ok(request) => request,
+    Err(e) => return Err(DoitError::RequestError(e.to_string())),
+};
+    % else:
+${self._request_value_impl(c, mc.m, request_cli_schema, prop_name, request_prop_type)}\
+    % endif
+    % elif is_project_param(p):
+let ${prop_name} = match self.profile.project(${opt_value(p.name)}) {
+    Ok(project) => project,
+    Err(e) => {
+        err.issues.push(e);
+        ""
+    }
+};
     % elif p.type != 'string':
     % if p.get('repeated', False):
 let ${prop_name}: Vec<${prop_type} = Vec::new();
@@ -208,7 +516,9 @@ let ${prop_name}: ${prop_type} = arg_from_str(&${opt_value(p.name)}, err, "<${ma
         # if type is not available, we know it's the request value, which should also be borrowed
         borrow = borrow_prefix(p)
         arg_name = mangle_ident(p.name)
-        if p.get('type', '') == 'string':
+        if is_project_param(p):
+            arg_name = mangle_ident(p.name)
+        elif p.get('type', '') == 'string':
             if p.get('repeated', False):
                 arg_name = opt_values(p.name) + '.map(|&v| v.to_string()).collect::<Vec<String>>()'
             else:
@@ -216,10 +526,39 @@ let ${prop_name}: ${prop_type} = arg_from_str(&${opt_value(p.name)}, err, "<${ma
         call_args.append(borrow + arg_name)
     # end for each required prop
 %>\
+% if supports_batch(mc) and not value:
+if !dry_run && ${SOPT}.is_present("${BATCH_FILE_ARG}") && !${SOPT}.is_present("${DRY_RUN_FLAG}") {
+    let mut base = json::value::to_value(&${request_prop_name}).expect("serde to work");
+    remove_json_null_values(&mut base);
+    return self.batch(opt, base, |request_value| self.${call_method_ident(resource, method)}_value(opt, request_value\
+    % if page_token_prop:
+, None\
+    % endif
+)).await;
+}
+% endif
+% if page_token_prop and not value:
+if !dry_run && ${SOPT}.is_present("${ALL_PAGES_FLAG}") && !${SOPT}.is_present("${DRY_RUN_FLAG}") {
+    % if mc.request_value:
+    let mut base = json::value::to_value(&${request_prop_name}).expect("serde to work");
+    remove_json_null_values(&mut base);
+    return self.pages(opt, |page_token| self.${call_method_ident(resource, method)}_value(opt, base.clone(), page_token)).await;
+    % else:
+    return self.pages(opt, |page_token| self.${call_method_ident(resource, method)}_value(opt, page_token)).await;
+    % endif
+}
+% endif
 % if track_download_flag:
 let mut download_mode = false;
 % endif
-let mut call = self.hub.${mangle_ident(resource)}().${mangle_ident(method)}(${', '.join(call_args)});
+let mut dlg = CallDelegate {
+    quota_project: self.profile.quota_project.clone(),
+};
+let mut call = self.hub.${mangle_ident(resource)}().${mangle_ident(method)}(${', '.join(call_args)})
+                       .delegate(&mut dlg);
+if let Some(timeout) = self.timeout {
+    call = call.${timeout_setter_fn(mc.params)}(timeout);
+}
 % if handle_props:
 for parg in ${opt_values(VALUE_ARG)} {
     let (key, value) = parse_kv_arg(&*parg, err, false);
@@ -283,33 +622,75 @@ let mime_type = input_mime_from_opts(${opt_value(MIME_ARG, default=DEFAULT_MIME)
 % else:
 let protocol = CallType::Standard;
 % endif # support upload
+% if page_size_prop:
+if let Some(page_size) = ${SOPT}.value_of("${PAGE_SIZE_ARG}") {
+    call = call.${mangle_ident(setter_fn_name(page_size_prop))}(arg_from_str(page_size, err, "${PAGE_SIZE_FLAG}", "${page_size_prop.get('format', 'integer')}"));
+}
+% endif
+% if page_token_prop and value:
+if let Some(page_token) = page_token {
+    call = call.${mangle_ident(setter_fn_name(page_token_prop))}(&page_token);
+}
+% endif
+% if value:
+{
+% else:
 if dry_run {
     Ok(())
 } else {
+% endif
     assert!(err.issues.len() == 0);
     % if method_default_scope(mc.m):
     for scope in ${opt_values(SCOPE_ARG, opt=SOPT)} {
         call = call.${ADD_SCOPE_FN}(scope);
     }
+    if let Some(key) = self.api_key.as_ref() {
+        call = call.${ADD_PARAM_FN}("key", key);
+    }
+    % endif
+    % if not value:
+    if ${SOPT}.is_present("${DRY_RUN_FLAG}") {
+        let (request, _) = call.build_request().map_err(DoitError::ApiError)?;
+        writeln!(io::stdout(), "{}", describe_request(&request)).ok();
+        % if mc.media_params:
+        writeln!(io::stdout(), "\nThe media of the file to upload would be sent as well.").ok();
+        % endif
+        return Ok(());
+    }
     % endif
     ## Make the call, handle uploads, handle downloads (also media downloads|json decoding)
-    % if handle_output:
+    % if handle_output and not value:
     let mut ostream = match writer_from_opts(opt.value_of("${(OUT_ARG)}")) {
         Ok(mut f) => f,
         Err(io_err) => return Err(DoitError::IoError(${opt_value(OUT_ARG, default='-')}.to_string(), io_err)),
     };
     % endif # handle output
-    match match protocol {
+    let call_result = match protocol {
         % if mc.media_params:
         % for p in mc.media_params:
-        CallType::Upload(UploadProtocol::${p.protocol.capitalize()}) => call.${upload_action_fn(api.terms.upload_action, p.type.suffix)}(input_file.unwrap(), mime_type.unwrap()).await,
+        CallType::Upload(UploadProtocol::${p.protocol.capitalize()}) => call.${upload_action_fn(api.terms.upload_action, p.type.suffix)}(ReadSeekAdapter::new(input_file.unwrap()), mime_type.unwrap()).await,
         % endfor
         CallType::Standard => unreachable!()
         % else:
         CallType::Standard => call.${api.terms.action}().await,
         _ => unreachable!()
         % endif
-    } {
+    };
+    % if value:
+    match call_result {
+        Err(api_err) => Err(DoitError::ApiError(api_err)),
+        % if mc.response_schema:
+        Ok((_, output_schema)) => {
+            let mut value = json::value::to_value(&output_schema).expect("serde to work");
+            remove_json_null_values(&mut value);
+            Ok(value)
+        }
+        % else:
+        Ok(_) => Ok(json::Value::Null),
+        % endif
+    }
+    % else:
+    match call_result {
         Err(api_err) => Err(DoitError::ApiError(api_err)),
         % if mc.response_schema:
         Ok((mut response, output_schema)) => {
@@ -325,7 +706,9 @@ if dry_run {
             % if mc.response_schema:
             let mut value = json::value::to_value(&output_schema).expect("serde to work");
             remove_json_null_values(&mut value);
-            json::to_writer_pretty(&mut ostream, &value).unwrap();
+            if let Err(err) = write_json_output(&mut ostream, &value, ${SOPT}.value_of("${OUTPUT_FIELD_ARG}")) {
+                return Err(DoitError::OutputError(err));
+            }
             ostream.flush().unwrap();
             % endif
             % if track_download_flag:
@@ -343,10 +726,11 @@ if dry_run {
             Ok(())
         }
     }
+    % endif # value
 }\
 </%def>
 
-<%def name="_request_value_impl(c, request_cli_schema, request_prop_name, request_prop_type)">
+<%def name="_request_value_impl(c, method, request_cli_schema, request_prop_name, request_prop_type)">
 <%
     allow_optionals_fn = lambda s: is_schema_with_optionals(schema_markers(s, c, transitive=False))
 
@@ -373,6 +757,12 @@ if dry_run {
     schema_fields = list()
     fields = set()
     flatten_schema_fields(request_cli_schema, schema_fields, fields)
+
+    # Only fields of the request itself are prompted for, as nested ones are required only if their
+    # parent is set
+    required_fields = [(schema, fe, f) for schema, fe, f in schema_fields
+                       if len(f) == 1 and fe.container_type == CTYPE_POD
+                       and is_required_request_field(fe.actual_property, method)]
 %>\
 let mut field_cursor = FieldCursor::default();
 let mut object = json::value::Value::Object(Default::default());
@@ -416,5 +806,26 @@ for kvarg in ${opt_values(KEY_VALUE_ARG)} {
         FieldCursor::from(field_cursor_str).set_json_value(&mut object, value.unwrap(), type_info, err, &temp_cursor);
     }
 }
+% if required_fields:
+if !dry_run && err.issues.is_empty() && ${SOPT}.is_present("${INTERACTIVE_FLAG}") && !${SOPT}.is_present("${BATCH_FILE_ARG}") {
+    let required_fields = [
+    % for schema, fe, f in required_fields:
+<%
+    ptype = actual_json_type(f[-1][1], fe.actual_property.type)
+    choices = ', '.join('"%s"' % v for v in fe.actual_property.get('enum', list()))
+%>\
+        RequiredField {
+            name: "${mangle_subcommand(f[-1][1])}",
+            json_path: "${f[-1][1]}",
+            type_info: JsonTypeInfo { jtype: JsonType::${JSON_TYPE_TO_ENUM_MAP[ptype]}, ctype: ComplexType::Pod },
+            choices: &[${choices}],
+        },
+    % endfor # each required field
+    ];
+    if let Err(io_err) = prompt_missing_fields(&mut object, &required_fields) {
+        return Err(DoitError::IoError("<stdin>".to_string(), io_err));
+    }
+}
+% endif
 let mut ${request_prop_name}: api::${request_prop_type} = json::value::from_value(object).unwrap();
 </%def>
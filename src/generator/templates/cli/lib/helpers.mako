@@ -0,0 +1,140 @@
+<%!
+    from generator.lib.cli import (OUT_ARG, OUTPUT_FLAG, OUTPUT_FIELD_ARG, DRY_RUN_FLAG)
+%>\
+## Hand-written subcommands which only make sense for particular APIs.
+## They are enabled by listing them in `api.helpers` within the API's overrides file, next to those of the API crate.
+
+## Waiting for test matrices of the testing API to finish, for CI pipelines
+###############################################################################################
+###############################################################################################
+## The subcommand, within the `projects` resource of `arg_data`
+<%def name="test_matrices_wait_arg_data()">\
+("test-matrices-wait",
+        Some(r##"Waits until the test matrix finished, and exits with 0 if its outcome is SUCCESS, 1 if it's FAILURE, or 2 otherwise, like if the tests were inconclusive or skipped, or the matrix was invalid or cancelled."##),
+        "Polls the test matrix with test-matrices-get, writes its state to standard error whenever it changes, and the final test matrix to the output.",
+      vec![
+        (Some(r##"project-id"##),
+         None,
+         Some(r##"Cloud project that owns the test matrix. Given as `_`, it is the project of the profile."##),
+         Some(true),
+         Some(false)),
+
+        (Some(r##"test-matrix-id"##),
+         None,
+         Some(r##"Unique test matrix id which was assigned by the service."##),
+         Some(true),
+         Some(false)),
+
+        (Some(r##"interval"##),
+         Some(r##"i"##),
+         Some(r##"The time between two polls of the test matrix, like `30s` or `1m`. [default: 10s]"##),
+         Some(false),
+         Some(false)),
+
+        (Some(r##"${OUT_ARG}"##),
+         Some(r##"${OUTPUT_FLAG}"##),
+         Some(r##"Specify the file into which to write the program's output"##),
+         Some(false),
+         Some(false)),
+      ]),
+</%def>
+
+## The arm of the subcommand within the match of the methods of the `projects` resource in `_doit()`
+<%def name="test_matrices_wait_dispatch()">\
+("test-matrices-wait", Some(opt)) => {
+    call_result = self._projects_test_matrices_wait(opt, dry_run, &mut err).await;
+},
+</%def>
+
+## The implementation of the subcommand, as method of the `Engine`
+<%def name="test_matrices_wait_impl(c)">\
+/// Polls the test matrix until it's in a final state, and fails with an exit code according to its outcome
+/// unless it was successful.
+async fn _projects_test_matrices_wait(&self, opt: &ArgMatches<'n>, dry_run: bool, err: &mut InvalidOptionsError)
+                                      -> Result<(), DoitError> {
+    let project_id = match self.profile.project(opt.value_of("project-id").unwrap_or("")) {
+        Ok(project) => project,
+        Err(e) => {
+            err.issues.push(e);
+            ""
+        }
+    };
+    let test_matrix_id = opt.value_of("test-matrix-id").unwrap_or("");
+    let interval = match opt.value_of("interval").map(|interval| parse_duration("interval", interval)) {
+        Some(Err(e)) => {
+            err.issues.push(e);
+            Duration::default()
+        }
+        Some(Ok(interval)) => interval,
+        None => Duration::from_secs(10),
+    };
+    if dry_run {
+        return Ok(());
+    }
+
+    if self.opt.is_present("${DRY_RUN_FLAG}") {
+        let (request, _) = self.hub.projects().test_matrices_get(project_id, test_matrix_id).build_request()
+            .map_err(DoitError::ApiError)?;
+        writeln!(io::stdout(), "{}", describe_request(&request)).ok();
+        return Ok(());
+    }
+
+    let mut ostream = match writer_from_opts(opt.value_of("${OUT_ARG}")) {
+        Ok(f) => f,
+        Err(io_err) => return Err(DoitError::IoError(opt.value_of("${OUT_ARG}").unwrap_or("-").to_string(), io_err)),
+    };
+    let wait = async {
+        let mut last_state = None;
+        loop {
+            let mut dlg = CallDelegate {
+                quota_project: self.profile.quota_project.clone(),
+            };
+            let matrix = match self.hub.projects().test_matrices_get(project_id, test_matrix_id)
+                                   .delegate(&mut dlg)
+                                   .doit().await {
+                Ok((_, matrix)) => matrix,
+                Err(api_err) => return Err(api_err),
+            };
+            let state = matrix.state.clone().unwrap_or_default();
+            if last_state.as_ref() != Some(&state) {
+                writeln!(io::stderr(), "{}: {}", test_matrix_id, state).ok();
+            }
+            match state.as_str() {
+                "" | "TEST_STATE_UNSPECIFIED" | "VALIDATING" | "PENDING" | "RUNNING" => {}
+                _ => return Ok(matrix),
+            }
+            last_state = Some(state);
+            tokio::time::sleep(interval).await;
+        }
+    };
+    let matrix: Result<api::TestMatrix, Error> = match self.timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, wait).await {
+            Ok(matrix) => matrix,
+            Err(_) => return Err(DoitError::TimeoutError(timeout)),
+        },
+        None => wait.await,
+    };
+    let matrix = match matrix {
+        Ok(matrix) => matrix,
+        Err(api_err) => return Err(DoitError::ApiError(api_err)),
+    };
+
+    let mut value = json::value::to_value(&matrix).expect("serde to work");
+    remove_json_null_values(&mut value);
+    if let Err(err) = write_json_output(&mut ostream, &value, self.opt.value_of("${OUTPUT_FIELD_ARG}")) {
+        return Err(DoitError::OutputError(err));
+    }
+    ostream.flush().unwrap();
+
+    // The outcome is only set for finished matrices, all others didn't run their tests
+    let outcome = match matrix.state.as_deref() {
+        Some("FINISHED") => matrix.outcome_summary.unwrap_or_default(),
+        state => state.unwrap_or_default().to_string(),
+    };
+    match outcome.as_str() {
+        "SUCCESS" => Ok(()),
+        "FAILURE" => Err(DoitError::OutcomeError(outcome, 1)),
+        _ => Err(DoitError::OutcomeError(outcome, 2)),
+    }
+}
+</%def>
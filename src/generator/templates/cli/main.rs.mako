@@ -21,7 +21,7 @@ use std::env;
 use std::io::{self, Write};
 use clap::{App, SubCommand, Arg};
 
-use ${to_extern_crate_name(library_to_crate_name(library_name(name, version), make.depends_on_suffix))}::{api, Error, oauth2, client::chrono, FieldMask};
+use ${to_extern_crate_name(library_to_crate_name(library_name(name, version), make.depends_on_suffix))}::{api, Error, oauth2, client::chrono, client::ReadSeekAdapter, client::Delegate, client::retry::RetryPolicy, client::describe_request, FieldMask};
 
 
 use google_clis_common as client;
@@ -53,12 +53,37 @@ async fn main() {
                     DoitError::IoError(path, err) => {
                         writeln!(io::stderr(), "Failed to open output file '{}': {}", path, err).ok();
                     },
+                    DoitError::TimeoutError(timeout) => {
+                        let report = client::ErrorReport {
+                            status: "DEADLINE_EXCEEDED".to_string(),
+                            message: format!("The call didn't finish within {:?}", timeout),
+                            ..Default::default()
+                        };
+                        exit_status = report.exit_code();
+                        writeln!(io::stderr(), "{}", report).ok();
+                    },
+                    DoitError::OutputError(err) => {
+                        writeln!(io::stderr(), "{}", err).ok();
+                    },
+                    DoitError::RequestError(err) => {
+                        writeln!(io::stderr(), "Invalid request: {}", err).ok();
+                    },
+                    DoitError::BatchError(failed, total) => {
+                        writeln!(io::stderr(), "{} of {} requests of the batch failed", failed, total).ok();
+                    },
+                    % if 'test_matrices_wait' in api.get('helpers', list()):
+                    DoitError::OutcomeError(outcome, code) => {
+                        exit_status = code;
+                        writeln!(io::stderr(), "The outcome of the test matrix is {}", outcome).ok();
+                    },
+                    % endif
                     DoitError::ApiError(err) => {
                         if debug {
                             writeln!(io::stderr(), "{:#?}", err).ok();
-                        } else {
-                            writeln!(io::stderr(), "{}", err).ok();
                         }
+                        let report = error_report(&err);
+                        exit_status = report.exit_code();
+                        writeln!(io::stderr(), "{}", report).ok();
                     }
                 }
             }
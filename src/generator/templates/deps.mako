@@ -1,6 +1,9 @@
 # DO NOT EDIT !
 # This file was generated automatically by '${self.uri}'
 # DO NOT EDIT !
+<%!
+	from generator.lib.conversions import latest_stable_version
+%>\
 
 <%
 	import os
@@ -20,7 +23,13 @@
 
 	central_api_index = lambda crate_name: doc_root + '/' + util.to_extern_crate_name(crate_name) + '/index.html'
 
-	if os.environ.get('FETCH_APIS') is not None:
+	import generator.lib.discovery_source as discovery_source
+	vendored_source = os.environ.get(discovery_source.ENV_VAR)
+	if vendored_source:
+		# documents are copied from the vendored source rather than downloaded
+		apis = {'items': discovery_source.list_documents(vendored_source)}
+		print('Found {} apis in {}'.format(len(apis['items']), vendored_source))
+	elif os.environ.get('FETCH_APIS') is not None:
 		import urllib3
 		http = urllib3.PoolManager()
 		# Seems like connecting to https stopped working, so download the json below manually and put it into
@@ -46,8 +55,12 @@
 	global_targets = make.get('global_targets', False)
 
 	post_processor_arg = ''
+	pre_processor_arg = ''
 	if mako is not UNDEFINED:
-		post_processor_arg = '--post-process-python-module=%s' % mako.post_processor_module
+		if mako.get('post_processor_module'):
+			post_processor_arg = '--post-process-python-module=%s' % mako.post_processor_module
+		if mako.get('pre_processor_module'):
+			pre_processor_arg = '--pre-process-python-module=%s' % mako.pre_processor_module
 %>\
 % for an, versions in api.list.items():
 % if an in api.get('blacklist', list()):
@@ -57,6 +70,9 @@
 % if an + '-' + version in api.get('blacklist', list()):
 <% continue %>\
 % endif
+% if make.get('latest_stable_only', False) and version != latest_stable_version(v for v in versions if an + '-' + v not in api.get('blacklist', list())):
+<% continue %>\
+% endif
 <%
 	import generator.lib.util as util
 	import os
@@ -65,7 +81,8 @@
 	def gen_type_cfg_path(id):
 		return '$(API_DIR)/type-' + id + '.yaml'
 
-	CMN_SRC = '/src/client.rs'
+	# The client module lives in google-apis-common, so the crate root stands in for all generated sources
+	CMN_SRC = '/src/lib.rs'
 
 	api_name = util.library_name(an, version)
 	api_target = util.target_directory_name(an, version, suffix)
@@ -73,6 +90,8 @@
 	if make.depends_on_suffix is not None:
 		depends_on_target = directories.output + '/' + util.target_directory_name(an, version, make.depends_on_suffix) + CMN_SRC
 	crate_name = util.library_to_crate_name(api_name, suffix)
+	if cargo.get('is_alias', False):
+		crate_name = util.alias_crate_name(an)
 	gen_root = directories.output + '/' + api_target
 	gen_root_stamp = gen_root + '/.timestamp'
 	api_common = gen_root + CMN_SRC
@@ -98,6 +117,9 @@
 			api_json_inputs = api_json + ' $(API_SHARED_INFO) ' + type_specific_cfg
 			if os.path.isfile(api_json_overrides):
 				api_json_inputs += ' ' + api_json_overrides
+			api_json_subset = api_meta_dir + '/' + an + '-api_subset.yaml'
+			if os.path.isfile(api_json_subset):
+				api_json_inputs += ' ' + api_json_subset
 			api_info.append((api_target, api_clean, api_cargo, api_doc, api_crate_publish_file, gen_root))
 
 			space_join = lambda i: ' '.join(a[i] for a in api_info)
@@ -109,7 +131,7 @@ ${api_common}: ${gen_root_stamp}
 
 ${gen_root_stamp}: $(MAKO_RENDER) ${' '.join(i[0] for i in sds)} ${api_json_inputs} $(MAKO_STANDARD_DEPENDENCIES)
 	@echo Generating ${api_target}
-	$(MAKO) -io ${' '.join("%s=%s" % (s, d) for s, d in sds)} ${post_processor_arg} --data-files ${api_json_inputs}
+	$(MAKO) -io ${' '.join("%s=%s" % (s, d) for s, d in sds)} ${pre_processor_arg} ${post_processor_arg} --data-files ${api_json_inputs}
 	@touch $@
 
 ${api_target}: ${api_common}
@@ -197,12 +219,16 @@ help${agsuffix}:
 	fake_target = target + '-force'
 	## Some service urls have $ in them. This may cause the console to treat them as env vars.
 	## To handle this properly, we need to escape the $.
-	url = info['discoveryRestUrl'].replace("$", "$$")
+	url = info.get('discoveryRestUrl', '').replace("$", "$$")
 	json_api_targets.append(fake_target)
 %>\
 ${fake_target}: $(PYTHON_BIN)
 	@mkdir -p ${target_dir}
+	% if vendored_source:
+	export PYTHONPATH=src:$(PYTHONPATH); $(PYTHON) -m generator.lib.discovery_source '${vendored_source}' '${info['name']}' '${info['version']}' '${target}'
+	% else:
 	-curl --silent --show-error --fail --retry 3 -o '${target}' '${url}'
+	% endif
 	$(PYTHON) $(SORT_JSON_FILE) --skip-missing-file '${target}' || rm ${target}
 % endfor
 
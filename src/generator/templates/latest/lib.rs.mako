@@ -0,0 +1,19 @@
+<%namespace name="util" file="../../lib/util.mako"/>\
+<%
+    from generator.lib.util import (rust_comment, library_to_crate_name, library_name, to_extern_crate_name)
+
+    api_crate = library_to_crate_name(library_name(name, version))
+%>\
+<%block filter="rust_comment">\
+<%util:gen_info source="${self.uri}" />\
+</%block>
+//! The newest stable version of the *${util.canonical_name()}* API, which is protocol *${version}* currently.
+//!
+//! This crate re-exports everything of `${api_crate}`, and is updated to re-export the crate of the next stable
+//! version once there is one. Depending on it allows to follow the API without renaming dependencies, at the
+//! cost of breaking changes whenever the version changes.
+
+pub use ::${to_extern_crate_name(api_crate)}::*;
+
+/// The protocol version of the API this crate re-exports.
+pub const API_VERSION: &str = "${version}";
@@ -0,0 +1,55 @@
+<%namespace name="util" file="../../lib/util.mako"/>\
+<%namespace name="schema" file="../api/lib/schema.mako"/>\
+<%
+    from generator.lib.util import (new_context, rust_comment, schema_markers, library_to_crate_name,
+                      library_name, to_extern_crate_name, UNUSED_TYPE_MARKER)
+
+    c = new_context(schemas, resources)
+    api_crate = to_extern_crate_name(library_to_crate_name(library_name(name, version)))
+%>\
+<%block filter="rust_comment">\
+<%util:gen_info source="${self.uri}" />\
+</%block>
+//! The schema types of the *${util.canonical_name()}* API (protocol *${version}*), as used in the requests and
+//! responses of its methods.
+//!
+//! This crate has no client to make calls with, and thus none of its dependencies, so servers, queues and
+//! storage layers can use the same types as clients. The `${api_crate}` crate has the same types, along
+//! with a hub to call the API.
+//!
+//! As new revisions of the API may add fields, structures are `#[non_exhaustive]` and built starting from
+//! `default()`, either by assigning their fields or by chaining their `with_*(...)` methods.
+//!
+//! Without the default `std` feature, the crate is `#![no_std]` and only needs `alloc`, to deserialize payloads
+//! like push notifications on embedded or wasm targets. Maps are `BTreeMap`s then, as `HashMap` needs `std`.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+// Depending on the API, some of the imports and structures are never used.
+#![allow(unused_imports, dead_code)]
+
+extern crate alloc;
+
+pub extern crate google_types_common as client;
+pub use client::{chrono, FieldMask};
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(not(feature = "std"))]
+use alloc::collections::BTreeMap as HashMap;
+use serde_json as json;
+use serde::{Serialize, Deserialize};
+
+use crate::client::serde_with;
+
+% if c.schemas:
+% for s in c.schemas.values():
+% if UNUSED_TYPE_MARKER not in schema_markers(s, c, transitive=True):
+${schema.new(s, c)}
+% endif
+% endfor
+
+${schema.conformance_tests(c)}
+% endif
@@ -0,0 +1,470 @@
+//! Compares two discovery documents of an API, like the one pinned in `etc/api` which the crates
+//! were generated from, and the latest one published by Google.
+//!
+//! The resulting [`Report`] lists each added or removed schema, field, method and parameter, as
+//! well as fields and parameters whose type changed, and tells which of these changes break code
+//! using the generated crate.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// A single difference between two discovery documents.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    SchemaAdded {
+        schema: String,
+    },
+    SchemaRemoved {
+        schema: String,
+    },
+    /// `field` is a path like `bucket.retentionPolicy.effectiveTime` for fields of nested objects.
+    FieldAdded {
+        schema: String,
+        field: String,
+    },
+    FieldRemoved {
+        schema: String,
+        field: String,
+    },
+    FieldTypeChanged {
+        schema: String,
+        field: String,
+        from: String,
+        to: String,
+    },
+    MethodAdded {
+        method: String,
+    },
+    MethodRemoved {
+        method: String,
+    },
+    ParameterAdded {
+        method: String,
+        parameter: String,
+        required: bool,
+    },
+    ParameterRemoved {
+        method: String,
+        parameter: String,
+    },
+    ParameterTypeChanged {
+        method: String,
+        parameter: String,
+        from: String,
+        to: String,
+    },
+    ParameterRequiredChanged {
+        method: String,
+        parameter: String,
+        required: bool,
+    },
+}
+
+impl Change {
+    /// Returns true if code using the crate generated from the old document may not compile with
+    /// the one generated from the new document.
+    pub fn is_breaking(&self) -> bool {
+        match self {
+            Change::SchemaAdded { .. } | Change::FieldAdded { .. } | Change::MethodAdded { .. } => {
+                false
+            }
+            Change::ParameterAdded { required, .. } => *required,
+            Change::ParameterRequiredChanged { required, .. } => *required,
+            _ => true,
+        }
+    }
+}
+
+/// All differences between two discovery documents.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Report {
+    /// The `id` of the API, like `storage:v1`
+    pub api: String,
+    pub old_revision: String,
+    pub new_revision: String,
+    pub changes: Vec<Change>,
+}
+
+impl Report {
+    /// Returns true if none of the changes is breaking.
+    pub fn is_compatible(&self) -> bool {
+        !self.changes.iter().any(Change::is_breaking)
+    }
+
+    /// Renders the report as markdown, to be put into the description of a pull request.
+    pub fn to_markdown(&self) -> String {
+        let mut md = String::new();
+        writeln!(
+            md,
+            "### {} ({} → {})\n",
+            self.api, self.old_revision, self.new_revision
+        )
+        .unwrap();
+        if self.changes.is_empty() {
+            md.push_str("No changes.\n");
+            return md;
+        }
+        for change in &self.changes {
+            let marker = if change.is_breaking() {
+                "**breaking** "
+            } else {
+                ""
+            };
+            let text = match change {
+                Change::SchemaAdded { schema } => format!("added schema `{}`", schema),
+                Change::SchemaRemoved { schema } => format!("removed schema `{}`", schema),
+                Change::FieldAdded { schema, field } => {
+                    format!("added field `{}.{}`", schema, field)
+                }
+                Change::FieldRemoved { schema, field } => {
+                    format!("removed field `{}.{}`", schema, field)
+                }
+                Change::FieldTypeChanged {
+                    schema,
+                    field,
+                    from,
+                    to,
+                } => format!(
+                    "changed type of field `{}.{}` from `{}` to `{}`",
+                    schema, field, from, to
+                ),
+                Change::MethodAdded { method } => format!("added method `{}`", method),
+                Change::MethodRemoved { method } => format!("removed method `{}`", method),
+                Change::ParameterAdded {
+                    method,
+                    parameter,
+                    required,
+                } => format!(
+                    "added {} parameter `{}` to `{}`",
+                    if *required { "required" } else { "optional" },
+                    parameter,
+                    method
+                ),
+                Change::ParameterRemoved { method, parameter } => {
+                    format!("removed parameter `{}` of `{}`", parameter, method)
+                }
+                Change::ParameterTypeChanged {
+                    method,
+                    parameter,
+                    from,
+                    to,
+                } => format!(
+                    "changed type of parameter `{}` of `{}` from `{}` to `{}`",
+                    parameter, method, from, to
+                ),
+                Change::ParameterRequiredChanged {
+                    method,
+                    parameter,
+                    required,
+                } => format!(
+                    "made parameter `{}` of `{}` {}",
+                    parameter,
+                    method,
+                    if *required { "required" } else { "optional" }
+                ),
+            };
+            writeln!(md, "- {}{}", marker, text).unwrap();
+        }
+        md
+    }
+}
+
+/// Computes the differences between the discovery documents `old` and `new`.
+pub fn diff(old: &Value, new: &Value) -> Report {
+    let mut changes = Vec::new();
+    diff_schemas(
+        &object(old, "schemas"),
+        &object(new, "schemas"),
+        &mut changes,
+    );
+    diff_methods(&methods(old), &methods(new), &mut changes);
+    Report {
+        api: str_of(new, "id"),
+        old_revision: str_of(old, "revision"),
+        new_revision: str_of(new, "revision"),
+        changes,
+    }
+}
+
+fn diff_schemas(old: &Map<String, Value>, new: &Map<String, Value>, changes: &mut Vec<Change>) {
+    for (id, old_schema) in old {
+        match new.get(id) {
+            None => changes.push(Change::SchemaRemoved { schema: id.clone() }),
+            Some(new_schema) => {
+                let (mut old_fields, mut new_fields) = (BTreeMap::new(), BTreeMap::new());
+                collect_fields(old_schema, "", &mut old_fields);
+                collect_fields(new_schema, "", &mut new_fields);
+                for (field, old_type) in &old_fields {
+                    match new_fields.get(field) {
+                        None => changes.push(Change::FieldRemoved {
+                            schema: id.clone(),
+                            field: field.clone(),
+                        }),
+                        Some(new_type) if new_type != old_type => {
+                            changes.push(Change::FieldTypeChanged {
+                                schema: id.clone(),
+                                field: field.clone(),
+                                from: old_type.clone(),
+                                to: new_type.clone(),
+                            })
+                        }
+                        Some(_) => {}
+                    }
+                }
+                for field in new_fields.keys().filter(|f| !old_fields.contains_key(*f)) {
+                    changes.push(Change::FieldAdded {
+                        schema: id.clone(),
+                        field: field.clone(),
+                    });
+                }
+            }
+        }
+    }
+    for id in new.keys().filter(|id| !old.contains_key(*id)) {
+        changes.push(Change::SchemaAdded { schema: id.clone() });
+    }
+}
+
+/// Collects the types of all fields of `schema`, including those of inline objects.
+fn collect_fields(schema: &Value, prefix: &str, fields: &mut BTreeMap<String, String>) {
+    let properties = match schema.get("properties").and_then(Value::as_object) {
+        Some(properties) => properties,
+        None => return,
+    };
+    for (name, property) in properties {
+        let path = format!("{}{}", prefix, name);
+        fields.insert(path.clone(), type_of(property));
+        let nested = property.get("items").unwrap_or(property);
+        collect_fields(nested, &format!("{}.", path), fields);
+    }
+}
+
+fn diff_methods(
+    old: &BTreeMap<String, Value>,
+    new: &BTreeMap<String, Value>,
+    changes: &mut Vec<Change>,
+) {
+    for (id, old_method) in old {
+        let new_method = match new.get(id) {
+            None => {
+                changes.push(Change::MethodRemoved { method: id.clone() });
+                continue;
+            }
+            Some(method) => method,
+        };
+        let (old_params, new_params) = (
+            object(old_method, "parameters"),
+            object(new_method, "parameters"),
+        );
+        for (name, old_param) in &old_params {
+            let new_param = match new_params.get(name) {
+                None => {
+                    changes.push(Change::ParameterRemoved {
+                        method: id.clone(),
+                        parameter: name.clone(),
+                    });
+                    continue;
+                }
+                Some(param) => param,
+            };
+            let (old_type, new_type) = (type_of(old_param), type_of(new_param));
+            if old_type != new_type {
+                changes.push(Change::ParameterTypeChanged {
+                    method: id.clone(),
+                    parameter: name.clone(),
+                    from: old_type,
+                    to: new_type,
+                });
+            }
+            if is_required(old_param) != is_required(new_param) {
+                changes.push(Change::ParameterRequiredChanged {
+                    method: id.clone(),
+                    parameter: name.clone(),
+                    required: is_required(new_param),
+                });
+            }
+        }
+        for (name, param) in new_params
+            .iter()
+            .filter(|(n, _)| !old_params.contains_key(*n))
+        {
+            changes.push(Change::ParameterAdded {
+                method: id.clone(),
+                parameter: name.clone(),
+                required: is_required(param),
+            });
+        }
+    }
+    for id in new.keys().filter(|id| !old.contains_key(*id)) {
+        changes.push(Change::MethodAdded { method: id.clone() });
+    }
+}
+
+/// Returns all methods of the API by their id.
+fn methods(doc: &Value) -> BTreeMap<String, Value> {
+    fn collect(container: &Value, methods: &mut BTreeMap<String, Value>) {
+        for method in object(container, "methods").values() {
+            methods.insert(str_of(method, "id"), method.clone());
+        }
+        for resource in object(container, "resources").values() {
+            collect(resource, methods);
+        }
+    }
+    let mut methods = BTreeMap::new();
+    collect(doc, &mut methods);
+    methods
+}
+
+/// Describes the type of a property or parameter, like `string(int64)`, `array<Bucket>` or
+/// `map<string>`.
+fn type_of(property: &Value) -> String {
+    if let Some(id) = property.get("$ref").and_then(Value::as_str) {
+        return id.to_string();
+    }
+    let ty = str_of(property, "type");
+    let ty = match ty.as_str() {
+        "array" => format!(
+            "array<{}>",
+            property.get("items").map(type_of).unwrap_or_default()
+        ),
+        "object" => match property.get("additionalProperties") {
+            Some(values) => format!("map<{}>", type_of(values)),
+            None => ty,
+        },
+        _ => ty,
+    };
+    let ty = match property.get("format").and_then(Value::as_str) {
+        Some(format) => format!("{}({})", ty, format),
+        None => ty,
+    };
+    if property
+        .get("repeated")
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+    {
+        format!("repeated {}", ty)
+    } else {
+        ty
+    }
+}
+
+fn is_required(param: &Value) -> bool {
+    param
+        .get("required")
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+fn object(value: &Value, key: &str) -> Map<String, Value> {
+    value
+        .get(key)
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn str_of(value: &Value, key: &str) -> String {
+    value
+        .get(key)
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn doc(revision: &str, schema: Value, method: Value) -> Value {
+        json!({
+            "id": "storage:v1",
+            "revision": revision,
+            "schemas": {"Bucket": schema},
+            "resources": {"buckets": {"methods": {"get": method}}},
+        })
+    }
+
+    #[test]
+    fn identical_documents_have_no_changes() {
+        let d = doc(
+            "1",
+            json!({"properties": {"name": {"type": "string"}}}),
+            json!({"id": "storage.buckets.get"}),
+        );
+        let report = diff(&d, &d);
+        assert!(report.changes.is_empty());
+        assert!(report.is_compatible());
+    }
+
+    #[test]
+    fn fields_and_parameters_are_compared() {
+        let old = doc(
+            "1",
+            json!({"properties": {
+                "name": {"type": "string"},
+                "size": {"type": "string", "format": "int64"},
+                "owner": {"type": "object", "properties": {"entity": {"type": "string"}}},
+            }}),
+            json!({"id": "storage.buckets.get", "parameters": {"bucket": {"type": "string", "required": true}}}),
+        );
+        let new = doc(
+            "2",
+            json!({"properties": {
+                "name": {"type": "string"},
+                "size": {"type": "string", "format": "uint64"},
+                "owner": {"type": "object", "properties": {"entityId": {"type": "string"}}},
+            }}),
+            json!({"id": "storage.buckets.get", "parameters": {
+                "bucket": {"type": "string", "required": true},
+                "userProject": {"type": "string"},
+            }}),
+        );
+        let report = diff(&old, &new);
+        assert_eq!(
+            report.changes,
+            vec![
+                Change::FieldRemoved {
+                    schema: "Bucket".into(),
+                    field: "owner.entity".into()
+                },
+                Change::FieldTypeChanged {
+                    schema: "Bucket".into(),
+                    field: "size".into(),
+                    from: "string(int64)".into(),
+                    to: "string(uint64)".into(),
+                },
+                Change::FieldAdded {
+                    schema: "Bucket".into(),
+                    field: "owner.entityId".into()
+                },
+                Change::ParameterAdded {
+                    method: "storage.buckets.get".into(),
+                    parameter: "userProject".into(),
+                    required: false,
+                },
+            ]
+        );
+        assert!(!report.is_compatible());
+        assert!(report
+            .to_markdown()
+            .contains("- **breaking** removed field `Bucket.owner.entity`"));
+    }
+
+    #[test]
+    fn added_methods_are_compatible() {
+        let old = json!({"resources": {}});
+        let new = json!({"resources": {"buckets": {"methods": {"list": {"id": "storage.buckets.list"}}}}});
+        let report = diff(&old, &new);
+        assert_eq!(
+            report.changes,
+            vec![Change::MethodAdded {
+                method: "storage.buckets.list".into()
+            }]
+        );
+        assert!(report.is_compatible());
+    }
+}
@@ -0,0 +1,45 @@
+//! Prints the differences between two discovery documents, like
+//! `discovery-diff etc/api/storage/v1/storage-api.json storage-latest.json`.
+//!
+//! With `--json`, the report is printed as JSON instead of markdown. The exit code is 1 if any
+//! change is breaking.
+use std::process;
+
+use serde_json::Value;
+
+fn load(path: &str) -> Value {
+    let contents = std::fs::read_to_string(path).unwrap_or_else(|err| {
+        eprintln!("Could not read '{}': {}", path, err);
+        process::exit(2)
+    });
+    serde_json::from_str(&contents).unwrap_or_else(|err| {
+        eprintln!("Could not parse '{}': {}", path, err);
+        process::exit(2)
+    })
+}
+
+fn main() {
+    let mut json = false;
+    let mut paths = Vec::new();
+    for arg in std::env::args().skip(1) {
+        if arg == "--json" {
+            json = true;
+        } else {
+            paths.push(arg);
+        }
+    }
+    if paths.len() != 2 {
+        eprintln!("Usage: discovery-diff [--json] <old-discovery.json> <new-discovery.json>");
+        process::exit(2);
+    }
+
+    let report = discovery_diff::diff(&load(&paths[0]), &load(&paths[1]));
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        print!("{}", report.to_markdown());
+    }
+    if !report.is_compatible() {
+        process::exit(1);
+    }
+}